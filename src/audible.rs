@@ -0,0 +1,93 @@
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+fn has_extension(path: &Path, extensions: &[&str]) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| extensions.iter().any(|x| e.eq_ignore_ascii_case(x)))
+}
+
+/// Whether a file is in one of Audible's DRM-protected formats. Apple Books plays them when
+/// they're imported, but Audiobookshelf can't.
+pub fn is_audible(path: &Path) -> bool {
+    has_extension(path, &["aax", "aa"])
+}
+
+/// Whether an Audible file can be converted with the account's activation bytes. Only
+/// `.aax` can; ffmpeg can't decrypt the older `.aa` format.
+pub fn is_convertible(path: &Path) -> bool {
+    has_extension(path, &["aax"])
+}
+
+/// Destination filename of a converted Audible file
+pub fn output_filename(filename: &str) -> String {
+    Path::new(filename)
+        .with_extension("m4b")
+        .to_string_lossy()
+        .to_string()
+}
+
+/// Clap value parser for `--activation-bytes`: 8 hex digits, as printed by tools that
+/// read them from an Audible account
+pub fn parse_activation_bytes(s: &str) -> Result<String> {
+    if s.len() != 8 || !s.chars().all(|c| c.is_ascii_hexdigit()) {
+        bail!("expected 8 hex digits, e.g. 1a2b3c4d");
+    }
+    Ok(s.to_lowercase())
+}
+
+/// Decrypt an `.aax` file into an `.m4b` without re-encoding, keeping tags and chapters.
+/// Output goes to a `.partial.m4b` renamed into place, so an interrupted run never leaves
+/// a truncated file that later runs would take for a finished conversion.
+pub fn convert(source: &Path, dest: &Path, activation_bytes: &str) -> Result<()> {
+    let partial = dest.with_extension("partial.m4b");
+    let output = Command::new("ffmpeg")
+        .args(["-v", "error", "-nostdin", "-y", "-activation_bytes"])
+        .arg(activation_bytes)
+        .arg("-i")
+        .arg(source)
+        .args([
+            "-map",
+            "0:a",
+            "-map_metadata",
+            "0",
+            "-c",
+            "copy",
+            "-f",
+            "mp4",
+        ])
+        .arg(&partial)
+        .output()
+        .context("Failed to run ffmpeg (is ffmpeg installed?)")?;
+
+    if !output.status.success() {
+        let _ = fs::remove_file(&partial);
+        bail!(
+            "ffmpeg failed to convert {:?}: {}",
+            source,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    fs::rename(&partial, dest).with_context(|| format!("Failed to move {:?} into place", dest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_audible_formats() {
+        assert!(is_audible(Path::new("Book.AAX")));
+        assert!(is_audible(Path::new("Book.aa")));
+        assert!(!is_audible(Path::new("Book.m4b")));
+        assert!(is_convertible(Path::new("Book.aax")));
+        assert!(!is_convertible(Path::new("Book.aa")));
+        assert_eq!(output_filename("01 Book.aax"), "01 Book.m4b");
+
+        assert_eq!(parse_activation_bytes("1A2B3C4D").unwrap(), "1a2b3c4d");
+        assert!(parse_activation_bytes("1a2b3c").is_err());
+        assert!(parse_activation_bytes("1a2b3c4g").is_err());
+    }
+}
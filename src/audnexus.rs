@@ -0,0 +1,257 @@
+use anyhow::{Context, Result};
+use regex::Regex;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use crate::chapters::Chapter;
+use crate::http::cached_get;
+use crate::identifiers::is_asin;
+use crate::{media, remap_track_path, series, Audiobook};
+
+const API: &str = "https://api.audnex.us";
+
+/// Tags Audible downloads and tools like Libation put the ASIN in
+const ASIN_TAGS: &[&str] = &["asin", "audible_asin", "cdek"];
+
+/// Audible ASINs in filenames, e.g. "Dune [B002V1OF70].m4b"
+fn asin_in_text() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"\b(B0[0-9A-Z]{8})\b").unwrap())
+}
+
+/// The Audible ASIN of a book, from the library, its track filenames or else the tags of
+/// its first track (requires ffprobe). Books bought from Apple have none.
+pub fn find_asin(book: &Audiobook, source_base: &Path) -> Option<String> {
+    if let Some(asin) = &book.details.asin {
+        return Some(asin.clone());
+    }
+    if let Some(asin) = book
+        .tracks
+        .iter()
+        .find_map(|track| asin_in_text().captures(&track.filename))
+    {
+        return Some(asin[1].to_string());
+    }
+    let first = book.tracks.first()?;
+    let tags = media::probe_format_tags(&remap_track_path(&first.path, source_base)).ok()?;
+    ASIN_TAGS
+        .iter()
+        .filter_map(|name| media::find_tag(&tags, name))
+        .find(|value| is_asin(value))
+        .map(str::to_string)
+}
+
+#[derive(Debug, Deserialize)]
+struct Person {
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct SeriesEntry {
+    name: String,
+    /// A string like "1" or "2.5", though sometimes a number
+    position: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BookResponse {
+    #[serde(default)]
+    narrators: Vec<Person>,
+    series_primary: Option<SeriesEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ChapterEntry {
+    length_ms: u64,
+    start_offset_ms: u64,
+    title: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChaptersResponse {
+    chapters: Vec<ChapterEntry>,
+}
+
+/// What Audnexus knows about a book
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Lookup {
+    pub narrators: Vec<String>,
+    pub series: Option<String>,
+    pub series_sequence: Option<String>,
+    /// Chapters of the Audible edition, empty if Audnexus has none
+    pub chapters: Vec<Chapter>,
+}
+
+fn parse_book(json: &str) -> Result<Lookup> {
+    let book: BookResponse = serde_json::from_str(json).context("Unexpected Audnexus book")?;
+    let (series, series_sequence) = match book.series_primary {
+        Some(entry) => {
+            let position = match entry.position {
+                Some(serde_json::Value::String(s)) => Some(s),
+                Some(serde_json::Value::Number(n)) => Some(n.to_string()),
+                _ => None,
+            };
+            let position = position
+                .map(|p| p.trim().to_string())
+                .filter(|p| !p.is_empty() && p.chars().all(|c| c.is_ascii_digit() || c == '.'))
+                .map(|p| series::normalize_sequence(&p));
+            (Some(entry.name), position)
+        }
+        None => (None, None),
+    };
+    Ok(Lookup {
+        narrators: book.narrators.into_iter().map(|p| p.name).collect(),
+        series,
+        series_sequence,
+        chapters: Vec::new(),
+    })
+}
+
+fn parse_chapters(json: &str) -> Result<Vec<Chapter>> {
+    let response: ChaptersResponse =
+        serde_json::from_str(json).context("Unexpected Audnexus chapters")?;
+    Ok(response
+        .chapters
+        .into_iter()
+        .enumerate()
+        .map(|(id, chapter)| Chapter {
+            id,
+            start: chapter.start_offset_ms as f64 / 1000.0,
+            end: (chapter.start_offset_ms + chapter.length_ms) as f64 / 1000.0,
+            title: chapter.title,
+        })
+        .collect())
+}
+
+/// Look a book up on Audnexus by ASIN in an Audible region ("us", "uk", "de", ...).
+/// `None` if Audnexus doesn't know the ASIN.
+pub fn lookup(asin: &str, region: &str, cache: Option<&Path>) -> Result<Option<Lookup>> {
+    let book_url = format!("{}/books/{}?region={}", API, asin, region);
+    let Some(json) = cached_get(cache, &format!("{}-{}.json", region, asin), &book_url, &[])?
+    else {
+        return Ok(None);
+    };
+    let mut found = parse_book(&json)?;
+    let chapters_url = format!("{}/books/{}/chapters?region={}", API, asin, region);
+    if let Some(json) = cached_get(
+        cache,
+        &format!("{}-{}-chapters.json", region, asin),
+        &chapters_url,
+        &[],
+    )? {
+        found.chapters = parse_chapters(&json)?;
+    }
+    Ok(Some(found))
+}
+
+/// Use what Audnexus knows over what the library has. Returns whether anything changed.
+pub fn apply(book: &mut Audiobook, found: &Lookup) -> bool {
+    let before = (
+        book.narrator.clone(),
+        book.series.clone(),
+        book.series_sequence.clone(),
+    );
+    if !found.narrators.is_empty() {
+        book.narrator = Some(found.narrators.join(", "));
+    }
+    if let Some(series) = &found.series {
+        book.series = Some(series.clone());
+        book.series_sequence = found.series_sequence.clone();
+        book.series_detected = true;
+    }
+    before
+        != (
+            book.narrator.clone(),
+            book.series.clone(),
+            book.series_sequence.clone(),
+        )
+}
+
+/// Whether chapters fit audio of the given duration in seconds, i.e. they are of the same
+/// edition: the last one ends within 2% of the end
+pub fn chapters_fit(chapters: &[Chapter], duration: f64) -> bool {
+    chapters
+        .last()
+        .is_some_and(|last| (last.end - duration).abs() <= duration * 0.02)
+}
+
+/// Where Audnexus responses are cached
+pub fn default_cache_dir() -> Option<PathBuf> {
+    dirs::cache_dir().map(|dir| dir.join("extract_apple_books").join("audnexus"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AudioTrack;
+
+    #[test]
+    fn test_find_asin_and_apply_lookup() {
+        let track = |filename: &str| AudioTrack {
+            track_number: 1,
+            disc_number: 0,
+            title: String::new(),
+            path: PathBuf::from("/nowhere").join(filename),
+            filename: filename.to_string(),
+        };
+        let mut book = Audiobook {
+            title: "Dune".to_string(),
+            author: "Frank Herbert".to_string(),
+            narrator: Some("Scott Brick".to_string()),
+            folder_id: "sha1-aaa".to_string(),
+            tracks: vec![track("Dune [B002V1OF70].m4b")],
+            ..Default::default()
+        };
+        assert_eq!(
+            find_asin(&book, Path::new("/nowhere")).as_deref(),
+            Some("B002V1OF70")
+        );
+        book.details.asin = Some("B0036S4B2G".to_string());
+        assert_eq!(
+            find_asin(&book, Path::new("/nowhere")).as_deref(),
+            Some("B0036S4B2G")
+        );
+        book.details.asin = None;
+
+        let found = parse_book(
+            r#"{"asin":"B002V1OF70","title":"Dune",
+                "narrators":[{"name":"Scott Brick"},{"name":"Orlagh Cassidy"}],
+                "seriesPrimary":{"asin":"B00M0BGXDA","name":"Dune","position":"1"}}"#,
+        )
+        .unwrap();
+        assert_eq!(found.narrators, ["Scott Brick", "Orlagh Cassidy"]);
+        assert_eq!(found.series.as_deref(), Some("Dune"));
+        assert_eq!(found.series_sequence.as_deref(), Some("1"));
+        assert!(apply(&mut book, &found));
+        assert_eq!(
+            book.narrator.as_deref(),
+            Some("Scott Brick, Orlagh Cassidy")
+        );
+        assert_eq!(book.series_sequence.as_deref(), Some("1"));
+        assert!(!apply(&mut book, &found));
+
+        let found = parse_book(r#"{"seriesPrimary":{"name":"Dune","position":"Book 1"}}"#).unwrap();
+        assert_eq!(found.series_sequence, None);
+        assert!(found.narrators.is_empty());
+    }
+
+    #[test]
+    fn test_parse_chapters() {
+        let chapters = parse_chapters(
+            r#"{"asin":"B002V1OF70","runtimeLengthMs":3600000,"chapters":[
+                {"lengthMs":1800000,"startOffsetMs":0,"startOffsetSec":0,"title":"Opening Credits"},
+                {"lengthMs":1800000,"startOffsetMs":1800000,"startOffsetSec":1800,"title":"Book One"}]}"#,
+        )
+        .unwrap();
+        assert_eq!(chapters.len(), 2);
+        assert_eq!(chapters[1].start, 1800.0);
+        assert_eq!(chapters[1].end, 3600.0);
+        assert_eq!(chapters[1].title, "Book One");
+        assert!(chapters_fit(&chapters, 3610.0));
+        assert!(!chapters_fit(&chapters, 5400.0));
+        assert!(!chapters_fit(&[], 3600.0));
+    }
+}
@@ -0,0 +1,444 @@
+use anyhow::Result;
+use eframe::egui;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+
+use extract_apple_books::tui::BookStatus;
+use extract_apple_books::*;
+
+fn main() -> eframe::Result {
+    let options = eframe::NativeOptions {
+        viewport: egui::ViewportBuilder::default().with_inner_size([760.0, 600.0]),
+        ..Default::default()
+    };
+    eframe::run_native(
+        "Extract Apple Books",
+        options,
+        Box::new(|_| Ok(Box::new(App::new()))),
+    )
+}
+
+/// The library as loaded, sorted by author and title, with the status of each book
+/// against the destination when one was given
+struct Library {
+    source: PathBuf,
+    audiobooks: Arc<Vec<Audiobook>>,
+    statuses: Option<Vec<BookStatus>>,
+}
+
+/// How an export of the checked books went
+#[derive(Default)]
+struct Outcome {
+    books_exported: usize,
+    problems: Vec<String>,
+}
+
+/// What the worker thread reports back to the window
+enum Message {
+    Loaded(Result<Library, String>),
+    Exporting(ExportProgress),
+    Exported(Outcome),
+}
+
+/// What the worker thread is busy with
+enum Busy {
+    Loading,
+    /// Exporting, and how far it has come once the export reports it
+    Exporting(Option<ExportProgress>),
+}
+
+struct App {
+    source: String,
+    dest: String,
+    options: ExportOptions,
+    email: Option<email::EmailConfig>,
+    library: Option<Library>,
+    checked: Vec<bool>,
+    busy: Option<Busy>,
+    outcome: Option<Outcome>,
+    error: Option<String>,
+    sender: Sender<Message>,
+    messages: Receiver<Message>,
+}
+
+impl App {
+    fn new() -> Self {
+        let (sender, messages) = mpsc::channel();
+        let mut app = App {
+            source: default_apple_books_path().display().to_string(),
+            dest: String::new(),
+            options: ExportOptions::default(),
+            email: None,
+            library: None,
+            checked: Vec::new(),
+            busy: None,
+            outcome: None,
+            error: None,
+            sender,
+            messages,
+        };
+        if let Err(e) = app.read_config() {
+            app.error = Some(format!("{:#}", e));
+        }
+        app
+    }
+
+    /// Take the library, destination and options from the `[export]` section of the config
+    /// file, so books end up as with `extract_apple_books export`
+    fn read_config(&mut self) -> Result<()> {
+        let Some(path) = config::default_config_path() else {
+            return Ok(());
+        };
+        let config = config::Config::read(&path)?;
+        config.filenames.validate()?;
+        sanitize::init(config.filenames.clone());
+        subtitle::init(config.subtitles.clone());
+
+        let defaults = &config.export;
+        if let Some(source) = &defaults.source {
+            self.source = source.display().to_string();
+        }
+        if let Some(dest) = &defaults.dest {
+            self.dest = dest.display().to_string();
+        }
+        let mut options = ExportOptions {
+            jobs: transcode::default_workers(),
+            hash_workers: 2,
+            ..Default::default()
+        };
+        defaults.apply(&mut options, None, &())?;
+        // The books checked in the window are the selection
+        options.filter = Default::default();
+        self.options = options;
+        self.email = config.email;
+        Ok(())
+    }
+
+    fn dest(&self) -> Option<PathBuf> {
+        let dest = self.dest.trim();
+        (!dest.is_empty()).then(|| PathBuf::from(dest))
+    }
+
+    /// Read the library and compare it with the destination on the worker thread
+    fn load(&mut self, ctx: &egui::Context) {
+        self.busy = Some(Busy::Loading);
+        self.error = None;
+        let source = PathBuf::from(self.source.trim());
+        let dest = self.dest();
+        let options = self.options.clone();
+        let send = self.sender(ctx);
+        std::thread::spawn(move || {
+            let library = load(source, dest.as_deref(), &options).map_err(|e| format!("{:#}", e));
+            send(Message::Loaded(library));
+        });
+    }
+
+    /// Export the checked books on the worker thread, reporting how far it has come
+    fn export(&mut self, ctx: &egui::Context) {
+        let (Some(library), Some(dest)) = (&self.library, self.dest()) else {
+            return;
+        };
+        let (progress, reports) = mpsc::channel();
+        let options = ExportOptions {
+            book_ids: library
+                .audiobooks
+                .iter()
+                .zip(&self.checked)
+                .filter(|(_, checked)| **checked)
+                .map(|(book, _)| book.folder_id.clone())
+                .collect(),
+            progress: Some(progress),
+            ..self.options.clone()
+        };
+        self.busy = Some(Busy::Exporting(None));
+        self.outcome = None;
+        self.error = None;
+
+        let send = self.sender(ctx);
+        std::thread::spawn(move || {
+            // Ends when the export drops its options
+            for report in reports {
+                send(Message::Exporting(report));
+            }
+        });
+        // The whole library goes along so books left unchecked aren't reported as removed
+        let audiobooks = Arc::clone(&library.audiobooks);
+        let source = library.source.clone();
+        let email = self.email.clone();
+        let send = self.sender(ctx);
+        std::thread::spawn(move || {
+            let outcome = export(&audiobooks, &source, &dest, options, email.as_ref())
+                .unwrap_or_else(|e| Outcome {
+                    books_exported: 0,
+                    problems: vec![format!("{:#}", e)],
+                });
+            send(Message::Exported(outcome));
+        });
+    }
+
+    /// Send a message to the window and wake it up to show it
+    fn sender(&self, ctx: &egui::Context) -> impl Fn(Message) + Send + 'static {
+        let sender = self.sender.clone();
+        let ctx = ctx.clone();
+        move |message| {
+            let _ = sender.send(message);
+            ctx.request_repaint();
+        }
+    }
+
+    fn receive(&mut self, message: Message, ctx: &egui::Context) {
+        match message {
+            Message::Loaded(Ok(library)) => {
+                self.checked = match &library.statuses {
+                    Some(statuses) => statuses
+                        .iter()
+                        .map(|status| needs_export(*status))
+                        .collect(),
+                    None => vec![false; library.audiobooks.len()],
+                };
+                self.library = Some(library);
+                self.busy = None;
+            }
+            Message::Loaded(Err(e)) => {
+                self.error = Some(e);
+                self.busy = None;
+            }
+            // Reports still on their way once the export is done are stale
+            Message::Exporting(progress) => {
+                if let Some(Busy::Exporting(_)) = self.busy {
+                    self.busy = Some(Busy::Exporting(Some(progress)));
+                }
+            }
+            Message::Exported(outcome) => {
+                self.outcome = Some(outcome);
+                // Read the destination again so the statuses show what was exported
+                self.load(ctx);
+            }
+        }
+    }
+
+    fn paths(&mut self, ui: &mut egui::Ui, ctx: &egui::Context) {
+        egui::Grid::new("paths").num_columns(3).show(ui, |ui| {
+            folder_row(ui, "Apple Books library", &mut self.source);
+            folder_row(ui, "Destination", &mut self.dest);
+        });
+        ui.horizontal(|ui| {
+            let idle = self.busy.is_none();
+            if ui
+                .add_enabled(idle, egui::Button::new("Load books"))
+                .clicked()
+            {
+                self.load(ctx);
+            }
+            let checked = self.checked.iter().filter(|checked| **checked).count();
+            let can_export = idle && checked > 0 && self.dest().is_some();
+            let export = egui::Button::new(format!("Export {} books", checked));
+            if ui.add_enabled(can_export, export).clicked() {
+                self.export(ctx);
+            }
+        });
+    }
+
+    fn books(&mut self, ui: &mut egui::Ui) {
+        let Some(library) = &self.library else {
+            ui.label("Choose the Apple Books library and a destination, then load the books.");
+            return;
+        };
+        if library.audiobooks.is_empty() {
+            ui.label("The library has no audiobooks.");
+            return;
+        }
+        ui.horizontal(|ui| {
+            if ui.button("Select all").clicked() {
+                self.checked.fill(true);
+            }
+            if ui.button("Select none").clicked() {
+                self.checked.fill(false);
+            }
+            if let Some(statuses) = &library.statuses {
+                if ui.button("Select books to export").clicked() {
+                    for (checked, status) in self.checked.iter_mut().zip(statuses) {
+                        *checked = needs_export(*status);
+                    }
+                }
+            }
+        });
+        ui.separator();
+
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            egui::Grid::new("books")
+                .num_columns(2)
+                .striped(true)
+                .show(ui, |ui| {
+                    for (i, book) in library.audiobooks.iter().enumerate() {
+                        let label = format!("{} - {}", book.author, book.title);
+                        ui.checkbox(&mut self.checked[i], label);
+                        match &library.statuses {
+                            Some(statuses) => ui.label(statuses[i].label()),
+                            None => ui.label(""),
+                        };
+                        ui.end_row();
+                    }
+                });
+        });
+    }
+
+    fn status(&self, ui: &mut egui::Ui) {
+        match &self.busy {
+            Some(Busy::Loading) => {
+                ui.horizontal(|ui| {
+                    ui.spinner();
+                    ui.label("Reading library");
+                });
+            }
+            Some(Busy::Exporting(None)) => {
+                ui.horizontal(|ui| {
+                    ui.spinner();
+                    ui.label("Exporting");
+                });
+            }
+            Some(Busy::Exporting(Some(progress))) => {
+                let fraction = progress.done as f32 / progress.total.max(1) as f32;
+                let text = format!(
+                    "{} {} of {}: {}",
+                    progress.phase, progress.done, progress.total, progress.book
+                );
+                ui.add(egui::ProgressBar::new(fraction).text(text));
+            }
+            None => {}
+        }
+        if let Some(error) = &self.error {
+            ui.colored_label(ui.visuals().error_fg_color, error);
+        }
+        let Some(outcome) = &self.outcome else {
+            return;
+        };
+        ui.label(format!("Exported {} books", outcome.books_exported));
+        if outcome.problems.is_empty() {
+            return;
+        }
+        egui::CollapsingHeader::new(format!("{} problems", outcome.problems.len()))
+            .default_open(true)
+            .show(ui, |ui| {
+                egui::ScrollArea::vertical()
+                    .max_height(160.0)
+                    .show(ui, |ui| {
+                        for problem in &outcome.problems {
+                            ui.colored_label(ui.visuals().warn_fg_color, problem);
+                        }
+                    });
+            });
+    }
+}
+
+impl eframe::App for App {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        while let Ok(message) = self.messages.try_recv() {
+            self.receive(message, ctx);
+        }
+        egui::TopBottomPanel::top("paths").show(ctx, |ui| {
+            ui.add_space(4.0);
+            self.paths(ui, ctx);
+            ui.add_space(4.0);
+        });
+        egui::TopBottomPanel::bottom("status").show(ctx, |ui| {
+            ui.add_space(4.0);
+            self.status(ui);
+            ui.add_space(4.0);
+        });
+        egui::CentralPanel::default().show(ctx, |ui| self.books(ui));
+    }
+}
+
+/// A labelled path field with a button to choose the folder
+fn folder_row(ui: &mut egui::Ui, label: &str, path: &mut String) {
+    ui.label(label);
+    ui.add(egui::TextEdit::singleline(path).desired_width(460.0));
+    if ui.button("Choose…").clicked() {
+        let mut dialog = rfd::FileDialog::new();
+        if Path::new(path.trim()).is_dir() {
+            dialog = dialog.set_directory(path.trim());
+        }
+        if let Some(folder) = dialog.pick_folder() {
+            *path = folder.display().to_string();
+        }
+    }
+    ui.end_row();
+}
+
+/// Whether a book has files to copy or update
+fn needs_export(status: BookStatus) -> bool {
+    matches!(
+        status,
+        BookStatus::New | BookStatus::Incomplete | BookStatus::Changed
+    )
+}
+
+/// Read the library the way `export` does, and compare it with `dest`
+fn load(source: PathBuf, dest: Option<&Path>, options: &ExportOptions) -> Result<Library> {
+    let mut audiobooks = parse_books_plist(&source.join("Books.plist"))?;
+    tags::fill_from_tags(&mut audiobooks, &source);
+    if let Some(path) = overrides::default_overrides_path() {
+        overrides::Overrides::read(&path)?.apply(&mut audiobooks);
+    }
+    audiobooks.sort_by_cached_key(|book| (book.author.to_lowercase(), book.title.to_lowercase()));
+
+    let statuses = dest.map(|dest| {
+        let diffs = compute_diff(&audiobooks, &source, dest, options);
+        let mut files: BTreeMap<(&str, &str), Vec<&FileStatus>> = BTreeMap::new();
+        for diff in &diffs {
+            files
+                .entry((&diff.author, &diff.book_title))
+                .or_default()
+                .push(&diff.status);
+        }
+        audiobooks
+            .iter()
+            .map(|book| {
+                let statuses = files
+                    .get(&(book.author.as_str(), book.title.as_str()))
+                    .map(Vec::as_slice)
+                    .unwrap_or_default();
+                BookStatus::from_files(statuses)
+            })
+            .collect()
+    });
+    Ok(Library {
+        source,
+        audiobooks: Arc::new(audiobooks),
+        statuses,
+    })
+}
+
+/// Export the books `options` selects in one run, and save its report and history as
+/// `export` does
+fn export(
+    audiobooks: &[Audiobook],
+    source: &Path,
+    dest: &Path,
+    options: ExportOptions,
+    email: Option<&email::EmailConfig>,
+) -> Result<Outcome> {
+    let stats = export_audiobooks(audiobooks, source, dest, &options)?;
+    let mut problems: Vec<_> = stats
+        .failures
+        .iter()
+        .map(|failure| {
+            format!(
+                "{}: {:?}: {}",
+                failure.book,
+                failure.source.file_name().unwrap_or_default(),
+                failure.error
+            )
+        })
+        .collect();
+    problems.extend(stats.warnings.iter().cloned());
+    let books_exported = stats.books_exported;
+    save_report(&report::default_report_path(dest), dest, stats, email)?;
+    Ok(Outcome {
+        books_exported,
+        problems,
+    })
+}
@@ -0,0 +1,312 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::http::cached_get;
+use crate::Audiobook;
+
+/// Book databases searched by title and author, for books Audnexus can't find
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Service {
+    OpenLibrary,
+    GoogleBooks,
+}
+
+impl Service {
+    fn name(self) -> &'static str {
+        match self {
+            Service::OpenLibrary => "openlibrary",
+            Service::GoogleBooks => "googlebooks",
+        }
+    }
+}
+
+impl fmt::Display for Service {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Service::OpenLibrary => "OpenLibrary",
+            Service::GoogleBooks => "Google Books",
+        })
+    }
+}
+
+/// What a book database knows about a book
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Found {
+    pub title: String,
+    pub isbn: Option<String>,
+    pub description: Option<String>,
+    pub year: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenLibrarySearch {
+    docs: Vec<OpenLibraryDoc>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenLibraryDoc {
+    key: String,
+    title: String,
+    first_publish_year: Option<i64>,
+    #[serde(default)]
+    isbn: Vec<String>,
+}
+
+/// A work's description is either a string or `{"type": ..., "value": ...}`
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum OpenLibraryText {
+    Plain(String),
+    Typed { value: String },
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenLibraryWork {
+    description: Option<OpenLibraryText>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GoogleBooksSearch {
+    #[serde(default)]
+    items: Vec<GoogleBooksVolume>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GoogleBooksVolume {
+    volume_info: GoogleBooksInfo,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GoogleBooksInfo {
+    title: String,
+    published_date: Option<String>,
+    description: Option<String>,
+    #[serde(default)]
+    industry_identifiers: Vec<GoogleBooksIdentifier>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GoogleBooksIdentifier {
+    #[serde(rename = "type")]
+    kind: String,
+    identifier: String,
+}
+
+/// ISBN-13s are preferred over ISBN-10s
+fn pick_isbn<'a>(isbns: impl Iterator<Item = &'a str>) -> Option<String> {
+    let isbns: Vec<_> = isbns.collect();
+    isbns
+        .iter()
+        .find(|isbn| isbn.len() == 13)
+        .or_else(|| isbns.first())
+        .map(|isbn| isbn.to_string())
+}
+
+fn parse_open_library(json: &str) -> Result<Option<(String, Found)>> {
+    let search: OpenLibrarySearch =
+        serde_json::from_str(json).context("Unexpected OpenLibrary search result")?;
+    Ok(search.docs.into_iter().next().map(|doc| {
+        let found = Found {
+            title: doc.title,
+            isbn: pick_isbn(doc.isbn.iter().map(String::as_str)),
+            description: None,
+            year: doc.first_publish_year.map(|year| year.to_string()),
+        };
+        (doc.key, found)
+    }))
+}
+
+fn parse_open_library_work(json: &str) -> Result<Option<String>> {
+    let work: OpenLibraryWork =
+        serde_json::from_str(json).context("Unexpected OpenLibrary work")?;
+    Ok(work.description.map(|text| match text {
+        OpenLibraryText::Plain(text) | OpenLibraryText::Typed { value: text } => text,
+    }))
+}
+
+fn parse_google_books(json: &str) -> Result<Option<Found>> {
+    let search: GoogleBooksSearch =
+        serde_json::from_str(json).context("Unexpected Google Books search result")?;
+    Ok(search.items.into_iter().next().map(|volume| {
+        let info = volume.volume_info;
+        Found {
+            title: info.title,
+            isbn: pick_isbn(
+                info.industry_identifiers
+                    .iter()
+                    .filter(|id| id.kind.starts_with("ISBN"))
+                    .map(|id| id.identifier.as_str()),
+            ),
+            description: info.description,
+            year: info
+                .published_date
+                .and_then(|date| date.get(..4).map(str::to_string)),
+        }
+    }))
+}
+
+fn normalize(text: &str) -> String {
+    text.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Whether a search result is the book, not just the closest thing the database has: one
+/// title starts with the other, so subtitles and editions still match
+fn is_match(book: &Audiobook, found: &Found) -> bool {
+    let (ours, theirs) = (normalize(&book.title), normalize(&found.title));
+    !ours.is_empty() && (ours.starts_with(&theirs) || theirs.starts_with(&ours))
+}
+
+/// Search a book database for a book by its title and first author. `None` if nothing
+/// found is the book.
+pub fn search(service: Service, book: &Audiobook, cache: Option<&Path>) -> Result<Option<Found>> {
+    let author = book.authors().into_iter().next().unwrap_or_default();
+    let key: String = Sha256::digest(format!("{}\n{}", book.title, author).as_bytes())[..8]
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    let cache_name = |what: &str| format!("{}-{}{}.json", service.name(), key, what);
+
+    let found = match service {
+        Service::OpenLibrary => {
+            let Some(json) = cached_get(
+                cache,
+                &cache_name(""),
+                "https://openlibrary.org/search.json",
+                &[
+                    ("title", &book.title),
+                    ("author", &author),
+                    ("limit", "1"),
+                    ("fields", "key,title,first_publish_year,isbn"),
+                ],
+            )?
+            else {
+                return Ok(None);
+            };
+            match parse_open_library(&json)? {
+                Some((work, mut found)) if is_match(book, &found) => {
+                    let url = format!("https://openlibrary.org{}.json", work);
+                    if let Some(json) = cached_get(cache, &cache_name("-work"), &url, &[])? {
+                        found.description = parse_open_library_work(&json)?;
+                    }
+                    Some(found)
+                }
+                _ => None,
+            }
+        }
+        Service::GoogleBooks => {
+            let query = format!("intitle:{} inauthor:{}", book.title, author);
+            cached_get(
+                cache,
+                &cache_name(""),
+                "https://www.googleapis.com/books/v1/volumes",
+                &[("q", &query), ("maxResults", "1")],
+            )?
+            .map(|json| parse_google_books(&json))
+            .transpose()?
+            .flatten()
+        }
+    };
+    Ok(found.filter(|found| is_match(book, found)))
+}
+
+/// Fill in what the library doesn't have. Returns whether anything changed.
+pub fn apply(book: &mut Audiobook, found: &Found) -> bool {
+    let mut changed = false;
+    let fields = [
+        (&mut book.details.isbn, &found.isbn),
+        (&mut book.description, &found.description),
+        (&mut book.details.year, &found.year),
+    ];
+    for (ours, theirs) in fields {
+        if let (None, Some(value)) = (&ours, theirs) {
+            *ours = Some(value.trim().to_string());
+            changed = true;
+        }
+    }
+    changed
+}
+
+/// Where search results are cached
+pub fn default_cache_dir() -> Option<PathBuf> {
+    dirs::cache_dir().map(|dir| dir.join("extract_apple_books").join("books"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn book() -> Audiobook {
+        Audiobook {
+            title: "Project Hail Mary".to_string(),
+            author: "Andy Weir".to_string(),
+            folder_id: "sha1-aaa".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_open_library() {
+        let (work, found) = parse_open_library(
+            r#"{"numFound":1,"docs":[{"key":"/works/OL21745884W","title":"Project Hail Mary",
+                "first_publish_year":2021,"isbn":["0593135202","9780593135204"]}]}"#,
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(work, "/works/OL21745884W");
+        assert_eq!(found.isbn.as_deref(), Some("9780593135204"));
+        assert_eq!(found.year.as_deref(), Some("2021"));
+        assert!(is_match(&book(), &found));
+        assert_eq!(parse_open_library(r#"{"docs":[]}"#).unwrap(), None);
+
+        assert_eq!(
+            parse_open_library_work(r#"{"description":{"type":"/type/text","value":"Alone."}}"#)
+                .unwrap()
+                .as_deref(),
+            Some("Alone.")
+        );
+        assert_eq!(
+            parse_open_library_work(r#"{"description":"Alone."}"#)
+                .unwrap()
+                .as_deref(),
+            Some("Alone.")
+        );
+    }
+
+    #[test]
+    fn test_google_books_and_apply() {
+        let found = parse_google_books(
+            r#"{"items":[{"volumeInfo":{"title":"Project Hail Mary: A Novel",
+                "publishedDate":"2021-05-04","description":"Ryland Grace wakes up.",
+                "industryIdentifiers":[{"type":"ISBN_10","identifier":"0593135202"},
+                                       {"type":"OTHER","identifier":"UOM:39015"}]}}]}"#,
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(found.isbn.as_deref(), Some("0593135202"));
+        assert_eq!(found.year.as_deref(), Some("2021"));
+        assert!(is_match(&book(), &found));
+        assert!(!is_match(
+            &book(),
+            &Found {
+                title: "The Martian".to_string(),
+                ..Default::default()
+            }
+        ));
+
+        let mut book = book();
+        book.details.year = Some("2020".to_string());
+        assert!(apply(&mut book, &found));
+        assert_eq!(book.details.isbn.as_deref(), Some("0593135202"));
+        assert_eq!(book.description.as_deref(), Some("Ryland Grace wakes up."));
+        assert_eq!(book.details.year.as_deref(), Some("2020"));
+        assert!(!apply(&mut book, &found));
+    }
+}
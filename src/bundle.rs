@@ -0,0 +1,297 @@
+use anyhow::{Context, Result};
+use plist::Value;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::path::Path;
+use std::process::Command;
+use std::time::SystemTime;
+
+use crate::{parse_books, validate};
+
+/// Folder in the archive holding the bundle's files
+const BUNDLE_DIR: &str = "extract_apple_books-debug";
+
+/// Path components that say nothing about the library, kept in redacted paths
+const KEPT_PATH_COMPONENTS: &[&str] = &[
+    "Users",
+    "Library",
+    "Containers",
+    "com.apple.BKAgentService",
+    "Data",
+    "Documents",
+    "iBooks",
+    "Books",
+    "Audiobooks",
+    "Mobile Documents",
+];
+
+/// Keys whose string values describe the kind of entry rather than the book, kept as they are
+const KEPT_KEYS: &[&str] = &["BKBookType"];
+
+/// Short stand-in for a string, the same for the same string
+fn hash(s: &str) -> String {
+    Sha256::digest(s.as_bytes())[..6]
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Folder ids keep their `sha1-` prefix, so they still match the folders in track paths
+fn redact_text(s: &str) -> String {
+    match s.strip_prefix("sha1-") {
+        Some(_) => format!("sha1-{}", hash(s)),
+        None => format!("redacted-{}", hash(s)),
+    }
+}
+
+/// Keep the folder structure and file extensions of a path, but not the names in it
+fn redact_path(path: &str) -> String {
+    let mut after_users = false;
+    path.split('/')
+        .map(|component| {
+            let redacted = if component.is_empty() || KEPT_PATH_COMPONENTS.contains(&component) {
+                component.to_string()
+            } else if after_users {
+                "user".to_string()
+            } else if component.starts_with("sha1-") {
+                redact_text(component)
+            } else {
+                match component.rsplit_once('.') {
+                    Some((stem, extension)) if !stem.is_empty() => {
+                        format!("{}.{}", hash(stem), extension)
+                    }
+                    _ => hash(component),
+                }
+            };
+            after_users = component == "Users";
+            redacted
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn redact_string(key: Option<&str>, s: &str) -> String {
+    if key.is_some_and(|key| KEPT_KEYS.contains(&key)) {
+        s.to_string()
+    } else if let Some(path) = s.strip_prefix("file://") {
+        format!("file://{}", redact_path(path))
+    } else if s.starts_with('/') {
+        redact_path(s)
+    } else {
+        redact_text(s)
+    }
+}
+
+fn redact_value(key: Option<&str>, value: &Value) -> Value {
+    match value {
+        Value::Array(items) => Value::Array(items.iter().map(|v| redact_value(key, v)).collect()),
+        Value::Dictionary(dict) => Value::Dictionary(
+            dict.iter()
+                .map(|(key, value)| (key.clone(), redact_value(Some(key), value)))
+                .collect(),
+        ),
+        Value::String(s) => Value::String(redact_string(key, s)),
+        Value::Data(bytes) => Value::Data(vec![0; bytes.len()]),
+        other => other.clone(),
+    }
+}
+
+/// A copy of a plist without the names in it: every string is replaced by a hash of it,
+/// paths keep their structure and file extensions, and data is zeroed. Numbers, dates and
+/// keys are kept, so the copy still parses the way the original does.
+pub fn redact(value: &Value) -> Value {
+    redact_value(None, value)
+}
+
+/// What the bundle records about the system the library was read on
+#[derive(Debug, Serialize)]
+pub struct Environment {
+    pub version: String,
+    pub os: String,
+    pub arch: String,
+    /// macOS version, from `sw_vers`
+    pub os_version: Option<String>,
+    /// First line of `ffmpeg -version`, if ffmpeg is installed
+    pub ffmpeg: Option<String>,
+    /// "binary" or "xml"
+    pub plist_format: String,
+    pub plist_bytes: u64,
+    /// Entries in the plist's `Books` array, of any kind
+    pub entries: usize,
+}
+
+fn first_line_of(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let line = stdout.lines().next()?.trim();
+    (output.status.success() && !line.is_empty()).then(|| line.to_string())
+}
+
+fn environment(bytes: &[u8], value: &Value) -> Environment {
+    Environment {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        os: std::env::consts::OS.to_string(),
+        arch: std::env::consts::ARCH.to_string(),
+        os_version: first_line_of("sw_vers", &["-productVersion"]),
+        ffmpeg: first_line_of("ffmpeg", &["-version"]),
+        plist_format: if bytes.starts_with(b"bplist") {
+            "binary"
+        } else {
+            "xml"
+        }
+        .to_string(),
+        plist_bytes: bytes.len() as u64,
+        entries: value
+            .as_dictionary()
+            .and_then(|dict| dict.get("Books"))
+            .and_then(|books| books.as_array())
+            .map_or(0, |books| books.len()),
+    }
+}
+
+fn append(builder: &mut tar::Builder<File>, name: &str, data: &[u8]) -> Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(
+        SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs()),
+    );
+    header.set_cksum();
+    builder
+        .append_data(&mut header, format!("{}/{}", BUNDLE_DIR, name), data)
+        .with_context(|| format!("Failed to add {} to the bundle", name))
+}
+
+/// Write a tar archive to attach to bug reports: the Books.plist at `plist_path` redacted
+/// (as an XML plist that can be used as a source), what this tool parses from the redacted
+/// copy along with any validation problems, and the environment
+pub fn write(plist_path: &Path, out: &Path) -> Result<()> {
+    let bytes = fs::read(plist_path).with_context(|| format!("Failed to read {:?}", plist_path))?;
+    let value = Value::from_reader(std::io::Cursor::new(&bytes))
+        .with_context(|| format!("Failed to parse plist at {:?}", plist_path))?;
+    let redacted = redact(&value);
+
+    let mut plist_xml = Vec::new();
+    redacted.to_writer_xml(&mut plist_xml)?;
+    let parsed = match parse_books(&redacted) {
+        Ok(books) => serde_json::json!({ "audiobooks": books }),
+        Err(e) => serde_json::json!({ "error": format!("{:#}", e) }),
+    };
+    let problems: Vec<_> = validate::validate(&redacted)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|p| format!("Entry {} ({}): {}", p.index, p.book, p.message))
+        .collect();
+    let parsed = serde_json::json!({ "parsed": parsed, "problems": problems });
+
+    let file = File::create(out).with_context(|| format!("Failed to create {:?}", out))?;
+    let mut builder = tar::Builder::new(file);
+    append(&mut builder, "Books.plist", &plist_xml)?;
+    append(
+        &mut builder,
+        "parsed.json",
+        &serde_json::to_vec_pretty(&parsed)?,
+    )?;
+    append(
+        &mut builder,
+        "environment.json",
+        &serde_json::to_vec_pretty(&environment(&bytes, &value))?,
+    )?;
+    builder
+        .into_inner()
+        .with_context(|| format!("Failed to write {:?}", out))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use plist::Dictionary;
+
+    fn library() -> Value {
+        let mut part = Dictionary::new();
+        part.insert(
+            "itemName".to_string(),
+            Value::String("Warbreaker".to_string()),
+        );
+        part.insert("BKTrackNumber".to_string(), Value::Integer(1.into()));
+        part.insert(
+            "path".to_string(),
+            Value::String(
+                "/Users/charlie/Library/Containers/com.apple.BKAgentService/Data/Documents/iBooks/Books/Audiobooks/sha1-aaa/01 Part.mp3"
+                    .to_string(),
+            ),
+        );
+        let mut book = Dictionary::new();
+        book.insert(
+            "BKBookType".to_string(),
+            Value::String("audiobook".to_string()),
+        );
+        book.insert(
+            "BKGeneratedItemId".to_string(),
+            Value::String("sha1-aaa".to_string()),
+        );
+        book.insert(
+            "artistName".to_string(),
+            Value::String("Brandon Sanderson".to_string()),
+        );
+        book.insert(
+            "BKParts".to_string(),
+            Value::Array(vec![Value::Dictionary(part)]),
+        );
+        let mut root = Dictionary::new();
+        root.insert(
+            "Books".to_string(),
+            Value::Array(vec![Value::Dictionary(book)]),
+        );
+        Value::Dictionary(root)
+    }
+
+    #[test]
+    fn test_redact() {
+        let books = parse_books(&redact(&library())).unwrap();
+        let book = &books[0];
+        assert!(book.title.starts_with("redacted-"));
+        assert!(book.author.starts_with("redacted-"));
+        assert_ne!(book.title, book.author);
+        assert!(book.folder_id.starts_with("sha1-"));
+        assert_ne!(book.folder_id, "sha1-aaa");
+
+        let path = book.tracks[0].path.to_string_lossy().to_string();
+        assert!(!path.contains("charlie") && !path.contains("Part"));
+        assert!(path.starts_with("/Users/user/Library/Containers/com.apple.BKAgentService/"));
+        assert!(path.contains(&format!("/Audiobooks/{}/", book.folder_id)));
+        assert!(path.ends_with(".mp3"));
+    }
+
+    #[test]
+    fn test_write_bundle() {
+        let dir = tempfile::tempdir().unwrap();
+        let plist_path = dir.path().join("Books.plist");
+        library().to_file_binary(&plist_path).unwrap();
+        let out = dir.path().join("debug.tar");
+        write(&plist_path, &out).unwrap();
+
+        let mut archive = tar::Archive::new(File::open(&out).unwrap());
+        let names: Vec<_> = archive
+            .entries()
+            .unwrap()
+            .map(|entry| entry.unwrap().path().unwrap().to_string_lossy().to_string())
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "extract_apple_books-debug/Books.plist",
+                "extract_apple_books-debug/parsed.json",
+                "extract_apple_books-debug/environment.json",
+            ]
+        );
+        let contents = fs::read(&out).unwrap();
+        let text = String::from_utf8_lossy(&contents);
+        assert!(!text.contains("Sanderson") && !text.contains("Warbreaker"));
+        assert!(text.contains("\"plist_format\": \"binary\""));
+    }
+}
@@ -0,0 +1,180 @@
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::Audiobook;
+
+/// Audiobook rows of the Apple Books catalog. Audiobooks have content type 6; rows with a
+/// path into the audiobooks folder are taken too, in case that changes.
+const QUERY: &str = "SELECT ZASSETID AS asset_id, ZTITLE AS title, ZAUTHOR AS author, \
+    ZPATH AS path, ZBOOKDESCRIPTION AS description FROM ZBKLIBRARYASSET \
+    WHERE ZCONTENTTYPE = 6 OR ZPATH LIKE '%/Audiobooks/%'";
+
+/// A book in the Apple Books catalog (the BKLibrary sqlite database)
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct CatalogEntry {
+    pub asset_id: Option<String>,
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub path: Option<String>,
+    pub description: Option<String>,
+}
+
+/// A title or author for matching: lowercased, without a trailing "(Unabridged)" or
+/// similar, which one catalog may have and the other not
+fn normalize(s: &str) -> String {
+    let s = s.trim();
+    let s = match s.strip_suffix(')').and_then(|rest| rest.rfind('(')) {
+        Some(i) if i > 0 => &s[..i],
+        _ => s,
+    };
+    s.trim().to_lowercase()
+}
+
+impl CatalogEntry {
+    /// Whether this entry is the catalog's record of a Books.plist book: by folder id in its
+    /// path or asset id, or failing that by title and author
+    fn is(&self, book: &Audiobook) -> bool {
+        let same = |a: &Option<String>, b: &str| {
+            a.as_deref().is_some_and(|a| normalize(a) == normalize(b))
+        };
+        self.path
+            .as_deref()
+            .is_some_and(|path| path.contains(&book.folder_id))
+            || self.asset_id.as_deref() == Some(book.folder_id.as_str())
+            || ((same(&self.title, &book.title) || same(&self.title, &book.full_title()))
+                && same(&self.author, &book.author))
+    }
+}
+
+/// The catalog belonging to a Books.plist folder, which lives in the Apple Books app's
+/// container next to the one holding the audiobooks
+pub fn find(source_base: &Path) -> Option<PathBuf> {
+    let containers = source_base
+        .ancestors()
+        .find(|dir| dir.file_name().is_some_and(|name| name == "Containers"))?;
+    let dir = containers.join("com.apple.iBooksX/Data/Documents/BKLibrary");
+    let mut databases: Vec<_> = fs::read_dir(dir)
+        .ok()?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            name.starts_with("BKLibrary") && name.ends_with(".sqlite")
+        })
+        .collect();
+    databases.sort();
+    databases.pop()
+}
+
+/// Read the audiobooks of a catalog (requires the `sqlite3` command line tool)
+pub fn read(path: &Path) -> Result<Vec<CatalogEntry>> {
+    let output = Command::new("sqlite3")
+        .args(["-readonly", "-json"])
+        .arg(path)
+        .arg(QUERY)
+        .output()
+        .context("Failed to run sqlite3 (is sqlite3 installed?)")?;
+    if !output.status.success() {
+        bail!(
+            "sqlite3 failed to read {:?}: {}",
+            path,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    parse(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Parse the output of `sqlite3 -json`, which is empty when no rows match
+fn parse(json: &str) -> Result<Vec<CatalogEntry>> {
+    if json.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+    serde_json::from_str(json).context("Unexpected output from sqlite3")
+}
+
+/// How Books.plist and the catalog line up
+#[derive(Debug, Default)]
+pub struct Comparison {
+    /// For each book, the index of its catalog entry
+    pub matches: Vec<Option<usize>>,
+    /// Indexes of catalog entries no book matched
+    pub only_in_catalog: Vec<usize>,
+}
+
+pub fn compare(audiobooks: &[Audiobook], entries: &[CatalogEntry]) -> Comparison {
+    let mut used = vec![false; entries.len()];
+    let matches = audiobooks
+        .iter()
+        .map(|book| {
+            let i = (0..entries.len()).find(|&i| !used[i] && entries[i].is(book))?;
+            used[i] = true;
+            Some(i)
+        })
+        .collect();
+    Comparison {
+        matches,
+        only_in_catalog: (0..entries.len()).filter(|&i| !used[i]).collect(),
+    }
+}
+
+/// Fill in metadata only the catalog has, such as descriptions. Returns how many books
+/// gained a description.
+pub fn merge(
+    audiobooks: &mut [Audiobook],
+    entries: &[CatalogEntry],
+    comparison: &Comparison,
+) -> usize {
+    let mut merged = 0;
+    for (book, i) in audiobooks.iter_mut().zip(&comparison.matches) {
+        let description = i
+            .and_then(|i| entries[i].description.as_deref())
+            .map(str::trim)
+            .filter(|d| !d.is_empty());
+        if let (None, Some(description)) = (&book.description, description) {
+            book.description = Some(description.to_string());
+            merged += 1;
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn book(title: &str, folder_id: &str) -> Audiobook {
+        Audiobook {
+            title: title.to_string(),
+            author: "Brandon Sanderson".to_string(),
+            folder_id: folder_id.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_compare_and_merge() {
+        let entries = parse(
+            r#"[{"asset_id":"1234","title":"Other","author":"Someone","path":"/x/Audiobooks/sha1-aaa","description":"A god returns."},
+                {"asset_id":"5678","title":"elantris","author":"brandon sanderson","path":null,"description":null},
+                {"asset_id":"9999","title":"Gone","author":"Nobody","path":null,"description":"Deleted"}]"#,
+        )
+        .unwrap();
+        assert!(parse("\n").unwrap().is_empty());
+
+        let mut audiobooks = vec![
+            book("Warbreaker", "sha1-aaa"),
+            book("Elantris (Unabridged)", "sha1-bbb"),
+            book("Mistborn", "sha1-ccc"),
+        ];
+        let comparison = compare(&audiobooks, &entries);
+        assert_eq!(comparison.matches, vec![Some(0), Some(1), None]);
+        assert_eq!(comparison.only_in_catalog, vec![2]);
+
+        assert_eq!(merge(&mut audiobooks, &entries, &comparison), 1);
+        assert_eq!(audiobooks[0].description.as_deref(), Some("A god returns."));
+        assert_eq!(audiobooks[1].description, None);
+    }
+}
@@ -0,0 +1,191 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+
+use crate::{media, sanitize_filename, write_file, AudioTrack};
+
+/// Name of the chapters file written into each book folder
+pub const CHAPTERS_FILENAME: &str = "chapters.json";
+
+/// A single chapter in Audiobookshelf's chapter format
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Chapter {
+    pub id: usize,
+    pub start: f64,
+    pub end: f64,
+    pub title: String,
+}
+
+#[derive(Serialize)]
+struct ChaptersFile<'a> {
+    chapters: &'a [Chapter],
+}
+
+/// Title to use for a track's chapter: the plist track title, or the filename stem when
+/// it has none or the titles don't tell the tracks apart
+fn chapter_title(track: &AudioTrack, titles_distinct: bool) -> String {
+    if titles_distinct && !track.title.trim().is_empty() {
+        return track.title.trim().to_string();
+    }
+
+    Path::new(&track.filename)
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| track.filename.clone())
+}
+
+/// Chapter titles for a book's tracks, one per track
+pub fn track_titles(tracks: &[&AudioTrack]) -> Vec<String> {
+    // Some books repeat the book title as every track's title
+    let titles_distinct = tracks.len() < 2
+        || tracks
+            .iter()
+            .any(|track| track.title.trim() != tracks[0].title.trim());
+    tracks
+        .iter()
+        .map(|track| chapter_title(track, titles_distinct))
+        .collect()
+}
+
+/// Build chapters from tracks and their durations (in seconds), one chapter per track
+pub fn build_chapters(tracks: &[(&AudioTrack, f64)]) -> Vec<Chapter> {
+    let mut chapters = Vec::with_capacity(tracks.len());
+    let mut start = 0.0;
+    let titles = track_titles(&tracks.iter().map(|(track, _)| *track).collect::<Vec<_>>());
+
+    for (id, ((_, duration), title)) in tracks.iter().zip(titles).enumerate() {
+        let end = start + duration;
+        chapters.push(Chapter {
+            id,
+            start,
+            end,
+            title,
+        });
+        start = end;
+    }
+
+    chapters
+}
+
+/// Write chapters as `chapters.json` into the book's destination directory
+pub fn write_chapters_file(dest_dir: &Path, chapters: &[Chapter]) -> Result<()> {
+    let path = dest_dir.join(CHAPTERS_FILENAME);
+    let json = serde_json::to_string_pretty(&ChaptersFile { chapters })?;
+    write_file(&path, json)
+}
+
+/// File extensions of single-file audiobooks that can carry embedded chapters
+const SPLITTABLE_EXTENSIONS: &[&str] = &["m4b", "m4a"];
+
+/// Whether a track is a chaptered container format that can be split
+pub fn is_splittable(path: &Path) -> bool {
+    path.extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .is_some_and(|e| SPLITTABLE_EXTENSIONS.contains(&e.as_str()))
+}
+
+/// Filename for the `index`-th (1-based) chapter file of a split book
+fn split_filename(index: usize, total: usize, title: &str, extension: &str) -> String {
+    let width = total.to_string().len().max(2);
+    let title = sanitize_filename(title);
+    if title.is_empty() {
+        format!("{:0width$}.{}", index, extension, width = width)
+    } else {
+        format!("{:0width$} - {}.{}", index, title, extension, width = width)
+    }
+}
+
+/// Split a single chaptered audio file into one file per chapter in `dest_dir`.
+/// Returns the number of chapter files written, or 0 if the source has fewer than two
+/// chapters (in which case the caller should copy the file as-is). When a chapter fails,
+/// the files written so far are removed, so a book copied as-is instead isn't left with
+/// half of its chapters next to it.
+pub fn split_into_chapter_files(source: &Path, dest_dir: &Path) -> Result<usize> {
+    let embedded = media::probe_chapters(source)?;
+    if embedded.len() < 2 {
+        return Ok(0);
+    }
+
+    let extension = source
+        .extension()
+        .map(|e| e.to_string_lossy().to_string())
+        .unwrap_or_else(|| "m4a".to_string());
+
+    let mut written = Vec::new();
+    for (i, chapter) in embedded.iter().enumerate() {
+        let dest_path = dest_dir.join(split_filename(
+            i + 1,
+            embedded.len(),
+            &chapter.title,
+            &extension,
+        ));
+        if dest_path.exists() {
+            continue;
+        }
+        if let Err(e) = media::extract_segment(source, chapter.start, chapter.end, &dest_path) {
+            for path in written.iter().chain([&dest_path]) {
+                let _ = fs::remove_file(path);
+            }
+            return Err(e);
+        }
+        written.push(dest_path);
+    }
+
+    Ok(written.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn track(title: &str, filename: &str) -> AudioTrack {
+        AudioTrack {
+            track_number: 1,
+            disc_number: 0,
+            title: title.to_string(),
+            path: PathBuf::from(filename),
+            filename: filename.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_build_chapters_cumulative_offsets() {
+        let t1 = track("Chapter 1: Arrival", "01.mp3");
+        let t2 = track("Chapter 12: The Siege", "02.mp3");
+
+        let chapters = build_chapters(&[(&t1, 100.5), (&t2, 200.0)]);
+
+        assert_eq!(chapters.len(), 2);
+        assert_eq!(chapters[0].start, 0.0);
+        assert_eq!(chapters[0].end, 100.5);
+        assert_eq!(chapters[1].start, 100.5);
+        assert_eq!(chapters[1].end, 300.5);
+        assert_eq!(chapters[1].title, "Chapter 12: The Siege");
+    }
+
+    #[test]
+    fn test_split_filename() {
+        assert_eq!(
+            split_filename(3, 12, "Chapter 3: Trouble", "m4b"),
+            "03 - Chapter 3_ Trouble.m4b"
+        );
+        assert_eq!(split_filename(7, 150, "", "m4a"), "007.m4a");
+        assert!(is_splittable(Path::new("/x/Book.M4B")));
+        assert!(!is_splittable(Path::new("/x/01.mp3")));
+    }
+
+    #[test]
+    fn test_chapter_title_falls_back_to_filename() {
+        let t = track("", "03 The Return.mp3");
+        let chapters = build_chapters(&[(&t, 10.0)]);
+        assert_eq!(chapters[0].title, "03 The Return");
+
+        let t1 = track("The Hobbit", "01 An Unexpected Party.mp3");
+        let t2 = track("The Hobbit", "02 Roast Mutton.mp3");
+        let chapters = build_chapters(&[(&t1, 10.0), (&t2, 10.0)]);
+        assert_eq!(chapters[0].title, "01 An Unexpected Party");
+        assert_eq!(chapters[1].title, "02 Roast Mutton");
+    }
+}
@@ -0,0 +1,480 @@
+use anyhow::{bail, Context, Result};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::conflict::ConflictStrategy;
+use crate::email::EmailConfig;
+use crate::sanitize::Sanitizer;
+use crate::subtitle::Subtitles;
+use crate::template::{self, Layout};
+use crate::{filter, finder, media, quarantine, transcode};
+use crate::{AuthorFolder, AuthorFormat, ExportOptions};
+
+/// Settings that apply to every run, read from `config.toml` or `config.json` in the user
+/// config directory
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Email the run summary after each export
+    pub email: Option<EmailConfig>,
+    /// How book, author and narrator names are turned into file and folder names
+    pub filenames: Sanitizer,
+    /// How titles are split into a title and a subtitle
+    pub subtitles: Subtitles,
+    /// Defaults for export options not given on the command line, as set up by `init`
+    pub export: ExportDefaults,
+    /// Named sets of export defaults, e.g. `[profile.nas]`, used instead of `export` when
+    /// chosen with `--profile`
+    #[serde(rename = "profile")]
+    pub profiles: BTreeMap<String, ExportDefaults>,
+}
+
+/// How exported books are stored in the destination
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Profile {
+    /// Full copies of the tracks
+    #[default]
+    Copy,
+    /// Symlinks to the tracks in the Apple Books folder
+    Symlink,
+    /// Tracks re-encoded to Opus at 64 kbit/s, for phones and small NAS volumes
+    Compact,
+}
+
+impl Profile {
+    /// The `--transcode` target of the profile
+    pub fn transcode(self) -> Option<&'static str> {
+        match self {
+            Profile::Compact => Some("opus:64k"),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ExportDefaults {
+    /// Apple Books folder, instead of the current user's
+    pub source: Option<PathBuf>,
+    /// Destination used when `--dest` isn't given
+    pub dest: Option<PathBuf>,
+    /// Used unless `--symlink` or `--transcode` is given. Also accepted as `copy_mode`,
+    /// which reads better in a named profile.
+    #[serde(alias = "copy_mode")]
+    pub profile: Profile,
+    /// Glob patterns for `--author`, used when none are given
+    pub authors: Vec<String>,
+    /// Glob patterns for `--title`, used when none are given
+    pub titles: Vec<String>,
+    /// Expressions for `--include-regex`, used when none are given
+    pub include_regex: Vec<String>,
+    /// Expressions for `--exclude-regex`, added to those given
+    pub exclude_regex: Vec<String>,
+    /// Exclusion file for `--exclude-from`, used when none is given
+    pub exclude_from: Option<PathBuf>,
+    /// `--transcode` target like "opus:64k", used when none is given. Takes precedence
+    /// over `profile`, as `--transcode` does
+    pub transcode: Option<String>,
+    /// Book folder layout for `--layout`, used when neither it nor `--template` is given
+    pub layout: Option<Layout>,
+    /// Folder template for `--template`, used when neither it nor `--layout` is given
+    pub template: Option<String>,
+    /// Track filename template for `--track-template`, used when none is given
+    pub track_template: Option<String>,
+    /// `--author-folder` policy, used when none is given
+    pub author_folder: Option<AuthorFolder>,
+    /// `--author-format`, used when none is given
+    pub author_format: Option<AuthorFormat>,
+    /// `--on-conflict` strategy, used when neither it nor `--force` is given
+    pub on_conflict: Option<ConflictStrategy>,
+    /// Options switched on for every run, by their command line names without the
+    /// dashes, e.g. `["chapters", "folder-dates"]`. `--no-<option>` switches one off again
+    pub flags: Vec<String>,
+}
+
+/// What the caller of [`ExportDefaults::apply`] set itself, which the config file leaves
+/// alone. Keys are named as in the config file: `flags` entries like `split-chapters`, and
+/// settings like `author_folder`.
+pub trait Given {
+    /// Whether the caller set the option, or switched it off
+    fn given(&self, key: &str) -> bool;
+
+    /// The option of the caller that `key` can't be combined with, to name in a warning
+    fn clash(&self, key: &str) -> Option<String>;
+}
+
+/// A caller without options of its own, such as the desktop app
+impl Given for () {
+    fn given(&self, _: &str) -> bool {
+        false
+    }
+
+    fn clash(&self, _: &str) -> Option<String> {
+        None
+    }
+}
+
+/// Compile the regex patterns of the config file
+fn patterns(patterns: &[String]) -> Result<Vec<regex::Regex>> {
+    patterns
+        .iter()
+        .map(|p| {
+            regex::Regex::new(p)
+                .with_context(|| format!("Invalid pattern {:?} in the config file", p))
+        })
+        .collect()
+}
+
+impl ExportDefaults {
+    /// Fill in what the caller leaves out of `options`. Settings that can't be combined with
+    /// an option the caller set are skipped, with a warning for flags. The profile, or
+    /// `copy_mode` if the caller chose one, applies unless symlinks or transcoding are
+    /// already set, or options that only work on copies (or without transcoding) rule it
+    /// out. `strict` and `check-catalog` are left to the command line.
+    pub fn apply(
+        &self,
+        options: &mut ExportOptions,
+        copy_mode: Option<Profile>,
+        given: &impl Given,
+    ) -> Result<()> {
+        let applies = |key: &str| !given.given(key) && given.clash(key).is_none();
+
+        let filter = &mut options.filter;
+        if !given.given("authors") {
+            filter.authors = self.authors.clone();
+        }
+        if !given.given("titles") {
+            filter.titles = self.titles.clone();
+        }
+        if !given.given("include_regex") {
+            filter.include = patterns(&self.include_regex)?;
+        }
+        filter.exclude.extend(patterns(&self.exclude_regex)?);
+        if let Some(path) = self
+            .exclude_from
+            .as_ref()
+            .filter(|_| !given.given("exclude_from"))
+        {
+            filter.exclude.extend(filter::read_patterns(path)?);
+        }
+
+        if self.layout.is_some() && self.template.is_some() {
+            bail!("The config file can't set both layout and template");
+        }
+        let invalid =
+            |what: &str, text: &str| format!("Invalid {} {:?} in the config file", what, text);
+        if let Some(text) = self.template.as_deref().filter(|_| applies("template")) {
+            options.template =
+                Some(template::parse_template(text).with_context(|| invalid("template", text))?);
+        }
+        if let Some(layout) = self.layout.filter(|_| applies("layout")) {
+            options.template = layout.template();
+        }
+        if let Some(text) = self
+            .track_template
+            .as_deref()
+            .filter(|_| applies("track_template"))
+        {
+            options.track_template = Some(
+                template::parse_track_template(text)
+                    .with_context(|| invalid("track template", text))?,
+            );
+        }
+        if let Some(text) = self.transcode.as_deref().filter(|_| applies("transcode")) {
+            options.transcode =
+                Some(transcode::parse_target(text).with_context(|| invalid("transcode", text))?);
+        }
+        if let Some(policy) = self.author_folder.filter(|_| applies("author_folder")) {
+            options.author_folder = policy;
+        }
+        if let Some(format) = self.author_format.filter(|_| applies("author_format")) {
+            options.author_format = format;
+        }
+        if let Some(strategy) = self.on_conflict.filter(|_| applies("on_conflict")) {
+            options.on_conflict = strategy;
+        }
+
+        let mut profile = copy_mode.unwrap_or(self.profile);
+        let mut normalize = options.normalize.is_some();
+        for flag in &self.flags {
+            let option = match flag.as_str() {
+                "symlink" => {
+                    if copy_mode.is_none() && !given.given("symlink") {
+                        profile = Profile::Symlink;
+                    }
+                    continue;
+                }
+                "chapters" => &mut options.write_chapters,
+                "metadata-json" => &mut options.write_metadata,
+                "opf" => &mut options.write_opf,
+                "cue" => &mut options.write_cue,
+                "nfo" => &mut options.write_nfo,
+                "cover" => &mut options.write_cover,
+                "embed-cover" => &mut options.embed_cover,
+                "split-chapters" => &mut options.split_chapters,
+                "normalize" => &mut normalize,
+                "fix-tags" => &mut options.fix_tags,
+                "retag" => &mut options.retag,
+                "strip-personal-tags" => &mut options.strip_personal_tags,
+                "finder-tags" => &mut options.finder_tags,
+                "folder-dates" => &mut options.folder_dates,
+                "strip-quarantine" => &mut options.strip_quarantine,
+                "snapshot" => &mut options.snapshot,
+                "extras" => &mut options.extras,
+                "renumber" => &mut options.renumber,
+                "checksum" => &mut options.checksum,
+                "full" => &mut options.full,
+                "check-catalog" | "strict" => continue,
+                _ => bail!("Unknown option {:?} in the flags of the config file", flag),
+            };
+            if *option || given.given(flag) {
+                continue;
+            }
+            if let Some(other) = given.clash(flag) {
+                eprintln!(
+                    "Warning: Ignoring {} from the config file, as it can't be used with {}",
+                    flag, other
+                );
+                continue;
+            }
+            *option = true;
+        }
+        if normalize && options.normalize.is_none() {
+            options.normalize = Some(media::DEFAULT_LOUDNESS_TARGET);
+        }
+        options.fix_tags |= options.retag;
+        // `--no-symlink` keeps copies whatever the config file's profile
+        if copy_mode.is_none() && given.given("symlink") && profile == Profile::Symlink {
+            profile = Profile::Copy;
+        }
+
+        if options.finder_tags && !finder::is_supported() {
+            eprintln!("Warning: --finder-tags only works on macOS; ignoring it");
+            options.finder_tags = false;
+        }
+        if options.strip_quarantine && !quarantine::is_supported() {
+            eprintln!("Warning: --strip-quarantine only works on macOS; ignoring it");
+            options.strip_quarantine = false;
+        }
+        if options.use_symlink || options.transcode.is_some() {
+            return Ok(());
+        }
+
+        let copies_only = options.normalize.is_some()
+            || options.fix_tags
+            || options.embed_cover
+            || options.strip_personal_tags
+            || options.renumber
+            || options.strip_quarantine
+            || options.move_sources;
+        match profile {
+            Profile::Copy => {}
+            Profile::Symlink if copies_only => {
+                eprintln!(
+                    "Warning: Exporting copies, as the options given don't work with symlinks"
+                )
+            }
+            Profile::Symlink => options.use_symlink = true,
+            Profile::Compact if options.move_sources => {
+                eprintln!("Warning: Not transcoding, as --move doesn't work with it")
+            }
+            Profile::Compact => {
+                options.transcode = profile
+                    .transcode()
+                    .map(transcode::parse_target)
+                    .transpose()?
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Whether a config file is TOML rather than JSON, by its extension
+pub fn is_toml(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "toml")
+}
+
+impl Config {
+    /// Read the config file, as TOML or JSON by its extension; a missing file means
+    /// default settings
+    pub fn read(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Config::default());
+        }
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config {:?}", path))?;
+        if is_toml(path) {
+            toml::from_str(&text).with_context(|| format!("Invalid config file {:?}", path))
+        } else {
+            serde_json::from_str(&text).with_context(|| format!("Invalid config file {:?}", path))
+        }
+    }
+
+    /// The export defaults of a named profile, or of the `export` section without one
+    pub fn export_defaults(&self, profile: Option<&str>) -> Result<&ExportDefaults> {
+        let Some(name) = profile else {
+            return Ok(&self.export);
+        };
+        match self.profiles.get(name) {
+            Some(defaults) => Ok(defaults),
+            None if self.profiles.is_empty() => {
+                bail!("No profile {:?}: the config file has no profiles", name)
+            }
+            None => bail!(
+                "No profile {:?} in the config file (profiles: {})",
+                name,
+                self.profiles.keys().cloned().collect::<Vec<_>>().join(", ")
+            ),
+        }
+    }
+}
+
+/// Where the config file is kept: the first of `config.toml` in the user config directory,
+/// `config.toml` in `~/.config/extract_apple_books`, and `config.json` in the user config
+/// directory that exists, or that last one if none does
+pub fn default_config_path() -> Option<PathBuf> {
+    let dir = dirs::config_dir()?.join("extract_apple_books");
+    let dot_config = dirs::home_dir().map(|home| home.join(".config/extract_apple_books"));
+    [
+        Some(dir.join("config.toml")),
+        dot_config.map(|dir| dir.join("config.toml")),
+    ]
+    .into_iter()
+    .flatten()
+    .find(|path| path.is_file())
+    .or(Some(dir.join("config.json")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_read_toml_config() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        fs::write(
+            &path,
+            r#"
+[export]
+dest = "/Volumes/NAS/Audiobooks"
+profile = "symlink"
+authors = ["Brandon*"]
+exclude_regex = ["(?i)podcast"]
+flags = ["chapters", "folder-dates"]
+layout = "series"
+author_format = "last-first"
+on_conflict = "update-if-newer"
+
+[filenames]
+replacement = "-"
+"#,
+        )
+        .unwrap();
+
+        let config = Config::read(&path).unwrap();
+        assert_eq!(config.export.profile, Profile::Symlink);
+        assert_eq!(config.export.authors, ["Brandon*"]);
+        assert_eq!(config.export.flags, ["chapters", "folder-dates"]);
+        assert_eq!(config.export.layout, Some(Layout::Series));
+        assert_eq!(config.export.author_format, Some(AuthorFormat::LastFirst));
+        assert_eq!(config.export.on_conflict, Some(ConflictStrategy::Update));
+        assert_eq!(config.filenames.replacement, "-");
+
+        assert_eq!(config.export_defaults(None).unwrap().authors, ["Brandon*"]);
+        assert!(config.export_defaults(Some("nas")).is_err());
+
+        fs::write(
+            &path,
+            r#"
+[profile.nas]
+dest = "/Volumes/NAS/Audiobooks"
+copy_mode = "compact"
+
+[profile.laptop]
+dest = "/Users/me/Audiobooks"
+flags = ["symlink"]
+"#,
+        )
+        .unwrap();
+        let config = Config::read(&path).unwrap();
+        let nas = config.export_defaults(Some("nas")).unwrap();
+        assert_eq!(nas.profile, Profile::Compact);
+        assert_eq!(
+            nas.dest.as_deref(),
+            Some(Path::new("/Volumes/NAS/Audiobooks"))
+        );
+        let error = config.export_defaults(Some("usb")).unwrap_err();
+        assert!(error.to_string().contains("profiles: laptop, nas"));
+
+        fs::write(&path, "[export]\nsymlinks = true\n").unwrap();
+        assert!(Config::read(&path).is_err());
+    }
+
+    /// A command line that gave `renumber` and switched off `cover`
+    struct Renumbering;
+
+    impl Given for Renumbering {
+        fn given(&self, key: &str) -> bool {
+            matches!(key, "renumber" | "cover")
+        }
+
+        fn clash(&self, key: &str) -> Option<String> {
+            (key == "split-chapters").then(|| "--renumber".to_string())
+        }
+    }
+
+    #[test]
+    fn test_apply_export_defaults() {
+        let defaults = ExportDefaults {
+            authors: vec!["Brandon*".to_string()],
+            layout: Some(Layout::Flat),
+            flags: ["symlink", "chapters", "cover", "split-chapters", "retag"]
+                .map(String::from)
+                .to_vec(),
+            ..Default::default()
+        };
+
+        let mut options = ExportOptions::default();
+        defaults.apply(&mut options, None, &()).unwrap();
+        assert_eq!(options.filter.authors, ["Brandon*"]);
+        assert!(options.template.is_some());
+        assert!(options.write_chapters && options.write_cover && options.split_chapters);
+        assert!(options.retag && options.fix_tags);
+        // Retagging only works on copies
+        assert!(!options.use_symlink);
+
+        let defaults = ExportDefaults {
+            flags: defaults.flags[..4].to_vec(),
+            ..defaults
+        };
+        let mut options = ExportOptions {
+            renumber: true,
+            ..Default::default()
+        };
+        defaults.apply(&mut options, None, &Renumbering).unwrap();
+        assert!(options.write_chapters);
+        assert!(!options.write_cover && !options.split_chapters && !options.use_symlink);
+
+        let mut options = ExportOptions::default();
+        defaults
+            .apply(&mut options, Some(Profile::Copy), &())
+            .unwrap();
+        assert!(!options.use_symlink);
+        defaults.apply(&mut options, None, &()).unwrap();
+        assert!(options.use_symlink);
+
+        let both = ExportDefaults {
+            template: Some("{title}".to_string()),
+            ..defaults
+        };
+        assert!(both
+            .apply(&mut ExportOptions::default(), None, &())
+            .is_err());
+    }
+}
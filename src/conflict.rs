@@ -0,0 +1,304 @@
+use clap::ValueEnum;
+use serde::Deserialize;
+use std::fs;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::{hash, trash};
+
+/// What to do when a destination file already exists
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum ConflictStrategy {
+    /// Keep the existing destination file
+    Skip,
+    /// Always replace the destination file
+    Overwrite,
+    /// Replace the destination file if the source changed since it was exported
+    #[default]
+    #[value(alias = "update-if-newer")]
+    #[serde(alias = "update-if-newer")]
+    Update,
+    /// Keep the existing file and export under a new name ("01 Track 2.mp3")
+    Rename,
+    /// Ask once per book whose existing files differ from the source
+    Ask,
+}
+
+/// How a single conflicting file is resolved
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Resolution {
+    Skip,
+    Replace,
+    /// Replace because the source changed
+    Update,
+    WriteAs(PathBuf),
+}
+
+/// How an existing destination file is compared with its source
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ChangeCheck {
+    /// The destination is a byte-for-byte copy, so sizes and contents are comparable.
+    /// Transcoded, normalized or retagged files are only compared by modification time.
+    pub plain_copy: bool,
+    /// Compare SHA-256 of plain copies with equal sizes
+    pub checksum: bool,
+}
+
+impl ChangeCheck {
+    /// Whether `source` changed since `dest` was exported from it
+    pub fn source_changed(&self, source: &Path, dest: &Path) -> bool {
+        let (Ok(source_meta), Ok(dest_meta)) = (fs::metadata(source), fs::metadata(dest)) else {
+            return false;
+        };
+        if self.plain_copy {
+            if source_meta.len() != dest_meta.len() {
+                return true;
+            }
+            if self.checksum {
+                return !hash::files_match(source, dest).unwrap_or(true);
+            }
+        }
+        match (source_meta.modified(), dest_meta.modified()) {
+            (Ok(source), Ok(dest)) => source > dest,
+            _ => false,
+        }
+    }
+}
+
+impl ConflictStrategy {
+    /// Resolve a conflict between `source` and an existing `dest`
+    pub fn resolve(self, source: &Path, dest: &Path, check: ChangeCheck) -> Resolution {
+        match self {
+            // `Ask` is resolved to a concrete strategy per book before files are compared
+            ConflictStrategy::Skip | ConflictStrategy::Ask => Resolution::Skip,
+            ConflictStrategy::Overwrite => Resolution::Replace,
+            ConflictStrategy::Update => {
+                if check.source_changed(source, dest) {
+                    Resolution::Update
+                } else {
+                    Resolution::Skip
+                }
+            }
+            // Only real conflicts get a new name, or every re-run would add another copy.
+            // Copies that aren't byte-for-byte can only be compared by modification time.
+            ConflictStrategy::Rename => {
+                let conflicting = if check.plain_copy {
+                    differs(source, dest)
+                } else {
+                    check.source_changed(source, dest)
+                };
+                if conflicting {
+                    Resolution::WriteAs(trash::unique_path(dest))
+                } else {
+                    Resolution::Skip
+                }
+            }
+        }
+    }
+}
+
+/// Whether an existing destination file differs from its source, by size and then SHA-256.
+/// Unreadable files count as differing so the user gets to decide.
+pub fn differs(source: &Path, dest: &Path) -> bool {
+    match (fs::metadata(source), fs::metadata(dest)) {
+        (Ok(s), Ok(d)) if s.len() != d.len() => true,
+        (Ok(_), Ok(_)) => !hash::files_match(source, dest).unwrap_or(false),
+        _ => true,
+    }
+}
+
+/// Asks the user how to resolve a book's conflicts, remembering "... all" answers
+/// for the rest of the run
+#[derive(Debug, Default)]
+pub struct Prompter {
+    remembered: Option<ConflictStrategy>,
+}
+
+impl Prompter {
+    /// Choose a strategy for a book's conflicting `(source, dest)` files. Falls back to
+    /// skipping when stdin is not a terminal.
+    pub fn choose(&mut self, book: &str, conflicts: &[(PathBuf, PathBuf)]) -> ConflictStrategy {
+        if let Some(strategy) = self.remembered {
+            return strategy;
+        }
+        if !io::stdin().is_terminal() {
+            eprintln!(
+                "Warning: Not a terminal, skipping {} conflicting file(s) of {}",
+                conflicts.len(),
+                book
+            );
+            return ConflictStrategy::Skip;
+        }
+
+        println!();
+        println!(
+            "{} file(s) of {} differ from the destination:",
+            conflicts.len(),
+            book
+        );
+        for (source, dest) in conflicts.iter().take(5) {
+            println!(
+                "  {}",
+                dest.file_name().unwrap_or_default().to_string_lossy()
+            );
+            println!("      source: {}", describe(source));
+            println!("      dest:   {}", describe(dest));
+        }
+        if conflicts.len() > 5 {
+            println!("  ... and {} more", conflicts.len() - 5);
+        }
+
+        let stdin = io::stdin();
+        loop {
+            print!("[s]kip, [o]verwrite, [r]ename, or S/O/R for all remaining books? ");
+            let _ = io::stdout().flush();
+
+            let mut line = String::new();
+            if stdin.lock().read_line(&mut line).unwrap_or(0) == 0 {
+                return ConflictStrategy::Skip;
+            }
+            if let Some((strategy, remember)) = parse_answer(&line) {
+                if remember {
+                    self.remembered = Some(strategy);
+                }
+                return strategy;
+            }
+        }
+    }
+}
+
+/// Parse a prompt answer into a strategy and whether to apply it to the rest of the run
+fn parse_answer(answer: &str) -> Option<(ConflictStrategy, bool)> {
+    let answer = answer.trim();
+    let strategy = match answer.to_lowercase().as_str() {
+        "s" | "skip" => ConflictStrategy::Skip,
+        "o" | "overwrite" => ConflictStrategy::Overwrite,
+        "r" | "rename" => ConflictStrategy::Rename,
+        _ => return None,
+    };
+    let remember = answer.chars().next().is_some_and(|c| c.is_uppercase());
+    Some((strategy, remember))
+}
+
+/// Size and age of a file for the conflict prompt, e.g. "12.3 MB, modified 3 days ago"
+fn describe(path: &Path) -> String {
+    let Ok(metadata) = fs::metadata(path) else {
+        return "unreadable".to_string();
+    };
+    let size = format!("{:.1} MB", metadata.len() as f64 / 1_000_000.0);
+    let age = metadata
+        .modified()
+        .ok()
+        .and_then(|m| SystemTime::now().duration_since(m).ok())
+        .map(|d| match d.as_secs() {
+            s if s < 3600 => format!("{} min ago", s / 60),
+            s if s < 86400 => format!("{} hours ago", s / 3600),
+            s => format!("{} days ago", s / 86400),
+        });
+    match age {
+        Some(age) => format!("{}, modified {}", size, age),
+        None => size,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, SystemTime};
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_resolve_conflicts() {
+        let dir = tempdir().unwrap();
+        let source = dir.path().join("source.mp3");
+        let dest = dir.path().join("dest.mp3");
+        fs::write(&source, b"new").unwrap();
+        fs::write(&dest, b"old").unwrap();
+
+        let an_hour_ago = SystemTime::now() - Duration::from_secs(3600);
+        fs::File::options()
+            .write(true)
+            .open(&dest)
+            .unwrap()
+            .set_modified(an_hour_ago)
+            .unwrap();
+
+        let check = ChangeCheck::default();
+        assert_eq!(
+            ConflictStrategy::Skip.resolve(&source, &dest, check),
+            Resolution::Skip
+        );
+        assert_eq!(
+            ConflictStrategy::Overwrite.resolve(&source, &dest, check),
+            Resolution::Replace
+        );
+        assert_eq!(
+            ConflictStrategy::Update.resolve(&source, &dest, check),
+            Resolution::Update
+        );
+        assert_eq!(
+            ConflictStrategy::Update.resolve(&dest, &source, check),
+            Resolution::Skip
+        );
+        assert_eq!(
+            ConflictStrategy::Rename.resolve(&source, &dest, check),
+            Resolution::WriteAs(dir.path().join("dest 2.mp3"))
+        );
+
+        let plain = ChangeCheck {
+            plain_copy: true,
+            checksum: false,
+        };
+        assert_eq!(
+            ConflictStrategy::Rename.resolve(&source, &dest, plain),
+            Resolution::WriteAs(dir.path().join("dest 2.mp3"))
+        );
+        fs::write(&dest, b"new").unwrap();
+        assert_eq!(
+            ConflictStrategy::Rename.resolve(&source, &dest, plain),
+            Resolution::Skip
+        );
+        assert_eq!(
+            ConflictStrategy::Rename.resolve(&source, &dest, check),
+            Resolution::Skip
+        );
+    }
+
+    #[test]
+    fn test_source_changed() {
+        let dir = tempdir().unwrap();
+        let source = dir.path().join("source.mp3");
+        let dest = dir.path().join("dest.mp3");
+        fs::write(&source, b"fixed audio").unwrap();
+        fs::write(&dest, b"first audio").unwrap();
+
+        let plain = ChangeCheck {
+            plain_copy: true,
+            checksum: false,
+        };
+        let checksum = ChangeCheck {
+            plain_copy: true,
+            checksum: true,
+        };
+        // Same size, dest written last: only a checksum notices the difference
+        assert!(!plain.source_changed(&source, &dest));
+        assert!(checksum.source_changed(&source, &dest));
+
+        fs::write(&dest, b"truncated").unwrap();
+        assert!(plain.source_changed(&source, &dest));
+        assert!(!ChangeCheck::default().source_changed(&source, &dest));
+    }
+
+    #[test]
+    fn test_parse_answer() {
+        assert_eq!(
+            parse_answer("o\n"),
+            Some((ConflictStrategy::Overwrite, false))
+        );
+        assert_eq!(parse_answer("R"), Some((ConflictStrategy::Rename, true)));
+        assert_eq!(parse_answer("Skip"), Some((ConflictStrategy::Skip, true)));
+        assert_eq!(parse_answer("x"), None);
+    }
+}
@@ -0,0 +1,123 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use crate::media;
+
+/// Name of the cover image written into each book folder
+pub const COVER_FILENAME: &str = "cover.jpg";
+
+const IMAGE_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png"];
+
+/// Whether a file starts like a JPEG image
+pub fn is_jpeg(path: &Path) -> bool {
+    let mut magic = [0; 3];
+    fs::File::open(path)
+        .and_then(|mut file| file.read_exact(&mut magic))
+        .is_ok_and(|()| magic == [0xFF, 0xD8, 0xFF])
+}
+
+/// The cover image in a book's folder in the Apple Books folder: `iTunesArtwork` (which has
+/// no extension), then an image named like a cover or artwork, then the largest image
+pub fn find_image(book_dir: &Path) -> Option<PathBuf> {
+    let images: Vec<_> = fs::read_dir(book_dir)
+        .ok()?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .filter(|path| {
+            let name = path
+                .file_name()
+                .map(|n| n.to_string_lossy().to_lowercase())
+                .unwrap_or_default();
+            name == "itunesartwork"
+                || path
+                    .extension()
+                    .map(|e| e.to_string_lossy().to_lowercase())
+                    .is_some_and(|e| IMAGE_EXTENSIONS.contains(&e.as_str()))
+        })
+        .collect();
+
+    let rank = |path: &PathBuf| {
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        let named = if name == "itunesartwork" {
+            0
+        } else if name.contains("cover") || name.contains("artwork") {
+            1
+        } else {
+            2
+        };
+        let size = fs::metadata(path).map_or(0, |m| m.len());
+        (named, std::cmp::Reverse(size), name)
+    };
+    images.into_iter().min_by_key(rank)
+}
+
+/// Write `cover.jpg` into `dest_dir` from the image in the book's source folder, converted
+/// to JPEG if it isn't one, or else from the artwork embedded in its first track. Returns
+/// whether a cover was written; an existing `cover.jpg` is kept. Converting and extracting
+/// need ffmpeg.
+pub fn write_cover(source_dir: &Path, first_track: &Path, dest_dir: &Path) -> Result<bool> {
+    let dest = dest_dir.join(COVER_FILENAME);
+    if dest.exists() {
+        return Ok(false);
+    }
+    fs::create_dir_all(dest_dir)
+        .with_context(|| format!("Failed to create directory {:?}", dest_dir))?;
+
+    match find_image(source_dir) {
+        Some(image) if is_jpeg(&image) => {
+            fs::copy(&image, &dest)
+                .with_context(|| format!("Failed to copy {:?} to {:?}", image, dest))?;
+            Ok(true)
+        }
+        Some(image) => media::write_jpeg(&image, &dest).map(|()| true),
+        None if media::has_embedded_image(first_track) => {
+            media::write_jpeg(first_track, &dest).map(|()| true)
+        }
+        None => Ok(false),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_find_image() {
+        let dir = tempdir().unwrap();
+        let book = dir.path();
+        let jpeg = [0xFF, 0xD8, 0xFF, 0xE0, 0, 0x10];
+        fs::write(book.join("01.mp3"), b"audio").unwrap();
+        fs::write(book.join("back.png"), b"a larger png image").unwrap();
+        fs::write(book.join("Cover.jpg"), jpeg).unwrap();
+        assert_eq!(find_image(book), Some(book.join("Cover.jpg")));
+        assert!(is_jpeg(&book.join("Cover.jpg")));
+        assert!(!is_jpeg(&book.join("back.png")));
+
+        fs::write(book.join("iTunesArtwork"), jpeg).unwrap();
+        assert_eq!(find_image(book), Some(book.join("iTunesArtwork")));
+
+        fs::remove_file(book.join("iTunesArtwork")).unwrap();
+        fs::remove_file(book.join("Cover.jpg")).unwrap();
+        fs::write(book.join("front.jpeg"), jpeg).unwrap();
+        assert_eq!(find_image(book), Some(book.join("back.png")));
+
+        // A cover already at the destination is kept
+        let dest = dir.path().join("dest");
+        fs::create_dir(&dest).unwrap();
+        fs::write(dest.join(COVER_FILENAME), b"mine").unwrap();
+        assert!(!write_cover(book, &book.join("01.mp3"), &dest).unwrap());
+        assert_eq!(fs::read(dest.join(COVER_FILENAME)).unwrap(), b"mine");
+
+        let fresh = dir.path().join("fresh");
+        fs::remove_file(book.join("back.png")).unwrap();
+        assert!(write_cover(book, &book.join("01.mp3"), &fresh).unwrap());
+        assert!(is_jpeg(&fresh.join(COVER_FILENAME)));
+    }
+}
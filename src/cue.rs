@@ -0,0 +1,161 @@
+use anyhow::Result;
+use std::path::Path;
+
+use crate::media::EmbeddedChapter;
+use crate::{write_file, Audiobook};
+
+/// Name of the CUE sheet written into each book folder
+pub const CUE_FILENAME: &str = "book.cue";
+
+/// One track of a CUE sheet: a chapter starting `start` seconds into `file`
+#[derive(Debug, Clone, PartialEq)]
+pub struct CueTrack {
+    pub file: String,
+    pub title: String,
+    pub start: f64,
+}
+
+/// Tracks for a book's exported files: one per file, or one per embedded chapter for a
+/// single-file book that has them
+pub fn tracks(
+    filenames: &[String],
+    titles: &[String],
+    embedded: &[EmbeddedChapter],
+) -> Vec<CueTrack> {
+    match filenames {
+        [file] if !embedded.is_empty() => embedded
+            .iter()
+            .map(|chapter| CueTrack {
+                file: file.clone(),
+                title: chapter.title.clone(),
+                start: chapter.start,
+            })
+            .collect(),
+        _ => filenames
+            .iter()
+            .zip(titles)
+            .map(|(file, title)| CueTrack {
+                file: file.clone(),
+                title: title.clone(),
+                start: 0.0,
+            })
+            .collect(),
+    }
+}
+
+/// A CUE time: minutes, seconds and frames of 1/75 second. Minutes go past 99 for long
+/// books, which players accept.
+fn timestamp(seconds: f64) -> String {
+    let frames = (seconds.max(0.0) * 75.0).round() as u64;
+    format!(
+        "{:02}:{:02}:{:02}",
+        frames / (75 * 60),
+        frames / 75 % 60,
+        frames % 75
+    )
+}
+
+/// CUE strings are double-quoted, with no way to escape a quote
+fn quoted(text: &str) -> String {
+    format!("\"{}\"", text.replace('"', "'"))
+}
+
+/// The file type CUE players expect: MP3 and AIFF by name, WAVE for everything else
+fn file_type(file: &str) -> &'static str {
+    let extension = Path::new(file)
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    match extension.as_str() {
+        "mp3" => "MP3",
+        "aif" | "aiff" => "AIFF",
+        _ => "WAVE",
+    }
+}
+
+/// The CUE sheet of a book, with the author as performer and the narrator as songwriter
+pub fn to_cue(book: &Audiobook, tracks: &[CueTrack]) -> String {
+    let mut lines = vec![
+        format!("PERFORMER {}", quoted(&book.author)),
+        format!("TITLE {}", quoted(&book.full_title())),
+    ];
+    if let Some(narrator) = &book.narrator {
+        lines.push(format!("SONGWRITER {}", quoted(narrator)));
+    }
+    let mut file = None;
+    for (number, track) in tracks.iter().enumerate() {
+        if file != Some(&track.file) {
+            lines.push(format!(
+                "FILE {} {}",
+                quoted(&track.file),
+                file_type(&track.file)
+            ));
+            file = Some(&track.file);
+        }
+        lines.push(format!("  TRACK {:02} AUDIO", number + 1));
+        lines.push(format!("    TITLE {}", quoted(&track.title)));
+        lines.push(format!("    INDEX 01 {}", timestamp(track.start)));
+    }
+    lines.push(String::new());
+    lines.join("\n")
+}
+
+/// Write the CUE sheet into the book's destination directory
+pub fn write_cue_file(dest_dir: &Path, book: &Audiobook, tracks: &[CueTrack]) -> Result<()> {
+    let path = dest_dir.join(CUE_FILENAME);
+    write_file(&path, to_cue(book, tracks))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_cue() {
+        let book = Audiobook {
+            title: "The Hobbit".to_string(),
+            author: "J.R.R. Tolkien".to_string(),
+            narrator: Some("Andy Serkis".to_string()),
+            folder_id: "sha1-aaa".to_string(),
+            ..Default::default()
+        };
+        let files = ["01.mp3".to_string(), "02.mp3".to_string()];
+        let titles = [
+            "An \"Unexpected\" Party".to_string(),
+            "Roast Mutton".to_string(),
+        ];
+        assert_eq!(
+            to_cue(&book, &tracks(&files, &titles, &[])),
+            "PERFORMER \"J.R.R. Tolkien\"\n\
+             TITLE \"The Hobbit\"\n\
+             SONGWRITER \"Andy Serkis\"\n\
+             FILE \"01.mp3\" MP3\n\
+             \x20 TRACK 01 AUDIO\n\
+             \x20   TITLE \"An 'Unexpected' Party\"\n\
+             \x20   INDEX 01 00:00:00\n\
+             FILE \"02.mp3\" MP3\n\
+             \x20 TRACK 02 AUDIO\n\
+             \x20   TITLE \"Roast Mutton\"\n\
+             \x20   INDEX 01 00:00:00\n"
+        );
+
+        let embedded = [
+            EmbeddedChapter {
+                start: 0.0,
+                end: 1834.2,
+                title: "Chapter 1".to_string(),
+            },
+            EmbeddedChapter {
+                start: 6001.5,
+                end: 7200.0,
+                title: "Chapter 2".to_string(),
+            },
+        ];
+        let cue = to_cue(
+            &book,
+            &tracks(&["The Hobbit.m4b".to_string()], &titles[..1], &embedded),
+        );
+        assert_eq!(cue.matches("FILE \"The Hobbit.m4b\" WAVE").count(), 1);
+        assert!(cue.contains("  TRACK 02 AUDIO\n    TITLE \"Chapter 2\"\n    INDEX 01 100:01:38\n"));
+    }
+}
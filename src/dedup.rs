@@ -0,0 +1,81 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read};
+use std::path::{Path, PathBuf};
+
+/// Compare two files byte by byte
+fn files_identical(a: &Path, b: &Path) -> io::Result<bool> {
+    let mut a = BufReader::new(File::open(a)?);
+    let mut b = BufReader::new(File::open(b)?);
+    let mut buf_a = [0u8; 64 * 1024];
+    let mut buf_b = [0u8; 64 * 1024];
+
+    loop {
+        let n = a.read(&mut buf_a)?;
+        if n == 0 {
+            return Ok(b.read(&mut buf_b)? == 0);
+        }
+        if b.read_exact(&mut buf_b[..n]).is_err() || buf_a[..n] != buf_b[..n] {
+            return Ok(false);
+        }
+    }
+}
+
+/// Find byte-identical duplicate files in a book's track list.
+/// Returns a map from the index of each duplicate to the index of the first identical
+/// track. Only files with equal sizes are compared, so books without duplicates cost no
+/// more than a stat per track. Missing or unreadable files are never reported.
+pub fn find_duplicates(paths: &[PathBuf]) -> HashMap<usize, usize> {
+    let mut by_size: HashMap<u64, Vec<usize>> = HashMap::new();
+    for (i, path) in paths.iter().enumerate() {
+        if let Ok(metadata) = fs::metadata(path) {
+            if metadata.is_file() && metadata.len() > 0 {
+                by_size.entry(metadata.len()).or_default().push(i);
+            }
+        }
+    }
+
+    let mut duplicates = HashMap::new();
+    for indices in by_size.values().filter(|v| v.len() > 1) {
+        for (pos, &candidate) in indices.iter().enumerate() {
+            let original = indices[..pos].iter().copied().find(|earlier| {
+                !duplicates.contains_key(earlier)
+                    && (paths[*earlier] == paths[candidate]
+                        || files_identical(&paths[*earlier], &paths[candidate]).unwrap_or(false))
+            });
+            if let Some(original) = original {
+                duplicates.insert(candidate, original);
+            }
+        }
+    }
+
+    duplicates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_find_duplicates() {
+        let dir = tempdir().unwrap();
+        let write = |name: &str, data: &[u8]| {
+            let path = dir.path().join(name);
+            fs::write(&path, data).unwrap();
+            path
+        };
+
+        let paths = vec![
+            write("01.mp3", b"chapter one audio"),
+            write("02.mp3", b"chapter two audio"),
+            write("03.mp3", b"chapter one audio"),
+            write("04.mp3", b"chapter 3 audio!!"),
+            dir.path().join("missing.mp3"),
+        ];
+
+        let duplicates = find_duplicates(&paths);
+        assert_eq!(duplicates.len(), 1);
+        assert_eq!(duplicates.get(&2), Some(&0));
+    }
+}
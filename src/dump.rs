@@ -0,0 +1,81 @@
+use plist::Value;
+use serde::Serializer;
+use serde_json::{json, Map};
+use std::time::SystemTime;
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+fn format_date(date: SystemTime) -> String {
+    OffsetDateTime::from(date)
+        .format(&Rfc3339)
+        .unwrap_or_default()
+}
+
+/// Serialize an optional time as an RFC 3339 date, for the JSON of parsed books
+pub fn serialize_date<S: Serializer>(date: &Option<SystemTime>, s: S) -> Result<S::Ok, S::Error> {
+    match date {
+        Some(date) => s.serialize_str(&format_date(*date)),
+        None => s.serialize_none(),
+    }
+}
+
+/// Convert a plist value to JSON, keeping every key. Dates become RFC 3339 strings and
+/// data becomes a hex string.
+pub fn plist_to_json(value: &Value) -> serde_json::Value {
+    match value {
+        Value::Array(items) => items.iter().map(plist_to_json).collect(),
+        Value::Dictionary(dict) => dict
+            .iter()
+            .map(|(key, value)| (key.clone(), plist_to_json(value)))
+            .collect::<Map<_, _>>()
+            .into(),
+        Value::Boolean(b) => json!(b),
+        Value::Integer(i) => match (i.as_signed(), i.as_unsigned()) {
+            (Some(n), _) => json!(n),
+            (_, Some(n)) => json!(n),
+            _ => serde_json::Value::Null,
+        },
+        Value::Real(r) => json!(r),
+        Value::String(s) => json!(s),
+        Value::Date(date) => json!(format_date(SystemTime::from(*date))),
+        Value::Data(bytes) => json!(bytes
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>()),
+        Value::Uid(uid) => json!(uid.get()),
+        _ => serde_json::Value::Null,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use plist::Dictionary;
+    use std::time::Duration;
+
+    #[test]
+    fn test_plist_to_json() {
+        let mut dict = Dictionary::new();
+        dict.insert(
+            "BKBookType".to_string(),
+            Value::String("audiobook".to_string()),
+        );
+        dict.insert("BKTrackNumber".to_string(), Value::Integer(3.into()));
+        dict.insert("BKIsLocked".to_string(), Value::Boolean(false));
+        dict.insert("BKData".to_string(), Value::Data(vec![0, 171]));
+        let purchased = SystemTime::UNIX_EPOCH + Duration::from_secs(1_682_899_200);
+        dict.insert("BKPurchaseDate".to_string(), Value::Date(purchased.into()));
+        dict.insert("BKParts".to_string(), Value::Array(vec![Value::Real(1.5)]));
+
+        assert_eq!(
+            plist_to_json(&Value::Dictionary(dict)),
+            json!({
+                "BKBookType": "audiobook",
+                "BKTrackNumber": 3,
+                "BKIsLocked": false,
+                "BKData": "00ab",
+                "BKPurchaseDate": "2023-05-01T00:00:00Z",
+                "BKParts": [1.5],
+            })
+        );
+    }
+}
@@ -0,0 +1,96 @@
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Where and when to email run summaries
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EmailConfig {
+    /// Recipient address
+    pub to: String,
+    /// Sender address; left to the mail program when unset
+    #[serde(default)]
+    pub from: Option<String>,
+    /// sendmail-compatible program reading the message on stdin, e.g. `msmtp -a gmail` to
+    /// send through an SMTP server
+    #[serde(default = "default_sendmail")]
+    pub sendmail: String,
+    /// Only send when files failed
+    #[serde(default)]
+    pub only_on_failure: bool,
+}
+
+fn default_sendmail() -> String {
+    "sendmail".to_string()
+}
+
+/// The message handed to the mail program
+fn message(config: &EmailConfig, subject: &str, body: &str) -> String {
+    let mut message = format!("To: {}\n", config.to);
+    if let Some(from) = &config.from {
+        message.push_str(&format!("From: {}\n", from));
+    }
+    message.push_str(&format!(
+        "Subject: {}\n\
+         MIME-Version: 1.0\n\
+         Content-Type: text/plain; charset=utf-8\n\
+         Content-Transfer-Encoding: 8bit\n\n{}",
+        subject, body
+    ));
+    message
+}
+
+/// Send a plain-text email through the configured mail program
+pub fn send(config: &EmailConfig, subject: &str, body: &str) -> Result<()> {
+    // Run through the shell so that programs with arguments ("msmtp -a gmail") work
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} -t -oi", config.sendmail))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()
+        .with_context(|| format!("Failed to run {:?}", config.sendmail))?;
+
+    let mut stdin = child.stdin.take().context("No stdin for mail program")?;
+    stdin.write_all(message(config, subject, body).as_bytes())?;
+    drop(stdin);
+
+    let status = child.wait()?;
+    if !status.success() {
+        bail!("{:?} exited with {}", config.sendmail, status);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_send_pipes_message_to_mail_program() {
+        let dir = tempdir().unwrap();
+        let mailbox = dir.path().join("mail.txt");
+        let config = EmailConfig {
+            to: "me@example.com".to_string(),
+            from: Some("nas@example.com".to_string()),
+            // `true` takes the -t -oi arguments meant for sendmail
+            sendmail: format!("cat > {:?}; true", mailbox),
+            only_on_failure: false,
+        };
+
+        send(&config, "Export finished", "Files copied: 3\n").unwrap();
+        let mail = fs::read_to_string(&mailbox).unwrap();
+        assert!(mail
+            .starts_with("To: me@example.com\nFrom: nas@example.com\nSubject: Export finished\n"));
+        assert!(mail.ends_with("\n\nFiles copied: 3\n"));
+
+        let failing = EmailConfig {
+            sendmail: "false".to_string(),
+            ..config
+        };
+        assert!(send(&failing, "Export finished", "").is_err());
+    }
+}
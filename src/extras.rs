@@ -0,0 +1,64 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Subfolder of an exported book that holds its extras
+pub const EXTRAS_DIR: &str = "extras";
+
+/// Files in a book's folder that aren't among its tracks, such as booklet PDFs, cover images
+/// or bonus videos, relative to the folder. Hidden files are left out.
+pub fn find(book_dir: &Path, tracks: &[PathBuf]) -> Vec<PathBuf> {
+    let mut extras = Vec::new();
+    let mut dirs = vec![PathBuf::new()];
+    while let Some(relative) = dirs.pop() {
+        let Ok(entries) = fs::read_dir(book_dir.join(&relative)) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            if entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+            let name = relative.join(entry.file_name());
+            match entry.file_type() {
+                Ok(t) if t.is_dir() => dirs.push(name),
+                Ok(_) if !tracks.contains(&entry.path()) => extras.push(name),
+                _ => {}
+            }
+        }
+    }
+    extras.sort();
+    extras
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_find_extras() {
+        let dir = tempdir().unwrap();
+        let book = dir.path();
+        fs::create_dir(book.join("Bonus")).unwrap();
+        for file in [
+            "01.mp3",
+            "02.mp3",
+            "Booklet.pdf",
+            "cover.jpg",
+            ".DS_Store",
+            "Bonus/Interview.m4v",
+        ] {
+            fs::write(book.join(file), b"data").unwrap();
+        }
+
+        let tracks = vec![book.join("01.mp3"), book.join("02.mp3")];
+        assert_eq!(
+            find(book, &tracks),
+            vec![
+                PathBuf::from("Bonus/Interview.m4v"),
+                PathBuf::from("Booklet.pdf"),
+                PathBuf::from("cover.jpg"),
+            ]
+        );
+        assert!(find(&book.join("missing"), &tracks).is_empty());
+    }
+}
@@ -0,0 +1,201 @@
+use anyhow::{Context, Result};
+use regex::Regex;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+use time::{macros::format_description, Date};
+
+use crate::Audiobook;
+
+/// Parse a `YYYY-MM-DD` date as the start of that day, UTC
+pub fn parse_date(s: &str) -> Result<SystemTime, String> {
+    let date = Date::parse(s, format_description!("[year]-[month]-[day]"))
+        .map_err(|_| format!("Expected a date like 2024-01-31, not {:?}", s))?;
+    Ok(date.midnight().assume_utc().into())
+}
+
+/// Whether `text` matches a glob pattern as a whole, ignoring case. `*` matches any run of
+/// characters and `?` any single character.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Position of the last `*` seen, and of the text when it was seen
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                // Let the last `*` take one more character
+                Some((star_p, star_t)) => {
+                    p = star_p + 1;
+                    t = star_t + 1;
+                    star = Some((star_p, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Read the regular expressions of an exclusion file, one per line. Blank lines and lines
+/// starting with `#` are skipped.
+pub fn read_patterns(path: &Path) -> Result<Vec<Regex>> {
+    let text = fs::read_to_string(path).with_context(|| format!("Failed to read {:?}", path))?;
+    text.lines()
+        .enumerate()
+        .map(|(i, line)| (i, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(i, line)| {
+            Regex::new(line)
+                .with_context(|| format!("Invalid pattern on line {} of {:?}", i + 1, path))
+        })
+        .collect()
+}
+
+/// Books to export, by author and title glob patterns and by regular expressions matched
+/// against "Author - Title". A book is selected when its author matches any of the author
+/// patterns, its title any of the title patterns, its name any of the include expressions
+/// and none of the exclude expressions; an empty list matches every book.
+#[derive(Debug, Clone, Default)]
+pub struct BookFilter {
+    pub authors: Vec<String>,
+    pub titles: Vec<String>,
+    pub include: Vec<Regex>,
+    pub exclude: Vec<Regex>,
+    /// Only books added on or after this time. Books without a date are left out.
+    pub since: Option<SystemTime>,
+    /// Only books added before the end of the day starting at this time
+    pub until: Option<SystemTime>,
+}
+
+impl BookFilter {
+    pub fn is_empty(&self) -> bool {
+        self.authors.is_empty()
+            && self.titles.is_empty()
+            && self.include.is_empty()
+            && self.exclude.is_empty()
+            && self.since.is_none()
+            && self.until.is_none()
+    }
+
+    fn added_in_range(&self, book: &Audiobook) -> bool {
+        if self.since.is_none() && self.until.is_none() {
+            return true;
+        }
+        let Some(added) = book.added else {
+            return false;
+        };
+        self.since.is_none_or(|since| added >= since)
+            && self
+                .until
+                .is_none_or(|until| added < until + Duration::from_secs(24 * 60 * 60))
+    }
+
+    pub fn matches(&self, book: &Audiobook) -> bool {
+        let any = |patterns: &[String], text: &str| {
+            patterns.is_empty() || patterns.iter().any(|p| glob_match(p, text))
+        };
+        let title = book.full_title();
+        let name = format!("{} - {}", book.author, title);
+        any(&self.authors, &book.author)
+            && any(&self.titles, &title)
+            && (self.include.is_empty() || self.include.iter().any(|re| re.is_match(&name)))
+            && !self.exclude.iter().any(|re| re.is_match(&name))
+            && self.added_in_range(book)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_match_and_filter() {
+        assert!(glob_match("Brandon*", "brandon sanderson"));
+        assert!(glob_match("*Mary", "Project Hail Mary"));
+        assert!(glob_match("*hail*", "Project Hail Mary"));
+        assert!(glob_match("W?rbreaker", "Warbreaker"));
+        assert!(glob_match("a*b*c", "aXbYbZc"));
+        assert!(!glob_match("Brandon", "Brandon Sanderson"));
+        assert!(!glob_match("*Sanderson?", "Brandon Sanderson"));
+
+        let book = Audiobook {
+            title: "Warbreaker".to_string(),
+            author: "Brandon Sanderson".to_string(),
+            folder_id: "sha1-aaa".to_string(),
+            ..Default::default()
+        };
+        let filter = |authors: &[&str], titles: &[&str]| BookFilter {
+            authors: authors.iter().map(|s| s.to_string()).collect(),
+            titles: titles.iter().map(|s| s.to_string()).collect(),
+            ..Default::default()
+        };
+        assert!(filter(&[], &[]).matches(&book));
+        assert!(filter(&["Andy*", "Brandon*"], &[]).matches(&book));
+        assert!(filter(&["Brandon*"], &["War*"]).matches(&book));
+        assert!(!filter(&["Brandon*"], &["Mist*"]).matches(&book));
+    }
+
+    #[test]
+    fn test_regex_filters() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("exclude.txt");
+        fs::write(&path, "# never export these\n\n(?i)podcast\n^Sample\n").unwrap();
+        let filter = BookFilter {
+            include: vec![Regex::new("^(Brandon|Various)").unwrap()],
+            exclude: read_patterns(&path).unwrap(),
+            ..Default::default()
+        };
+        let book = |author: &str, title: &str| Audiobook {
+            title: title.to_string(),
+            author: author.to_string(),
+            folder_id: "sha1-aaa".to_string(),
+            ..Default::default()
+        };
+        assert!(filter.matches(&book("Brandon Sanderson", "Warbreaker")));
+        assert!(!filter.matches(&book("Andy Weir", "Project Hail Mary")));
+        assert!(!filter.matches(&book("Various", "The Daily Podcast")));
+
+        fs::write(&path, "fine\n(unclosed\n").unwrap();
+        let error = read_patterns(&path).unwrap_err();
+        assert!(format!("{:#}", error).contains("line 2"));
+    }
+
+    #[test]
+    fn test_date_range() {
+        assert!(parse_date("2024-13-01").is_err());
+        let day = 24 * 60 * 60;
+        let jan_1 = parse_date("2024-01-01").unwrap();
+        assert_eq!(
+            jan_1,
+            SystemTime::UNIX_EPOCH + Duration::from_secs(19_723 * day)
+        );
+
+        let added = |time: Option<SystemTime>| Audiobook {
+            title: "Warbreaker".to_string(),
+            author: "Brandon Sanderson".to_string(),
+            folder_id: "sha1-aaa".to_string(),
+            added: time,
+            ..Default::default()
+        };
+        let filter = BookFilter {
+            since: Some(jan_1),
+            until: Some(parse_date("2024-01-31").unwrap()),
+            ..Default::default()
+        };
+        assert!(filter.matches(&added(Some(jan_1))));
+        assert!(filter.matches(&added(Some(jan_1 + Duration::from_secs(31 * day - 1)))));
+        assert!(!filter.matches(&added(Some(jan_1 + Duration::from_secs(31 * day)))));
+        assert!(!filter.matches(&added(Some(jan_1 - Duration::from_secs(1)))));
+        assert!(!filter.matches(&added(None)));
+    }
+}
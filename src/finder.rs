@@ -0,0 +1,90 @@
+use anyhow::{bail, Context, Result};
+use plist::Value;
+use std::path::Path;
+use std::process::Command;
+
+/// Extended attribute holding Finder tags
+const TAGS_ATTR: &str = "com.apple.metadata:_kMDItemUserTags";
+/// Extended attribute holding the Finder comment, as indexed by Spotlight
+const COMMENT_ATTR: &str = "com.apple.metadata:kMDItemFinderComment";
+
+/// Whether Finder metadata can be written on this system
+pub fn is_supported() -> bool {
+    cfg!(target_os = "macos")
+}
+
+fn binary_plist(value: &Value) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    value
+        .to_writer_binary(&mut bytes)
+        .context("Failed to encode Finder metadata")?;
+    Ok(bytes)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Set an extended attribute with the `xattr` tool
+fn set_xattr(path: &Path, name: &str, value: &[u8]) -> Result<()> {
+    let output = Command::new("xattr")
+        .args(["-wx", name, &hex(value)])
+        .arg(path)
+        .output()
+        .context("Failed to run xattr")?;
+    if !output.status.success() {
+        bail!(
+            "xattr failed for {:?}: {}",
+            path,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+/// The attribute values for a folder's tags and comment
+fn attributes(tags: &[String], comment: Option<&str>) -> Result<Vec<(&'static str, Vec<u8>)>> {
+    let mut attributes = Vec::new();
+    if !tags.is_empty() {
+        let tags = Value::Array(tags.iter().cloned().map(Value::String).collect());
+        attributes.push((TAGS_ATTR, binary_plist(&tags)?));
+    }
+    if let Some(comment) = comment {
+        attributes.push((
+            COMMENT_ATTR,
+            binary_plist(&Value::String(comment.to_string()))?,
+        ));
+    }
+    Ok(attributes)
+}
+
+/// Give an exported folder Finder tags and a comment
+pub fn label_folder(dir: &Path, tags: &[String], comment: Option<&str>) -> Result<()> {
+    for (name, value) in attributes(tags, comment)? {
+        set_xattr(dir, name, &value)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_attributes_are_binary_plists() {
+        let attributes = attributes(&["Fantasy".to_string()], Some("Michael Kramer")).unwrap();
+        assert_eq!(attributes.len(), 2);
+
+        let (name, tags) = &attributes[0];
+        assert_eq!(*name, TAGS_ATTR);
+        assert!(tags.starts_with(b"bplist00"));
+        let tags = Value::from_reader(std::io::Cursor::new(tags)).unwrap();
+        assert_eq!(tags, Value::Array(vec![Value::String("Fantasy".into())]));
+
+        let comment = Value::from_reader(std::io::Cursor::new(&attributes[1].1)).unwrap();
+        assert_eq!(comment.as_string(), Some("Michael Kramer"));
+
+        assert!(super::attributes(&[], None).unwrap().is_empty());
+        assert_eq!(hex(&[0x0f, 0xa0]), "0fa0");
+    }
+}
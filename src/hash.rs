@@ -0,0 +1,57 @@
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+/// Compute the SHA-256 of a file as a lowercase hex string
+pub fn sha256_file(path: &Path) -> Result<String> {
+    let file = File::open(path).with_context(|| format!("Failed to open {:?}", path))?;
+    let mut reader = BufReader::new(file);
+    let mut hasher = Sha256::new();
+    let mut buf = [0u8; 64 * 1024];
+
+    loop {
+        let n = reader
+            .read(&mut buf)
+            .with_context(|| format!("Failed to read {:?}", path))?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect())
+}
+
+/// Whether two files have identical contents, compared by SHA-256
+pub fn files_match(a: &Path, b: &Path) -> Result<bool> {
+    Ok(sha256_file(a)? == sha256_file(b)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_sha256_file() {
+        let dir = tempdir().unwrap();
+        let a = dir.path().join("a");
+        let b = dir.path().join("b");
+        fs::write(&a, b"abc").unwrap();
+        fs::write(&b, b"abd").unwrap();
+
+        assert_eq!(
+            sha256_file(&a).unwrap(),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert!(files_match(&a, &a).unwrap());
+        assert!(!files_match(&a, &b).unwrap());
+    }
+}
@@ -0,0 +1,121 @@
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use time::{macros::format_description, OffsetDateTime};
+
+use crate::report::{RunReport, STATE_DIR};
+
+/// Where the reports of past runs into a destination are kept
+pub fn history_dir(dest: &Path) -> PathBuf {
+    dest.join(STATE_DIR).join("history")
+}
+
+/// Id of a run, from the time it finished, e.g. `20261016-142300`
+pub fn run_id(finished_at: u64) -> String {
+    OffsetDateTime::from_unix_timestamp(finished_at as i64)
+        .ok()
+        .and_then(|t| {
+            t.format(format_description!(
+                "[year][month][day]-[hour][minute][second]"
+            ))
+            .ok()
+        })
+        .unwrap_or_else(|| finished_at.to_string())
+}
+
+/// Keep a copy of a run's report in the destination's history. Returns the run id.
+pub fn record(dest: &Path, report: &RunReport) -> Result<String> {
+    let dir = history_dir(dest);
+    let base = run_id(report.finished_at);
+    // Runs finishing within the same second get "-2", "-3", ...
+    let id = std::iter::once(base.clone())
+        .chain((2..).map(|n| format!("{}-{}", base, n)))
+        .find(|id| !dir.join(format!("{}.json", id)).exists())
+        .expect("unbounded search always finds a free id");
+    report.write(&dir.join(format!("{}.json", id)))?;
+    Ok(id)
+}
+
+/// Past runs into a destination, oldest first. Unreadable reports are skipped.
+pub fn list(dest: &Path) -> Result<Vec<(String, RunReport)>> {
+    let dir = history_dir(dest);
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut runs = Vec::new();
+    for entry in fs::read_dir(&dir).with_context(|| format!("Failed to read {:?}", dir))? {
+        let path = entry?.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("json") {
+            continue;
+        }
+        let Some(id) = path.file_stem().map(|s| s.to_string_lossy().to_string()) else {
+            continue;
+        };
+        match RunReport::read(&path) {
+            Ok(report) => runs.push((id, report)),
+            Err(e) => eprintln!("Warning: Skipping run {}: {:#}", id, e),
+        }
+    }
+    runs.sort_by(|(a, _), (b, _)| a.cmp(b));
+    Ok(runs)
+}
+
+/// The run with this id, or the only run whose id starts with it
+pub fn find(dest: &Path, id: &str) -> Result<(String, RunReport)> {
+    let mut matches: Vec<_> = list(dest)?
+        .into_iter()
+        .filter(|(run, _)| run.starts_with(id))
+        .collect();
+    if let Some(exact) = matches.iter().position(|(run, _)| run == id) {
+        return Ok(matches.swap_remove(exact));
+    }
+    match matches.len() {
+        0 => bail!("No run {:?} in {:?}", id, history_dir(dest)),
+        1 => Ok(matches.remove(0)),
+        n => bail!("{:?} matches {} runs; use a longer id", id, n),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ExportStats;
+    use tempfile::tempdir;
+
+    fn report(dest: &Path, finished_at: u64, files_copied: usize) -> RunReport {
+        let mut report = RunReport::new(
+            dest,
+            ExportStats {
+                files_copied,
+                ..Default::default()
+            },
+        );
+        report.finished_at = finished_at;
+        report
+    }
+
+    #[test]
+    fn test_record_list_and_find() {
+        let dest = tempdir().unwrap();
+        // 2026-10-16 14:23:00 UTC
+        let at = 1_792_160_580;
+        assert_eq!(run_id(at), "20261016-142300");
+
+        let first = record(dest.path(), &report(dest.path(), at, 3)).unwrap();
+        let second = record(dest.path(), &report(dest.path(), at, 5)).unwrap();
+        let third = record(dest.path(), &report(dest.path(), at + 86_400, 0)).unwrap();
+        assert_eq!(first, "20261016-142300");
+        assert_eq!(second, "20261016-142300-2");
+        assert_eq!(third, "20261017-142300");
+
+        let runs = list(dest.path()).unwrap();
+        let ids: Vec<_> = runs.iter().map(|(id, _)| id.as_str()).collect();
+        assert_eq!(ids, vec![first.as_str(), second.as_str(), third.as_str()]);
+
+        assert_eq!(find(dest.path(), &first).unwrap().1.stats.files_copied, 3);
+        assert_eq!(find(dest.path(), "20261017").unwrap().0, third);
+        assert!(find(dest.path(), "2026").is_err());
+        assert!(find(dest.path(), "2025").is_err());
+    }
+}
@@ -0,0 +1,175 @@
+use std::fmt::Write;
+use std::path::Path;
+
+use crate::cover::COVER_FILENAME;
+use crate::markdown::{by_author, format_duration, ExportStatus, ReportEntry};
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// A relative path as a URL path, percent-encoding everything but unreserved characters
+fn url_path(path: &Path) -> String {
+    path.components()
+        .map(|component| {
+            component
+                .as_os_str()
+                .to_string_lossy()
+                .bytes()
+                .map(|b| match b {
+                    b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                        (b as char).to_string()
+                    }
+                    _ => format!("%{:02X}", b),
+                })
+                .collect::<String>()
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+const STYLE: &str = "body{font-family:-apple-system,system-ui,sans-serif;margin:2em;color:#222}\
+table{border-collapse:collapse;width:100%}\
+th,td{text-align:left;padding:.4em .6em;border-bottom:1px solid #ddd;vertical-align:middle}\
+th{background:#f4f4f4}td.cover{width:64px}td.cover img{width:64px;height:64px;object-fit:cover}\
+td.number{text-align:right;white-space:nowrap}.subtitle,.series{color:#666;font-size:.9em}\
+.missing{color:#999}";
+
+/// A static HTML page of the library: a table of every book, sorted by author, series
+/// and title. Covers link to the `cover.jpg` of exported books relative to the
+/// destination, so the page works when saved at its root.
+pub fn library_page(entries: &[ReportEntry]) -> String {
+    let by_author = by_author(entries);
+    let with_status = entries.iter().any(|entry| entry.status.is_some());
+
+    let mut out = String::new();
+    let _ = writeln!(out, "<!DOCTYPE html>\n<html lang=\"en\">\n<head>");
+    let _ = writeln!(out, "<meta charset=\"utf-8\">");
+    let _ = writeln!(
+        out,
+        "<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">"
+    );
+    let _ = writeln!(out, "<title>Audiobook Library</title>");
+    let _ = writeln!(out, "<style>{}</style>\n</head>\n<body>", STYLE);
+    let _ = writeln!(out, "<h1>Audiobook Library</h1>");
+    let _ = writeln!(
+        out,
+        "<p>{} books by {} authors.</p>",
+        entries.len(),
+        by_author.len()
+    );
+
+    let _ = write!(
+        out,
+        "<table>\n<thead><tr><th></th><th>Title</th><th>Author</th><th>Narrator</th>\
+         <th>Duration</th>"
+    );
+    if with_status {
+        let _ = write!(out, "<th>Status</th>");
+    }
+    let _ = writeln!(out, "</tr></thead>\n<tbody>");
+    for entry in by_author.values().flatten() {
+        let book = entry.book;
+        let cover = match &entry.status {
+            Some(ExportStatus::Exported { dir, cover: true }) => format!(
+                "<img src=\"{}\" alt=\"\" loading=\"lazy\">",
+                url_path(&dir.join(COVER_FILENAME))
+            ),
+            _ => String::new(),
+        };
+        let mut title = escape(&book.title);
+        if let Some(subtitle) = &book.subtitle {
+            let _ = write!(
+                title,
+                "<br><span class=\"subtitle\">{}</span>",
+                escape(subtitle)
+            );
+        }
+        if let Some(series) = &book.series {
+            let series = match &book.series_sequence {
+                Some(sequence) => format!("{} #{}", series, sequence),
+                None => series.clone(),
+            };
+            let _ = write!(
+                title,
+                "<br><span class=\"series\">{}</span>",
+                escape(&series)
+            );
+        }
+        let _ = write!(
+            out,
+            "<tr><td class=\"cover\">{}</td><td>{}</td><td>{}</td><td>{}</td>\
+             <td class=\"number\">{}</td>",
+            cover,
+            title,
+            escape(&book.author),
+            escape(book.narrator.as_deref().unwrap_or("")),
+            format_duration(entry.seconds)
+        );
+        if let Some(status) = &entry.status {
+            let class = match status {
+                ExportStatus::Exported { .. } => "",
+                ExportStatus::NotExported => " class=\"missing\"",
+            };
+            let _ = write!(out, "<td{}>{}</td>", class, status.label());
+        }
+        let _ = writeln!(out, "</tr>");
+    }
+    let _ = writeln!(out, "</tbody>\n</table>\n</body>\n</html>");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Audiobook;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_library_page() {
+        let book = Audiobook {
+            title: "Warbreaker".to_string(),
+            author: "Brandon Sanderson".to_string(),
+            narrator: Some("Alyssa Bresnahan".to_string()),
+            series: Some("Cosmere".to_string()),
+            series_sequence: Some("4".to_string()),
+            folder_id: "sha1-aaa".to_string(),
+            ..Default::default()
+        };
+        let other = Audiobook {
+            title: "Tom & Jerry <Live>".to_string(),
+            author: "Andy Weir".to_string(),
+            series: None,
+            ..book.clone()
+        };
+        let entries = [
+            ReportEntry {
+                book: &book,
+                seconds: 90_000.0,
+                status: Some(ExportStatus::Exported {
+                    dir: PathBuf::from("Brandon Sanderson/Cosmere/Vol 4 - Warbreaker"),
+                    cover: true,
+                }),
+            },
+            ReportEntry {
+                book: &other,
+                seconds: 0.0,
+                status: Some(ExportStatus::NotExported),
+            },
+        ];
+        let page = library_page(&entries);
+
+        assert!(page.contains("<p>2 books by 2 authors.</p>"));
+        assert!(page.contains(
+            "<img src=\"Brandon%20Sanderson/Cosmere/Vol%204%20-%20Warbreaker/cover.jpg\""
+        ));
+        assert!(page.contains("Warbreaker<br><span class=\"series\">Cosmere #4</span>"));
+        assert!(page.contains("<td class=\"number\">25h 00m</td><td>Exported</td>"));
+        assert!(page.contains("Tom &amp; Jerry &lt;Live&gt;"));
+        assert!(page.contains("<td class=\"missing\">Not exported</td>"));
+        assert!(page.find("Andy Weir").unwrap() < page.find("Brandon Sanderson").unwrap());
+    }
+}
@@ -0,0 +1,50 @@
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+/// GET a URL with curl, with `query` parameters URL-encoded onto it; `None` if the server
+/// has nothing there
+pub fn get(url: &str, query: &[(&str, &str)]) -> Result<Option<String>> {
+    let mut command = Command::new("curl");
+    command.args(["-fsSL", "--max-time", "30"]);
+    if !query.is_empty() {
+        command.arg("--get");
+        for (name, value) in query {
+            command
+                .arg("--data-urlencode")
+                .arg(format!("{}={}", name, value));
+        }
+    }
+    let output = command.arg(url).output().context("Failed to run curl")?;
+    match output.status.code() {
+        Some(0) => Ok(Some(String::from_utf8_lossy(&output.stdout).to_string())),
+        // --fail: the server answered with an HTTP error, e.g. 404 for an unknown ASIN
+        Some(22) => Ok(None),
+        _ => bail!(
+            "Request to {} failed: {}",
+            url,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ),
+    }
+}
+
+/// GET a URL, answering from and saving to `cache/name` when there is a cache
+pub fn cached_get(
+    cache: Option<&Path>,
+    name: &str,
+    url: &str,
+    query: &[(&str, &str)],
+) -> Result<Option<String>> {
+    let path = cache.map(|dir| dir.join(name));
+    if let Some(text) = path.as_ref().and_then(|p| fs::read_to_string(p).ok()) {
+        return Ok(Some(text));
+    }
+    let text = get(url, query)?;
+    if let (Some(path), Some(text)) = (&path, &text) {
+        // A cache that could not be written only costs the next run a request
+        let _ = fs::create_dir_all(path.parent().unwrap_or(Path::new(".")));
+        let _ = fs::write(path, text);
+    }
+    Ok(text)
+}
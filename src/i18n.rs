@@ -0,0 +1,263 @@
+use clap::ValueEnum;
+use std::sync::OnceLock;
+
+/// Language of user-facing messages
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Language {
+    /// English
+    En,
+    /// Greek
+    El,
+}
+
+impl Language {
+    /// The language of a POSIX locale such as `el_GR.UTF-8`
+    fn from_locale(locale: &str) -> Option<Self> {
+        let code = locale.split(['_', '.', '@']).next()?.to_lowercase();
+        match code.as_str() {
+            "el" => Some(Language::El),
+            "en" | "c" | "posix" => Some(Language::En),
+            _ => None,
+        }
+    }
+
+    /// The language from `LC_ALL`, `LC_MESSAGES` or `LANG`, English if none is supported
+    fn detect() -> Self {
+        ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .filter_map(|var| std::env::var(var).ok())
+            .find(|value| !value.is_empty())
+            .and_then(|locale| Language::from_locale(&locale))
+            .unwrap_or(Language::En)
+    }
+
+    fn catalog(self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Language::En => &[],
+            Language::El => GREEK,
+        }
+    }
+
+    /// `message` in this language. Messages are looked up by their English text; missing
+    /// translations fall back to English.
+    fn translate(self, message: &'static str) -> &'static str {
+        self.catalog()
+            .iter()
+            .find(|(english, _)| *english == message)
+            .map(|(_, translated)| *translated)
+            .unwrap_or(message)
+    }
+}
+
+static LANGUAGE: OnceLock<Language> = OnceLock::new();
+
+/// Set the message language once at startup; `None` follows the locale
+pub fn init(language: Option<Language>) {
+    let _ = LANGUAGE.set(language.unwrap_or_else(Language::detect));
+}
+
+fn language() -> Language {
+    *LANGUAGE.get_or_init(Language::detect)
+}
+
+/// Translate a message
+pub fn tr(message: &'static str) -> &'static str {
+    language().translate(message)
+}
+
+/// Translate a message and fill its `{}` placeholders in order
+pub fn tr_args(message: &'static str, args: &[&dyn std::fmt::Display]) -> String {
+    fill(tr(message), args)
+}
+
+fn fill(template: &str, args: &[&dyn std::fmt::Display]) -> String {
+    let mut parts = template.split("{}");
+    let mut text = parts.next().unwrap_or_default().to_string();
+    for (i, part) in parts.enumerate() {
+        if let Some(arg) = args.get(i) {
+            text.push_str(&arg.to_string());
+        }
+        text.push_str(part);
+    }
+    text
+}
+
+const GREEK: &[(&str, &str)] = &[
+    // Progress
+    ("Reading library", "Ανάγνωση βιβλιοθήκης"),
+    ("Preparing", "Προετοιμασία"),
+    ("Copying", "Αντιγραφή"),
+    ("Transcoding", "Μετατροπή"),
+    ("Retagging", "Ετικέτες"),
+    ("Hashing", "Υπολογισμός SHA-256"),
+    ("Verifying", "Έλεγχος"),
+    ("Clearing quarantine", "Αφαίρεση καραντίνας"),
+    ("Scanning destination", "Σάρωση προορισμού"),
+    ("Scanning library", "Σάρωση βιβλιοθήκης"),
+    // Export
+    (
+        "Reading audiobook library from:",
+        "Ανάγνωση βιβλιοθήκης από:",
+    ),
+    ("Found {} audiobooks", "Βρέθηκαν {} ηχητικά βιβλία"),
+    (
+        "=== DRY RUN - No files will be copied ===",
+        "=== ΔΟΚΙΜΑΣΤΙΚΗ ΕΚΤΕΛΕΣΗ - Δεν θα αντιγραφεί κανένα αρχείο ===",
+    ),
+    (
+        "{} files failed. Retry just those with:",
+        "{} αρχεία απέτυχαν. Δοκιμάστε ξανά μόνο αυτά με:",
+    ),
+    // Results table
+    ("Book", "Βιβλίο"),
+    ("Files", "Αρχεία"),
+    ("Size", "Μέγεθος"),
+    ("Time", "Χρόνος"),
+    ("Status", "Κατάσταση"),
+    ("ok", "εντάξει"),
+    ("partial", "μερικώς"),
+    ("failed", "απέτυχε"),
+    // Export summary
+    ("Export Summary", "Σύνοψη εξαγωγής"),
+    ("Audiobooks processed", "Βιβλία που επεξεργάστηκαν"),
+    (
+        "Audiobooks unchanged since last run (skipped)",
+        "Βιβλία χωρίς αλλαγές από την προηγούμενη εκτέλεση (παραλείφθηκαν)",
+    ),
+    (
+        "Audiobooks removed from the library since last run",
+        "Βιβλία που αφαιρέθηκαν από τη βιβλιοθήκη μετά την προηγούμενη εκτέλεση",
+    ),
+    ("Files copied", "Αρχεία που αντιγράφηκαν"),
+    ("Files transcoded", "Αρχεία που μετατράπηκαν"),
+    ("Audible files converted", "Αρχεία Audible που μετατράπηκαν"),
+    (
+        "Files loudness-normalized",
+        "Αρχεία με κανονικοποίηση έντασης",
+    ),
+    ("Files retagged", "Αρχεία με νέες ετικέτες"),
+    (
+        "Files stripped of personal tags",
+        "Αρχεία χωρίς προσωπικές ετικέτες",
+    ),
+    (
+        "Files with an embedded cover",
+        "Αρχεία με ενσωματωμένο εξώφυλλο",
+    ),
+    (
+        "Files updated (changed at source)",
+        "Αρχεία που ενημερώθηκαν (άλλαξαν στην πηγή)",
+    ),
+    ("Files overwritten", "Αρχεία που αντικαταστάθηκαν"),
+    (
+        "Files exported under a new name",
+        "Αρχεία που εξήχθησαν με νέο όνομα",
+    ),
+    (
+        "Files skipped (already exist)",
+        "Αρχεία που παραλείφθηκαν (υπάρχουν ήδη)",
+    ),
+    (
+        "Files missing (skipped)",
+        "Αρχεία που λείπουν (παραλείφθηκαν)",
+    ),
+    (
+        "Duplicate tracks (exported once)",
+        "Διπλότυπα κομμάτια (εξήχθησαν μία φορά)",
+    ),
+    (
+        "Files empty or not downloaded (skipped)",
+        "Κενά ή μη ληφθέντα αρχεία (παραλείφθηκαν)",
+    ),
+    (
+        "Audible files Audiobookshelf can't play (skipped)",
+        "Αρχεία Audible που δεν αναπαράγει το Audiobookshelf (παραλείφθηκαν)",
+    ),
+    ("Chapter files split", "Αρχεία που χωρίστηκαν σε κεφάλαια"),
+    (
+        "Extras found in book folders",
+        "Επιπλέον αρχεία στους φακέλους βιβλίων",
+    ),
+    ("exported", "εξήχθησαν"),
+    ("Chapter files written", "Αρχεία κεφαλαίων που γράφτηκαν"),
+    ("CUE sheets written", "Φύλλα CUE που γράφτηκαν"),
+    (
+        "Source files moved (verified and deleted)",
+        "Αρχεία πηγής που μετακινήθηκαν (ελέγχθηκαν και διαγράφηκαν)",
+    ),
+    (
+        "Source files kept (verification failed)",
+        "Αρχεία πηγής που κρατήθηκαν (ο έλεγχος απέτυχε)",
+    ),
+    ("Files failed", "Αρχεία που απέτυχαν"),
+    ("Warnings", "Προειδοποιήσεις"),
+    // Diff
+    ("DIFF SUMMARY", "ΣΥΝΟΨΗ ΔΙΑΦΟΡΩΝ"),
+    ("TO ADD", "ΓΙΑ ΠΡΟΣΘΗΚΗ"),
+    ("ALREADY EXISTS", "ΥΠΑΡΧΟΥΝ ΗΔΗ"),
+    ("CHANGED", "ΑΛΛΑΓΜΕΝΑ"),
+    ("SOURCE MISSING", "ΛΕΙΠΕΙ Η ΠΗΓΗ"),
+    ("PLACEHOLDER", "ΚΕΝΑ ΑΡΧΕΙΑ"),
+    ("DUPLICATES", "ΔΙΠΛΟΤΥΠΑ"),
+    ("TOTALS", "ΣΥΝΟΛΑ"),
+    ("files", "αρχεία"),
+    ("empty files", "κενά αρχεία"),
+    ("identical files", "ίδια αρχεία"),
+    ("{} {} in {} books", "{} {} σε {} βιβλία"),
+    ("{} files", "{} αρχεία"),
+    ("... and {} more books", "... και {} ακόμη βιβλία"),
+    ("New files to copy:", "Νέα αρχεία για αντιγραφή:"),
+    ("Already exist (skip):", "Υπάρχουν ήδη (παράλειψη):"),
+    ("Changed (update):", "Αλλαγμένα (ενημέρωση):"),
+    ("Source missing:", "Λείπει η πηγή:"),
+    ("Empty/placeholder:", "Κενά/μη ληφθέντα:"),
+    ("Duplicates (skip):", "Διπλότυπα (παράλειψη):"),
+    // Library changes
+    (
+        "No changes since the last run.",
+        "Καμία αλλαγή από την προηγούμενη εκτέλεση.",
+    ),
+    ("+ NEW", "+ ΝΕΑ"),
+    ("- REMOVED", "- ΑΦΑΙΡΕΘΗΚΑΝ"),
+    ("~ CHANGED", "~ ΑΛΛΑΓΜΕΝΑ"),
+    ("{} books", "{} βιβλία"),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_locale() {
+        assert_eq!(Language::from_locale("el_GR.UTF-8"), Some(Language::El));
+        assert_eq!(Language::from_locale("en_US"), Some(Language::En));
+        assert_eq!(Language::from_locale("C"), Some(Language::En));
+        assert_eq!(Language::from_locale("fr_FR.UTF-8"), None);
+    }
+
+    #[test]
+    fn test_translate_and_fill() {
+        assert_eq!(Language::El.translate("TOTALS"), "ΣΥΝΟΛΑ");
+        assert_eq!(Language::El.translate("Not translated"), "Not translated");
+        assert_eq!(Language::En.translate("TOTALS"), "TOTALS");
+        assert_eq!(
+            fill(
+                Language::El.translate("{} {} in {} books"),
+                &[&3, &"αρχεία", &1]
+            ),
+            "3 αρχεία σε 1 βιβλία"
+        );
+    }
+
+    #[test]
+    fn test_greek_catalog_keeps_placeholders() {
+        for (english, greek) in GREEK {
+            assert_eq!(
+                english.matches("{}").count(),
+                greek.matches("{}").count(),
+                "{:?}",
+                english
+            );
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use plist::{Dictionary, Value};
+
+/// Whether a value looks like an Amazon/Audible ASIN, e.g. "B002V1OF70"
+pub fn is_asin(value: &str) -> bool {
+    value.len() == 10
+        && value
+            .chars()
+            .all(|c| c.is_ascii_digit() || c.is_ascii_uppercase())
+}
+
+/// An ISBN-10 or ISBN-13 without hyphens or spaces, if the value is one
+pub fn normalize_isbn(value: &str) -> Option<String> {
+    let isbn: String = value.chars().filter(|c| !matches!(c, '-' | ' ')).collect();
+    let (body, last) = isbn.split_at(isbn.len().checked_sub(1)?);
+    let valid = match isbn.len() {
+        10 => {
+            body.chars().all(|c| c.is_ascii_digit())
+                && (last == "X" || last == "x" || last.chars().all(|c| c.is_ascii_digit()))
+        }
+        13 => isbn.chars().all(|c| c.is_ascii_digit()),
+        _ => false,
+    };
+    valid.then(|| isbn.to_uppercase())
+}
+
+/// Identifiers that tie a book to a store or catalog record, for exact matching
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Identifiers {
+    pub asin: Option<String>,
+    pub isbn: Option<String>,
+    /// The Apple Books store ID of a purchased book
+    pub store_id: Option<String>,
+}
+
+/// Text of a string or integer value
+fn text(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.trim().to_string()),
+        Value::Integer(i) => Some(i.to_string()),
+        _ => None,
+    }
+}
+
+/// The first value under a key containing one of `names` (ignoring case) that `valid`
+/// accepts, in the order of `dicts`
+fn find(
+    dicts: &[&Dictionary],
+    names: &[&str],
+    valid: impl Fn(&str) -> Option<String>,
+) -> Option<String> {
+    dicts.iter().find_map(|dict| {
+        dict.iter()
+            .filter(|(key, _)| {
+                let key = key.to_lowercase();
+                names.iter().any(|name| key.contains(name))
+            })
+            .filter_map(|(_, value)| text(value))
+            .find_map(|value| valid(&value))
+    })
+}
+
+/// Identifiers under keys like `isbn`, `asin` and `storeId` of a Books.plist entry and its
+/// first part
+pub fn from_plist(dicts: &[&Dictionary]) -> Identifiers {
+    Identifiers {
+        asin: find(dicts, &["asin"], |value| {
+            is_asin(value).then(|| value.to_string())
+        }),
+        isbn: find(dicts, &["isbn"], normalize_isbn),
+        store_id: find(dicts, &["storeid", "store-id", "store_id"], |value| {
+            (!value.is_empty() && value.chars().all(|c| c.is_ascii_digit()))
+                .then(|| value.to_string())
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_plist() {
+        let mut book = Dictionary::new();
+        book.insert(
+            "BKStoreID".to_string(),
+            Value::Integer(1_234_567_890.into()),
+        );
+        book.insert(
+            "isbn".to_string(),
+            Value::String("978-0-593-13520-4".to_string()),
+        );
+        let mut part = Dictionary::new();
+        part.insert("asin".to_string(), Value::String("not an asin".to_string()));
+        part.insert(
+            "AudibleASIN".to_string(),
+            Value::String("B08G9PRS1K".to_string()),
+        );
+
+        let ids = from_plist(&[&book, &part]);
+        assert_eq!(ids.asin.as_deref(), Some("B08G9PRS1K"));
+        assert_eq!(ids.isbn.as_deref(), Some("9780593135204"));
+        assert_eq!(ids.store_id.as_deref(), Some("1234567890"));
+        assert_eq!(from_plist(&[]), Identifiers::default());
+
+        assert_eq!(
+            normalize_isbn("0-8044-2957-x").as_deref(),
+            Some("080442957X")
+        );
+        assert_eq!(normalize_isbn("12345"), None);
+        assert_eq!(normalize_isbn(""), None);
+        assert!(is_asin("B002V1OF70"));
+        assert!(is_asin("1250237238"));
+        assert!(!is_asin("b002v1of70"));
+    }
+}
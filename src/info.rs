@@ -0,0 +1,193 @@
+use plist::{Dictionary, Value};
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::SystemTime;
+use time::{macros::format_description, OffsetDateTime};
+
+use crate::{format_bytes, is_placeholder_file, remap_track_path, Audiobook};
+
+fn format_date(date: SystemTime) -> String {
+    OffsetDateTime::from(date)
+        .format(format_description!(
+            "[year]-[month]-[day] [hour]:[minute]:[second] UTC"
+        ))
+        .unwrap_or_default()
+}
+
+/// What is at a track's source path
+fn describe_source(path: &Path) -> String {
+    match fs::metadata(path) {
+        Err(_) => "missing".to_string(),
+        Ok(_) if is_placeholder_file(path) => "placeholder".to_string(),
+        Ok(m) => format_bytes(m.len()),
+    }
+}
+
+/// Write a plist value under `key`, nested arrays and dictionaries indented below it
+fn write_value(out: &mut impl Write, key: &str, value: &Value, indent: usize) -> io::Result<()> {
+    let pad = "  ".repeat(indent);
+    match value {
+        Value::Array(items) => {
+            writeln!(out, "{}{}: {} items", pad, key, items.len())?;
+            for (i, item) in items.iter().enumerate() {
+                write_value(out, &format!("[{}]", i), item, indent + 1)?;
+            }
+            Ok(())
+        }
+        Value::Dictionary(dict) => {
+            writeln!(out, "{}{}:", pad, key)?;
+            for (key, value) in dict {
+                write_value(out, key, value, indent + 1)?;
+            }
+            Ok(())
+        }
+        Value::String(s) => writeln!(out, "{}{}: {:?}", pad, key, s),
+        Value::Integer(n) => writeln!(out, "{}{}: {}", pad, key, n),
+        Value::Real(n) => writeln!(out, "{}{}: {}", pad, key, n),
+        Value::Boolean(b) => writeln!(out, "{}{}: {}", pad, key, b),
+        Value::Date(d) => writeln!(out, "{}{}: {}", pad, key, format_date((*d).into())),
+        Value::Data(d) => writeln!(out, "{}{}: <{} bytes>", pad, key, d.len()),
+        _ => writeln!(out, "{}{}: {:?}", pad, key, value),
+    }
+}
+
+/// Write everything known about a book: its parsed metadata, each track with the state of
+/// its source file, and the raw Books.plist entry it was parsed from
+pub fn write_info(
+    out: &mut impl Write,
+    book: &Audiobook,
+    source_base: &Path,
+    raw: Option<&Dictionary>,
+) -> io::Result<()> {
+    let none = || "-".to_string();
+    writeln!(out, "Title:     {}", book.title)?;
+    if let Some(subtitle) = &book.subtitle {
+        writeln!(out, "Subtitle:  {}", subtitle)?;
+    }
+    writeln!(out, "Author:    {}", book.author)?;
+    writeln!(
+        out,
+        "Narrator:  {}",
+        book.narrator.clone().unwrap_or_else(none)
+    )?;
+    writeln!(
+        out,
+        "Series:    {}",
+        match (&book.series, &book.series_sequence) {
+            (Some(series), Some(sequence)) => format!("{} #{}", series, sequence),
+            (series, _) => series.clone().unwrap_or_else(none),
+        }
+    )?;
+    let details = [
+        ("Genre", &book.details.genre),
+        ("Year", &book.details.year),
+        ("Publisher", &book.details.publisher),
+        ("Copyright", &book.details.copyright),
+        ("ISBN", &book.details.isbn),
+        ("ASIN", &book.details.asin),
+        ("Store id", &book.details.store_id),
+    ];
+    for (label, value) in details {
+        if let Some(value) = value {
+            writeln!(out, "{:<10} {}", format!("{}:", label), value)?;
+        }
+    }
+    writeln!(out, "Folder id: {}", book.folder_id)?;
+    writeln!(
+        out,
+        "Added:     {}",
+        book.added.map(format_date).unwrap_or_else(none)
+    )?;
+    let missing = book.missing_tracks();
+    if !missing.is_empty() {
+        writeln!(
+            out,
+            "Missing:   {} track(s) in the numbering",
+            missing.len()
+        )?;
+    }
+
+    if let Some(description) = &book.description {
+        writeln!(out)?;
+        writeln!(out, "Description:")?;
+        for line in description.lines() {
+            writeln!(out, "  {}", line)?;
+        }
+    }
+
+    writeln!(out)?;
+    writeln!(out, "Tracks ({}):", book.tracks.len())?;
+    for track in &book.tracks {
+        let source = remap_track_path(&track.path, source_base);
+        writeln!(
+            out,
+            "  disc {:>2} track {:>3}  {}  [{}]",
+            track.disc_number,
+            track.track_number,
+            if track.title.is_empty() {
+                &track.filename
+            } else {
+                &track.title
+            },
+            describe_source(&source)
+        )?;
+        writeln!(out, "      {}", source.display())?;
+        if source != track.path {
+            writeln!(out, "      (listed as {})", track.path.display())?;
+        }
+    }
+
+    if let Some(raw) = raw {
+        writeln!(out)?;
+        writeln!(out, "Books.plist entry:")?;
+        for (key, value) in raw {
+            write_value(out, key, value, 1)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AudioTrack;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_write_info() {
+        let book = Audiobook {
+            title: "Warbreaker".to_string(),
+            author: "Brandon Sanderson".to_string(),
+            folder_id: "sha1-aaa".to_string(),
+            tracks: vec![AudioTrack {
+                track_number: 1,
+                disc_number: 1,
+                title: "Part 1".to_string(),
+                path: PathBuf::from("/Users/old/Audiobooks/sha1-aaa/01.mp3"),
+                filename: "01.mp3".to_string(),
+            }],
+            description: Some("A god returns.\n\nTo a city of colour.".to_string()),
+            ..Default::default()
+        };
+        let mut raw = Dictionary::new();
+        raw.insert("BKBookType".to_string(), Value::from("audiobook"));
+        raw.insert(
+            "BKParts".to_string(),
+            Value::Array(vec![Value::Dictionary(Dictionary::from_iter([(
+                "BKTrackNumber".to_string(),
+                Value::from(1),
+            )]))]),
+        );
+
+        let mut out = Vec::new();
+        write_info(&mut out, &book, Path::new("/src"), Some(&raw)).unwrap();
+        let info = String::from_utf8(out).unwrap();
+        assert!(info.contains("Narrator:  -\n"));
+        assert!(info.contains("Description:\n  A god returns.\n  \n  To a city of colour.\n"));
+        assert!(info.contains("  disc  1 track   1  Part 1  [missing]\n"));
+        assert!(info.contains("      /src/Audiobooks/sha1-aaa/01.mp3\n"));
+        assert!(info.contains("  BKBookType: \"audiobook\"\n"));
+        assert!(info.contains("  BKParts: 1 items\n    [0]:\n      BKTrackNumber: 1\n"));
+    }
+}
@@ -0,0 +1,268 @@
+use anyhow::{bail, Context, Result};
+use plist::{Dictionary, Value};
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::config::{self, Profile};
+use crate::BOOKS_CONTAINER;
+
+/// Label of the launchd job installed by `init`, also the name of its plist
+pub const LAUNCHD_LABEL: &str = "com.github.manos.extract_apple_books";
+
+/// Home folders that may hold an Apple Books library: the current user's, the other
+/// users' on this Mac, and those on mounted volumes, e.g. an old Mac's disk
+pub fn candidate_homes() -> Vec<PathBuf> {
+    let mut homes: Vec<_> = dirs::home_dir().into_iter().collect();
+    let users = |dir: &Path| -> Vec<PathBuf> {
+        fs::read_dir(dir)
+            .map(|entries| entries.flatten().map(|entry| entry.path()).collect())
+            .unwrap_or_default()
+    };
+    homes.extend(users(Path::new("/Users")));
+    for volume in users(Path::new("/Volumes")) {
+        homes.extend(users(&volume.join("Users")));
+    }
+    homes.dedup();
+    homes
+}
+
+/// Apple Books folders with a library under the given home folders
+pub fn detect_sources(homes: &[PathBuf]) -> Vec<PathBuf> {
+    let mut sources: Vec<PathBuf> = Vec::new();
+    for source in homes.iter().map(|home| home.join(BOOKS_CONTAINER)) {
+        if source.join("Books.plist").is_file() && !sources.contains(&source) {
+            sources.push(source);
+        }
+    }
+    sources
+}
+
+/// Print a question and read the answer, or `default` for an empty one
+pub fn ask(question: &str, default: Option<&str>) -> Result<String> {
+    let stdin = io::stdin();
+    loop {
+        match default {
+            Some(default) => print!("{} [{}] ", question, default),
+            None => print!("{} ", question),
+        }
+        let _ = io::stdout().flush();
+
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line)? == 0 {
+            bail!("No answer to {:?}", question);
+        }
+        match (line.trim(), default) {
+            ("", Some(default)) => return Ok(default.to_string()),
+            ("", None) => continue,
+            (answer, _) => return Ok(answer.to_string()),
+        }
+    }
+}
+
+/// Ask to pick one of `choices` (name and description) by number. Returns its index.
+pub fn choose(question: &str, choices: &[(&str, &str)]) -> Result<usize> {
+    println!("{}", question);
+    for (i, (name, description)) in choices.iter().enumerate() {
+        println!("  {}) {} - {}", i + 1, name, description);
+    }
+    loop {
+        let answer = ask("Choice:", Some("1"))?;
+        match answer.parse::<usize>() {
+            Ok(n) if (1..=choices.len()).contains(&n) => return Ok(n - 1),
+            _ => println!("Enter a number from 1 to {}", choices.len()),
+        }
+    }
+}
+
+pub fn confirm(question: &str) -> Result<bool> {
+    let answer = ask(&format!("{} (y/n)", question), Some("y"))?;
+    Ok(answer.to_lowercase().starts_with('y'))
+}
+
+/// Store the settings chosen in `init` in the config file, TOML or JSON, keeping any other
+/// settings already there, such as `email` or export filters
+pub fn update_config(
+    path: &Path,
+    source: Option<&Path>,
+    dest: &Path,
+    profile: Profile,
+    filenames_mode: &str,
+) -> Result<()> {
+    let toml = config::is_toml(path);
+    let mut config: serde_json::Value = if path.exists() {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config {:?}", path))?;
+        if toml {
+            toml::from_str(&text).with_context(|| format!("Invalid config file {:?}", path))?
+        } else {
+            serde_json::from_str(&text)
+                .with_context(|| format!("Invalid config file {:?}", path))?
+        }
+    } else {
+        serde_json::json!({})
+    };
+    let Some(settings) = config.as_object_mut() else {
+        bail!("Config file {:?} is not a JSON object", path);
+    };
+
+    let export = settings
+        .entry("export")
+        .or_insert_with(|| serde_json::json!({}));
+    if let Some(export) = export.as_object_mut() {
+        export.insert("dest".to_string(), serde_json::json!(dest));
+        export.insert("profile".to_string(), serde_json::json!(profile));
+        match source {
+            Some(source) => export.insert("source".to_string(), serde_json::json!(source)),
+            None => export.remove("source"),
+        };
+    }
+    let filenames = settings
+        .entry("filenames")
+        .or_insert_with(|| serde_json::json!({}));
+    if let Some(filenames) = filenames.as_object_mut() {
+        filenames.insert("mode".to_string(), serde_json::json!(filenames_mode));
+    }
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory {:?}", parent))?;
+    }
+    let text = if toml {
+        toml::to_string_pretty(&config)?
+    } else {
+        serde_json::to_string_pretty(&config)?
+    };
+    fs::write(path, text).with_context(|| format!("Failed to write {:?}", path))
+}
+
+/// A launchd job running `export` at 3:00, every day or on Sundays, logging to `log_dir`
+pub fn launch_agent(program: &Path, weekly: bool, log_dir: &Path) -> Value {
+    let mut interval = Dictionary::new();
+    interval.insert("Hour".to_string(), Value::Integer(3.into()));
+    interval.insert("Minute".to_string(), Value::Integer(0.into()));
+    if weekly {
+        interval.insert("Weekday".to_string(), Value::Integer(0.into()));
+    }
+
+    let mut job = Dictionary::new();
+    job.insert(
+        "Label".to_string(),
+        Value::String(LAUNCHD_LABEL.to_string()),
+    );
+    job.insert(
+        "ProgramArguments".to_string(),
+        Value::Array(vec![
+            Value::String(program.to_string_lossy().to_string()),
+            Value::String("export".to_string()),
+            Value::String("--plain".to_string()),
+        ]),
+    );
+    job.insert(
+        "StartCalendarInterval".to_string(),
+        Value::Dictionary(interval),
+    );
+    for (key, file) in [
+        ("StandardOutPath", "export.log"),
+        ("StandardErrorPath", "export.err"),
+    ] {
+        job.insert(
+            key.to_string(),
+            Value::String(log_dir.join(file).to_string_lossy().to_string()),
+        );
+    }
+    Value::Dictionary(job)
+}
+
+/// Install the job in `~/Library/LaunchAgents` and load it, replacing an earlier one.
+/// Returns the path of its plist.
+pub fn install_schedule(weekly: bool) -> Result<PathBuf> {
+    let home = dirs::home_dir().context("Could not determine home directory")?;
+    let program = std::env::current_exe().context("Could not determine program path")?;
+    let log_dir = home.join("Library/Logs/extract_apple_books");
+    fs::create_dir_all(&log_dir)
+        .with_context(|| format!("Failed to create directory {:?}", log_dir))?;
+
+    let agents = home.join("Library/LaunchAgents");
+    fs::create_dir_all(&agents)
+        .with_context(|| format!("Failed to create directory {:?}", agents))?;
+    let path = agents.join(format!("{}.plist", LAUNCHD_LABEL));
+    let _ = Command::new("launchctl").arg("unload").arg(&path).output();
+    launch_agent(&program, weekly, &log_dir)
+        .to_file_xml(&path)
+        .with_context(|| format!("Failed to write {:?}", path))?;
+
+    let output = Command::new("launchctl")
+        .args(["load", "-w"])
+        .arg(&path)
+        .output()
+        .context("Failed to run launchctl")?;
+    if !output.status.success() {
+        bail!(
+            "launchctl failed to load {:?}: {}",
+            path,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_detect_sources_and_update_config() {
+        let dir = tempdir().unwrap();
+        let (alice, bob) = (dir.path().join("alice"), dir.path().join("bob"));
+        fs::create_dir_all(alice.join(BOOKS_CONTAINER)).unwrap();
+        fs::write(alice.join(BOOKS_CONTAINER).join("Books.plist"), b"").unwrap();
+        fs::create_dir_all(&bob).unwrap();
+        assert_eq!(
+            detect_sources(&[alice.clone(), bob]),
+            vec![alice.join(BOOKS_CONTAINER)]
+        );
+
+        let path = dir.path().join("config/config.json");
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(
+            &path,
+            r#"{"filenames": {"replacement": "-"}, "email": {"to": "me@example.com"}}"#,
+        )
+        .unwrap();
+        update_config(
+            &path,
+            None,
+            Path::new("/Volumes/NAS/Audiobooks"),
+            Profile::Compact,
+            "lookalike",
+        )
+        .unwrap();
+
+        let config = Config::read(&path).unwrap();
+        assert_eq!(
+            config.export.dest.as_deref(),
+            Some(Path::new("/Volumes/NAS/Audiobooks"))
+        );
+        assert_eq!(config.export.profile, Profile::Compact);
+        assert_eq!(config.filenames.replacement, "-");
+        assert!(config.email.is_some());
+
+        let path = dir.path().join("config/config.toml");
+        fs::write(&path, "[export]\nflags = [\"chapters\"]\n").unwrap();
+        update_config(
+            &path,
+            None,
+            Path::new("/backup"),
+            Profile::Copy,
+            "lookalike",
+        )
+        .unwrap();
+        let config = Config::read(&path).unwrap();
+        assert_eq!(config.export.dest.as_deref(), Some(Path::new("/backup")));
+        assert_eq!(config.export.flags, ["chapters"]);
+    }
+}
@@ -0,0 +1,4192 @@
+//! Export audiobooks from Apple Books to an Audiobookshelf-compatible folder tree.
+//!
+//! The `extract_apple_books` binary is a thin command line front end to this library:
+//!
+//! ```no_run
+//! use extract_apple_books::{default_apple_books_path, export_audiobooks, parse_books_plist};
+//! use extract_apple_books::ExportOptions;
+//! use std::path::Path;
+//!
+//! let source = default_apple_books_path();
+//! let audiobooks = parse_books_plist(&source.join("Books.plist"))?;
+//! for book in &audiobooks {
+//!     println!("{} - {} ({} tracks)", book.author, book.title, book.tracks.len());
+//! }
+//!
+//! let stats = export_audiobooks(&audiobooks, &source, Path::new("/Volumes/NAS/Audiobooks"), &ExportOptions::default())?;
+//! println!("{} files copied", stats.files_copied);
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+//!
+//! Output settings that the binary takes from its command line and config file, such as
+//! colors, language and filename rules, are set once per process with `output::init`,
+//! `i18n::init`, `sanitize::init` and `subtitle::init`; without them the defaults apply.
+
+use anyhow::{bail, Context, Result};
+use clap::ValueEnum;
+use plist::Value;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use i18n::{tr, tr_args};
+use output::{paint, Color};
+use thiserror::Error;
+
+pub mod audible;
+pub mod audnexus;
+pub mod booksearch;
+pub mod bundle;
+pub mod catalog;
+pub mod chapters;
+pub mod config;
+pub mod conflict;
+pub mod cover;
+pub mod cue;
+pub mod dedup;
+pub mod dump;
+pub mod email;
+pub mod extras;
+pub mod filter;
+pub mod finder;
+pub mod hash;
+pub mod history;
+pub mod html;
+pub mod http;
+pub mod i18n;
+pub mod identifiers;
+pub mod info;
+pub mod init;
+pub mod list;
+pub mod manifest;
+pub mod markdown;
+pub mod media;
+pub mod metadata;
+pub mod narrator;
+pub mod orphans;
+pub mod output;
+pub mod overrides;
+pub mod picker;
+pub mod pipeline;
+pub mod progress;
+pub mod prune;
+pub mod quarantine;
+pub mod reconnect;
+pub mod relink;
+pub mod report;
+pub mod rollback;
+pub mod sanitize;
+pub mod series;
+pub mod snapshot;
+pub mod stats;
+pub mod subtitle;
+pub mod tags;
+pub mod template;
+pub mod transcode;
+pub mod trash;
+pub mod tui;
+pub mod validate;
+pub mod verify;
+
+#[derive(Error, Debug)]
+pub enum ExportError {
+    #[error("Books.plist not found at {0}")]
+    PlistNotFound(PathBuf),
+    #[error("Invalid plist structure: {0}")]
+    InvalidPlistStructure(String),
+    #[error("No audiobooks found in library")]
+    NoAudiobooksFound,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Audiobook {
+    pub title: String,
+    /// Split from the title at a separator in the `subtitles` config section, e.g. "A
+    /// Brief History of Humankind" from "Sapiens: A Brief History of Humankind"
+    pub subtitle: Option<String>,
+    pub author: String,
+    pub narrator: Option<String>,
+    /// From Books.plist where the store recorded it, or from titles like "Title (Series,
+    /// Book 3)"; metadata overrides can change it
+    pub series: Option<String>,
+    /// The book's number in its series, e.g. "3" or "2.5"
+    pub series_sequence: Option<String>,
+    /// The title without the series it names, e.g. "Elantris" for "Elantris (Cosmere #1)",
+    /// for the series layout
+    pub title_in_series: Option<String>,
+    /// Whether the series was detected rather than set through metadata overrides. Only
+    /// the series layout gives detected series a folder, so detecting them doesn't move
+    /// books that were already exported.
+    pub series_detected: bool,
+    pub folder_id: String,
+    pub tracks: Vec<AudioTrack>,
+    /// When the book was purchased, or imported into Apple Books if it wasn't bought there
+    #[serde(serialize_with = "dump::serialize_date")]
+    pub added: Option<SystemTime>,
+    /// From Books.plist for store purchases, otherwise merged from the Apple Books catalog
+    /// with `--check-catalog`
+    pub description: Option<String>,
+    #[serde(flatten)]
+    pub details: BookDetails,
+}
+
+/// Title of a book Books.plist has no title for
+pub const UNKNOWN_TITLE: &str = "Unknown Title";
+
+/// Author of a book Books.plist has no author for
+pub const UNKNOWN_AUTHOR: &str = "Unknown Author";
+
+/// Publication details the store records for purchased books; empty for imported ones
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct BookDetails {
+    pub genre: Option<String>,
+    /// Release year
+    pub year: Option<String>,
+    pub publisher: Option<String>,
+    pub copyright: Option<String>,
+    /// From Books.plist when it has one, otherwise found by title and author with `--enrich`
+    pub isbn: Option<String>,
+    /// Audible ASIN, from Books.plist when it has one
+    pub asin: Option<String>,
+    /// Apple Books store ID of a purchased book
+    pub store_id: Option<String>,
+}
+
+/// A track number that is absent from an otherwise numbered sequence
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MissingTrack {
+    pub disc_number: u32,
+    pub track_number: u32,
+}
+
+impl Audiobook {
+    /// Each of the book's authors, from lists like "Jane Roe & John Doe" or "Jane Roe,
+    /// John Doe"
+    pub fn authors(&self) -> Vec<String> {
+        metadata::split_names(&self.author)
+    }
+
+    /// Each of the book's narrators, from lists like "A, B and C"
+    pub fn narrators(&self) -> Vec<String> {
+        self.narrator
+            .as_deref()
+            .map(narrator::split)
+            .unwrap_or_default()
+    }
+
+    /// The title with its subtitle, as the library has it
+    pub fn full_title(&self) -> String {
+        match &self.subtitle {
+            Some(subtitle) => format!("{}: {}", self.title, subtitle),
+            None => self.title.clone(),
+        }
+    }
+
+    /// Find gaps in the track numbering of each disc (e.g. tracks 1-14 and 16-30 present
+    /// means track 15 is missing). Unnumbered tracks (number 0) are ignored.
+    pub fn missing_tracks(&self) -> Vec<MissingTrack> {
+        let mut discs: std::collections::BTreeMap<u32, Vec<u32>> =
+            std::collections::BTreeMap::new();
+        for track in self.tracks.iter().filter(|t| t.track_number > 0) {
+            discs
+                .entry(track.disc_number)
+                .or_default()
+                .push(track.track_number);
+        }
+
+        let mut missing = Vec::new();
+        for (disc_number, mut numbers) in discs {
+            numbers.sort_unstable();
+            numbers.dedup();
+            let highest = numbers.last().copied().unwrap_or(0);
+            for track_number in 1..highest {
+                if numbers.binary_search(&track_number).is_err() {
+                    missing.push(MissingTrack {
+                        disc_number,
+                        track_number,
+                    });
+                }
+            }
+        }
+
+        missing
+    }
+}
+
+/// Format missing tracks compactly, e.g. "15, 22-24" or "disc 2: 3"
+fn format_missing_tracks(missing: &[MissingTrack]) -> String {
+    let multi_disc = missing.iter().any(|m| m.disc_number > 1);
+    let mut parts: Vec<String> = Vec::new();
+    let mut i = 0;
+
+    while i < missing.len() {
+        let start = missing[i];
+        let mut end = start;
+        while i + 1 < missing.len()
+            && missing[i + 1].disc_number == start.disc_number
+            && missing[i + 1].track_number == end.track_number + 1
+        {
+            i += 1;
+            end = missing[i];
+        }
+
+        let range = if start == end {
+            start.track_number.to_string()
+        } else {
+            format!("{}-{}", start.track_number, end.track_number)
+        };
+        parts.push(if multi_disc {
+            format!("disc {}: {}", start.disc_number, range)
+        } else {
+            range
+        });
+        i += 1;
+    }
+
+    parts.join(", ")
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AudioTrack {
+    pub track_number: u32,
+    pub disc_number: u32,
+    pub title: String,
+    pub path: PathBuf,
+    pub filename: String,
+}
+
+/// Source files smaller than this are treated as iCloud placeholders or failed downloads
+pub const MIN_AUDIO_FILE_SIZE: u64 = 4096;
+
+/// Whether an existing source file is empty or too small to be real audio
+pub fn is_placeholder_file(path: &Path) -> bool {
+    fs::metadata(path)
+        .map(|m| m.is_file() && m.len() < MIN_AUDIO_FILE_SIZE)
+        .unwrap_or(false)
+}
+
+/// Where Apple Books keeps audiobooks, relative to a home folder
+pub const BOOKS_CONTAINER: &str =
+    "Library/Containers/com.apple.BKAgentService/Data/Documents/iBooks/Books";
+
+/// Get the default Apple Books path for the current user
+pub fn default_apple_books_path() -> PathBuf {
+    dirs::home_dir()
+        .unwrap_or_else(|| PathBuf::from("/"))
+        .join(BOOKS_CONTAINER)
+}
+
+/// Parse the Books.plist file and extract audiobook metadata
+pub fn parse_books_plist(plist_path: &Path) -> Result<Vec<Audiobook>> {
+    if !plist_path.exists() {
+        return Err(ExportError::PlistNotFound(plist_path.to_path_buf()).into());
+    }
+
+    let plist_value: Value = plist::from_file(plist_path)
+        .with_context(|| format!("Failed to parse plist at {:?}", plist_path))?;
+    parse_books(&plist_value)
+}
+
+/// Extract the audiobooks of an already read Books.plist
+pub fn parse_books(plist_value: &Value) -> Result<Vec<Audiobook>> {
+    let dict = plist_value
+        .as_dictionary()
+        .ok_or_else(|| ExportError::InvalidPlistStructure("Root is not a dictionary".into()))?;
+
+    let books_array = dict
+        .get("Books")
+        .and_then(|v| v.as_array())
+        .ok_or_else(|| ExportError::InvalidPlistStructure("Missing 'Books' array".into()))?;
+
+    let mut audiobooks = Vec::new();
+
+    for book_value in books_array {
+        if let Some(audiobook) = parse_audiobook_entry(book_value)? {
+            audiobooks.push(audiobook);
+        }
+    }
+
+    if audiobooks.is_empty() {
+        return Err(ExportError::NoAudiobooksFound.into());
+    }
+
+    Ok(audiobooks)
+}
+
+/// The Books.plist entry of a book as stored, with every key Apple Books wrote for it
+pub fn raw_plist_entry(plist_path: &Path, folder_id: &str) -> Result<Option<plist::Dictionary>> {
+    let plist_value: Value = plist::from_file(plist_path)
+        .with_context(|| format!("Failed to parse plist at {:?}", plist_path))?;
+    let books = plist_value
+        .as_dictionary()
+        .and_then(|dict| dict.get("Books"))
+        .and_then(|v| v.as_array())
+        .ok_or_else(|| ExportError::InvalidPlistStructure("Missing 'Books' array".into()))?;
+    Ok(books
+        .iter()
+        .filter_map(|book| book.as_dictionary())
+        .find(|dict| dict.get("BKGeneratedItemId").and_then(|v| v.as_string()) == Some(folder_id))
+        .cloned())
+}
+
+/// Store descriptions come as HTML: keep the text, with paragraphs and line breaks as
+/// newlines
+fn plain_text(html: &str) -> String {
+    let mut text = String::new();
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        text.push_str(&rest[..start]);
+        let Some(end) = rest[start..].find('>') else {
+            rest = &rest[start..];
+            break;
+        };
+        let tag = rest[start + 1..start + end].trim().to_lowercase();
+        if tag.starts_with("br") || tag == "/p" || tag.starts_with("p ") || tag == "p" {
+            text.push('\n');
+        }
+        rest = &rest[start + end + 1..];
+    }
+    text.push_str(rest);
+    let text = text
+        .replace("&nbsp;", " ")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&");
+    let lines: Vec<_> = text.lines().map(str::trim).collect();
+    lines
+        .split(|line| line.is_empty())
+        .filter(|paragraph| !paragraph.is_empty())
+        .map(|paragraph| paragraph.join("\n"))
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// Parse a single audiobook entry from the plist
+fn parse_audiobook_entry(value: &Value) -> Result<Option<Audiobook>> {
+    let dict = match value.as_dictionary() {
+        Some(d) => d,
+        None => return Ok(None),
+    };
+
+    // Check if this is an audiobook
+    let book_type = dict
+        .get("BKBookType")
+        .and_then(|v| v.as_string())
+        .unwrap_or("");
+
+    if book_type != "audiobook" {
+        return Ok(None);
+    }
+
+    let folder_id = dict
+        .get("BKGeneratedItemId")
+        .and_then(|v| v.as_string())
+        .unwrap_or("")
+        .to_string();
+
+    let author = dict
+        .get("artistName")
+        .and_then(|v| v.as_string())
+        .filter(|s| !s.trim().is_empty())
+        .unwrap_or(UNKNOWN_AUTHOR)
+        .to_string();
+
+    let added = ["BKPurchaseDate", "BKInsertionDate"]
+        .iter()
+        .find_map(|key| dict.get(key).and_then(|v| v.as_date()))
+        .map(SystemTime::from);
+
+    // Parse tracks to get title and other metadata
+    let parts = dict.get("BKParts").and_then(|v| v.as_array());
+
+    // Store purchases carry a description and publication details on the book or on its
+    // first part
+    let first_part = parts
+        .and_then(|p| p.first())
+        .and_then(|p| p.as_dictionary());
+    let text = |keys: &[&str]| {
+        std::iter::once(dict)
+            .chain(first_part)
+            .flat_map(|d| keys.iter().filter_map(|key| d.get(key)))
+            .find_map(|v| match v {
+                Value::String(s) => Some(s.trim().to_string()),
+                Value::Integer(i) => Some(i.to_string()),
+                Value::Date(date) => Some(
+                    time::OffsetDateTime::from(SystemTime::from(*date))
+                        .year()
+                        .to_string(),
+                ),
+                _ => None,
+            })
+            .filter(|s| !s.is_empty())
+    };
+    let ids = identifiers::from_plist(&std::iter::once(dict).chain(first_part).collect::<Vec<_>>());
+    let description = text(&["longDescription", "long-description", "description"])
+        .map(|d| plain_text(&d))
+        .filter(|d| !d.is_empty());
+    let details = BookDetails {
+        genre: text(&["genre"]),
+        year: text(&["year", "releaseDate"])
+            .and_then(|year| year.get(..4).map(str::to_string))
+            .filter(|year| year.chars().all(|c| c.is_ascii_digit())),
+        publisher: text(&["publisher", "publisherName"]),
+        copyright: text(&["copyright"]),
+        isbn: ids.isbn,
+        asin: ids.asin,
+        store_id: ids.store_id,
+    };
+
+    let mut tracks = Vec::new();
+    let mut title = String::new();
+    let mut narrator: Option<String> = None;
+
+    if let Some(parts_array) = parts {
+        for part_value in parts_array {
+            if let Some(part_dict) = part_value.as_dictionary() {
+                // Get title from first track if not set
+                if title.is_empty() {
+                    title = part_dict
+                        .get("itemName")
+                        .and_then(|v| v.as_string())
+                        .filter(|s| !s.trim().is_empty())
+                        .unwrap_or(UNKNOWN_TITLE)
+                        .to_string();
+                }
+
+                // Try to get narrator from composer field (common in audiobooks)
+                if narrator.is_none() {
+                    narrator = part_dict
+                        .get("composer")
+                        .and_then(|v| v.as_string())
+                        .map(|s| s.to_string());
+                }
+
+                let track_number = part_dict
+                    .get("BKTrackNumber")
+                    .and_then(|v| v.as_unsigned_integer())
+                    .unwrap_or(0) as u32;
+
+                let disc_number = part_dict
+                    .get("BKDiscNumber")
+                    .and_then(|v| v.as_unsigned_integer())
+                    .unwrap_or(0) as u32;
+
+                let track_title = part_dict
+                    .get("BKTrackTitle")
+                    .and_then(|v| v.as_string())
+                    .unwrap_or("")
+                    .to_string();
+
+                let path_str = part_dict
+                    .get("path")
+                    .and_then(|v| v.as_string())
+                    .unwrap_or("");
+
+                if !path_str.is_empty() {
+                    let path = PathBuf::from(path_str);
+                    let filename = path
+                        .file_name()
+                        .map(|s| s.to_string_lossy().to_string())
+                        .unwrap_or_default();
+
+                    tracks.push(AudioTrack {
+                        track_number,
+                        disc_number,
+                        title: track_title,
+                        path,
+                        filename,
+                    });
+                }
+            }
+        }
+    }
+
+    // Sort tracks by disc number then track number
+    tracks.sort_by(|a, b| {
+        a.disc_number
+            .cmp(&b.disc_number)
+            .then(a.track_number.cmp(&b.track_number))
+    });
+
+    if title.is_empty() || tracks.is_empty() {
+        return Ok(None);
+    }
+
+    // Narrator keys on the book win over the composer of its tracks, and either over a
+    // "Narrated by" note in the title, which is dropped from the title in any case
+    let mut narrator = text(narrator::NARRATOR_KEYS).or(narrator);
+    if let Some((without_note, named)) = narrator::from_title(&title) {
+        title = without_note;
+        narrator = narrator.or(Some(named));
+    }
+
+    let mut series = text(&["seriesName", "series"]);
+    let mut series_sequence = text(&["seriesNumber", "seriesSequence", "seriesPosition"])
+        .map(|sequence| series::normalize_sequence(&sequence));
+    let mut title_in_series = None;
+    if series.is_none() {
+        if let Some(found) = series::from_title(&title) {
+            title_in_series = Some(found.title);
+            series = Some(found.series);
+            series_sequence = Some(found.sequence);
+        }
+    }
+    if series.is_none() {
+        series_sequence = None;
+    }
+    let series_detected = series.is_some();
+    let (title, subtitle) = subtitle::current().split(&title);
+
+    Ok(Some(Audiobook {
+        title,
+        subtitle,
+        author,
+        narrator,
+        series,
+        series_sequence,
+        title_in_series,
+        series_detected,
+        folder_id,
+        tracks,
+        added,
+        description,
+        details,
+    }))
+}
+
+/// Sanitize a string for use as a filename/directory name
+fn sanitize_filename(name: &str) -> String {
+    sanitize::current().sanitize(name)
+}
+
+/// Replace a leading track number in a filename with a continuous sequence number,
+/// e.g. disc 2's "01 Part.mp3" becomes "15 Part.mp3"
+fn renumbered_filename(filename: &str, sequence: usize, total: usize) -> String {
+    let path = Path::new(filename);
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let extension = path
+        .extension()
+        .map(|e| format!(".{}", e.to_string_lossy()))
+        .unwrap_or_default();
+
+    // A track number is up to three digits, alone or before a separator, so titles
+    // starting with a number ("1984 Part 1") keep it
+    let digits = stem.len() - stem.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    let numbered = (1..=3).contains(&digits)
+        && stem[digits..]
+            .chars()
+            .next()
+            .is_none_or(|c| matches!(c, ' ' | '-' | '_' | '.'));
+    let rest = if numbered {
+        stem[digits..].trim_start_matches([' ', '-', '_', '.'])
+    } else {
+        stem.as_str()
+    };
+    let width = total.to_string().len().max(2);
+
+    if rest.is_empty() {
+        format!("{:0width$}{}", sequence, extension, width = width)
+    } else {
+        format!("{:0width$} {}{}", sequence, rest, extension, width = width)
+    }
+}
+
+/// Which of a book's authors its author folder is named after
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum AuthorFolder {
+    /// The authors as the library lists them
+    #[default]
+    Listed,
+    /// The first author
+    First,
+    /// All authors, joined with " & "
+    All,
+    /// "Various" for books with more than one author
+    Various,
+}
+
+/// How author names are written in author folders
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum AuthorFormat {
+    /// "Brandon Sanderson", as the library has them
+    #[default]
+    FirstLast,
+    /// "Sanderson, Brandon"
+    LastFirst,
+}
+
+/// Words that belong to the last name when they come before it, as in "Ursula K. Le Guin"
+const NAME_PARTICLES: &[&str] = &[
+    "da", "de", "del", "della", "der", "di", "du", "la", "le", "van", "von",
+];
+
+/// Words after the last name that aren't part of it, as in "Martin Luther King Jr."
+const NAME_SUFFIXES: &[&str] = &["jr", "jr.", "sr", "sr.", "ii", "iii", "iv"];
+
+/// "Brandon Sanderson" as "Sanderson, Brandon". Single names and names that already
+/// have a comma are kept as they are.
+pub fn last_first(name: &str) -> String {
+    let mut words: Vec<_> = name.split_whitespace().collect();
+    if name.contains(',') || words.len() < 2 {
+        return name.trim().to_string();
+    }
+    let suffix = match words.last() {
+        Some(last) if NAME_SUFFIXES.contains(&last.to_lowercase().as_str()) => words.pop(),
+        _ => None,
+    };
+    let mut split = words.len() - 1;
+    while split > 1 && NAME_PARTICLES.contains(&words[split - 1].to_lowercase().as_str()) {
+        split -= 1;
+    }
+    let mut formatted = format!("{}, {}", words[split..].join(" "), words[..split].join(" "));
+    if let Some(suffix) = suffix {
+        formatted = format!("{}, {}", formatted, suffix);
+    }
+    formatted
+}
+
+impl AuthorFolder {
+    /// The author folder name of a book, before sanitizing
+    pub fn name(self, audiobook: &Audiobook, format: AuthorFormat) -> String {
+        let mut authors = audiobook.authors();
+        if format == AuthorFormat::LastFirst {
+            authors = authors.iter().map(|author| last_first(author)).collect();
+        }
+        match self {
+            _ if authors.is_empty() => audiobook.author.clone(),
+            // Commas would be ambiguous between inverted names
+            AuthorFolder::Listed if format == AuthorFormat::LastFirst => authors.join(" & "),
+            AuthorFolder::Listed => audiobook.author.clone(),
+            AuthorFolder::First => authors[0].clone(),
+            AuthorFolder::All => authors.join(" & "),
+            AuthorFolder::Various if authors.len() > 1 => "Various".to_string(),
+            AuthorFolder::Various => authors[0].clone(),
+        }
+    }
+}
+
+/// Create Audiobookshelf-compatible folder name for an audiobook
+/// Format: Author/Title {Narrator} or Author/Title, and Author/Series/Title {Narrator} for
+/// series set through metadata overrides, unless `options.template` lays it out
+fn create_audiobookshelf_path(
+    dest: &Path,
+    audiobook: &Audiobook,
+    options: &ExportOptions,
+) -> PathBuf {
+    if let Some(template) = &options.template {
+        return dest.join(template.render(audiobook, options));
+    }
+
+    let author_dir =
+        sanitize_filename(&options.author_folder.name(audiobook, options.author_format));
+
+    let mut title_dir = if subtitle::current().in_folder_names {
+        sanitize_filename(&audiobook.full_title())
+    } else {
+        sanitize_filename(&audiobook.title)
+    };
+    let narrators = audiobook.narrators();
+    if !narrators.is_empty() {
+        title_dir = format!(
+            "{} {{{}}}",
+            title_dir,
+            sanitize_filename(&narrators.join(", "))
+        );
+    }
+
+    match &audiobook.series {
+        Some(series) if !audiobook.series_detected => dest
+            .join(author_dir)
+            .join(sanitize_filename(series))
+            .join(title_dir),
+        _ => dest.join(author_dir).join(title_dir),
+    }
+}
+
+/// Remap the source path in a track to use the actual source base path
+/// The plist contains paths like /Users/charlie/Library/... but we might be reading from /Volumes/charlie/Library/...
+pub fn remap_track_path(track_path: &Path, source_base: &Path) -> PathBuf {
+    // Extract the relative path after "Audiobooks/" (the sha1 folder and filename)
+    let path_str = track_path.to_string_lossy();
+
+    if let Some(idx) = path_str.find("Audiobooks/") {
+        // Get just the part starting from "Audiobooks/"
+        let relative = &path_str[idx..];
+        source_base.join(relative)
+    } else {
+        // Fallback: try to find just the audiobook folder and filename
+        let components: Vec<_> = track_path.components().collect();
+        if components.len() >= 2 {
+            // Get the sha1-xxx folder and filename
+            let folder = components[components.len() - 2]
+                .as_os_str()
+                .to_string_lossy();
+            let filename = components[components.len() - 1]
+                .as_os_str()
+                .to_string_lossy();
+
+            if folder.starts_with("sha1-") {
+                return source_base
+                    .join("Audiobooks")
+                    .join(folder.as_ref())
+                    .join(filename.as_ref());
+            }
+        }
+        track_path.to_path_buf()
+    }
+}
+
+/// Options controlling how audiobooks are exported
+#[derive(Debug, Clone, Default)]
+pub struct ExportOptions {
+    pub dry_run: bool,
+    pub use_symlink: bool,
+    /// Which of a book's authors names its author folder
+    pub author_folder: AuthorFolder,
+    /// How author names are written in author folders
+    pub author_format: AuthorFormat,
+    /// Layout of book folders under the destination, instead of `Author/Title {Narrator}`
+    pub template: Option<template::PathTemplate>,
+    /// Filenames of exported tracks, instead of the source filenames
+    pub track_template: Option<template::TrackTemplate>,
+    pub write_chapters: bool,
+    /// Chapters looked up on Audnexus, by folder id, written to `chapters.json` instead of
+    /// one chapter per track when they fit the book's audio
+    pub enriched_chapters: HashMap<String, Vec<chapters::Chapter>>,
+    /// Write an Audiobookshelf `metadata.json` into each book folder
+    pub write_metadata: bool,
+    /// Write the book's artwork as `cover.jpg` into each book folder
+    pub write_cover: bool,
+    /// Embed the book's cover image into each exported track, for players that ignore
+    /// `cover.jpg`
+    pub embed_cover: bool,
+    /// Write an OPF `metadata.opf` into each book folder
+    pub write_opf: bool,
+    /// Write a CUE sheet of the book's tracks into each book folder
+    pub write_cue: bool,
+    /// Write a Kodi/Jellyfin `album.nfo` into each book folder
+    pub write_nfo: bool,
+    pub split_chapters: bool,
+    pub transcode: Option<transcode::TranscodeTarget>,
+    pub jobs: usize,
+    /// Threads hashing source files ahead of the transfers
+    pub hash_workers: usize,
+    /// Files transferred to the destination at once
+    pub copy_workers: usize,
+    /// Threads checking copies against their source before a move
+    pub verify_workers: usize,
+    /// Audible activation bytes, to convert `.aax` files instead of skipping them
+    pub activation_bytes: Option<String>,
+    /// Integrated loudness target in LUFS when normalizing exported copies
+    pub normalize: Option<f64>,
+    pub fix_tags: bool,
+    /// With `fix_tags`, also rewrite the tags of copies already in the destination, so
+    /// corrected metadata reaches books exported before
+    pub retag: bool,
+    pub strip_personal_tags: bool,
+    /// Label book folders with Finder tags (genre) and comments (narrator)
+    pub finder_tags: bool,
+    /// Date book folders with the purchase date
+    pub folder_dates: bool,
+    /// Remove quarantine attributes from exported copies
+    pub strip_quarantine: bool,
+    /// Snapshot the destination before exporting
+    pub snapshot: bool,
+    /// Copy non-audio files found in book folders into `extras/`
+    pub extras: bool,
+    pub renumber: bool,
+    /// Delete source files once their copy has been verified
+    pub move_sources: bool,
+    /// How removed files are disposed of (Trash, quarantine folder, or permanent)
+    pub disposal: trash::Disposal,
+    pub on_conflict: conflict::ConflictStrategy,
+    pub checksum: bool,
+    pub full: bool,
+    /// Folder ids of the books to export; all books when empty
+    pub book_ids: Vec<String>,
+    /// Author and title patterns the books to export must match
+    pub filter: filter::BookFilter,
+    /// Books to pass over, in author and title order, before exporting any
+    pub skip: usize,
+    /// Most books to export
+    pub limit: Option<usize>,
+    /// How long an unattended run waits for a disconnected destination to come back
+    pub reconnect_timeout: std::time::Duration,
+    /// Where to report how far the export has come, for frontends without progress bars
+    pub progress: Option<std::sync::mpsc::Sender<ExportProgress>>,
+}
+
+/// How far an export has come through one of its phases, sent on
+/// [`ExportOptions::progress`] as each book or file is done
+#[derive(Debug, Clone)]
+pub struct ExportProgress {
+    /// The phase, named as on its progress bar, e.g. "Copying"
+    pub phase: &'static str,
+    pub done: usize,
+    pub total: usize,
+    /// The book being worked on, as "Author - Title"
+    pub book: String,
+}
+
+impl ExportOptions {
+    /// Report progress, if anyone asked for it
+    fn report(&self, phase: &'static str, done: usize, total: usize, book: &Audiobook) {
+        if let Some(progress) = &self.progress {
+            let _ = progress.send(ExportProgress {
+                phase,
+                done,
+                total,
+                book: format!("{} - {}", book.author, book.title),
+            });
+        }
+    }
+
+    /// Whether a book is selected for export
+    pub fn selects(&self, audiobook: &Audiobook) -> bool {
+        (self.book_ids.is_empty() || self.book_ids.contains(&audiobook.folder_id))
+            && self.filter.matches(audiobook)
+    }
+
+    /// The books to export. With `skip` or `limit`, the books are sorted by author and
+    /// title, as `list` shows them, and only that slice of them is kept.
+    pub fn selected<'a>(&self, audiobooks: &'a [Audiobook]) -> Vec<&'a Audiobook> {
+        let mut selected: Vec<_> = audiobooks.iter().filter(|b| self.selects(b)).collect();
+        if self.skip > 0 || self.limit.is_some() {
+            selected.sort_by_cached_key(|b| (b.author.to_lowercase(), b.title.to_lowercase()));
+            selected = selected
+                .into_iter()
+                .skip(self.skip)
+                .take(self.limit.unwrap_or(usize::MAX))
+                .collect();
+        }
+        selected
+    }
+
+    /// Filename the `index`-th track (0-based, in sorted order) of a book will have at the
+    /// destination
+    pub fn dest_filename(&self, audiobook: &Audiobook, index: usize) -> String {
+        self.dest_filenames(audiobook).swap_remove(index)
+    }
+
+    /// Filenames a book's tracks will have at the destination, in sorted order. Names that
+    /// would collide, ignoring case as macOS does (e.g. a track template without the disc
+    /// on a book that numbers tracks per disc), get " 2", " 3", ... appended to later ones.
+    pub fn dest_filenames(&self, audiobook: &Audiobook) -> Vec<String> {
+        let mut taken = HashSet::new();
+        (0..audiobook.tracks.len())
+            .map(|index| {
+                let filename = self.track_filename(audiobook, index);
+                let path = Path::new(&filename);
+                let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+                let extension = path
+                    .extension()
+                    .map(|e| format!(".{}", e.to_string_lossy()))
+                    .unwrap_or_default();
+                let mut candidate = filename.clone();
+                let mut n = 2;
+                while !taken.insert(candidate.to_lowercase()) {
+                    candidate = format!("{} {}{}", stem, n, extension);
+                    n += 1;
+                }
+                candidate
+            })
+            .collect()
+    }
+
+    /// Filename of one track at the destination, before names are made unique
+    fn track_filename(&self, audiobook: &Audiobook, index: usize) -> String {
+        let track = &audiobook.tracks[index];
+        let filename = if let Some(template) = &self.track_template {
+            template.render(audiobook, index, self, self.renumber)
+        } else if self.renumber {
+            renumbered_filename(&track.filename, index + 1, audiobook.tracks.len())
+        } else {
+            track.filename.clone()
+        };
+
+        if self.converts(Path::new(&filename)) {
+            return audible::output_filename(&filename);
+        }
+        match &self.transcode {
+            Some(target) => target.output_filename(&filename),
+            None => filename,
+        }
+    }
+
+    /// Whether a source file is an Audible file that gets converted rather than copied
+    fn converts(&self, source: &Path) -> bool {
+        self.activation_bytes.is_some() && audible::is_convertible(source)
+    }
+
+    /// How existing destination files are checked for upstream changes
+    fn change_check(&self) -> conflict::ChangeCheck {
+        conflict::ChangeCheck {
+            plain_copy: self.transcode.is_none()
+                && self.normalize.is_none()
+                && !self.rewrites_tags()
+                && !self.use_symlink,
+            checksum: self.checksum,
+        }
+    }
+
+    /// Options that affect what ends up in the destination. A manifest written with a
+    /// different profile can't vouch for the current run's output.
+    fn output_profile(&self) -> String {
+        format!(
+            "{:?}",
+            (
+                self.use_symlink,
+                (
+                    self.write_chapters,
+                    self.write_metadata,
+                    self.write_cover,
+                    self.write_opf,
+                    self.embed_cover,
+                    self.write_cue,
+                    self.write_nfo
+                ),
+                self.split_chapters,
+                &self.transcode,
+                self.normalize,
+                self.fix_tags,
+                self.strip_personal_tags,
+                self.renumber,
+                self.move_sources,
+                (
+                    self.finder_tags,
+                    self.folder_dates,
+                    self.strip_quarantine,
+                    self.extras
+                ),
+                sanitize::current(),
+            )
+        )
+    }
+
+    /// Whether exported copies need a tag-rewriting pass
+    fn rewrites_tags(&self) -> bool {
+        self.fix_tags || self.strip_personal_tags || self.renumber || self.embed_cover
+    }
+}
+
+/// Probe track durations and write chapters.json for a book, with `enriched` chapters if
+/// they fit its audio
+fn write_book_chapters(
+    audiobook: &Audiobook,
+    source_base: &Path,
+    dest_dir: &Path,
+    enriched: Option<&[chapters::Chapter]>,
+) -> Result<()> {
+    let mut timed_tracks = Vec::with_capacity(audiobook.tracks.len());
+    for track in &audiobook.tracks {
+        let source_path = remap_track_path(&track.path, source_base);
+        let duration = media::probe_duration(&source_path)?;
+        timed_tracks.push((track, duration));
+    }
+
+    let duration = timed_tracks.iter().map(|(_, d)| d).sum();
+    match enriched.filter(|chapters| audnexus::chapters_fit(chapters, duration)) {
+        Some(enriched) => chapters::write_chapters_file(dest_dir, enriched),
+        None => chapters::write_chapters_file(dest_dir, &chapters::build_chapters(&timed_tracks)),
+    }
+}
+
+/// A file on its way through the hash, transfer and verify stages of an export
+struct FileJob<'a> {
+    book: &'a Audiobook,
+    /// Position of the book in the run's book results
+    result: usize,
+    source: PathBuf,
+    dest: PathBuf,
+    source_size: u64,
+    /// Already at the destination and kept; only hashed, and verified before a move
+    existing: bool,
+    source_sha256: Option<std::result::Result<String, String>>,
+    /// `None` if the file wasn't transferred: existing, or the destination disconnected
+    transferred: Option<std::result::Result<(), String>>,
+    transfer_time: std::time::Duration,
+    normalized: Option<std::result::Result<(), String>>,
+    /// Whether the destination file matches the source hash, checked before a move
+    verified: Option<std::result::Result<bool, String>>,
+}
+
+impl<'a> FileJob<'a> {
+    fn new(book: &'a Audiobook, result: usize, source: PathBuf, dest: PathBuf) -> Self {
+        FileJob {
+            book,
+            result,
+            source_size: fs::metadata(&source).map(|m| m.len()).unwrap_or(0),
+            source,
+            dest,
+            existing: false,
+            source_sha256: None,
+            transferred: None,
+            transfer_time: std::time::Duration::ZERO,
+            normalized: None,
+            verified: None,
+        }
+    }
+}
+
+/// Write a file through a `.partial` file renamed into place. An existing file is replaced
+/// rather than written through, so the copy a snapshot hardlinked keeps its contents, and
+/// an interrupted write leaves it intact.
+pub fn write_file(path: &Path, contents: impl AsRef<[u8]>) -> Result<()> {
+    let extension = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let partial = path.with_extension(format!("partial.{}", extension));
+    if let Err(e) = fs::write(&partial, contents) {
+        let _ = fs::remove_file(&partial);
+        return Err(e).with_context(|| format!("Failed to write {:?}", path));
+    }
+    fs::rename(&partial, path).with_context(|| format!("Failed to move {:?} into place", path))
+}
+
+/// Copy (or symlink) a single source file to its destination
+fn transfer_file(source_path: &Path, dest_path: &Path, use_symlink: bool) -> Result<()> {
+    if use_symlink {
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(source_path, dest_path).with_context(|| {
+                format!("Failed to symlink {:?} -> {:?}", source_path, dest_path)
+            })?;
+            return Ok(());
+        }
+    }
+
+    fs::copy(source_path, dest_path)
+        .with_context(|| format!("Failed to copy {:?} -> {:?}", source_path, dest_path))?;
+    Ok(())
+}
+
+/// Date a book folder: its modification time and, on macOS, its creation time
+fn set_folder_date(dir: &Path, date: SystemTime) -> Result<()> {
+    let times = fs::FileTimes::new().set_accessed(date).set_modified(date);
+    #[cfg(target_os = "macos")]
+    let times = {
+        use std::os::macos::fs::FileTimesExt;
+        times.set_created(date)
+    };
+    fs::File::open(dir)
+        .and_then(|folder| folder.set_times(times))
+        .with_context(|| format!("Failed to set the date of {:?}", dir))
+}
+
+/// File in a book folder that Audiobookshelf reads the book's description from
+const DESCRIPTION_FILE: &str = "desc.txt";
+
+/// File in a book folder that Audiobookshelf reads the book's narrators from
+const READER_FILE: &str = "reader.txt";
+
+/// The companion text files of a book, with what they hold, and their text if it has one
+fn companions(audiobook: &Audiobook) -> [(&'static str, &'static str, Option<String>); 2] {
+    let narrators = Some(audiobook.narrators().join(", ")).filter(|n| !n.is_empty());
+    [
+        (
+            DESCRIPTION_FILE,
+            "description",
+            audiobook.description.clone(),
+        ),
+        (READER_FILE, "narrator", narrators),
+    ]
+}
+
+/// Write a companion text file Audiobookshelf reads into a book folder, unless it's already
+/// there
+fn write_companion(dest_dir: &Path, name: &str, text: &str) -> Result<()> {
+    let path = dest_dir.join(name);
+    if fs::read_to_string(&path).is_ok_and(|existing| existing == text) {
+        return Ok(());
+    }
+    fs::create_dir_all(dest_dir)
+        .with_context(|| format!("Failed to create directory {:?}", dest_dir))?;
+    write_file(&path, text)
+}
+
+/// Split a single-file chaptered book into per-chapter files.
+/// Returns `Ok(None)` when the book is not a candidate and should be copied normally.
+fn split_book(audiobook: &Audiobook, source_base: &Path, dest_dir: &Path) -> Result<Option<usize>> {
+    let [track] = audiobook.tracks.as_slice() else {
+        return Ok(None);
+    };
+
+    let source_path = remap_track_path(&track.path, source_base);
+    if !chapters::is_splittable(&source_path) || !source_path.exists() {
+        return Ok(None);
+    }
+
+    match chapters::split_into_chapter_files(&source_path, dest_dir)? {
+        0 => Ok(None),
+        written => Ok(Some(written)),
+    }
+}
+
+/// Export audiobooks to the destination directory
+pub fn export_audiobooks(
+    audiobooks: &[Audiobook],
+    source_base: &Path,
+    dest: &Path,
+    options: &ExportOptions,
+) -> Result<ExportStats> {
+    let dry_run = options.dry_run;
+    let use_symlink = options.use_symlink;
+    let mut stats = ExportStats::default();
+    let mut transcode_jobs = Vec::new();
+    let mut transcode_books = Vec::new();
+    let mut tag_jobs = Vec::new();
+    let mut file_jobs = Vec::new();
+    let mut prompter = conflict::Prompter::default();
+
+    // Only the default update strategy can trust the manifest: the others act on
+    // existing destination files, which the manifest lets us skip
+    let manifest_path = manifest::manifest_path(dest);
+    let previous = if dry_run {
+        manifest::Manifest::default()
+    } else {
+        manifest::Manifest::read(&manifest_path)
+    };
+    // Books the manifest records as current still need retagging with --retag
+    let use_manifest = !options.full
+        && !options.retag
+        && options.on_conflict == conflict::ConflictStrategy::Update;
+    let mut manifest = manifest::Manifest {
+        profile: options.output_profile(),
+        root: dest.to_path_buf(),
+        plain_copy: options.change_check().plain_copy,
+        tool_version: env!("CARGO_PKG_VERSION").to_string(),
+        ..Default::default()
+    };
+    let mut exported_books = Vec::new();
+    let mut book_results = Vec::new();
+    let mut dated_dirs = Vec::new();
+    let mut quarantined_dirs = Vec::new();
+
+    if !dry_run {
+        fs::create_dir_all(dest)
+            .with_context(|| format!("Failed to create directory {:?}", dest))?;
+    }
+    if options.snapshot && !dry_run {
+        let id = rollback::create(dest)?;
+        output::message(format_args!("Snapshot {} taken of {:?}", id, dest));
+        if let Err(e) = rollback::prune(dest, rollback::KEEP_SNAPSHOTS) {
+            stats.warn(format!("Could not remove old snapshots: {:#}", e));
+        }
+    }
+    let destination = reconnect::Destination::new(dest);
+    let mut disconnected = false;
+
+    let selected = options.selected(audiobooks);
+    let selected_ids: HashSet<_> = selected.iter().map(|b| &b.folder_id).collect();
+    for audiobook in audiobooks
+        .iter()
+        .filter(|b| !selected_ids.contains(&b.folder_id))
+    {
+        if let Some(entry) = previous.books.get(&audiobook.folder_id) {
+            manifest
+                .books
+                .insert(audiobook.folder_id.clone(), entry.clone());
+        }
+    }
+
+    // Books are scanned first: conflicts resolved (asking if need be) and files queued. The
+    // queued files then go through the hash, transfer and verify stages concurrently.
+    let pb = progress::bar(selected.len(), "Preparing");
+    // Destination folders by lowercased path, as most Mac volumes ignore case
+    let mut book_dirs: std::collections::HashMap<String, &Audiobook> =
+        std::collections::HashMap::new();
+
+    let total = selected.len();
+    for (prepared, audiobook) in selected.into_iter().enumerate() {
+        pb.set_message(format!("{} - {}", audiobook.author, audiobook.title));
+        options.report("Preparing", prepared, total, audiobook);
+        if !dry_run
+            && !destination.is_available()
+            && !pb.suspend(|| destination.wait(options.reconnect_timeout))
+        {
+            disconnected = true;
+            break;
+        }
+
+        let dest_dir = create_audiobookshelf_path(dest, audiobook, options);
+        let dir_key = dest_dir.to_string_lossy().to_lowercase();
+        if let Some(other) = book_dirs.insert(dir_key, audiobook) {
+            stats.warn(format!(
+                "{} ({}) and {} ({}) both export to {:?}",
+                other.title, other.folder_id, audiobook.title, audiobook.folder_id, dest_dir
+            ));
+        }
+        let source_paths: Vec<_> = audiobook
+            .tracks
+            .iter()
+            .map(|t| remap_track_path(&t.path, source_base))
+            .collect();
+        let dest_filenames = options.dest_filenames(audiobook);
+        let planned: Vec<_> = source_paths
+            .iter()
+            .zip(&dest_filenames)
+            .map(|(source, filename)| (source.clone(), dest_dir.join(filename)))
+            .collect();
+
+        for (name, what, text) in companions(audiobook) {
+            let Some(text) = text.as_deref().filter(|_| !dry_run) else {
+                continue;
+            };
+            if let Err(e) = write_companion(&dest_dir, name, text) {
+                stats.warn(format!(
+                    "Could not write the {} of {}: {:#}",
+                    what, audiobook.title, e
+                ));
+            }
+        }
+
+        if use_manifest {
+            if let Some(entry) = previous.current_entry(&manifest.profile, &audiobook.folder_id) {
+                if entry.is_current(&planned) {
+                    manifest
+                        .books
+                        .insert(audiobook.folder_id.clone(), entry.clone());
+                    stats.books_unchanged += 1;
+                    pb.inc(1);
+                    continue;
+                }
+            }
+        }
+
+        if !dry_run {
+            fs::create_dir_all(&dest_dir)
+                .with_context(|| format!("Failed to create directory {:?}", dest_dir))?;
+            if let Some(added) = audiobook.added.filter(|_| options.folder_dates) {
+                dated_dirs.push((dest_dir.clone(), added));
+            }
+            if options.strip_quarantine {
+                quarantined_dirs.push(dest_dir.clone());
+            }
+        }
+
+        if let Some(book_dir) = source_paths.first().and_then(|path| path.parent()) {
+            for extra in extras::find(book_dir, &source_paths) {
+                let (source, dest) = (
+                    book_dir.join(&extra),
+                    dest_dir.join(extras::EXTRAS_DIR).join(&extra),
+                );
+                if options.extras && !dry_run && !dest.exists() {
+                    let copied = dest
+                        .parent()
+                        .map_or(Ok(()), fs::create_dir_all)
+                        .map_err(anyhow::Error::from)
+                        .and_then(|()| transfer_file(&source, &dest, use_symlink));
+                    match copied {
+                        Ok(()) => stats.extras_copied += 1,
+                        Err(e) => {
+                            stats.warn(format!("Could not export extra {:?}: {:#}", source, e))
+                        }
+                    }
+                }
+                stats.extras.push(source);
+            }
+        }
+
+        if options.split_chapters && !dry_run {
+            match split_book(audiobook, source_base, &dest_dir) {
+                Ok(Some(written)) => {
+                    stats.chapter_files_split += written;
+                    stats.books_exported += 1;
+                    pb.inc(1);
+                    continue;
+                }
+                Ok(None) => {}
+                Err(e) => stats.warn(format!(
+                    "Could not split {} into chapters, copying as-is: {:#}",
+                    audiobook.title, e
+                )),
+            }
+        }
+
+        let duplicates = dedup::find_duplicates(&source_paths);
+
+        let mut book_strategy = options.on_conflict;
+        let mut conflicting = HashSet::new();
+        if options.on_conflict == conflict::ConflictStrategy::Ask && !dry_run {
+            let conflicts: Vec<_> = (0..audiobook.tracks.len())
+                .filter(|i| !duplicates.contains_key(i))
+                .map(|i| {
+                    let dest_path = dest_dir.join(&dest_filenames[i]);
+                    (i, source_paths[i].clone(), dest_path)
+                })
+                .filter(|(_, source, dest)| {
+                    dest.exists() && !is_placeholder_file(source) && conflict::differs(source, dest)
+                })
+                .collect();
+            if !conflicts.is_empty() {
+                let pairs: Vec<_> = conflicts
+                    .iter()
+                    .map(|(_, source, dest)| (source.clone(), dest.clone()))
+                    .collect();
+                let book = format!("{} - {}", audiobook.author, audiobook.title);
+                book_strategy = pb.suspend(|| prompter.choose(&book, &pairs));
+                conflicting = conflicts.into_iter().map(|(i, _, _)| i).collect();
+            }
+        }
+
+        let cover_image = source_paths
+            .first()
+            .and_then(|path| path.parent())
+            .filter(|_| options.embed_cover)
+            .and_then(cover::find_image);
+
+        let started = Instant::now();
+        let mut result = report::BookResult::new(audiobook);
+        let mut complete = true;
+        for (index, track) in audiobook.tracks.iter().enumerate() {
+            let (source_path, dest_path) = planned[index].clone();
+
+            if let Some(&original) = duplicates.get(&index) {
+                eprintln!(
+                    "Skipping duplicate track {:?} (identical to {:?})",
+                    source_path, source_paths[original]
+                );
+                stats.duplicates_skipped += 1;
+                continue;
+            }
+
+            let converts = options.converts(&source_path);
+            if audible::is_audible(&source_path) && !converts {
+                stats.warn(format!(
+                    "{:?} is an Audible file, which Audiobookshelf can't play; {}",
+                    source_path,
+                    if audible::is_convertible(&source_path) {
+                        "skipping it (convert it with --activation-bytes)"
+                    } else {
+                        "skipping it (.aa files can't be converted)"
+                    }
+                ));
+                stats.audible_skipped += 1;
+                result.problems += 1;
+                complete = false;
+                continue;
+            }
+
+            if dry_run {
+                output::message(format_args!(
+                    "Would {} {:?} -> {:?}",
+                    if converts {
+                        "convert"
+                    } else if options.transcode.is_some() {
+                        "transcode"
+                    } else if use_symlink {
+                        "symlink"
+                    } else {
+                        "copy"
+                    },
+                    source_path,
+                    dest_path
+                ));
+                stats.files_would_copy += 1;
+                continue;
+            } else if !source_path.exists() {
+                stats.warn(format!("Source file not found: {:?}", source_path));
+                stats.source_missing += 1;
+                result.problems += 1;
+                complete = false;
+                continue;
+            } else if is_placeholder_file(&source_path) {
+                stats.warn(format!(
+                    "Source file is empty or a placeholder (not downloaded?): {:?}",
+                    source_path
+                ));
+                stats.source_placeholder += 1;
+                result.problems += 1;
+                complete = false;
+                continue;
+            }
+
+            let dest_path = if dest_path.exists() {
+                let strategy = if conflicting.contains(&index) {
+                    book_strategy
+                } else {
+                    options.on_conflict
+                };
+                let mut check = options.change_check();
+                check.plain_copy &= !converts;
+                match strategy.resolve(&source_path, &dest_path, check) {
+                    conflict::Resolution::Skip => {
+                        stats.files_already_exist += 1;
+                        // Never through a symlink, which may lead to the source
+                        if options.retag && !dest_path.is_symlink() {
+                            tag_jobs.push((
+                                dest_path.clone(),
+                                tags::TagSet::for_track(audiobook, track, index, options.renumber),
+                                cover_image.clone(),
+                            ));
+                        }
+                        // Still hashed for the manifest, and verified before a move
+                        if (options.move_sources || manifest.plain_copy) && !converts {
+                            let mut job =
+                                FileJob::new(audiobook, book_results.len(), source_path, dest_path);
+                            job.existing = true;
+                            file_jobs.push(job);
+                        }
+                        continue;
+                    }
+                    resolution @ (conflict::Resolution::Replace | conflict::Resolution::Update) => {
+                        // Never write through an existing file: it may be a symlink to the source
+                        if let Err(e) = fs::remove_file(&dest_path) {
+                            eprintln!("Error: Could not replace {:?}: {}", dest_path, e);
+                            stats.failures.push(report::FailedFile::new(
+                                audiobook,
+                                source_path,
+                                dest_path,
+                                format!("Could not replace existing file: {}", e),
+                            ));
+                            continue;
+                        }
+                        if resolution == conflict::Resolution::Update {
+                            stats.files_updated += 1;
+                        } else {
+                            stats.files_overwritten += 1;
+                        }
+                        dest_path
+                    }
+                    conflict::Resolution::WriteAs(renamed) => {
+                        stats.files_renamed += 1;
+                        renamed
+                    }
+                }
+            } else {
+                dest_path
+            };
+
+            if options.rewrites_tags() {
+                let tag_set = if options.fix_tags {
+                    tags::TagSet::for_track(audiobook, track, index, options.renumber)
+                } else {
+                    tags::TagSet::numbering(audiobook, track, index, options.renumber)
+                };
+                tag_jobs.push((dest_path.clone(), tag_set, cover_image.clone()));
+            }
+
+            if options.transcode.is_some() && !converts {
+                let source_size = fs::metadata(&source_path).map(|m| m.len()).unwrap_or(0);
+                transcode_jobs.push(transcode::TranscodeJob {
+                    source: source_path,
+                    dest: dest_path,
+                });
+                // Counted in the book's result once the transcode succeeded
+                transcode_books.push((audiobook, book_results.len(), source_size));
+                continue;
+            }
+
+            file_jobs.push(FileJob::new(
+                audiobook,
+                book_results.len(),
+                source_path,
+                dest_path,
+            ));
+        }
+
+        if options.finder_tags && !dry_run {
+            let genre = source_paths
+                .first()
+                .and_then(|path| media::probe_format_tags(path).ok())
+                .and_then(|tags| media::find_tag(&tags, "genre").map(str::to_string));
+            let tags: Vec<_> = genre.into_iter().collect();
+            if let Err(e) = finder::label_folder(&dest_dir, &tags, audiobook.narrator.as_deref()) {
+                stats.warn(format!(
+                    "Could not set Finder tags on {:?}: {:#}",
+                    dest_dir, e
+                ));
+            }
+        }
+
+        if options.write_chapters && !dry_run {
+            let enriched = options
+                .enriched_chapters
+                .get(&audiobook.folder_id)
+                .map(Vec::as_slice);
+            match write_book_chapters(audiobook, source_base, &dest_dir, enriched) {
+                Ok(()) => stats.chapter_files_written += 1,
+                Err(e) => stats.warn(format!(
+                    "Could not write chapters for {}: {:#}",
+                    audiobook.title, e
+                )),
+            }
+        }
+
+        if options.write_cue && !dry_run {
+            let titles = chapters::track_titles(&audiobook.tracks.iter().collect::<Vec<_>>());
+            let embedded = match source_paths.as_slice() {
+                [single] => media::probe_chapters(single).unwrap_or_default(),
+                _ => Vec::new(),
+            };
+            let tracks = cue::tracks(&dest_filenames, &titles, &embedded);
+            match cue::write_cue_file(&dest_dir, audiobook, &tracks) {
+                Ok(()) => stats.cue_files_written += 1,
+                Err(e) => stats.warn(format!(
+                    "Could not write the CUE sheet for {}: {:#}",
+                    audiobook.title, e
+                )),
+            }
+        }
+
+        if options.write_cover && !dry_run {
+            if let Some(first) = source_paths.first() {
+                let source_dir = first.parent().unwrap_or(source_base);
+                if let Err(e) = cover::write_cover(source_dir, first, &dest_dir) {
+                    stats.warn(format!(
+                        "Could not write the cover of {}: {:#}",
+                        audiobook.title, e
+                    ));
+                }
+            }
+        }
+
+        if (options.write_metadata || options.write_opf || options.write_nfo) && !dry_run {
+            let tags = source_paths
+                .first()
+                .and_then(|path| media::probe_format_tags(path).ok())
+                .unwrap_or_default();
+            let book_metadata = metadata::AbsMetadata::new(audiobook, &tags);
+            if options.write_metadata {
+                if let Err(e) = metadata::write_metadata_file(&dest_dir, &book_metadata) {
+                    stats.warn(format!(
+                        "Could not write metadata.json for {}: {:#}",
+                        audiobook.title, e
+                    ));
+                }
+            }
+            if options.write_opf {
+                if let Err(e) =
+                    metadata::write_opf_file(&dest_dir, &book_metadata, &audiobook.folder_id)
+                {
+                    stats.warn(format!(
+                        "Could not write metadata.opf for {}: {:#}",
+                        audiobook.title, e
+                    ));
+                }
+            }
+            if options.write_nfo {
+                if let Err(e) = metadata::write_nfo_file(&dest_dir, &book_metadata) {
+                    stats.warn(format!(
+                        "Could not write album.nfo for {}: {:#}",
+                        audiobook.title, e
+                    ));
+                }
+            }
+        }
+
+        if !dry_run {
+            result.elapsed_secs = started.elapsed().as_secs_f64();
+            book_results.push(result);
+        }
+
+        if complete && !dry_run {
+            let files: Option<Vec<_>> = planned
+                .iter()
+                .map(|(source, dest)| manifest::ManifestFile::new(source, dest))
+                .collect();
+            if let Some(files) = files {
+                exported_books.push((
+                    audiobook.folder_id.clone(),
+                    manifest::BookEntry {
+                        author: audiobook.author.clone(),
+                        title: audiobook.title.clone(),
+                        dest_dir: dest_dir.clone(),
+                        files,
+                        exported_at: SystemTime::now()
+                            .duration_since(UNIX_EPOCH)
+                            .map_or(0, |d| d.as_secs()),
+                    },
+                ));
+            }
+        }
+
+        stats.books_exported += 1;
+        pb.inc(1);
+    }
+
+    pb.finish_and_clear();
+
+    let stop = AtomicBool::new(disconnected);
+    let waiting = Mutex::new(());
+    let hash_sources = manifest.plain_copy || options.move_sources;
+    let hash_stage = |job: &mut FileJob| {
+        if hash_sources && !options.converts(&job.source) {
+            job.source_sha256 =
+                Some(hash::sha256_file(&job.source).map_err(|e| format!("{:#}", e)));
+        }
+    };
+    let pb = progress::bar(file_jobs.len(), "Copying");
+    let transfer_stage = |job: &mut FileJob| {
+        if job.existing || stop.load(Ordering::SeqCst) {
+            return;
+        }
+        pb.set_message(job.book.title.clone());
+        let started = Instant::now();
+        let transfer = || match &options.activation_bytes {
+            Some(bytes) if options.converts(&job.source) => {
+                audible::convert(&job.source, &job.dest, bytes)
+            }
+            _ => transfer_file(&job.source, &job.dest, use_symlink),
+        };
+        let mut transferred = transfer();
+        while transferred.is_err() && !destination.is_available() {
+            // One worker waits for the destination to come back, the others queue up
+            let _waiting = waiting.lock().unwrap();
+            if stop.load(Ordering::SeqCst)
+                || !destination.is_available()
+                    && !pb.suspend(|| destination.wait(options.reconnect_timeout))
+            {
+                stop.store(true, Ordering::SeqCst);
+                return;
+            }
+            // The interrupted copy may have left a partial file behind
+            let _ = fs::remove_file(&job.dest);
+            transferred = transfer();
+        }
+        if let (Ok(()), Some(target_lufs)) = (&transferred, options.normalize) {
+            job.normalized = Some(
+                media::normalize_in_place(&job.dest, target_lufs).map_err(|e| format!("{:#}", e)),
+            );
+        }
+        job.transfer_time = started.elapsed();
+        job.transferred = Some(transferred.map_err(|e| format!("{:#}", e)));
+    };
+    let verify_stage = |job: &mut FileJob| {
+        if !options.move_sources
+            || options.converts(&job.source)
+            || !(job.existing || matches!(job.transferred, Some(Ok(()))))
+        {
+            return;
+        }
+        // A symlink, or a path that resolves to the source itself, hashes the same as
+        // the source but goes away with it
+        let is_link = fs::symlink_metadata(&job.dest).is_ok_and(|m| m.file_type().is_symlink());
+        let is_source = matches!(
+            (fs::canonicalize(&job.source), fs::canonicalize(&job.dest)),
+            (Ok(source), Ok(dest)) if source == dest
+        );
+        if is_link || is_source {
+            job.verified = Some(Err(
+                "it is a link to the source, not a copy of it".to_string()
+            ));
+            return;
+        }
+        job.verified = Some(match &job.source_sha256 {
+            Some(Ok(expected)) => hash::sha256_file(&job.dest)
+                .map(|actual| &actual == expected)
+                .map_err(|e| format!("{:#}", e)),
+            Some(Err(e)) => Err(e.clone()),
+            None => Err("source was not hashed".to_string()),
+        });
+    };
+    let stages = [
+        pipeline::Stage {
+            workers: options.hash_workers,
+            work: &hash_stage,
+        },
+        pipeline::Stage {
+            workers: options.copy_workers,
+            work: &transfer_stage,
+        },
+        pipeline::Stage {
+            workers: options.verify_workers,
+            work: &verify_stage,
+        },
+    ];
+    let (mut copied, to_copy) = (0, file_jobs.len());
+    let file_jobs = pipeline::run(file_jobs, &stages, |job| {
+        pb.inc(1);
+        copied += 1;
+        options.report("Copying", copied, to_copy, job.book);
+        if let Some(Err(e)) = &job.transferred {
+            pb.suspend(|| eprintln!("Error: {}", e));
+        }
+    });
+    pb.finish_and_clear();
+    let disconnected = stop.into_inner();
+
+    let mut source_hashes = std::collections::HashMap::new();
+    let mut verified_moves = Vec::new();
+    for job in file_jobs {
+        let result = &mut book_results[job.result];
+        result.elapsed_secs += job.transfer_time.as_secs_f64();
+        match job.transferred {
+            Some(Ok(())) => {
+                if options.converts(&job.source) {
+                    stats.audible_converted += 1;
+                } else {
+                    stats.files_copied += 1;
+                }
+                result.files += 1;
+                result.bytes += job.source_size;
+            }
+            Some(Err(e)) => {
+                stats.failures.push(report::FailedFile::new(
+                    job.book,
+                    job.source.clone(),
+                    job.dest.clone(),
+                    e,
+                ));
+            }
+            None => {}
+        }
+        match job.normalized {
+            Some(Ok(())) => stats.files_normalized += 1,
+            Some(Err(e)) => stats.warn(format!("Could not normalize {:?}: {}", job.dest, e)),
+            None => {}
+        }
+        if let Some(verified) = job.verified {
+            verified_moves.push((job.source.clone(), job.dest.clone(), verified));
+        }
+        if let Some(Ok(sha256)) = job.source_sha256 {
+            source_hashes.insert(job.dest, sha256);
+        }
+    }
+
+    // Nothing more can be written. Pending transcodes, tags and moves are left for the next
+    // run, and the manifest as it was, so the next run checks this run's books again.
+    if disconnected {
+        stats.warn(format!(
+            "Destination {:?} disconnected; stopped after copying {} files",
+            dest, stats.files_copied
+        ));
+        for result in &mut book_results {
+            result.finish();
+        }
+        stats.book_results = book_results;
+        return Ok(stats);
+    }
+
+    // Books exported by an earlier run that are no longer in the library
+    let in_library: HashSet<_> = audiobooks.iter().map(|b| &b.folder_id).collect();
+    for (folder_id, entry) in &previous.books {
+        if !in_library.contains(folder_id) {
+            output::message(format_args!(
+                "Removed from library since last sync: {} - {}",
+                entry.author, entry.title
+            ));
+            stats.books_removed += 1;
+            manifest.books.insert(folder_id.clone(), entry.clone());
+        }
+    }
+
+    if let Some(target) = &options.transcode {
+        let filter = options.normalize.map(media::loudnorm_filter);
+        let transcoded = AtomicUsize::new(0);
+        let outcome = transcode::run_jobs(
+            target,
+            &transcode_jobs,
+            options.jobs,
+            filter.as_deref(),
+            |i| {
+                let done = transcoded.fetch_add(1, Ordering::SeqCst) + 1;
+                options.report(
+                    "Transcoding",
+                    done,
+                    transcode_jobs.len(),
+                    transcode_books[i].0,
+                );
+            },
+        );
+        stats.files_transcoded = outcome.transcoded;
+        if filter.is_some() {
+            stats.files_normalized += outcome.transcoded;
+        }
+        let failed: HashSet<_> = outcome.failed.iter().map(|(i, _)| *i).collect();
+        for (i, (_, result, source_size)) in transcode_books.iter().enumerate() {
+            if !failed.contains(&i) {
+                book_results[*result].files += 1;
+                book_results[*result].bytes += source_size;
+            }
+        }
+        for (i, error) in outcome.failed {
+            let job = &transcode_jobs[i];
+            eprintln!("Error: {}", error);
+            stats.failures.push(report::FailedFile::new(
+                transcode_books[i].0,
+                job.source.clone(),
+                job.dest.clone(),
+                error,
+            ));
+        }
+    }
+
+    let pb = progress::bar(tag_jobs.len(), "Retagging");
+    for (path, tag_set, cover_image) in tag_jobs.iter().filter(|(path, _, _)| path.exists()) {
+        pb.inc(1);
+        if options.strip_personal_tags && tags::has_store_atoms(path) {
+            match tags::strip_personal_tags(path) {
+                Ok(_) => stats.files_stripped += 1,
+                Err(e) => stats.warn(format!("Could not strip tags from {:?}: {:#}", path, e)),
+            }
+        }
+        if options.fix_tags || options.renumber {
+            match tags::rewrite_tags(path, tag_set) {
+                Ok(()) => stats.files_retagged += 1,
+                Err(e) => stats.warn(format!("Could not rewrite tags on {:?}: {:#}", path, e)),
+            }
+        }
+        if let Some(image) = cover_image {
+            match tags::embed_cover(path, image) {
+                Ok(()) => stats.covers_embedded += 1,
+                Err(e) => stats.warn(format!("Could not embed the cover in {:?}: {:#}", path, e)),
+            }
+        }
+    }
+    pb.finish_and_clear();
+
+    // Recorded in the manifest, so the destination can be verified without the sources.
+    // Plain copies were hashed from their source while being copied; other files are
+    // hashed once they're final.
+    if !options.use_symlink {
+        let files = exported_books.iter().map(|(_, e)| e.files.len()).sum();
+        let pb = progress::bar(files, "Hashing");
+        for (_, entry) in &mut exported_books {
+            for file in &mut entry.files {
+                pb.inc(1);
+                file.sha256 = if manifest.plain_copy && !options.converts(&file.source) {
+                    source_hashes
+                        .remove(&file.dest)
+                        .or_else(|| hash::sha256_file(&file.source).ok())
+                } else {
+                    hash::sha256_file(&file.dest).ok()
+                };
+            }
+        }
+        pb.finish_and_clear();
+    }
+
+    let disposer = trash::Disposer::new(options.disposal.clone());
+    for (source_path, dest_path, verified) in &verified_moves {
+        match verified {
+            Ok(true) => match disposer.dispose(source_path, source_base) {
+                Ok(_) => stats.sources_removed += 1,
+                Err(e) => stats.warn(format!("Could not remove {:?}: {:#}", source_path, e)),
+            },
+            Ok(false) => {
+                stats.warn(format!(
+                    "{:?} does not match its source; keeping {:?}",
+                    dest_path, source_path
+                ));
+                stats.verification_failed += 1;
+            }
+            Err(e) => {
+                stats.warn(format!(
+                    "Could not verify {:?}, keeping source: {}",
+                    dest_path, e
+                ));
+                stats.verification_failed += 1;
+            }
+        }
+    }
+
+    let pb = progress::bar(quarantined_dirs.len(), "Clearing quarantine");
+    let mut not_stripped: std::collections::BTreeMap<&str, (usize, anyhow::Error)> =
+        std::collections::BTreeMap::new();
+    for dir in &quarantined_dirs {
+        pb.inc(1);
+        for (name, error) in quarantine::strip(dir) {
+            not_stripped.entry(name).or_insert((0, error)).0 += 1;
+        }
+    }
+    pb.finish_and_clear();
+    for (name, (count, error)) in not_stripped {
+        stats.warn(format!(
+            "Could not remove {} from {} book folders: {:#}",
+            name, count, error
+        ));
+    }
+
+    // Last, as writing files into a folder changes its modification time
+    for (dir, added) in &dated_dirs {
+        if let Err(e) = set_folder_date(dir, *added) {
+            stats.warn(format!("{:#}", e));
+        }
+    }
+
+    // Failures are only known once transcoding has finished
+    for result in &mut book_results {
+        let failed = stats
+            .failures
+            .iter()
+            .filter(|f| f.folder_id == result.folder_id)
+            .count();
+        result.problems += failed;
+        result.finish();
+    }
+    stats.book_results = book_results;
+
+    if !dry_run {
+        let failed: HashSet<_> = stats.failures.iter().map(|f| &f.folder_id).collect();
+        for (folder_id, entry) in exported_books {
+            if !failed.contains(&folder_id) {
+                manifest.books.insert(folder_id, entry);
+            }
+        }
+        if let Err(e) = manifest.write(&manifest_path) {
+            stats.warn(format!("Could not save manifest: {:#}", e));
+        }
+    }
+
+    Ok(stats)
+}
+
+/// Re-attempt only the files that failed in a previous run, without re-reading the library
+pub fn retry_failed(failed: &[report::FailedFile], options: &ExportOptions) -> Result<ExportStats> {
+    let mut stats = ExportStats::default();
+    let mut transcode_jobs = Vec::new();
+    let mut transcode_items = Vec::new();
+    let mut books = std::collections::HashSet::new();
+
+    for item in failed {
+        books.insert(&item.folder_id);
+
+        if !item.source.exists() {
+            stats.warn(format!("Source file not found: {:?}", item.source));
+            stats.source_missing += 1;
+            stats.failures.push(report::FailedFile {
+                error: "Source file not found".to_string(),
+                ..item.clone()
+            });
+            continue;
+        }
+
+        if let Some(parent) = item.dest.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory {:?}", parent))?;
+        }
+        // A failed copy may have left a truncated file (or a symlink) behind
+        if fs::symlink_metadata(&item.dest).is_ok() {
+            fs::remove_file(&item.dest)
+                .with_context(|| format!("Failed to remove partial file {:?}", item.dest))?;
+        }
+
+        if options.transcode.is_some() {
+            transcode_jobs.push(transcode::TranscodeJob {
+                source: item.source.clone(),
+                dest: item.dest.clone(),
+            });
+            transcode_items.push(item);
+            continue;
+        }
+
+        match transfer_file(&item.source, &item.dest, options.use_symlink) {
+            Ok(()) => stats.files_copied += 1,
+            Err(e) => {
+                eprintln!("Error: {:#}", e);
+                stats.failures.push(report::FailedFile {
+                    error: format!("{:#}", e),
+                    ..item.clone()
+                });
+            }
+        }
+    }
+
+    if let Some(target) = &options.transcode {
+        let filter = options.normalize.map(media::loudnorm_filter);
+        let outcome = transcode::run_jobs(
+            target,
+            &transcode_jobs,
+            options.jobs,
+            filter.as_deref(),
+            |_| {},
+        );
+        stats.files_transcoded = outcome.transcoded;
+        for (i, error) in outcome.failed {
+            eprintln!("Error: {}", error);
+            stats.failures.push(report::FailedFile {
+                error,
+                ..transcode_items[i].clone()
+            });
+        }
+    }
+
+    stats.books_exported = books.len();
+    Ok(stats)
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExportStats {
+    pub books_exported: usize,
+    pub books_unchanged: usize,
+    pub books_removed: usize,
+    pub files_copied: usize,
+    pub files_would_copy: usize,
+    pub files_missing: usize,
+    pub files_already_exist: usize,
+    pub source_missing: usize,
+    pub source_placeholder: usize,
+    /// Audible files that Audiobookshelf can't play, left out
+    pub audible_skipped: usize,
+    /// Audible files converted with the activation bytes
+    pub audible_converted: usize,
+    pub duplicates_skipped: usize,
+    /// Files in book folders that aren't tracks (booklets, covers, videos)
+    pub extras: Vec<PathBuf>,
+    pub extras_copied: usize,
+    pub chapter_files_written: usize,
+    pub cue_files_written: usize,
+    pub chapter_files_split: usize,
+    pub files_transcoded: usize,
+    pub files_normalized: usize,
+    pub files_retagged: usize,
+    pub files_stripped: usize,
+    pub covers_embedded: usize,
+    pub files_overwritten: usize,
+    pub files_updated: usize,
+    pub files_renamed: usize,
+    pub sources_removed: usize,
+    pub verification_failed: usize,
+    pub failures: Vec<report::FailedFile>,
+    pub book_results: Vec<report::BookResult>,
+    /// Problems that didn't stop the run, e.g. missing sources or unverified moves
+    pub warnings: Vec<String>,
+}
+
+impl ExportStats {
+    /// Print a warning and keep it for the summary
+    fn warn(&mut self, message: String) {
+        eprintln!("Warning: {}", message);
+        self.warnings.push(message);
+    }
+}
+
+/// Status of a file comparison between source and destination
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileStatus {
+    /// File exists in source, not in destination - will be copied
+    New,
+    /// File exists in both source and destination
+    Exists,
+    /// File exists in both, but the source changed since it was exported - will be updated
+    Changed,
+    /// File missing from source (referenced in plist but not on disk)
+    SourceMissing,
+    /// Source file is zero-byte or too small to be audio (iCloud placeholder, failed download)
+    SourcePlaceholder,
+    /// Byte-identical copy of another track in the same book - skipped
+    Duplicate,
+}
+
+/// Information about a file for diff display
+#[derive(Debug, Clone, Serialize)]
+pub struct FileDiff {
+    pub source_path: PathBuf,
+    pub dest_path: PathBuf,
+    pub status: FileStatus,
+    pub book_title: String,
+    pub author: String,
+}
+
+impl FileStatus {
+    /// Name used in diff files, e.g. `source_missing`
+    fn name(&self) -> &'static str {
+        match self {
+            FileStatus::New => "new",
+            FileStatus::Exists => "exists",
+            FileStatus::Changed => "changed",
+            FileStatus::SourceMissing => "source_missing",
+            FileStatus::SourcePlaceholder => "source_placeholder",
+            FileStatus::Duplicate => "duplicate",
+        }
+    }
+}
+
+/// A diff as written by `diff --output`
+#[derive(Debug, Serialize)]
+struct DiffFile<'a> {
+    /// Unix timestamp (seconds) at which the diff was computed
+    generated_at: u64,
+    source: &'a Path,
+    dest: &'a Path,
+    files: &'a [FileDiff],
+}
+
+/// Quote a CSV field if needed
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Write every file of a diff to `path`: as CSV if the path ends in `.csv`, else as JSON
+pub fn save_diff(path: &Path, source: &Path, dest: &Path, diffs: &[FileDiff]) -> Result<()> {
+    let is_csv = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("csv"));
+    let contents = if is_csv {
+        let mut csv = String::from("status,author,book,source,dest\n");
+        for diff in diffs {
+            let fields = [
+                diff.status.name().to_string(),
+                diff.author.clone(),
+                diff.book_title.clone(),
+                diff.source_path.display().to_string(),
+                diff.dest_path.display().to_string(),
+            ];
+            let row: Vec<_> = fields.iter().map(|f| csv_field(f)).collect();
+            csv.push_str(&row.join(","));
+            csv.push('\n');
+        }
+        csv
+    } else {
+        diff_json(source, dest, diffs)?
+    };
+    fs::write(path, contents).with_context(|| format!("Failed to write diff {:?}", path))
+}
+
+/// A diff as JSON, as written by `diff --output` and printed by `diff --format json`
+pub fn diff_json(source: &Path, dest: &Path, diffs: &[FileDiff]) -> serde_json::Result<String> {
+    let file = DiffFile {
+        generated_at: std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0),
+        source,
+        dest,
+        files: diffs,
+    };
+    serde_json::to_string_pretty(&file)
+}
+
+/// Compute the diff between source and destination for all audiobooks
+pub fn compute_diff(
+    audiobooks: &[Audiobook],
+    source_base: &Path,
+    dest: &Path,
+    options: &ExportOptions,
+) -> Vec<FileDiff> {
+    let mut diffs = Vec::new();
+    let selected = options.selected(audiobooks);
+    let pb = progress::bar(selected.len(), "Scanning destination");
+
+    for audiobook in selected {
+        pb.inc(1);
+        pb.set_message(format!("{} - {}", audiobook.author, audiobook.title));
+        let dest_dir = create_audiobookshelf_path(dest, audiobook, options);
+        let source_paths: Vec<_> = audiobook
+            .tracks
+            .iter()
+            .map(|t| remap_track_path(&t.path, source_base))
+            .collect();
+        let duplicates = dedup::find_duplicates(&source_paths);
+        let dest_filenames = options.dest_filenames(audiobook);
+
+        for (index, source_path) in source_paths.into_iter().enumerate() {
+            let dest_path = dest_dir.join(&dest_filenames[index]);
+
+            let status = if duplicates.contains_key(&index) {
+                FileStatus::Duplicate
+            } else if !source_path.exists() {
+                FileStatus::SourceMissing
+            } else if is_placeholder_file(&source_path) {
+                FileStatus::SourcePlaceholder
+            } else if dest_path.exists() {
+                if options.on_conflict == conflict::ConflictStrategy::Update
+                    && options
+                        .change_check()
+                        .source_changed(&source_path, &dest_path)
+                {
+                    FileStatus::Changed
+                } else {
+                    FileStatus::Exists
+                }
+            } else {
+                FileStatus::New
+            };
+
+            diffs.push(FileDiff {
+                source_path,
+                dest_path,
+                status,
+                book_title: audiobook.title.clone(),
+                author: audiobook.author.clone(),
+            });
+        }
+    }
+
+    pb.finish_and_clear();
+    diffs
+}
+
+/// How one group of files with the same status is listed in the diff summary
+struct DiffSection {
+    status: FileStatus,
+    /// Marker shown in front of the section title and each book
+    symbol: &'static str,
+    /// Stand-in for `symbol` in plain ASCII output
+    ascii_symbol: &'static str,
+    title: &'static str,
+    color: Color,
+    /// What the files are called in the section header, e.g. "empty files"
+    noun: &'static str,
+    /// Books listed before the rest are summarized (all books with `--full`)
+    book_limit: usize,
+    show_file_counts: bool,
+    /// List files by source path (they don't have a destination)
+    show_source: bool,
+}
+
+const DIFF_SECTIONS: &[DiffSection] = &[
+    DiffSection {
+        status: FileStatus::New,
+        symbol: "+",
+        ascii_symbol: "+",
+        title: "TO ADD",
+        color: Color::Green,
+        noun: "files",
+        book_limit: 20,
+        show_file_counts: true,
+        show_source: false,
+    },
+    DiffSection {
+        status: FileStatus::Exists,
+        symbol: "=",
+        ascii_symbol: "=",
+        title: "ALREADY EXISTS",
+        color: Color::Yellow,
+        noun: "files",
+        book_limit: 10,
+        show_file_counts: false,
+        show_source: false,
+    },
+    DiffSection {
+        status: FileStatus::Changed,
+        symbol: "~",
+        ascii_symbol: "~",
+        title: "CHANGED",
+        color: Color::Blue,
+        noun: "files",
+        book_limit: 10,
+        show_file_counts: false,
+        show_source: false,
+    },
+    DiffSection {
+        status: FileStatus::SourceMissing,
+        symbol: "!",
+        ascii_symbol: "!",
+        title: "SOURCE MISSING",
+        color: Color::Red,
+        noun: "files",
+        book_limit: 10,
+        show_file_counts: false,
+        show_source: true,
+    },
+    DiffSection {
+        status: FileStatus::SourcePlaceholder,
+        symbol: "∅",
+        ascii_symbol: "0",
+        title: "PLACEHOLDER",
+        color: Color::Red,
+        noun: "empty files",
+        book_limit: 10,
+        show_file_counts: false,
+        show_source: true,
+    },
+    DiffSection {
+        status: FileStatus::Duplicate,
+        symbol: "≡",
+        ascii_symbol: "*",
+        title: "DUPLICATES",
+        color: Color::Cyan,
+        noun: "identical files",
+        book_limit: 10,
+        show_file_counts: false,
+        show_source: true,
+    },
+];
+
+fn write_diff_section(
+    out: &mut impl Write,
+    section: &DiffSection,
+    diffs: &[FileDiff],
+    full: bool,
+) -> io::Result<()> {
+    let mut books: std::collections::BTreeMap<String, Vec<&FileDiff>> =
+        std::collections::BTreeMap::new();
+    for diff in diffs.iter().filter(|d| d.status == section.status) {
+        let key = format!("{} - {}", diff.author, diff.book_title);
+        books.entry(key).or_default().push(diff);
+    }
+    if books.is_empty() {
+        return Ok(());
+    }
+
+    let file_count: usize = books.values().map(Vec::len).sum();
+    let symbol = output::symbol(section.symbol, section.ascii_symbol);
+    let title = format!("{} {}", symbol, tr(section.title));
+    let counts = tr_args(
+        "{} {} in {} books",
+        &[&file_count, &tr(section.noun), &books.len()],
+    );
+    writeln!(
+        out,
+        "{}",
+        output::title_box(&format!("{} ({})", paint(&title, section.color), counts))
+    )?;
+
+    let marker = paint(symbol, section.color);
+    let limit = if full { usize::MAX } else { section.book_limit };
+    for (book_key, files) in books.iter().take(limit) {
+        if section.show_file_counts {
+            let count = tr_args("{} files", &[&files.len()]);
+            writeln!(out, "  {} {} ({})", marker, book_key, count)?;
+        } else {
+            writeln!(out, "  {} {}", marker, book_key)?;
+        }
+        if full {
+            for file in files {
+                if section.show_source {
+                    writeln!(out, "      {}", file.source_path.display())?;
+                } else {
+                    writeln!(out, "      {}", file.dest_path.display())?;
+                }
+            }
+        }
+    }
+    if books.len() > limit {
+        let more = books.len() - limit;
+        writeln!(out, "  {}", tr_args("... and {} more books", &[&more]))?;
+    }
+    writeln!(out)
+}
+
+/// Write a formatted diff summary. With `full`, every book and file is listed.
+pub fn write_diff(out: &mut impl Write, diffs: &[FileDiff], full: bool) -> io::Result<()> {
+    let count = |status: FileStatus| diffs.iter().filter(|d| d.status == status).count();
+
+    writeln!(out, "\n{}\n", output::banner(tr("DIFF SUMMARY")))?;
+
+    for section in DIFF_SECTIONS {
+        write_diff_section(out, section, diffs, full)?;
+    }
+
+    // Summary
+    let totals = [
+        (FileStatus::New, "New files to copy:"),
+        (FileStatus::Exists, "Already exist (skip):"),
+        (FileStatus::Changed, "Changed (update):"),
+        (FileStatus::SourceMissing, "Source missing:"),
+        (FileStatus::SourcePlaceholder, "Empty/placeholder:"),
+        (FileStatus::Duplicate, "Duplicates (skip):"),
+    ];
+    let label_width = totals
+        .iter()
+        .map(|(_, label)| tr(label).chars().count())
+        .max()
+        .unwrap_or_default();
+    let rows: Vec<_> = totals
+        .into_iter()
+        .map(|(status, label)| {
+            let section = DIFF_SECTIONS.iter().find(|s| s.status == status).unwrap();
+            format!(
+                " {} {:<width$}{:>8}",
+                paint(
+                    output::symbol(section.symbol, section.ascii_symbol),
+                    section.color
+                ),
+                tr(label),
+                count(status),
+                width = label_width
+            )
+        })
+        .collect();
+    writeln!(out, "{}", output::table_box(tr("TOTALS"), &rows))
+}
+
+/// Path a diff lists a file by: its destination, or its source when it has no destination
+/// (missing, placeholder and duplicate files)
+fn diff_path(diff: &FileDiff) -> &Path {
+    let section = DIFF_SECTIONS.iter().find(|s| s.status == diff.status);
+    if section.is_some_and(|s| s.show_source) {
+        &diff.source_path
+    } else {
+        &diff.dest_path
+    }
+}
+
+/// Write the path of every file in a diff, one per line, for piping into other tools
+pub fn write_diff_paths(out: &mut impl Write, diffs: &[FileDiff]) -> io::Result<()> {
+    for diff in diffs {
+        writeln!(out, "{}", diff_path(diff).display())?;
+    }
+    Ok(())
+}
+
+/// Write one line per book with how many of its files have each status
+pub fn write_diff_by_book(out: &mut impl Write, diffs: &[FileDiff]) -> io::Result<()> {
+    let mut books: std::collections::BTreeMap<String, Vec<&FileDiff>> =
+        std::collections::BTreeMap::new();
+    for diff in diffs {
+        let key = format!("{} - {}", diff.author, diff.book_title);
+        books.entry(key).or_default().push(diff);
+    }
+
+    for (book_key, files) in &books {
+        let counts: Vec<_> = DIFF_SECTIONS
+            .iter()
+            .filter_map(|section| {
+                let count = files.iter().filter(|f| f.status == section.status).count();
+                (count > 0).then(|| {
+                    let symbol = output::symbol(section.symbol, section.ascii_symbol);
+                    paint(&format!("{}{}", symbol, count), section.color)
+                })
+            })
+            .collect();
+        writeln!(out, "{}  {}", book_key, counts.join(" "))?;
+    }
+    Ok(())
+}
+
+/// Display a formatted diff summary
+pub fn display_diff(diffs: &[FileDiff]) {
+    let _ = write_diff(&mut io::stdout().lock(), diffs, false);
+}
+
+/// Show long output through `$PAGER` (default `less -R`) when stdout is a terminal
+pub fn page_output(text: &str) {
+    if io::stdout().is_terminal() {
+        let pager = std::env::var("PAGER").unwrap_or_else(|_| "less -R".to_string());
+        let child = std::process::Command::new("sh")
+            .arg("-c")
+            .arg(&pager)
+            .stdin(std::process::Stdio::piped())
+            .spawn();
+        if let Ok(mut child) = child {
+            if let Some(mut stdin) = child.stdin.take() {
+                // The user may quit the pager before reading everything
+                let _ = stdin.write_all(text.as_bytes());
+            }
+            if child.wait().is_ok() {
+                return;
+            }
+        }
+    }
+    print!("{}", text);
+}
+
+/// Render the destination tree of a book, relative to the destination directory
+pub fn preview_tree(audiobook: &Audiobook, source_base: &Path, options: &ExportOptions) -> String {
+    let mut lines = Vec::new();
+    let book_dir = create_audiobookshelf_path(Path::new(""), audiobook, options);
+    let dirs: Vec<_> = book_dir
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().to_string())
+        .collect();
+    for (depth, dir) in dirs.iter().enumerate() {
+        if depth == 0 {
+            lines.push(format!("{}/", dir));
+        } else {
+            lines.push(format!(
+                "{}{} {}/",
+                "    ".repeat(depth - 1),
+                output::symbol("└──", "`--"),
+                dir
+            ));
+        }
+    }
+
+    let source_paths: Vec<_> = audiobook
+        .tracks
+        .iter()
+        .map(|t| remap_track_path(&t.path, source_base))
+        .collect();
+    let duplicates = dedup::find_duplicates(&source_paths);
+
+    let dest_filenames = options.dest_filenames(audiobook);
+    let mut files = Vec::new();
+    for (index, source_path) in source_paths.iter().enumerate() {
+        if duplicates.contains_key(&index) {
+            continue;
+        }
+        let filename = dest_filenames[index].clone();
+        let note = if !source_path.exists() {
+            " (source missing)"
+        } else if is_placeholder_file(source_path) {
+            " (not downloaded)"
+        } else if options.split_chapters && chapters::is_splittable(source_path) {
+            " (split into one file per chapter if it has chapters)"
+        } else {
+            ""
+        };
+        files.push(format!("{}{}", filename, note));
+    }
+    if options.write_chapters {
+        files.push(chapters::CHAPTERS_FILENAME.to_string());
+    }
+    if options.write_metadata {
+        files.push(metadata::METADATA_FILENAME.to_string());
+    }
+    if options.write_opf {
+        files.push(metadata::OPF_FILENAME.to_string());
+    }
+    if options.write_cue {
+        files.push(cue::CUE_FILENAME.to_string());
+    }
+    if options.write_nfo {
+        files.push(metadata::NFO_FILENAME.to_string());
+    }
+    if options.write_cover {
+        files.push(cover::COVER_FILENAME.to_string());
+    }
+    for (name, _, text) in companions(audiobook) {
+        if text.is_some() {
+            files.push(name.to_string());
+        }
+    }
+
+    let indent = "    ".repeat(dirs.len().saturating_sub(1));
+    for (i, file) in files.iter().enumerate() {
+        let branch = if i + 1 == files.len() {
+            output::symbol("└──", "`--")
+        } else {
+            output::symbol("├──", "|--")
+        };
+        lines.push(format!("{}{} {}", indent, branch, file));
+    }
+
+    lines.join("\n")
+}
+
+/// Display books with gaps in their track numbering (likely incomplete downloads)
+pub fn display_track_gaps(audiobooks: &[Audiobook]) {
+    let mut incomplete: Vec<_> = audiobooks
+        .iter()
+        .map(|book| (book, book.missing_tracks()))
+        .filter(|(_, missing)| !missing.is_empty())
+        .collect();
+
+    if incomplete.is_empty() {
+        return;
+    }
+    incomplete.sort_by(|(a, _), (b, _)| (&a.author, &a.title).cmp(&(&b.author, &b.title)));
+
+    println!();
+    println!(
+        "{}",
+        output::title_box(&format!(
+            "{} ({} books with gaps in track numbering)",
+            paint("? INCOMPLETE", Color::Magenta),
+            incomplete.len()
+        ))
+    );
+
+    for (book, missing) in &incomplete {
+        println!(
+            "  {} {} - {} (missing tracks {})",
+            paint("?", Color::Magenta),
+            book.author,
+            book.title,
+            format_missing_tracks(missing)
+        );
+    }
+    println!();
+}
+
+/// Format a byte count for display, e.g. "12.3 MB"
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: &[&str] = &["B", "KB", "MB", "GB", "TB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1000.0 && unit < UNITS.len() - 1 {
+        value /= 1000.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+/// Shorten `text` to at most `width` characters, marking cut text with an ellipsis
+fn truncate(text: &str, width: usize) -> String {
+    if text.chars().count() <= width {
+        text.to_string()
+    } else {
+        let cut: String = text.chars().take(width.saturating_sub(1)).collect();
+        format!("{}{}", cut, output::symbol("…", "~"))
+    }
+}
+
+/// Print a table of the books this run wrote files for or had problems with
+fn write_book_table(out: &mut impl Write, results: &[report::BookResult]) -> io::Result<()> {
+    let rows: Vec<_> = results
+        .iter()
+        .filter(|r| r.files > 0 || r.problems > 0)
+        .collect();
+    if rows.is_empty() {
+        return Ok(());
+    }
+
+    writeln!(
+        out,
+        "\n{:<44} {:>6} {:>10} {:>8}  {}",
+        tr("Book"),
+        tr("Files"),
+        tr("Size"),
+        tr("Time"),
+        tr("Status")
+    )?;
+    for row in rows {
+        writeln!(
+            out,
+            "{:<44} {:>6} {:>10} {:>7.1}s  {}",
+            truncate(&row.book, 44),
+            row.files,
+            format_bytes(row.bytes),
+            row.elapsed_secs,
+            tr(row.status.label())
+        )?;
+    }
+    Ok(())
+}
+
+/// Write the end-of-run summary for an export
+fn write_export_summary(out: &mut impl Write, stats: &ExportStats) -> io::Result<()> {
+    write_book_table(out, &stats.book_results)?;
+    writeln!(out, "\n=== {} ===", tr("Export Summary"))?;
+    writeln!(
+        out,
+        "{}: {}",
+        tr("Audiobooks processed"),
+        stats.books_exported
+    )?;
+    if stats.books_unchanged > 0 {
+        writeln!(
+            out,
+            "{}: {}",
+            tr("Audiobooks unchanged since last run (skipped)"),
+            stats.books_unchanged
+        )?;
+    }
+    if stats.books_removed > 0 {
+        writeln!(
+            out,
+            "{}: {}",
+            tr("Audiobooks removed from the library since last run"),
+            stats.books_removed
+        )?;
+    }
+    writeln!(out, "{}: {}", tr("Files copied"), stats.files_copied)?;
+    if stats.files_transcoded > 0 {
+        writeln!(
+            out,
+            "{}: {}",
+            tr("Files transcoded"),
+            stats.files_transcoded
+        )?;
+    }
+    if stats.audible_converted > 0 {
+        writeln!(
+            out,
+            "{}: {}",
+            tr("Audible files converted"),
+            stats.audible_converted
+        )?;
+    }
+    if stats.files_normalized > 0 {
+        writeln!(
+            out,
+            "{}: {}",
+            tr("Files loudness-normalized"),
+            stats.files_normalized
+        )?;
+    }
+    if stats.files_retagged > 0 {
+        writeln!(out, "{}: {}", tr("Files retagged"), stats.files_retagged)?;
+    }
+    if stats.files_stripped > 0 {
+        writeln!(
+            out,
+            "{}: {}",
+            tr("Files stripped of personal tags"),
+            stats.files_stripped
+        )?;
+    }
+    if stats.covers_embedded > 0 {
+        writeln!(
+            out,
+            "{}: {}",
+            tr("Files with an embedded cover"),
+            stats.covers_embedded
+        )?;
+    }
+    if stats.files_updated > 0 {
+        writeln!(
+            out,
+            "{}: {}",
+            tr("Files updated (changed at source)"),
+            stats.files_updated
+        )?;
+    }
+    if stats.files_overwritten > 0 {
+        writeln!(
+            out,
+            "{}: {}",
+            tr("Files overwritten"),
+            stats.files_overwritten
+        )?;
+    }
+    if stats.files_renamed > 0 {
+        writeln!(
+            out,
+            "{}: {}",
+            tr("Files exported under a new name"),
+            stats.files_renamed
+        )?;
+    }
+    if stats.files_already_exist > 0 {
+        writeln!(
+            out,
+            "{}: {}",
+            tr("Files skipped (already exist)"),
+            stats.files_already_exist
+        )?;
+    }
+    if stats.files_missing > 0 {
+        writeln!(
+            out,
+            "{}: {}",
+            tr("Files missing (skipped)"),
+            stats.files_missing
+        )?;
+    }
+    if stats.duplicates_skipped > 0 {
+        writeln!(
+            out,
+            "{}: {}",
+            tr("Duplicate tracks (exported once)"),
+            stats.duplicates_skipped
+        )?;
+    }
+    if stats.source_placeholder > 0 {
+        writeln!(
+            out,
+            "{}: {}",
+            tr("Files empty or not downloaded (skipped)"),
+            stats.source_placeholder
+        )?;
+    }
+    if stats.audible_skipped > 0 {
+        writeln!(
+            out,
+            "{}: {}",
+            tr("Audible files Audiobookshelf can't play (skipped)"),
+            stats.audible_skipped
+        )?;
+    }
+    if !stats.extras.is_empty() {
+        writeln!(
+            out,
+            "{}: {} ({} {})",
+            tr("Extras found in book folders"),
+            stats.extras.len(),
+            stats.extras_copied,
+            tr("exported")
+        )?;
+    }
+    if stats.chapter_files_split > 0 {
+        writeln!(
+            out,
+            "{}: {}",
+            tr("Chapter files split"),
+            stats.chapter_files_split
+        )?;
+    }
+    if stats.chapter_files_written > 0 {
+        writeln!(
+            out,
+            "{}: {}",
+            tr("Chapter files written"),
+            stats.chapter_files_written
+        )?;
+    }
+    if stats.cue_files_written > 0 {
+        writeln!(
+            out,
+            "{}: {}",
+            tr("CUE sheets written"),
+            stats.cue_files_written
+        )?;
+    }
+    if stats.sources_removed > 0 {
+        writeln!(
+            out,
+            "{}: {}",
+            tr("Source files moved (verified and deleted)"),
+            stats.sources_removed
+        )?;
+    }
+    if stats.verification_failed > 0 {
+        writeln!(
+            out,
+            "{}: {}",
+            tr("Source files kept (verification failed)"),
+            stats.verification_failed
+        )?;
+    }
+    if !stats.failures.is_empty() {
+        writeln!(out, "{}: {}", tr("Files failed"), stats.failures.len())?;
+    }
+    if !stats.warnings.is_empty() {
+        writeln!(out, "{}: {}", tr("Warnings"), stats.warnings.len())?;
+    }
+    Ok(())
+}
+
+/// Print the end-of-run summary for an export
+pub fn print_export_summary(stats: &ExportStats) {
+    let _ = write_export_summary(&mut io::stdout().lock(), stats);
+}
+
+/// Email the summary of a run, warning if it can't be sent
+fn email_summary(config: &email::EmailConfig, dest: &Path, stats: &ExportStats) {
+    let subject = if stats.failures.is_empty() {
+        format!(
+            "extract_apple_books: {} files copied to {}",
+            stats.files_copied,
+            dest.display()
+        )
+    } else {
+        format!(
+            "extract_apple_books: {} files failed exporting to {}",
+            stats.failures.len(),
+            dest.display()
+        )
+    };
+
+    let mut body = Vec::new();
+    let _ = write_export_summary(&mut body, stats);
+    for failure in &stats.failures {
+        let _ = writeln!(
+            body,
+            "\n{}: {:?}\n    {}",
+            failure.book, failure.source, failure.error
+        );
+    }
+
+    if let Err(e) = email::send(config, &subject, &String::from_utf8_lossy(&body)) {
+        eprintln!("Warning: Could not email the run summary: {:#}", e);
+    }
+}
+
+/// In `--strict` mode, list the run's warnings and fail if there were any, or any failures
+pub fn check_strict(stats: &ExportStats) -> Result<()> {
+    if stats.warnings.is_empty() && stats.failures.is_empty() {
+        return Ok(());
+    }
+    if !stats.warnings.is_empty() {
+        output::message("\nWarnings:");
+        for warning in &stats.warnings {
+            output::message(format!("  {}", warning));
+        }
+    }
+    bail!(
+        "Strict mode: {} warnings and {} failed files",
+        stats.warnings.len(),
+        stats.failures.len()
+    )
+}
+
+/// Save the run report and point the user at it when files failed
+pub fn save_report(
+    path: &Path,
+    dest: &Path,
+    stats: ExportStats,
+    email: Option<&email::EmailConfig>,
+) -> Result<()> {
+    let failed = stats.failures.len();
+    if let Some(config) = email {
+        if failed > 0 || !config.only_on_failure {
+            email_summary(config, dest, &stats);
+        }
+    }
+    let run = report::RunReport::new(dest, stats);
+    run.write(path)?;
+    if let Err(e) = history::record(dest, &run) {
+        eprintln!("Warning: Could not record run in history: {:#}", e);
+    }
+
+    if failed > 0 {
+        output::message(format!(
+            "\n{}\n  extract_apple_books export --retry-failed {:?}",
+            tr_args("{} files failed. Retry just those with:", &[&failed]),
+            path
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use std::io::Write;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_sanitize_filename() {
+        assert_eq!(sanitize_filename("Normal Title"), "Normal Title");
+        assert_eq!(sanitize_filename("Title: Subtitle"), "Title_ Subtitle");
+        assert_eq!(sanitize_filename("What/Why"), "What_Why");
+        assert_eq!(
+            sanitize_filename("File<>Name*With?Bad|Chars"),
+            "File__Name_With_Bad_Chars"
+        );
+        assert_eq!(sanitize_filename("  Trimmed  "), "Trimmed");
+    }
+
+    #[test]
+    fn test_format_bytes_and_truncate() {
+        assert_eq!(format_bytes(512), "512 B");
+        assert_eq!(format_bytes(12_345_678), "12.3 MB");
+        assert_eq!(format_bytes(2_500_000_000), "2.5 GB");
+        assert_eq!(truncate("Short", 10), "Short");
+        assert_eq!(truncate("Brandon Sanderson - Warbreaker", 10), "Brandon S…");
+    }
+
+    #[test]
+    fn test_renumbered_filename() {
+        assert_eq!(renumbered_filename("01 Part.mp3", 15, 30), "15 Part.mp3");
+        assert_eq!(
+            renumbered_filename("02-Chapter.m4a", 3, 120),
+            "003 Chapter.m4a"
+        );
+        assert_eq!(renumbered_filename("01.mp3", 7, 9), "07.mp3");
+        assert_eq!(renumbered_filename("Intro.mp3", 1, 9), "01 Intro.mp3");
+        assert_eq!(
+            renumbered_filename("1984 Part 1.mp3", 2, 9),
+            "02 1984 Part 1.mp3"
+        );
+        assert_eq!(renumbered_filename("007.mp3", 3, 9), "03.mp3");
+        assert_eq!(
+            renumbered_filename("12Chapter.mp3", 4, 9),
+            "04 12Chapter.mp3"
+        );
+    }
+
+    #[test]
+    fn test_missing_tracks() {
+        let track = |disc_number, track_number| AudioTrack {
+            track_number,
+            disc_number,
+            title: String::new(),
+            path: PathBuf::from("/x.mp3"),
+            filename: "x.mp3".to_string(),
+        };
+        let mut tracks: Vec<_> = (1..=30).filter(|n| *n != 15).map(|n| track(1, n)).collect();
+        tracks.extend([track(2, 1), track(2, 4)]);
+
+        let book = Audiobook {
+            title: "Gappy".to_string(),
+            author: "Author".to_string(),
+            folder_id: "sha1-gap".to_string(),
+            tracks,
+            ..Default::default()
+        };
+
+        let missing = book.missing_tracks();
+        assert_eq!(
+            missing,
+            vec![
+                MissingTrack {
+                    disc_number: 1,
+                    track_number: 15
+                },
+                MissingTrack {
+                    disc_number: 2,
+                    track_number: 2
+                },
+                MissingTrack {
+                    disc_number: 2,
+                    track_number: 3
+                },
+            ]
+        );
+        assert_eq!(format_missing_tracks(&missing), "disc 1: 15, disc 2: 2-3");
+    }
+
+    #[test]
+    fn test_selected_with_skip_and_limit() {
+        let book = |author: &str, title: &str| Audiobook {
+            title: title.to_string(),
+            author: author.to_string(),
+            folder_id: format!("sha1-{}", title),
+            ..Default::default()
+        };
+        let audiobooks = vec![
+            book("Weir", "Project Hail Mary"),
+            book("Sanderson", "Warbreaker"),
+            book("Sanderson", "Elantris"),
+            book("Jemisin", "The Fifth Season"),
+        ];
+        let titles = |options: &ExportOptions| {
+            options
+                .selected(&audiobooks)
+                .iter()
+                .map(|b| b.title.as_str())
+                .collect::<Vec<_>>()
+        };
+
+        let mut options = ExportOptions::default();
+        assert_eq!(titles(&options).len(), 4);
+        options.limit = Some(2);
+        assert_eq!(titles(&options), ["The Fifth Season", "Elantris"]);
+        options.skip = 2;
+        assert_eq!(titles(&options), ["Warbreaker", "Project Hail Mary"]);
+        options.skip = 3;
+        options.limit = None;
+        assert_eq!(titles(&options), ["Project Hail Mary"]);
+    }
+
+    #[test]
+    fn test_create_audiobookshelf_path() {
+        let dest = PathBuf::from("/dest");
+
+        let book_without_narrator = Audiobook {
+            title: "The Great Book".to_string(),
+            author: "John Doe".to_string(),
+            folder_id: "sha1-abc123".to_string(),
+            ..Default::default()
+        };
+
+        let path =
+            create_audiobookshelf_path(&dest, &book_without_narrator, &ExportOptions::default());
+        assert_eq!(path, PathBuf::from("/dest/John Doe/The Great Book"));
+
+        let book_with_narrators = Audiobook {
+            narrator: Some("Bob Reader & Ann Voice".to_string()),
+            ..book_without_narrator.clone()
+        };
+        assert_eq!(
+            create_audiobookshelf_path(&dest, &book_with_narrators, &ExportOptions::default()),
+            dest.join("John Doe/The Great Book {Bob Reader, Ann Voice}")
+        );
+
+        let book_with_narrator = Audiobook {
+            title: "Another Book".to_string(),
+            author: "Jane Smith".to_string(),
+            narrator: Some("Bob Reader".to_string()),
+            folder_id: "sha1-def456".to_string(),
+            ..Default::default()
+        };
+
+        let path =
+            create_audiobookshelf_path(&dest, &book_with_narrator, &ExportOptions::default());
+        assert_eq!(
+            path,
+            PathBuf::from("/dest/Jane Smith/Another Book {Bob Reader}")
+        );
+    }
+
+    #[test]
+    fn test_author_folder() {
+        let book = Audiobook {
+            title: "The Long Earth".to_string(),
+            author: "Terry Pratchett & Stephen Baxter".to_string(),
+            folder_id: "sha1-abc123".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(book.authors(), ["Terry Pratchett", "Stephen Baxter"]);
+        assert_eq!(
+            AuthorFolder::Listed.name(&book, AuthorFormat::FirstLast),
+            "Terry Pratchett & Stephen Baxter"
+        );
+        assert_eq!(
+            AuthorFolder::First.name(&book, AuthorFormat::FirstLast),
+            "Terry Pratchett"
+        );
+        assert_eq!(
+            AuthorFolder::Various.name(&book, AuthorFormat::FirstLast),
+            "Various"
+        );
+
+        let book = Audiobook {
+            author: "Jane Roe, John Doe; Max Mustermann".to_string(),
+            ..book
+        };
+        assert_eq!(
+            AuthorFolder::All.name(&book, AuthorFormat::FirstLast),
+            "Jane Roe & John Doe & Max Mustermann"
+        );
+        let options = ExportOptions {
+            author_folder: AuthorFolder::First,
+            author_format: AuthorFormat::LastFirst,
+            ..Default::default()
+        };
+        let path = create_audiobookshelf_path(Path::new("/dest"), &book, &options);
+        assert_eq!(path, PathBuf::from("/dest/Roe, Jane/The Long Earth"));
+
+        let book = Audiobook {
+            author: "Andy Weir".to_string(),
+            ..book
+        };
+        assert_eq!(
+            AuthorFolder::Various.name(&book, AuthorFormat::FirstLast),
+            "Andy Weir"
+        );
+        assert_eq!(
+            AuthorFolder::All.name(&book, AuthorFormat::FirstLast),
+            "Andy Weir"
+        );
+    }
+
+    #[test]
+    fn test_last_first() {
+        assert_eq!(last_first("Brandon Sanderson"), "Sanderson, Brandon");
+        assert_eq!(last_first("J. R. R. Tolkien"), "Tolkien, J. R. R.");
+        assert_eq!(last_first("Ursula K. Le Guin"), "Le Guin, Ursula K.");
+        assert_eq!(last_first("Ludwig van Beethoven"), "van Beethoven, Ludwig");
+        assert_eq!(
+            last_first("Martin Luther King Jr."),
+            "King, Martin Luther, Jr."
+        );
+        assert_eq!(last_first("Homer"), "Homer");
+        assert_eq!(last_first("Sanderson, Brandon"), "Sanderson, Brandon");
+
+        let book = Audiobook {
+            title: "The Long Earth".to_string(),
+            author: "Terry Pratchett & Stephen Baxter".to_string(),
+            folder_id: "sha1-abc123".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            AuthorFolder::Listed.name(&book, AuthorFormat::LastFirst),
+            "Pratchett, Terry & Baxter, Stephen"
+        );
+    }
+
+    #[test]
+    fn test_create_audiobookshelf_path_with_series() {
+        let book = Audiobook {
+            title: "Warbreaker".to_string(),
+            author: "Brandon Sanderson".to_string(),
+            series: Some("Cosmere: Nalthis".to_string()),
+            folder_id: "sha1-abc123".to_string(),
+            ..Default::default()
+        };
+
+        let path = create_audiobookshelf_path(Path::new("/dest"), &book, &ExportOptions::default());
+        assert_eq!(
+            path,
+            PathBuf::from("/dest/Brandon Sanderson/Cosmere_ Nalthis/Warbreaker")
+        );
+
+        // Detected series only get a folder in the series layout
+        let book = Audiobook {
+            title: "Warbreaker (Cosmere: Nalthis #3)".to_string(),
+            narrator: Some("Kate Reading".to_string()),
+            series_sequence: Some("3".to_string()),
+            title_in_series: Some("Warbreaker".to_string()),
+            series_detected: true,
+            ..book
+        };
+        let path = create_audiobookshelf_path(Path::new("/dest"), &book, &ExportOptions::default());
+        assert_eq!(
+            path,
+            PathBuf::from(
+                "/dest/Brandon Sanderson/Warbreaker (Cosmere_ Nalthis #3) {Kate Reading}"
+            )
+        );
+        let options = ExportOptions {
+            template: template::Layout::Series.template(),
+            ..Default::default()
+        };
+        assert_eq!(
+            create_audiobookshelf_path(Path::new("/dest"), &book, &options),
+            PathBuf::from(
+                "/dest/Brandon Sanderson/Cosmere_ Nalthis/Vol 3 - Warbreaker {Kate Reading}"
+            )
+        );
+    }
+
+    #[test]
+    fn test_create_audiobookshelf_path_with_special_chars() {
+        let dest = PathBuf::from("/dest");
+
+        let book = Audiobook {
+            title: "Book: A Subtitle".to_string(),
+            author: "Author/Writer".to_string(),
+            narrator: Some("Narrator: The Voice".to_string()),
+            folder_id: "sha1-abc123".to_string(),
+            ..Default::default()
+        };
+
+        let path = create_audiobookshelf_path(&dest, &book, &ExportOptions::default());
+        assert_eq!(
+            path,
+            PathBuf::from("/dest/Author_Writer/Book_ A Subtitle {Narrator_ The Voice}")
+        );
+
+        // Split titles keep their folder name
+        let book = Audiobook {
+            title: "Book".to_string(),
+            subtitle: Some("A Subtitle".to_string()),
+            ..book
+        };
+        assert_eq!(
+            create_audiobookshelf_path(&dest, &book, &ExportOptions::default()),
+            path
+        );
+    }
+
+    #[test]
+    fn test_remap_track_path() {
+        let source_base = PathBuf::from("/Volumes/charlie/Library/Containers/com.apple.BKAgentService/Data/Documents/iBooks/Books");
+
+        let original_path = PathBuf::from(
+            "/Users/charlie/Library/Containers/com.apple.BKAgentService/Data/Documents/iBooks/Books/Audiobooks/sha1-abc123/01 Track.mp3"
+        );
+
+        let remapped = remap_track_path(&original_path, &source_base);
+
+        assert_eq!(
+            remapped,
+            PathBuf::from("/Volumes/charlie/Library/Containers/com.apple.BKAgentService/Data/Documents/iBooks/Books/Audiobooks/sha1-abc123/01 Track.mp3")
+        );
+    }
+
+    #[test]
+    fn test_parse_audiobook_entry_non_audiobook() {
+        let mut dict = plist::Dictionary::new();
+        dict.insert("BKBookType".to_string(), Value::String("ebook".to_string()));
+
+        let value = Value::Dictionary(dict);
+        let result = parse_audiobook_entry(&value).unwrap();
+        assert!(result.is_none());
+    }
+
+    #[test]
+    fn test_plain_text() {
+        assert_eq!(plain_text("A plain description."), "A plain description.");
+        assert_eq!(
+            plain_text("<p>First &amp; <b>bold</b>.</p><p>Second<br/>line</p>"),
+            "First & bold.\n\nSecond\nline"
+        );
+        assert_eq!(plain_text("  <i>Tom</i> &lt;3 Jerry  "), "Tom <3 Jerry");
+    }
+
+    #[test]
+    fn test_parse_audiobook_entry_valid() {
+        let mut track1 = plist::Dictionary::new();
+        track1.insert(
+            "itemName".to_string(),
+            Value::String("Test Book".to_string()),
+        );
+        track1.insert("BKTrackNumber".to_string(), Value::Integer(1.into()));
+        track1.insert("BKDiscNumber".to_string(), Value::Integer(0.into()));
+        track1.insert(
+            "BKTrackTitle".to_string(),
+            Value::String("Chapter 1".to_string()),
+        );
+        track1.insert(
+            "path".to_string(),
+            Value::String("/path/to/track1.mp3".to_string()),
+        );
+
+        let mut track2 = plist::Dictionary::new();
+        track2.insert(
+            "itemName".to_string(),
+            Value::String("Test Book".to_string()),
+        );
+        track2.insert("BKTrackNumber".to_string(), Value::Integer(2.into()));
+        track2.insert("BKDiscNumber".to_string(), Value::Integer(0.into()));
+        track2.insert(
+            "BKTrackTitle".to_string(),
+            Value::String("Chapter 2".to_string()),
+        );
+        track2.insert(
+            "path".to_string(),
+            Value::String("/path/to/track2.mp3".to_string()),
+        );
+
+        let parts = vec![Value::Dictionary(track1), Value::Dictionary(track2)];
+
+        let mut dict = plist::Dictionary::new();
+        dict.insert(
+            "BKBookType".to_string(),
+            Value::String("audiobook".to_string()),
+        );
+        dict.insert(
+            "BKGeneratedItemId".to_string(),
+            Value::String("sha1-abc123".to_string()),
+        );
+        dict.insert(
+            "artistName".to_string(),
+            Value::String("Test Author".to_string()),
+        );
+        dict.insert("BKParts".to_string(), Value::Array(parts));
+        dict.insert(
+            "description".to_string(),
+            Value::String(" A test book. ".to_string()),
+        );
+        dict.insert("genre".to_string(), Value::String("Fantasy".to_string()));
+        dict.insert("year".to_string(), Value::Integer(2009.into()));
+        dict.insert(
+            "copyright".to_string(),
+            Value::String("©2009 Test Author".to_string()),
+        );
+        dict.insert("storeId".to_string(), Value::Integer(1_234_567_890.into()));
+        let purchased = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_682_899_200);
+        dict.insert("BKPurchaseDate".to_string(), Value::Date(purchased.into()));
+
+        let value = Value::Dictionary(dict);
+        let result = parse_audiobook_entry(&value).unwrap();
+
+        assert!(result.is_some());
+        let audiobook = result.unwrap();
+
+        assert_eq!(audiobook.title, "Test Book");
+        assert_eq!(audiobook.author, "Test Author");
+        assert_eq!(audiobook.folder_id, "sha1-abc123");
+        assert_eq!(audiobook.tracks.len(), 2);
+        assert_eq!(audiobook.tracks[0].track_number, 1);
+        assert_eq!(audiobook.tracks[1].track_number, 2);
+        assert_eq!(audiobook.added, Some(purchased));
+        assert_eq!(audiobook.description.as_deref(), Some("A test book."));
+        assert_eq!(
+            audiobook.details,
+            BookDetails {
+                genre: Some("Fantasy".to_string()),
+                year: Some("2009".to_string()),
+                publisher: None,
+                copyright: Some("©2009 Test Author".to_string()),
+                isbn: None,
+                asin: None,
+                store_id: Some("1234567890".to_string()),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_audiobook_entry_series_and_narrators() {
+        let entry = |title: &str, fields: &[(&str, Value)]| {
+            let mut part = plist::Dictionary::new();
+            part.insert("itemName".to_string(), Value::String(title.to_string()));
+            part.insert(
+                "path".to_string(),
+                Value::String("/path/to/track1.mp3".to_string()),
+            );
+            let mut dict = plist::Dictionary::new();
+            dict.insert(
+                "BKBookType".to_string(),
+                Value::String("audiobook".to_string()),
+            );
+            dict.insert(
+                "BKParts".to_string(),
+                Value::Array(vec![Value::Dictionary(part)]),
+            );
+            for (key, value) in fields {
+                dict.insert(key.to_string(), value.clone());
+            }
+            parse_audiobook_entry(&Value::Dictionary(dict))
+                .unwrap()
+                .unwrap()
+        };
+
+        let book = entry("Mistborn (Mistborn, Book 1)", &[]);
+        assert_eq!(book.title, "Mistborn (Mistborn, Book 1)");
+        assert_eq!(book.title_in_series.as_deref(), Some("Mistborn"));
+        assert_eq!(book.series.as_deref(), Some("Mistborn"));
+        assert_eq!(book.series_sequence.as_deref(), Some("1"));
+        assert!(book.series_detected);
+
+        // What the store recorded wins over the title
+        let book = entry(
+            "Elantris (Cosmere #1)",
+            &[
+                ("seriesName", Value::String("Elantris".to_string())),
+                ("seriesNumber", Value::Integer(1.into())),
+            ],
+        );
+        assert_eq!(book.title, "Elantris (Cosmere #1)");
+        assert_eq!(book.series.as_deref(), Some("Elantris"));
+        assert_eq!(book.series_sequence.as_deref(), Some("1"));
+
+        let book = entry(
+            "Warbreaker",
+            &[("seriesNumber", Value::String("2".to_string()))],
+        );
+        assert_eq!(book.series, None);
+        assert_eq!(book.series_sequence, None);
+
+        let book = entry("Sapiens: A Brief History of Humankind", &[]);
+        assert_eq!(book.title, "Sapiens");
+        assert_eq!(
+            book.subtitle.as_deref(),
+            Some("A Brief History of Humankind")
+        );
+        assert_eq!(book.full_title(), "Sapiens: A Brief History of Humankind");
+        assert_eq!(book.series, None);
+
+        let book = entry(
+            "Dune (Narrated by Scott Brick, Euan Morton and Simon Vance)",
+            &[],
+        );
+        assert_eq!(book.title, "Dune");
+        assert_eq!(
+            book.narrators(),
+            ["Scott Brick", "Euan Morton", "Simon Vance"]
+        );
+
+        let book = entry(
+            "Dune, read by Someone Else",
+            &[("narrator", Value::String("Scott Brick".to_string()))],
+        );
+        assert_eq!(book.title, "Dune");
+        assert_eq!(book.narrator.as_deref(), Some("Scott Brick"));
+    }
+
+    #[test]
+    fn test_set_folder_date() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("01.mp3"), b"audio").unwrap();
+        let added = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_682_899_200);
+
+        set_folder_date(dir.path(), added).unwrap();
+        let modified = fs::metadata(dir.path()).unwrap().modified().unwrap();
+        assert_eq!(modified, added);
+    }
+
+    #[test]
+    fn test_export_creates_directory_structure() {
+        let temp_source = tempdir().unwrap();
+        let temp_dest = tempdir().unwrap();
+
+        // Create a mock audiobook folder with a track
+        // Source base is like: /path/to/iBooks/Books
+        let audiobook_dir = temp_source.path().join("Audiobooks/sha1-test123");
+        fs::create_dir_all(&audiobook_dir).unwrap();
+
+        let track_file = audiobook_dir.join("01 Chapter 1.mp3");
+        let mut file = File::create(&track_file).unwrap();
+        file.write_all(&[0u8; MIN_AUDIO_FILE_SIZE as usize])
+            .unwrap();
+
+        let audiobook = Audiobook {
+            title: "Test Book".to_string(),
+            author: "Test Author".to_string(),
+            folder_id: "sha1-test123".to_string(),
+            tracks: vec![AudioTrack {
+                track_number: 1,
+                disc_number: 0,
+                title: "Chapter 1".to_string(),
+                path: PathBuf::from("/Users/charlie/Library/Containers/com.apple.BKAgentService/Data/Documents/iBooks/Books/Audiobooks/sha1-test123/01 Chapter 1.mp3"),
+                filename: "01 Chapter 1.mp3".to_string(),
+            }],
+            ..Default::default()
+        };
+
+        let stats = export_audiobooks(
+            &[audiobook],
+            temp_source.path(),
+            temp_dest.path(),
+            &ExportOptions::default(),
+        )
+        .unwrap();
+
+        assert_eq!(stats.books_exported, 1);
+        assert_eq!(stats.files_copied, 1);
+
+        // Check the directory structure was created
+        let expected_dir = temp_dest.path().join("Test Author/Test Book");
+        assert!(expected_dir.exists());
+
+        let expected_file = expected_dir.join("01 Chapter 1.mp3");
+        assert!(expected_file.exists());
+    }
+
+    #[test]
+    fn test_export_writes_companion_files() {
+        let temp_source = tempdir().unwrap();
+        let temp_dest = tempdir().unwrap();
+        let audiobook_dir = temp_source.path().join("Audiobooks/sha1-test123");
+        fs::create_dir_all(&audiobook_dir).unwrap();
+        fs::write(
+            audiobook_dir.join("01.mp3"),
+            [0u8; MIN_AUDIO_FILE_SIZE as usize],
+        )
+        .unwrap();
+
+        let audiobook = Audiobook {
+            title: "Test Book".to_string(),
+            author: "Test Author".to_string(),
+            narrator: Some("Kate Reading".to_string()),
+            folder_id: "sha1-test123".to_string(),
+            tracks: vec![AudioTrack {
+                track_number: 1,
+                disc_number: 0,
+                title: String::new(),
+                path: PathBuf::from("/Users/x/Books/Audiobooks/sha1-test123/01.mp3"),
+                filename: "01.mp3".to_string(),
+            }],
+            description: Some("A test.".to_string()),
+            ..Default::default()
+        };
+        let dest_dir =
+            create_audiobookshelf_path(temp_dest.path(), &audiobook, &ExportOptions::default());
+        export_audiobooks(
+            &[audiobook],
+            temp_source.path(),
+            temp_dest.path(),
+            &ExportOptions::default(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            fs::read_to_string(dest_dir.join(DESCRIPTION_FILE)).unwrap(),
+            "A test."
+        );
+        assert_eq!(
+            fs::read_to_string(dest_dir.join(READER_FILE)).unwrap(),
+            "Kate Reading"
+        );
+    }
+
+    #[test]
+    fn test_export_skips_placeholder_files() {
+        let temp_source = tempdir().unwrap();
+        let temp_dest = tempdir().unwrap();
+
+        let audiobook_dir = temp_source.path().join("Audiobooks/sha1-empty");
+        fs::create_dir_all(&audiobook_dir).unwrap();
+        File::create(audiobook_dir.join("01.mp3")).unwrap();
+
+        let audiobook = Audiobook {
+            title: "Empty Book".to_string(),
+            author: "Test Author".to_string(),
+            folder_id: "sha1-empty".to_string(),
+            tracks: vec![AudioTrack {
+                track_number: 1,
+                disc_number: 0,
+                title: "Chapter 1".to_string(),
+                path: PathBuf::from("/Users/x/Books/Audiobooks/sha1-empty/01.mp3"),
+                filename: "01.mp3".to_string(),
+            }],
+            ..Default::default()
+        };
+
+        let diffs = compute_diff(
+            std::slice::from_ref(&audiobook),
+            temp_source.path(),
+            temp_dest.path(),
+            &ExportOptions::default(),
+        );
+        assert_eq!(diffs[0].status, FileStatus::SourcePlaceholder);
+
+        let stats = export_audiobooks(
+            &[audiobook],
+            temp_source.path(),
+            temp_dest.path(),
+            &ExportOptions::default(),
+        )
+        .unwrap();
+
+        assert_eq!(stats.source_placeholder, 1);
+        assert_eq!(stats.files_copied, 0);
+        assert_eq!(stats.book_results[0].status, report::BookStatus::Failed);
+        assert!(!temp_dest
+            .path()
+            .join("Test Author/Empty Book/01.mp3")
+            .exists());
+    }
+
+    #[test]
+    fn test_export_skips_audible_files() {
+        let temp_source = tempdir().unwrap();
+        let temp_dest = tempdir().unwrap();
+
+        let audiobook_dir = temp_source.path().join("Audiobooks/sha1-audible");
+        fs::create_dir_all(&audiobook_dir).unwrap();
+        fs::write(audiobook_dir.join("Book.aax"), b"encrypted audio").unwrap();
+
+        let audiobook = Audiobook {
+            title: "Audible Book".to_string(),
+            author: "Test Author".to_string(),
+            folder_id: "sha1-audible".to_string(),
+            tracks: vec![AudioTrack {
+                track_number: 1,
+                disc_number: 0,
+                title: "Book".to_string(),
+                path: PathBuf::from("/Users/x/Books/Audiobooks/sha1-audible/Book.aax"),
+                filename: "Book.aax".to_string(),
+            }],
+            ..Default::default()
+        };
+
+        let stats = export_audiobooks(
+            std::slice::from_ref(&audiobook),
+            temp_source.path(),
+            temp_dest.path(),
+            &ExportOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(stats.audible_skipped, 1);
+        assert_eq!(stats.files_copied, 0);
+        assert_eq!(stats.book_results[0].status, report::BookStatus::Failed);
+
+        let options = ExportOptions {
+            activation_bytes: Some("1a2b3c4d".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(options.dest_filename(&audiobook, 0), "Book.m4b");
+    }
+
+    #[test]
+    fn test_retry_failed_copies_only_failed_files() {
+        let temp_source = tempdir().unwrap();
+        let temp_dest = tempdir().unwrap();
+
+        let source = temp_source.path().join("01.mp3");
+        fs::write(&source, [1u8; MIN_AUDIO_FILE_SIZE as usize]).unwrap();
+        let dest = temp_dest.path().join("Author/Book/01.mp3");
+        fs::create_dir_all(dest.parent().unwrap()).unwrap();
+        fs::write(&dest, b"truncated").unwrap();
+
+        let failed = vec![
+            report::FailedFile {
+                book: "Author - Book".to_string(),
+                folder_id: "sha1-retry".to_string(),
+                source: source.clone(),
+                dest: dest.clone(),
+                error: "Input/output error".to_string(),
+            },
+            report::FailedFile {
+                book: "Author - Book".to_string(),
+                folder_id: "sha1-retry".to_string(),
+                source: temp_source.path().join("gone.mp3"),
+                dest: temp_dest.path().join("Author/Book/gone.mp3"),
+                error: "Input/output error".to_string(),
+            },
+        ];
+
+        let stats = retry_failed(&failed, &ExportOptions::default()).unwrap();
+
+        assert_eq!(stats.books_exported, 1);
+        assert_eq!(stats.files_copied, 1);
+        assert_eq!(stats.source_missing, 1);
+        assert_eq!(stats.failures.len(), 1);
+        assert_eq!(fs::read(&dest).unwrap(), fs::read(&source).unwrap());
+    }
+
+    #[test]
+    fn test_transcode_failures_are_not_counted_as_files() {
+        let temp_source = tempdir().unwrap();
+        let temp_dest = tempdir().unwrap();
+
+        let audiobook_dir = temp_source.path().join("Audiobooks/sha1-fail");
+        fs::create_dir_all(&audiobook_dir).unwrap();
+        fs::write(audiobook_dir.join("01.aax"), [1u8; 5000]).unwrap();
+        fs::write(audiobook_dir.join("02.mp3"), [2u8; 5000]).unwrap();
+        fs::write(audiobook_dir.join("03.mp3"), [3u8; 5000]).unwrap();
+
+        let track = |n: u32, extension: &str| AudioTrack {
+            track_number: n,
+            disc_number: 0,
+            title: String::new(),
+            path: PathBuf::from(format!(
+                "/Users/x/Books/Audiobooks/sha1-fail/0{}.{}",
+                n, extension
+            )),
+            filename: format!("0{}.{}", n, extension),
+        };
+        let audiobook = Audiobook {
+            title: "Failing Book".to_string(),
+            author: "Author".to_string(),
+            folder_id: "sha1-fail".to_string(),
+            tracks: vec![track(1, "aax"), track(2, "mp3"), track(3, "mp3")],
+            ..Default::default()
+        };
+        // The source files aren't audio, so the conversion and the transcode jobs fail
+        let stats = export_audiobooks(
+            &[audiobook],
+            temp_source.path(),
+            temp_dest.path(),
+            &ExportOptions {
+                transcode: Some("opus".parse().unwrap()),
+                activation_bytes: Some("1a2b3c4d".to_string()),
+                jobs: 1,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(stats.failures.len(), 3);
+        assert_eq!(stats.book_results[0].files, 0);
+        assert_eq!(stats.book_results[0].bytes, 0);
+        assert_eq!(stats.book_results[0].status, report::BookStatus::Failed);
+    }
+
+    #[test]
+    fn test_move_deletes_only_verified_sources() {
+        let temp_source = tempdir().unwrap();
+        let temp_dest = tempdir().unwrap();
+
+        let audiobook_dir = temp_source.path().join("Audiobooks/sha1-move");
+        fs::create_dir_all(&audiobook_dir).unwrap();
+        fs::write(audiobook_dir.join("01.mp3"), [1u8; 5000]).unwrap();
+        fs::write(audiobook_dir.join("02.mp3"), [2u8; 5000]).unwrap();
+
+        // A stale, different copy of track 2 already sits at the destination
+        let dest_dir = temp_dest.path().join("Author/Moved Book");
+        fs::create_dir_all(&dest_dir).unwrap();
+        fs::write(dest_dir.join("02.mp3"), [9u8; 5000]).unwrap();
+
+        let track = |n: u32| AudioTrack {
+            track_number: n,
+            disc_number: 0,
+            title: String::new(),
+            path: PathBuf::from(format!("/Users/x/Books/Audiobooks/sha1-move/0{}.mp3", n)),
+            filename: format!("0{}.mp3", n),
+        };
+        let audiobook = Audiobook {
+            title: "Moved Book".to_string(),
+            author: "Author".to_string(),
+            folder_id: "sha1-move".to_string(),
+            tracks: vec![track(1), track(2)],
+            ..Default::default()
+        };
+
+        let stats = export_audiobooks(
+            &[audiobook],
+            temp_source.path(),
+            temp_dest.path(),
+            &ExportOptions {
+                move_sources: true,
+                disposal: trash::Disposal::Permanent,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(stats.sources_removed, 1);
+        assert_eq!(stats.verification_failed, 1);
+        assert_eq!(stats.book_results[0].files, 1);
+        assert_eq!(stats.book_results[0].bytes, 5000);
+        assert_eq!(stats.book_results[0].status, report::BookStatus::Ok);
+        assert!(!audiobook_dir.join("01.mp3").exists());
+        assert!(audiobook_dir.join("02.mp3").exists());
+        assert!(dest_dir.join("01.mp3").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_rollback_restores_rewritten_companions() {
+        let temp_source = tempdir().unwrap();
+        let temp_dest = tempdir().unwrap();
+
+        let audiobook_dir = temp_source.path().join("Audiobooks/sha1-desc");
+        fs::create_dir_all(&audiobook_dir).unwrap();
+        fs::write(audiobook_dir.join("01.mp3"), [1u8; 5000]).unwrap();
+
+        let mut audiobook = Audiobook {
+            title: "Book".to_string(),
+            author: "Author".to_string(),
+            folder_id: "sha1-desc".to_string(),
+            description: Some("First blurb".to_string()),
+            tracks: vec![AudioTrack {
+                track_number: 1,
+                disc_number: 0,
+                title: String::new(),
+                path: PathBuf::from("/Users/x/Books/Audiobooks/sha1-desc/01.mp3"),
+                filename: "01.mp3".to_string(),
+            }],
+            ..Default::default()
+        };
+        let options = ExportOptions {
+            snapshot: true,
+            ..Default::default()
+        };
+        let export = |audiobook: &Audiobook| {
+            export_audiobooks(
+                std::slice::from_ref(audiobook),
+                temp_source.path(),
+                temp_dest.path(),
+                &options,
+            )
+            .unwrap()
+        };
+        let book_dir = temp_dest.path().join("Author/Book");
+        let read = |name: &str| fs::read_to_string(book_dir.join(name)).unwrap();
+
+        export(&audiobook);
+        audiobook.description = Some("Second blurb".to_string());
+        export(&audiobook);
+        assert_eq!(read(DESCRIPTION_FILE), "Second blurb");
+
+        let id = rollback::list(temp_dest.path()).unwrap().pop().unwrap();
+        let restored = rollback::restore(temp_dest.path(), &id).unwrap();
+        assert_eq!(restored.restored, 1);
+        assert_eq!(read(DESCRIPTION_FILE), "First blurb");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_move_keeps_sources_of_symlinked_exports() {
+        let temp_source = tempdir().unwrap();
+        let temp_dest = tempdir().unwrap();
+
+        let audiobook_dir = temp_source.path().join("Audiobooks/sha1-link");
+        fs::create_dir_all(&audiobook_dir).unwrap();
+        fs::write(audiobook_dir.join("01.mp3"), [1u8; 5000]).unwrap();
+
+        let audiobook = Audiobook {
+            title: "Linked Book".to_string(),
+            author: "Author".to_string(),
+            folder_id: "sha1-link".to_string(),
+            tracks: vec![AudioTrack {
+                track_number: 1,
+                disc_number: 0,
+                title: String::new(),
+                path: PathBuf::from("/Users/x/Books/Audiobooks/sha1-link/01.mp3"),
+                filename: "01.mp3".to_string(),
+            }],
+            ..Default::default()
+        };
+        let export = |options: &ExportOptions| {
+            export_audiobooks(
+                std::slice::from_ref(&audiobook),
+                temp_source.path(),
+                temp_dest.path(),
+                options,
+            )
+            .unwrap()
+        };
+
+        export(&ExportOptions {
+            use_symlink: true,
+            ..Default::default()
+        });
+        let stats = export(&ExportOptions {
+            move_sources: true,
+            disposal: trash::Disposal::Permanent,
+            ..Default::default()
+        });
+
+        assert_eq!(stats.sources_removed, 0);
+        assert_eq!(stats.verification_failed, 1);
+        assert!(audiobook_dir.join("01.mp3").exists());
+        assert_eq!(
+            fs::read(temp_dest.path().join("Author/Linked Book/01.mp3")).unwrap(),
+            [1u8; 5000]
+        );
+    }
+
+    #[test]
+    fn test_export_updates_changed_sources() {
+        let temp_source = tempdir().unwrap();
+        let temp_dest = tempdir().unwrap();
+
+        let audiobook_dir = temp_source.path().join("Audiobooks/sha1-update");
+        fs::create_dir_all(&audiobook_dir).unwrap();
+        fs::write(audiobook_dir.join("01.mp3"), [1u8; 5000]).unwrap();
+        fs::write(audiobook_dir.join("02.mp3"), [2u8; 5000]).unwrap();
+
+        let track = |n: u32| AudioTrack {
+            track_number: n,
+            disc_number: 0,
+            title: String::new(),
+            path: PathBuf::from(format!("/Users/x/Books/Audiobooks/sha1-update/0{}.mp3", n)),
+            filename: format!("0{}.mp3", n),
+        };
+        let audiobook = Audiobook {
+            title: "Book".to_string(),
+            author: "Author".to_string(),
+            folder_id: "sha1-update".to_string(),
+            tracks: vec![track(1), track(2)],
+            ..Default::default()
+        };
+        let export = || {
+            export_audiobooks(
+                std::slice::from_ref(&audiobook),
+                temp_source.path(),
+                temp_dest.path(),
+                &ExportOptions::default(),
+            )
+            .unwrap()
+        };
+
+        assert_eq!(export().files_copied, 2);
+
+        // Apple re-delivers a fixed track 2
+        fs::write(audiobook_dir.join("02.mp3"), [3u8; 6000]).unwrap();
+        let stats = export();
+        assert_eq!(stats.files_updated, 1);
+        assert_eq!(stats.files_already_exist, 1);
+        assert_eq!(
+            fs::read(temp_dest.path().join("Author/Book/02.mp3")).unwrap(),
+            [3u8; 6000]
+        );
+
+        // Nothing changed since: the manifest vouches for the book
+        let stats = export();
+        assert_eq!(stats.books_unchanged, 1);
+        assert_eq!(stats.files_already_exist, 0);
+    }
+
+    #[test]
+    fn test_force_overwrites_only_selected_books() {
+        let temp_source = tempdir().unwrap();
+        let temp_dest = tempdir().unwrap();
+
+        let book = |id: &str| {
+            let dir = temp_source.path().join("Audiobooks").join(id);
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("01.mp3"), [1u8; 5000]).unwrap();
+            let dest_dir = temp_dest.path().join("Author").join(id);
+            fs::create_dir_all(&dest_dir).unwrap();
+            fs::write(dest_dir.join("01.mp3"), b"truncated").unwrap();
+            Audiobook {
+                title: id.to_string(),
+                author: "Author".to_string(),
+                folder_id: id.to_string(),
+                tracks: vec![AudioTrack {
+                    track_number: 1,
+                    disc_number: 0,
+                    title: String::new(),
+                    path: PathBuf::from(format!("/Users/x/Books/Audiobooks/{}/01.mp3", id)),
+                    filename: "01.mp3".to_string(),
+                }],
+                ..Default::default()
+            }
+        };
+        let audiobooks = vec![book("sha1-fix"), book("sha1-keep")];
+
+        let stats = export_audiobooks(
+            &audiobooks,
+            temp_source.path(),
+            temp_dest.path(),
+            &ExportOptions {
+                on_conflict: conflict::ConflictStrategy::Overwrite,
+                book_ids: vec!["sha1-fix".to_string()],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(stats.books_exported, 1);
+        assert_eq!(stats.files_overwritten, 1);
+        let exported =
+            |id: &str| fs::read(temp_dest.path().join("Author").join(id).join("01.mp3")).unwrap();
+        assert_eq!(exported("sha1-fix"), [1u8; 5000]);
+        assert_eq!(exported("sha1-keep"), b"truncated");
+    }
+
+    #[test]
+    fn test_export_warns_about_folder_collisions() {
+        let temp_source = tempdir().unwrap();
+        let temp_dest = tempdir().unwrap();
+
+        let book = |id: &str, title: &str| {
+            let dir = temp_source.path().join("Audiobooks").join(id);
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join(format!("{}.mp3", id)), [1u8; 5000]).unwrap();
+            Audiobook {
+                title: title.to_string(),
+                author: "Author".to_string(),
+                folder_id: id.to_string(),
+                tracks: vec![AudioTrack {
+                    track_number: 1,
+                    disc_number: 0,
+                    title: String::new(),
+                    path: PathBuf::from(format!("/Users/x/Books/Audiobooks/{0}/{0}.mp3", id)),
+                    filename: format!("{}.mp3", id),
+                }],
+                ..Default::default()
+            }
+        };
+        let audiobooks = vec![book("sha1-a", "Dune"), book("sha1-b", "DUNE")];
+
+        let stats = export_audiobooks(
+            &audiobooks,
+            temp_source.path(),
+            temp_dest.path(),
+            &ExportOptions::default(),
+        )
+        .unwrap();
+
+        assert_eq!(stats.warnings.len(), 1);
+        assert!(stats.warnings[0].contains("Dune (sha1-a) and DUNE (sha1-b) both export to"));
+        assert!(check_strict(&stats).is_err());
+        assert!(check_strict(&ExportStats::default()).is_ok());
+    }
+
+    #[test]
+    fn test_preview_tree() {
+        let track = |n: u32| AudioTrack {
+            track_number: n,
+            disc_number: 0,
+            title: String::new(),
+            path: PathBuf::from(format!("/Users/x/Books/Audiobooks/sha1-prev/0{}.mp3", n)),
+            filename: format!("0{}.mp3", n),
+        };
+        let audiobook = Audiobook {
+            title: "Book".to_string(),
+            author: "Author".to_string(),
+            narrator: Some("Reader".to_string()),
+            folder_id: "sha1-prev".to_string(),
+            tracks: vec![track(1), track(2)],
+            ..Default::default()
+        };
+        let options = ExportOptions {
+            write_chapters: true,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            preview_tree(&audiobook, Path::new("/nonexistent"), &options),
+            "Author/\n\
+             └── Book {Reader}/\n    \
+             ├── 01.mp3 (source missing)\n    \
+             ├── 02.mp3 (source missing)\n    \
+             ├── chapters.json\n    \
+             └── reader.txt"
+        );
+    }
+
+    #[test]
+    fn test_write_diff_full_lists_every_book() {
+        let diffs: Vec<_> = (0..25)
+            .map(|i| FileDiff {
+                source_path: PathBuf::from(format!("/src/{}.mp3", i)),
+                dest_path: PathBuf::from(format!("/dest/Author/Book {:02}/01.mp3", i)),
+                status: FileStatus::New,
+                book_title: format!("Book {:02}", i),
+                author: "Author".to_string(),
+            })
+            .collect();
+
+        let render = |full| {
+            let mut out = Vec::new();
+            write_diff(&mut out, &diffs, full).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        let summary = render(false);
+        assert!(summary.contains("Author - Book 19 (1 files)"));
+        assert!(!summary.contains("Author - Book 20"));
+        assert!(summary.contains("... and 5 more books"));
+
+        let full = render(true);
+        assert!(full.contains("Author - Book 24 (1 files)"));
+        assert!(full.contains("/dest/Author/Book 24/01.mp3"));
+        assert!(!full.contains("more books"));
+    }
+
+    #[test]
+    fn test_write_diff_paths_and_by_book() {
+        let diff = |book: &str, file: &str, status| FileDiff {
+            source_path: PathBuf::from(format!("/src/{}", file)),
+            dest_path: PathBuf::from(format!("/dest/Author/{}/{}", book, file)),
+            status,
+            book_title: book.to_string(),
+            author: "Author".to_string(),
+        };
+        let diffs = vec![
+            diff("Book A", "01.mp3", FileStatus::New),
+            diff("Book A", "02.mp3", FileStatus::New),
+            diff("Book A", "03.mp3", FileStatus::SourceMissing),
+            diff("Book B", "01.mp3", FileStatus::Exists),
+        ];
+
+        let mut out = Vec::new();
+        write_diff_paths(&mut out, &diffs).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "/dest/Author/Book A/01.mp3\n/dest/Author/Book A/02.mp3\n/src/03.mp3\n/dest/Author/Book B/01.mp3\n"
+        );
+
+        let mut out = Vec::new();
+        write_diff_by_book(&mut out, &diffs).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "Author - Book A  +2 !1\nAuthor - Book B  =1\n"
+        );
+    }
+
+    #[test]
+    fn test_save_diff_as_csv_and_json() {
+        let dir = tempdir().unwrap();
+        let diffs = vec![FileDiff {
+            source_path: PathBuf::from("/src/01.mp3"),
+            dest_path: PathBuf::from("/dest/Sanderson, Brandon/Warbreaker/01.mp3"),
+            status: FileStatus::SourceMissing,
+            book_title: "Warbreaker \"Tenth\"".to_string(),
+            author: "Sanderson, Brandon".to_string(),
+        }];
+
+        let csv_path = dir.path().join("pending.csv");
+        save_diff(&csv_path, Path::new("/src"), Path::new("/dest"), &diffs).unwrap();
+        assert_eq!(
+            fs::read_to_string(&csv_path).unwrap(),
+            "status,author,book,source,dest\n\
+             source_missing,\"Sanderson, Brandon\",\"Warbreaker \"\"Tenth\"\"\",/src/01.mp3,\
+             \"/dest/Sanderson, Brandon/Warbreaker/01.mp3\"\n"
+        );
+
+        let json_path = dir.path().join("pending.json");
+        save_diff(&json_path, Path::new("/src"), Path::new("/dest"), &diffs).unwrap();
+        let json: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&json_path).unwrap()).unwrap();
+        assert_eq!(json["dest"], "/dest");
+        assert_eq!(json["files"][0]["status"], "source_missing");
+        assert_eq!(json["files"][0]["book_title"], "Warbreaker \"Tenth\"");
+    }
+
+    #[test]
+    fn test_dry_run_does_not_copy() {
+        let temp_source = tempdir().unwrap();
+        let temp_dest = tempdir().unwrap();
+
+        let audiobook = Audiobook {
+            title: "Dry Run Book".to_string(),
+            author: "Dry Run Author".to_string(),
+            folder_id: "sha1-dryrun".to_string(),
+            tracks: vec![AudioTrack {
+                track_number: 1,
+                disc_number: 0,
+                title: "Chapter 1".to_string(),
+                path: PathBuf::from("/fake/path/track.mp3"),
+                filename: "track.mp3".to_string(),
+            }],
+            ..Default::default()
+        };
+
+        let stats = export_audiobooks(
+            &[audiobook],
+            temp_source.path(),
+            temp_dest.path(),
+            &ExportOptions {
+                dry_run: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(stats.files_would_copy, 1);
+        assert_eq!(stats.files_copied, 0);
+
+        // Directory should NOT be created in dry run
+        let expected_dir = temp_dest.path().join("Dry Run Author/Dry Run Book");
+        assert!(!expected_dir.exists());
+    }
+}
@@ -0,0 +1,237 @@
+use clap::ValueEnum;
+use serde::Serialize;
+use std::cmp::Ordering;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::{csv_field, format_bytes, remap_track_path, truncate, Audiobook};
+
+/// Column to sort the book list by
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum SortBy {
+    #[default]
+    Author,
+    Title,
+    Narrator,
+    /// Most tracks first
+    Tracks,
+    /// Largest first
+    Size,
+}
+
+/// How `catalog` writes the library
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum CatalogFormat {
+    /// One row per book, for spreadsheets
+    #[default]
+    Csv,
+    Json,
+}
+
+/// One line of the book list
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Row {
+    pub author: String,
+    pub title: String,
+    pub narrator: Option<String>,
+    pub genre: Option<String>,
+    pub year: Option<String>,
+    pub publisher: Option<String>,
+    pub copyright: Option<String>,
+    pub tracks: usize,
+    /// Size of the source files that exist
+    pub bytes: u64,
+    pub folder_id: String,
+    /// The book's folder in the Apple Books folder
+    pub source: PathBuf,
+}
+
+impl Row {
+    pub fn new(book: &Audiobook, source_base: &Path) -> Self {
+        Row {
+            author: book.author.clone(),
+            title: book.title.clone(),
+            narrator: book.narrator.clone(),
+            genre: book.details.genre.clone(),
+            year: book.details.year.clone(),
+            publisher: book.details.publisher.clone(),
+            copyright: book.details.copyright.clone(),
+            tracks: book.tracks.len(),
+            bytes: book
+                .tracks
+                .iter()
+                .filter_map(|t| fs::metadata(remap_track_path(&t.path, source_base)).ok())
+                .map(|m| m.len())
+                .sum(),
+            folder_id: book.folder_id.clone(),
+            source: source_base.join(&book.folder_id),
+        }
+    }
+}
+
+fn compare_text(a: &str, b: &str) -> Ordering {
+    a.to_lowercase().cmp(&b.to_lowercase())
+}
+
+/// Sort rows by a column, then by author and title. Books without a narrator sort last.
+pub fn sort(rows: &mut [Row], by: SortBy) {
+    rows.sort_by(|a, b| {
+        let first = match by {
+            SortBy::Author => Ordering::Equal,
+            SortBy::Title => compare_text(&a.title, &b.title),
+            SortBy::Narrator => match (&a.narrator, &b.narrator) {
+                (Some(x), Some(y)) => compare_text(x, y),
+                (x, y) => x.is_none().cmp(&y.is_none()),
+            },
+            SortBy::Tracks => b.tracks.cmp(&a.tracks),
+            SortBy::Size => b.bytes.cmp(&a.bytes),
+        };
+        first
+            .then_with(|| compare_text(&a.author, &b.author))
+            .then_with(|| compare_text(&a.title, &b.title))
+    });
+}
+
+/// Write the rows as a table, with a total line
+pub fn write_table(out: &mut impl Write, rows: &[Row]) -> io::Result<()> {
+    writeln!(
+        out,
+        "{:<25} {:<40} {:<20} {:<15} {:<4} {:>6} {:>10}",
+        "Author", "Title", "Narrator", "Genre", "Year", "Tracks", "Size"
+    )?;
+    for row in rows {
+        writeln!(
+            out,
+            "{:<25} {:<40} {:<20} {:<15} {:<4} {:>6} {:>10}",
+            truncate(&row.author, 25),
+            truncate(&row.title, 40),
+            truncate(row.narrator.as_deref().unwrap_or(""), 20),
+            truncate(row.genre.as_deref().unwrap_or(""), 15),
+            row.year.as_deref().unwrap_or(""),
+            row.tracks,
+            format_bytes(row.bytes)
+        )?;
+    }
+    writeln!(
+        out,
+        "{} books, {} tracks, {}",
+        rows.len(),
+        rows.iter().map(|row| row.tracks).sum::<usize>(),
+        format_bytes(rows.iter().map(|row| row.bytes).sum())
+    )
+}
+
+/// Write the rows as CSV with a header line, sizes in bytes
+pub fn write_csv(out: &mut impl Write, rows: &[Row]) -> io::Result<()> {
+    writeln!(
+        out,
+        "author,title,narrator,genre,year,publisher,copyright,tracks,bytes,folder_id,source"
+    )?;
+    for row in rows {
+        let fields = [
+            row.author.clone(),
+            row.title.clone(),
+            row.narrator.clone().unwrap_or_default(),
+            row.genre.clone().unwrap_or_default(),
+            row.year.clone().unwrap_or_default(),
+            row.publisher.clone().unwrap_or_default(),
+            row.copyright.clone().unwrap_or_default(),
+            row.tracks.to_string(),
+            row.bytes.to_string(),
+            row.folder_id.clone(),
+            row.source.display().to_string(),
+        ];
+        let fields: Vec<_> = fields.iter().map(|f| csv_field(f)).collect();
+        writeln!(out, "{}", fields.join(","))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(author: &str, title: &str, narrator: Option<&str>, tracks: usize, bytes: u64) -> Row {
+        Row {
+            author: author.to_string(),
+            title: title.to_string(),
+            narrator: narrator.map(str::to_string),
+            genre: None,
+            year: None,
+            publisher: None,
+            copyright: None,
+            tracks,
+            bytes,
+            folder_id: format!("sha1-{}", title.len()),
+            source: PathBuf::from("/src").join(format!("sha1-{}", title.len())),
+        }
+    }
+
+    #[test]
+    fn test_sort_and_write_table() {
+        let mut rows = vec![
+            row("Weir", "Project Hail Mary", Some("Ray Porter"), 1, 500),
+            row("Sanderson", "Warbreaker", None, 30, 300),
+            row("sanderson", "Elantris", Some("Jack Garrett"), 24, 400),
+        ];
+        let titles = |rows: &[Row]| rows.iter().map(|r| r.title.clone()).collect::<Vec<_>>();
+
+        sort(&mut rows, SortBy::Author);
+        assert_eq!(
+            titles(&rows),
+            ["Elantris", "Warbreaker", "Project Hail Mary"]
+        );
+        sort(&mut rows, SortBy::Narrator);
+        assert_eq!(
+            titles(&rows),
+            ["Elantris", "Project Hail Mary", "Warbreaker"]
+        );
+        sort(&mut rows, SortBy::Tracks);
+        assert_eq!(
+            titles(&rows),
+            ["Warbreaker", "Elantris", "Project Hail Mary"]
+        );
+        sort(&mut rows, SortBy::Size);
+        assert_eq!(
+            titles(&rows),
+            ["Project Hail Mary", "Elantris", "Warbreaker"]
+        );
+
+        let mut out = Vec::new();
+        write_table(&mut out, &rows).unwrap();
+        let table = String::from_utf8(out).unwrap();
+        assert!(table.starts_with("Author"));
+        assert!(table.ends_with("3 books, 55 tracks, 1.2 KB\n"));
+    }
+
+    #[test]
+    fn test_rows_as_json() {
+        let rows = vec![row("Sanderson", "Warbreaker", None, 30, 300)];
+        let json: serde_json::Value =
+            serde_json::from_str(&serde_json::to_string(&rows).unwrap()).unwrap();
+        assert_eq!(json[0]["title"], "Warbreaker");
+        assert_eq!(json[0]["narrator"], serde_json::Value::Null);
+        assert_eq!(json[0]["bytes"], 300);
+    }
+
+    #[test]
+    fn test_write_csv() {
+        let mut rows = vec![
+            row("Sanderson, Brandon", "Warbreaker", None, 30, 300),
+            row("Weir", "Project \"Hail\" Mary", Some("Ray Porter"), 1, 500),
+        ];
+        rows[1].genre = Some("Science Fiction".to_string());
+        rows[1].year = Some("2021".to_string());
+        rows[1].publisher = Some("Audible Studios".to_string());
+        rows[1].copyright = Some("©2021 Andy Weir".to_string());
+        let mut out = Vec::new();
+        write_csv(&mut out, &rows).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "author,title,narrator,genre,year,publisher,copyright,tracks,bytes,folder_id,source\n\
+             \"Sanderson, Brandon\",Warbreaker,,,,,,30,300,sha1-10,/src/sha1-10\n\
+             Weir,\"Project \"\"Hail\"\" Mary\",Ray Porter,Science Fiction,2021,Audible Studios,©2021 Andy Weir,1,500,sha1-19,/src/sha1-19\n"
+        );
+    }
+}
@@ -1,825 +1,2523 @@
-use anyhow::{Context, Result};
-use clap::Parser;
-use indicatif::{ProgressBar, ProgressStyle};
-use plist::Value;
+use anyhow::{bail, Context, Result};
+use clap::{Args, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use std::collections::{HashMap, HashSet};
 use std::fs;
+use std::io::{self, IsTerminal, Write};
 use std::path::{Path, PathBuf};
-use thiserror::Error;
 
-#[derive(Error, Debug)]
-pub enum ExportError {
-    #[error("Books.plist not found at {0}")]
-    PlistNotFound(PathBuf),
-    #[error("Invalid plist structure: {0}")]
-    InvalidPlistStructure(String),
-    #[error("No audiobooks found in library")]
-    NoAudiobooksFound,
-}
+use extract_apple_books::i18n::{tr, tr_args};
+use extract_apple_books::*;
 
 /// Export audiobooks from Apple Books to Audiobookshelf-compatible format
 #[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
-struct Args {
+#[command(
+    author,
+    version,
+    about,
+    long_about = None,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Running without a subcommand is the same as `export`
+    #[command(flatten)]
+    export: ExportArgs,
+
+    /// When to use colors (also disabled by the NO_COLOR environment variable)
+    #[arg(long, value_enum, value_name = "WHEN", global = true, default_value_t = output::ColorChoice::Auto)]
+    color: output::ColorChoice,
+
+    /// Plain ASCII output without box drawing, symbols or colors (for logs and emails)
+    #[arg(long, global = true)]
+    plain: bool,
+
+    /// Language of messages and reports (default: from LC_ALL, LC_MESSAGES or LANG)
+    #[arg(long, value_enum, value_name = "LANG", global = true, env = "EAB_LANG")]
+    lang: Option<i18n::Language>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Export audiobooks to the destination (the default when no subcommand is given)
+    Export(Box<ExportArgs>),
+    /// List books added, removed or changed in the library since the last export
+    Changes(ChangesArgs),
+    /// List the audiobooks in the library
+    List(ListArgs),
+    /// Write the whole library as CSV, one row per book, e.g. for a spreadsheet
+    Catalog(CatalogArgs),
+    /// Show everything parsed for one book, and its raw Books.plist entry
+    Info(InfoArgs),
+    /// Show the destination folders and files that would be produced for one book
+    Preview(PreviewArgs),
+    /// Compare the library with the destination without exporting
+    Diff(DiffArgs),
+    /// Write a report of the library, or of an export run
+    Report(ReportArgs),
+    /// List past export runs into a destination, or show the report of one
+    History(HistoryArgs),
+    /// Show the number, size and listening hours of the books in the library
+    Stats(StatsArgs),
+    /// Check that the files exported into a destination are intact
+    Verify(VerifyArgs),
+    /// Repair symlinks in a `--symlink` destination after the Apple Books folder moved
+    Relink(RelinkArgs),
+    /// Remove books from a destination that are no longer in the library
+    Prune(PruneArgs),
+    /// Remove broken symlinks into the Apple Books folder from a `--symlink` destination
+    Clean(CleanArgs),
+    /// List the snapshots of a destination taken with `export --snapshot`, or restore one
+    Rollback(RollbackArgs),
+    /// Print Books.plist, or the audiobooks parsed from it, as JSON for bug reports
+    Dump(DumpArgs),
+    /// Write an archive to attach to bug reports, with the library's names and paths
+    /// redacted
+    DebugBundle(DebugBundleArgs),
+    /// Check the audiobook entries of Books.plist for problems, without exporting
+    Validate(ValidateArgs),
+    /// Find book folders in the Apple Books folder that no library entry refers to
+    Orphans(OrphansArgs),
+    /// Browse the library as a tree of authors, books and tracks, with each book's status
+    /// at the destination, and export the books marked there
+    Tui(Box<TuiArgs>),
+    /// Print a shell completion script for bash, zsh, fish, elvish or PowerShell
+    Completions(CompletionsArgs),
+    /// Set up a default library, destination and profile, and optionally a launchd schedule
+    Init,
+}
+
+#[derive(Args, Debug)]
+struct DiffArgs {
     /// Source path to Apple Books audiobooks directory
-    /// Defaults to ~/Library/Containers/com.apple.BKAgentService/Data/Documents/iBooks/Books
-    #[arg(short, long)]
+    #[arg(short, long, env = "EAB_SOURCE")]
     source: Option<PathBuf>,
 
-    /// Destination path for exported audiobooks
-    #[arg(short, long)]
-    dest: PathBuf,
+    /// Destination path to compare with (default: the one set up with `init`)
+    #[arg(short, long, env = "EAB_DEST")]
+    dest: Option<PathBuf>,
 
-    /// Dry run - show what would be copied without actually copying
-    #[arg(long, default_value = "false")]
-    dry_run: bool,
+    /// Use the destination, filters and layout of this profile from the config file
+    /// (`[profile.NAME]`) instead of its `[export]` section
+    #[arg(long, value_name = "NAME", env = "EAB_PROFILE")]
+    profile: Option<String>,
 
-    /// Use symlinks instead of copying files
-    #[arg(long, default_value = "false")]
-    symlink: bool,
-}
+    /// List every book and file instead of the first few books per section, through
+    /// $PAGER when run in a terminal
+    #[arg(long)]
+    full: bool,
 
-#[derive(Debug, Clone)]
-pub struct Audiobook {
-    pub title: String,
-    pub author: String,
-    pub narrator: Option<String>,
-    pub folder_id: String,
-    pub tracks: Vec<AudioTrack>,
-}
+    /// Only compare the book with this Apple Books folder id (can be repeated)
+    #[arg(long = "book-id", value_name = "ID")]
+    book_ids: Vec<String>,
 
-#[derive(Debug, Clone)]
-pub struct AudioTrack {
-    pub track_number: u32,
-    pub disc_number: u32,
-    pub title: String,
-    pub path: PathBuf,
-    pub filename: String,
-}
+    /// Only compare books whose author matches this glob pattern, e.g. "Brandon*" (can be
+    /// repeated)
+    #[arg(long = "author", value_name = "PATTERN")]
+    authors: Vec<String>,
 
-/// Get the default Apple Books path for the current user
-fn default_apple_books_path() -> PathBuf {
-    dirs::home_dir()
-        .unwrap_or_else(|| PathBuf::from("/"))
-        .join("Library/Containers/com.apple.BKAgentService/Data/Documents/iBooks/Books")
-}
+    /// Only compare books whose title matches this glob pattern (can be repeated)
+    #[arg(long = "title", value_name = "PATTERN")]
+    titles: Vec<String>,
 
-/// Parse the Books.plist file and extract audiobook metadata
-pub fn parse_books_plist(plist_path: &Path) -> Result<Vec<Audiobook>> {
-    if !plist_path.exists() {
-        return Err(ExportError::PlistNotFound(plist_path.to_path_buf()).into());
-    }
+    /// Only compare books purchased or added on or after this date (YYYY-MM-DD)
+    #[arg(long, value_name = "DATE", value_parser = filter::parse_date)]
+    since: Option<std::time::SystemTime>,
 
-    let plist_value: Value = plist::from_file(plist_path)
-        .with_context(|| format!("Failed to parse plist at {:?}", plist_path))?;
+    /// Only compare books purchased or added on or before this date (YYYY-MM-DD)
+    #[arg(long, value_name = "DATE", value_parser = filter::parse_date)]
+    until: Option<std::time::SystemTime>,
 
-    let dict = plist_value
-        .as_dictionary()
-        .ok_or_else(|| ExportError::InvalidPlistStructure("Root is not a dictionary".into()))?;
+    /// Skip the first N selected books, in author and title order
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    skip: usize,
 
-    let books_array = dict
-        .get("Books")
-        .and_then(|v| v.as_array())
-        .ok_or_else(|| ExportError::InvalidPlistStructure("Missing 'Books' array".into()))?;
+    /// Compare at most N books, in author and title order
+    #[arg(long, value_name = "N")]
+    limit: Option<usize>,
 
-    let mut audiobooks = Vec::new();
+    #[command(flatten)]
+    layout: LayoutArgs,
 
-    for book_value in books_array {
-        if let Some(audiobook) = parse_audiobook_entry(book_value)? {
-            audiobooks.push(audiobook);
-        }
-    }
+    /// Detect changed source files by SHA-256 rather than size and modification time
+    #[arg(long)]
+    checksum: bool,
 
-    if audiobooks.is_empty() {
-        return Err(ExportError::NoAudiobooksFound.into());
-    }
+    /// Also write every file of the diff to PATH, as CSV if it ends in .csv, else as JSON
+    #[arg(short, long, value_name = "PATH")]
+    output: Option<PathBuf>,
 
-    Ok(audiobooks)
-}
+    /// Only show files with these statuses (comma-separated or repeated)
+    #[arg(long, value_enum, value_delimiter = ',', value_name = "STATUS")]
+    only: Vec<DiffStatus>,
 
-/// Parse a single audiobook entry from the plist
-fn parse_audiobook_entry(value: &Value) -> Result<Option<Audiobook>> {
-    let dict = match value.as_dictionary() {
-        Some(d) => d,
-        None => return Ok(None),
-    };
+    /// One line per book with how many of its files have each status
+    #[arg(long, conflicts_with = "full")]
+    by_book: bool,
 
-    // Check if this is an audiobook
-    let book_type = dict
-        .get("BKBookType")
-        .and_then(|v| v.as_string())
-        .unwrap_or("");
-
-    if book_type != "audiobook" {
-        return Ok(None);
-    }
-
-    let folder_id = dict
-        .get("BKGeneratedItemId")
-        .and_then(|v| v.as_string())
-        .unwrap_or("")
-        .to_string();
-
-    let author = dict
-        .get("artistName")
-        .and_then(|v| v.as_string())
-        .unwrap_or("Unknown Author")
-        .to_string();
-
-    // Parse tracks to get title and other metadata
-    let parts = dict.get("BKParts").and_then(|v| v.as_array());
-
-    let mut tracks = Vec::new();
-    let mut title = String::new();
-    let mut narrator: Option<String> = None;
-
-    if let Some(parts_array) = parts {
-        for part_value in parts_array {
-            if let Some(part_dict) = part_value.as_dictionary() {
-                // Get title from first track if not set
-                if title.is_empty() {
-                    title = part_dict
-                        .get("itemName")
-                        .and_then(|v| v.as_string())
-                        .unwrap_or("Unknown Title")
-                        .to_string();
-                }
+    /// Only print file paths, one per line: destinations, or sources of files without one
+    #[arg(long, conflicts_with_all = ["full", "by_book"])]
+    paths: bool,
 
-                // Try to get narrator from composer field (common in audiobooks)
-                if narrator.is_none() {
-                    narrator = part_dict
-                        .get("composer")
-                        .and_then(|v| v.as_string())
-                        .map(|s| s.to_string());
-                }
+    /// Print the diff as text, or as JSON for scripts (ignores --full, --by-book and --paths)
+    #[arg(long, value_enum, value_name = "FORMAT", default_value_t = output::Format::Text)]
+    format: output::Format,
+}
 
-                let track_number = part_dict
-                    .get("BKTrackNumber")
-                    .and_then(|v| v.as_unsigned_integer())
-                    .unwrap_or(0) as u32;
-
-                let disc_number = part_dict
-                    .get("BKDiscNumber")
-                    .and_then(|v| v.as_unsigned_integer())
-                    .unwrap_or(0) as u32;
-
-                let track_title = part_dict
-                    .get("BKTrackTitle")
-                    .and_then(|v| v.as_string())
-                    .unwrap_or("")
-                    .to_string();
-
-                let path_str = part_dict
-                    .get("path")
-                    .and_then(|v| v.as_string())
-                    .unwrap_or("");
-
-                if !path_str.is_empty() {
-                    let path = PathBuf::from(path_str);
-                    let filename = path
-                        .file_name()
-                        .map(|s| s.to_string_lossy().to_string())
-                        .unwrap_or_default();
-
-                    tracks.push(AudioTrack {
-                        track_number,
-                        disc_number,
-                        title: track_title,
-                        path,
-                        filename,
-                    });
-                }
-            }
-        }
+impl DiffArgs {
+    /// Export options as these arguments say, with what they leave out filled in from the
+    /// config file as `export` does, so the diff compares with what an export would write
+    fn options(
+        &self,
+        defaults: &config::ExportDefaults,
+        matches: &clap::ArgMatches,
+    ) -> Result<ExportOptions> {
+        let mut options = ExportOptions {
+            checksum: self.checksum,
+            book_ids: self.book_ids.clone(),
+            filter: filter::BookFilter {
+                authors: self.authors.clone(),
+                titles: self.titles.clone(),
+                since: self.since,
+                until: self.until,
+                ..Default::default()
+            },
+            skip: self.skip,
+            limit: self.limit,
+            ..self.layout.options()
+        };
+        defaults.apply(&mut options, None, &CommandLine::new::<DiffArgs>(matches))?;
+        Ok(options)
     }
+}
 
-    // Sort tracks by disc number then track number
-    tracks.sort_by(|a, b| {
-        a.disc_number
-            .cmp(&b.disc_number)
-            .then(a.track_number.cmp(&b.track_number))
-    });
+/// File statuses `diff --only` can select
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum DiffStatus {
+    New,
+    Existing,
+    Changed,
+    Missing,
+    Placeholder,
+    Duplicate,
+}
 
-    if title.is_empty() || tracks.is_empty() {
-        return Ok(None);
+impl DiffStatus {
+    fn matches(self, status: &FileStatus) -> bool {
+        *status
+            == match self {
+                DiffStatus::New => FileStatus::New,
+                DiffStatus::Existing => FileStatus::Exists,
+                DiffStatus::Changed => FileStatus::Changed,
+                DiffStatus::Missing => FileStatus::SourceMissing,
+                DiffStatus::Placeholder => FileStatus::SourcePlaceholder,
+                DiffStatus::Duplicate => FileStatus::Duplicate,
+            }
     }
+}
+
+#[derive(Args, Debug)]
+struct PreviewArgs {
+    /// Apple Books folder id, position in the library, or part of the title or author
+    book: String,
+
+    /// Source path to Apple Books audiobooks directory
+    #[arg(short, long, env = "EAB_SOURCE")]
+    source: Option<PathBuf>,
 
-    Ok(Some(Audiobook {
-        title,
-        author,
-        narrator,
-        folder_id,
-        tracks,
-    }))
+    /// Use the source and layout of this profile from the config file
+    /// (`[profile.NAME]`) instead of its `[export]` section
+    #[arg(long, value_name = "NAME", env = "EAB_PROFILE")]
+    profile: Option<String>,
+
+    #[command(flatten)]
+    layout: LayoutArgs,
 }
 
-/// Sanitize a string for use as a filename/directory name
-fn sanitize_filename(name: &str) -> String {
-    name.chars()
-        .map(|c| match c {
-            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
-            _ => c,
-        })
-        .collect::<String>()
-        .trim()
-        .to_string()
+/// Metadata services of `export --enrich`, in the order they are asked
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum Enrichment {
+    /// Audnexus (api.audnex.us), which serves Audible's metadata
+    Audnexus,
+    /// OpenLibrary (openlibrary.org), searched by title and author
+    Openlibrary,
+    /// Google Books, searched by title and author
+    GoogleBooks,
 }
 
-/// Create Audiobookshelf-compatible folder name for an audiobook
-/// Format: Author/Title {Narrator} or Author/Title
-fn create_audiobookshelf_path(dest: &Path, audiobook: &Audiobook) -> PathBuf {
-    let author_dir = sanitize_filename(&audiobook.author);
+/// Document formats of `report`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum ReportFormat {
+    #[value(alias = "md")]
+    Markdown,
+    /// A static page with cover thumbnails (library reports only)
+    Html,
+}
 
-    let title_dir = if let Some(ref narrator) = audiobook.narrator {
-        format!(
-            "{} {{{}}}",
-            sanitize_filename(&audiobook.title),
-            sanitize_filename(narrator)
-        )
-    } else {
-        sanitize_filename(&audiobook.title)
-    };
+#[derive(Args, Debug)]
+struct ReportArgs {
+    /// Document format
+    #[arg(long, value_enum, default_value_t = ReportFormat::Markdown)]
+    format: ReportFormat,
 
-    dest.join(author_dir).join(title_dir)
+    /// Source path to Apple Books audiobooks directory
+    #[arg(short, long, env = "EAB_SOURCE")]
+    source: Option<PathBuf>,
+
+    /// Report on an export run instead of the library, from its saved report
+    /// (e.g. <dest>/.extract_apple_books/last-run.json)
+    #[arg(long, value_name = "REPORT", conflicts_with = "source")]
+    run: Option<PathBuf>,
+
+    /// Destination to show each book's export status and exported cover for
+    #[arg(short, long, env = "EAB_DEST", conflicts_with = "run")]
+    dest: Option<PathBuf>,
+
+    /// Write the report to a file instead of stdout
+    #[arg(short, long, value_name = "PATH")]
+    output: Option<PathBuf>,
 }
 
-/// Remap the source path in a track to use the actual source base path
-/// The plist contains paths like /Users/charlie/Library/... but we might be reading from /Volumes/charlie/Library/...
-fn remap_track_path(track_path: &Path, source_base: &Path) -> PathBuf {
-    // Extract the relative path after "Audiobooks/" (the sha1 folder and filename)
-    let path_str = track_path.to_string_lossy();
+#[derive(Args, Debug)]
+struct HistoryArgs {
+    /// Destination the runs exported to
+    #[arg(short, long, env = "EAB_DEST")]
+    dest: PathBuf,
 
-    if let Some(idx) = path_str.find("Audiobooks/") {
-        // Get just the part starting from "Audiobooks/"
-        let relative = &path_str[idx..];
-        source_base.join(relative)
-    } else {
-        // Fallback: try to find just the audiobook folder and filename
-        let components: Vec<_> = track_path.components().collect();
-        if components.len() >= 2 {
-            // Get the sha1-xxx folder and filename
-            let folder = components[components.len() - 2]
-                .as_os_str()
-                .to_string_lossy();
-            let filename = components[components.len() - 1]
-                .as_os_str()
-                .to_string_lossy();
-
-            if folder.starts_with("sha1-") {
-                return source_base
-                    .join("Audiobooks")
-                    .join(folder.as_ref())
-                    .join(filename.as_ref());
-            }
-        }
-        track_path.to_path_buf()
-    }
+    /// Show the detailed report of this run (its id, or the start of it)
+    run: Option<String>,
 }
 
-/// Export audiobooks to the destination directory
-pub fn export_audiobooks(
-    audiobooks: &[Audiobook],
-    source_base: &Path,
-    dest: &Path,
+#[derive(Args, Debug)]
+struct VerifyArgs {
+    /// Destination to check (default: the one set up with `init`)
+    #[arg(short, long, env = "EAB_DEST")]
+    dest: Option<PathBuf>,
+
+    /// Use the destination of this profile from the config file
+    /// (`[profile.NAME]`) instead of its `[export]` section
+    #[arg(long, value_name = "NAME", env = "EAB_PROFILE")]
+    profile: Option<String>,
+
+    /// Check files against the SHA-256 hashes recorded when they were exported, without
+    /// reading the sources, e.g. from another machine
+    #[arg(long)]
+    against_manifest: bool,
+
+    /// Only compare the sizes of the files with their sources, without hashing: quick,
+    /// and enough to find truncated copies
+    #[arg(long, conflicts_with = "against_manifest")]
+    quick: bool,
+}
+
+#[derive(Args, Debug)]
+struct RelinkArgs {
+    /// Source path to Apple Books audiobooks directory, where symlinks should point now
+    #[arg(short, long, env = "EAB_SOURCE")]
+    source: Option<PathBuf>,
+
+    /// Destination exported with --symlink
+    #[arg(short, long, env = "EAB_DEST")]
+    dest: PathBuf,
+
+    /// Show which symlinks would be repaired without changing them
+    #[arg(long)]
     dry_run: bool,
-    use_symlink: bool,
-) -> Result<ExportStats> {
-    let mut stats = ExportStats::default();
-
-    let pb = ProgressBar::new(audiobooks.len() as u64);
-    pb.set_style(
-        ProgressStyle::default_bar()
-            .template("{spinner:.green} [{bar:40.cyan/blue}] {pos}/{len} {msg}")
-            .unwrap()
-            .progress_chars("#>-"),
-    );
+}
 
-    for audiobook in audiobooks {
-        pb.set_message(format!("{} - {}", audiobook.author, audiobook.title));
+#[derive(Args, Debug)]
+struct CleanArgs {
+    /// Source path to Apple Books audiobooks directory, to tell which links relink can
+    /// still repair
+    #[arg(short, long, env = "EAB_SOURCE")]
+    source: Option<PathBuf>,
 
-        let dest_dir = create_audiobookshelf_path(dest, audiobook);
+    /// Destination exported with --symlink
+    #[arg(short, long, env = "EAB_DEST")]
+    dest: PathBuf,
 
-        if !dry_run {
-            fs::create_dir_all(&dest_dir)
-                .with_context(|| format!("Failed to create directory {:?}", dest_dir))?;
-        }
+    /// Only list the broken symlinks without removing them
+    #[arg(long)]
+    dry_run: bool,
+}
 
-        for track in &audiobook.tracks {
-            let source_path = remap_track_path(&track.path, source_base);
-            let dest_path = dest_dir.join(&track.filename);
+#[derive(Args, Debug)]
+struct PruneArgs {
+    /// Source path to Apple Books audiobooks directory
+    #[arg(short, long, env = "EAB_SOURCE")]
+    source: Option<PathBuf>,
 
-            if dry_run {
-                println!(
-                    "Would {} {:?} -> {:?}",
-                    if use_symlink { "symlink" } else { "copy" },
-                    source_path,
-                    dest_path
-                );
-                stats.files_would_copy += 1;
-            } else if !source_path.exists() {
-                eprintln!("Warning: Source file not found: {:?}", source_path);
-                stats.source_missing += 1;
-            } else if dest_path.exists() {
-                // Skip files that already exist
-                stats.files_already_exist += 1;
-            } else {
-                if use_symlink {
-                    #[cfg(unix)]
-                    {
-                        std::os::unix::fs::symlink(&source_path, &dest_path).with_context(
-                            || format!("Failed to symlink {:?} -> {:?}", source_path, dest_path),
-                        )?;
-                    }
-                    #[cfg(not(unix))]
-                    {
-                        fs::copy(&source_path, &dest_path).with_context(|| {
-                            format!("Failed to copy {:?} -> {:?}", source_path, dest_path)
-                        })?;
-                    }
-                } else {
-                    fs::copy(&source_path, &dest_path).with_context(|| {
-                        format!("Failed to copy {:?} -> {:?}", source_path, dest_path)
-                    })?;
-                }
-                stats.files_copied += 1;
-            }
-        }
+    /// Destination to remove books from (default: the one set up with `init`)
+    #[arg(short, long, env = "EAB_DEST")]
+    dest: Option<PathBuf>,
 
-        stats.books_exported += 1;
-        pb.inc(1);
-    }
+    /// Use the source and destination of this profile from the config file
+    /// (`[profile.NAME]`) instead of its `[export]` section
+    #[arg(long, value_name = "NAME", env = "EAB_PROFILE")]
+    profile: Option<String>,
+
+    /// Show which books and files would be removed without removing them
+    #[arg(long)]
+    dry_run: bool,
 
-    pb.finish_with_message("Done!");
+    /// Permanently delete the files instead of moving them to the Trash
+    #[arg(long, conflicts_with = "quarantine_dir")]
+    permanent: bool,
 
-    Ok(stats)
+    /// Move the files into timestamped subdirectories of this folder instead of the Trash
+    #[arg(long, value_name = "DIR")]
+    quarantine_dir: Option<PathBuf>,
 }
 
-#[derive(Debug, Default)]
-pub struct ExportStats {
-    pub books_exported: usize,
-    pub files_copied: usize,
-    pub files_would_copy: usize,
-    pub files_missing: usize,
-    pub files_already_exist: usize,
-    pub source_missing: usize,
+#[derive(Args, Debug)]
+struct RollbackArgs {
+    /// Destination the snapshots were taken of
+    #[arg(short, long, env = "EAB_DEST")]
+    dest: PathBuf,
+
+    /// Restore this snapshot (its id, or the start of it)
+    snapshot: Option<String>,
 }
 
-/// Status of a file comparison between source and destination
-#[derive(Debug, Clone, PartialEq)]
-pub enum FileStatus {
-    /// File exists in source, not in destination - will be copied
-    New,
-    /// File exists in both source and destination
-    Exists,
-    /// File missing from source (referenced in plist but not on disk)
-    SourceMissing,
+#[derive(Args, Debug)]
+#[command(group(clap::ArgGroup::new("what").args(["raw", "parsed"]).required(true)))]
+struct DumpArgs {
+    /// Source path to Apple Books audiobooks directory
+    #[arg(short, long, env = "EAB_SOURCE")]
+    source: Option<PathBuf>,
+
+    /// The whole Books.plist with every key, books that aren't audiobooks included
+    #[arg(long)]
+    raw: bool,
+
+    /// The audiobooks as this tool reads them, before metadata corrections
+    #[arg(long)]
+    parsed: bool,
 }
 
-/// Information about a file for diff display
-#[derive(Debug, Clone)]
-pub struct FileDiff {
-    pub source_path: PathBuf,
-    pub dest_path: PathBuf,
-    pub status: FileStatus,
-    pub book_title: String,
-    pub author: String,
+#[derive(Args, Debug)]
+struct DebugBundleArgs {
+    /// Source path to Apple Books audiobooks directory
+    #[arg(short, long, env = "EAB_SOURCE")]
+    source: Option<PathBuf>,
+
+    /// Where to write the archive
+    #[arg(short, long, default_value = "extract_apple_books-debug.tar")]
+    output: PathBuf,
 }
 
-/// Compute the diff between source and destination for all audiobooks
-pub fn compute_diff(audiobooks: &[Audiobook], source_base: &Path, dest: &Path) -> Vec<FileDiff> {
-    let mut diffs = Vec::new();
+#[derive(Args, Debug)]
+struct ValidateArgs {
+    /// Source path to Apple Books audiobooks directory
+    #[arg(short, long, env = "EAB_SOURCE")]
+    source: Option<PathBuf>,
+}
 
-    for audiobook in audiobooks {
-        let dest_dir = create_audiobookshelf_path(dest, audiobook);
+#[derive(Args, Debug)]
+struct OrphansArgs {
+    /// Source path to Apple Books audiobooks directory
+    #[arg(short, long, env = "EAB_SOURCE")]
+    source: Option<PathBuf>,
 
-        for track in &audiobook.tracks {
-            let source_path = remap_track_path(&track.path, source_base);
-            let dest_path = dest_dir.join(&track.filename);
+    /// Destination to export orphaned books into; asks about each one
+    #[arg(short, long)]
+    dest: Option<PathBuf>,
 
-            let status = if !source_path.exists() {
-                FileStatus::SourceMissing
-            } else if dest_path.exists() {
-                FileStatus::Exists
-            } else {
-                FileStatus::New
-            };
+    /// Use the source and layout of this profile from the config file
+    /// (`[profile.NAME]`) instead of its `[export]` section
+    #[arg(long, value_name = "NAME", env = "EAB_PROFILE")]
+    profile: Option<String>,
 
-            diffs.push(FileDiff {
-                source_path,
-                dest_path,
-                status,
-                book_title: audiobook.title.clone(),
-                author: audiobook.author.clone(),
-            });
-        }
-    }
+    /// Also list folders that were ignored before
+    #[arg(long)]
+    all: bool,
 
-    diffs
+    #[command(flatten)]
+    layout: LayoutArgs,
 }
 
-/// Display a formatted diff summary
-pub fn display_diff(diffs: &[FileDiff]) {
-    let new_files: Vec<_> = diffs
-        .iter()
-        .filter(|d| d.status == FileStatus::New)
-        .collect();
-    let existing_files: Vec<_> = diffs
-        .iter()
-        .filter(|d| d.status == FileStatus::Exists)
-        .collect();
-    let missing_files: Vec<_> = diffs
-        .iter()
-        .filter(|d| d.status == FileStatus::SourceMissing)
-        .collect();
+#[derive(Args, Debug)]
+struct ListArgs {
+    /// Source path to Apple Books audiobooks directory
+    #[arg(short, long, env = "EAB_SOURCE")]
+    source: Option<PathBuf>,
 
-    // Group new files by book
-    let mut books_to_add: std::collections::HashMap<String, Vec<&FileDiff>> =
-        std::collections::HashMap::new();
-    for diff in &new_files {
-        let key = format!("{} - {}", diff.author, diff.book_title);
-        books_to_add.entry(key).or_default().push(diff);
-    }
+    /// Column to sort by
+    #[arg(long, value_enum, value_name = "COLUMN", default_value_t = list::SortBy::Author)]
+    sort: list::SortBy,
 
-    println!("\n╔══════════════════════════════════════════════════════════════════╗");
-    println!("║                        DIFF SUMMARY                               ║");
-    println!("╚══════════════════════════════════════════════════════════════════╝\n");
+    /// Reverse the order
+    #[arg(long)]
+    reverse: bool,
 
-    // New files (to be added)
-    if !new_files.is_empty() {
-        println!("┌─────────────────────────────────────────────────────────────────┐");
-        println!(
-            "│ \x1b[32m+ TO ADD\x1b[0m ({} files in {} books)                              ",
-            new_files.len(),
-            books_to_add.len()
-        );
-        println!("└─────────────────────────────────────────────────────────────────┘");
+    /// Print the result as text, or as JSON for scripts (messages then go to stderr)
+    #[arg(long, value_enum, value_name = "FORMAT", default_value_t = output::Format::Text)]
+    format: output::Format,
+}
 
-        let mut sorted_books: Vec<_> = books_to_add.keys().collect();
-        sorted_books.sort();
+#[derive(Args, Debug)]
+struct CompletionsArgs {
+    /// Shell to complete in
+    #[arg(value_enum)]
+    shell: clap_complete::Shell,
+}
 
-        for book_key in sorted_books.iter().take(20) {
-            let files = &books_to_add[*book_key];
-            println!("  \x1b[32m+\x1b[0m {} ({} files)", book_key, files.len());
-        }
-        if books_to_add.len() > 20 {
-            println!("  ... and {} more books", books_to_add.len() - 20);
-        }
-        println!();
-    }
+#[derive(Args, Debug)]
+struct TuiArgs {
+    /// Export options, used to compare with the destination and for the export
+    #[command(flatten)]
+    export: ExportArgs,
+}
 
-    // Existing files (already in destination)
-    if !existing_files.is_empty() {
-        let mut existing_books: std::collections::HashSet<String> =
-            std::collections::HashSet::new();
-        for diff in &existing_files {
-            existing_books.insert(format!("{} - {}", diff.author, diff.book_title));
-        }
+#[derive(Args, Debug)]
+struct CatalogArgs {
+    /// Source path to Apple Books audiobooks directory
+    #[arg(short, long, env = "EAB_SOURCE")]
+    source: Option<PathBuf>,
 
-        println!("┌─────────────────────────────────────────────────────────────────┐");
-        println!(
-            "│ \x1b[33m= ALREADY EXISTS\x1b[0m ({} files in {} books)                     ",
-            existing_files.len(),
-            existing_books.len()
-        );
-        println!("└─────────────────────────────────────────────────────────────────┘");
+    /// Write the catalog as CSV or JSON
+    #[arg(long, value_enum, value_name = "FORMAT", default_value_t = list::CatalogFormat::Csv)]
+    format: list::CatalogFormat,
 
-        let mut sorted_books: Vec<_> = existing_books.iter().collect();
-        sorted_books.sort();
+    /// Column to sort by
+    #[arg(long, value_enum, value_name = "COLUMN", default_value_t = list::SortBy::Author)]
+    sort: list::SortBy,
+}
 
-        for book_key in sorted_books.iter().take(10) {
-            println!("  \x1b[33m=\x1b[0m {}", book_key);
-        }
-        if existing_books.len() > 10 {
-            println!("  ... and {} more books", existing_books.len() - 10);
-        }
-        println!();
-    }
+#[derive(Args, Debug)]
+struct InfoArgs {
+    /// Apple Books folder id, position in the library, or part of the title or author
+    book: String,
 
-    // Missing source files
-    if !missing_files.is_empty() {
-        let mut missing_books: std::collections::HashSet<String> = std::collections::HashSet::new();
-        for diff in &missing_files {
-            missing_books.insert(format!("{} - {}", diff.author, diff.book_title));
-        }
+    /// Source path to Apple Books audiobooks directory
+    #[arg(short, long, env = "EAB_SOURCE")]
+    source: Option<PathBuf>,
+}
 
-        println!("┌─────────────────────────────────────────────────────────────────┐");
-        println!(
-            "│ \x1b[31m! SOURCE MISSING\x1b[0m ({} files in {} books)                     ",
-            missing_files.len(),
-            missing_books.len()
-        );
-        println!("└─────────────────────────────────────────────────────────────────┘");
+#[derive(Args, Debug)]
+struct StatsArgs {
+    /// Source path to Apple Books audiobooks directory
+    #[arg(short, long, env = "EAB_SOURCE")]
+    source: Option<PathBuf>,
 
-        let mut sorted_books: Vec<_> = missing_books.iter().collect();
-        sorted_books.sort();
+    /// Break the totals down by author, genre or year, largest first
+    #[arg(long, value_enum, value_name = "FIELD")]
+    group_by: Option<stats::GroupBy>,
 
-        for book_key in sorted_books.iter().take(10) {
-            println!("  \x1b[31m!\x1b[0m {}", book_key);
-        }
-        if missing_books.len() > 10 {
-            println!("  ... and {} more books", missing_books.len() - 10);
-        }
-        println!();
-    }
+    /// Number of largest books to list
+    #[arg(long, default_value_t = 5, value_name = "N")]
+    top: usize,
+}
 
-    // Summary
-    println!("┌─────────────────────────────────────────────────────────────────┐");
-    println!("│ TOTALS                                                          │");
-    println!("├─────────────────────────────────────────────────────────────────┤");
-    println!(
-        "│  \x1b[32m+\x1b[0m New files to copy:     {:>6}                               │",
-        new_files.len()
-    );
-    println!(
-        "│  \x1b[33m=\x1b[0m Already exist (skip):  {:>6}                               │",
-        existing_files.len()
-    );
-    println!(
-        "│  \x1b[31m!\x1b[0m Source missing:        {:>6}                               │",
-        missing_files.len()
-    );
-    println!("└─────────────────────────────────────────────────────────────────┘");
+#[derive(Args, Debug)]
+struct ChangesArgs {
+    /// Source path to Apple Books audiobooks directory
+    #[arg(short, long, env = "EAB_SOURCE")]
+    source: Option<PathBuf>,
+
+    /// Record the current library as seen, so the next `changes` starts from here
+    #[arg(long)]
+    update: bool,
 }
 
-fn main() -> Result<()> {
-    let args = Args::parse();
+/// Options that change the folders and files an export produces. `diff` and `preview` take
+/// the same ones, so they show what `export` would write, and `orphans` exports with them.
+#[derive(Args, Debug, Clone)]
+struct LayoutArgs {
+    /// Write an Audiobookshelf chapters.json into each book folder, built from track titles
+    /// and durations (requires ffprobe)
+    #[arg(long, default_value = "false")]
+    chapters: bool,
 
-    let source_base = args.source.unwrap_or_else(default_apple_books_path);
-    let plist_path = source_base.join("Books.plist");
+    /// Write an Audiobookshelf metadata.json into each book folder with the title,
+    /// authors, narrators, series, description, and genre and year from the tags
+    #[arg(long, default_value = "false")]
+    metadata_json: bool,
 
-    println!("Reading audiobook library from: {:?}", plist_path);
+    /// Write an OPF metadata.opf into each book folder (Dublin Core title, authors,
+    /// narrators, description and identifier), for Calibre and other managers
+    #[arg(long, default_value = "false")]
+    opf: bool,
 
-    let audiobooks = parse_books_plist(&plist_path)?;
+    /// Write a CUE sheet (book.cue) listing the book's tracks with their titles into each
+    /// book folder, or its embedded chapters for single-file books (needs ffprobe)
+    #[arg(long, default_value = "false")]
+    cue: bool,
 
-    println!("Found {} audiobooks", audiobooks.len());
+    /// Write a Kodi-style album.nfo into each book folder (title, authors, narrators,
+    /// genres, year, publisher and description), for Jellyfin and Kodi
+    #[arg(long, default_value = "false")]
+    nfo: bool,
 
-    if args.dry_run {
-        println!("\n=== DRY RUN - No files will be copied ===");
+    /// Write the book's artwork as cover.jpg into each book folder: the image in its Apple
+    /// Books folder, or the artwork embedded in its first track (converting needs ffmpeg)
+    #[arg(long, default_value = "false")]
+    cover: bool,
 
-        // Compute and display diff
-        let diffs = compute_diff(&audiobooks, &source_base, &args.dest);
-        display_diff(&diffs);
+    /// Split single-file m4b/m4a audiobooks into one file per embedded chapter
+    /// (requires ffmpeg)
+    #[arg(long, default_value = "false")]
+    split_chapters: bool,
 
-        return Ok(());
-    }
+    /// Re-encode tracks during export via ffmpeg, e.g. `opus:64k` or `aac:96k`.
+    /// Already-transcoded files at the destination are skipped
+    #[arg(long, value_name = "CODEC[:BITRATE]", value_parser = transcode::parse_target)]
+    transcode: Option<transcode::TranscodeTarget>,
 
-    let stats = export_audiobooks(&audiobooks, &source_base, &args.dest, false, args.symlink)?;
+    /// Renumber tracks into one continuous sequence (1..N across discs) in filenames and
+    /// track/disc tags of exported copies
+    #[arg(long, default_value = "false")]
+    renumber: bool,
+
+    /// How books with several authors (e.g. "Jane Roe & John Doe") name their author
+    /// folder: as listed, after the first author, all authors joined with " & ", or
+    /// "Various"
+    #[arg(long, value_enum, value_name = "POLICY", default_value_t = AuthorFolder::Listed)]
+    author_folder: AuthorFolder,
+
+    /// How author folders write names: "Brandon Sanderson" (first-last) or "Sanderson,
+    /// Brandon" (last-first)
+    #[arg(long, value_enum, value_name = "FORMAT", default_value_t = AuthorFormat::FirstLast)]
+    author_format: AuthorFormat,
+
+    /// How book folders are arranged: Author/Title {Narrator} (nested), Author/Series/Vol N -
+    /// Title {Narrator} for books in a series (series), or "Author - Title {Narrator}"
+    /// directly under the destination (flat)
+    #[arg(long = "layout", value_enum, value_name = "LAYOUT", default_value_t = template::Layout::Nested, conflicts_with = "template")]
+    folder_layout: template::Layout,
+
+    /// Lay out book folders with a template instead of Author/Title {Narrator}, e.g.
+    /// "{author}/{series}/[{sequence}. ]{title}". Fields: author, title, title_in_series,
+    /// subtitle, series, sequence, narrator, year, genre, publisher, asin, isbn, folder_id.
+    /// "/" separates folders, {?field}...{/field} is kept only when the field has a value
+    /// and {!field}...{/field} only when it hasn't, [...] is left out when a field in it is
+    /// empty, and {{ }} [[ ]] are literal
+    #[arg(long, value_name = "TEMPLATE", value_parser = template::parse_template)]
+    template: Option<template::PathTemplate>,
+
+    /// Name exported tracks with a template instead of their source filenames, e.g.
+    /// "{disc}-{track:02} - {track_title}.{ext}". Fields: disc, track (continuous with
+    /// --renumber), track_title, filename, ext and those of --template. {field:02} pads
+    /// numbers with zeros, and the extension is added when there is no {ext}
+    #[arg(long, value_name = "TEMPLATE", value_parser = template::parse_track_template)]
+    track_template: Option<template::TrackTemplate>,
+
+    /// Switch off `chapters` from the config file
+    #[arg(long, hide = true, overrides_with = "chapters")]
+    no_chapters: bool,
+
+    /// Switch off `metadata-json` from the config file
+    #[arg(long, hide = true, overrides_with = "metadata_json")]
+    no_metadata_json: bool,
+
+    /// Switch off `opf` from the config file
+    #[arg(long, hide = true, overrides_with = "opf")]
+    no_opf: bool,
+
+    /// Switch off `cue` from the config file
+    #[arg(long, hide = true, overrides_with = "cue")]
+    no_cue: bool,
+
+    /// Switch off `nfo` from the config file
+    #[arg(long, hide = true, overrides_with = "nfo")]
+    no_nfo: bool,
+
+    /// Switch off `cover` from the config file
+    #[arg(long, hide = true, overrides_with = "cover")]
+    no_cover: bool,
+
+    /// Switch off `split-chapters` from the config file
+    #[arg(long, hide = true, overrides_with = "split_chapters")]
+    no_split_chapters: bool,
+
+    /// Switch off `renumber` from the config file
+    #[arg(long, hide = true, overrides_with = "renumber")]
+    no_renumber: bool,
+}
 
-    println!("\n=== Export Summary ===");
-    println!("Audiobooks processed: {}", stats.books_exported);
-    println!("Files copied: {}", stats.files_copied);
-    if stats.files_already_exist > 0 {
-        println!(
-            "Files skipped (already exist): {}",
-            stats.files_already_exist
-        );
-    }
-    if stats.files_missing > 0 {
-        println!("Files missing (skipped): {}", stats.files_missing);
+impl LayoutArgs {
+    /// Export options laying books out as these arguments say
+    fn options(&self) -> ExportOptions {
+        ExportOptions {
+            write_chapters: self.chapters,
+            write_metadata: self.metadata_json,
+            write_cover: self.cover,
+            write_opf: self.opf,
+            write_cue: self.cue,
+            write_nfo: self.nfo,
+            split_chapters: self.split_chapters,
+            transcode: self.transcode.clone(),
+            renumber: self.renumber,
+            author_folder: self.author_folder,
+            author_format: self.author_format,
+            template: self
+                .template
+                .clone()
+                .or_else(|| self.folder_layout.template()),
+            track_template: self.track_template.clone(),
+            ..Default::default()
+        }
     }
+}
 
-    Ok(())
+/// The options given on a command line, to tell which settings of the config file still
+/// apply. Settings that can't be combined with one of them are left out, as clap would
+/// refuse the two together.
+struct CommandLine<'a> {
+    command: clap::Command,
+    matches: &'a clap::ArgMatches,
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::fs::File;
-    use std::io::Write;
-    use tempfile::tempdir;
-
-    #[test]
-    fn test_sanitize_filename() {
-        assert_eq!(sanitize_filename("Normal Title"), "Normal Title");
-        assert_eq!(sanitize_filename("Title: Subtitle"), "Title_ Subtitle");
-        assert_eq!(sanitize_filename("What/Why"), "What_Why");
-        assert_eq!(
-            sanitize_filename("File<>Name*With?Bad|Chars"),
-            "File__Name_With_Bad_Chars"
-        );
-        assert_eq!(sanitize_filename("  Trimmed  "), "Trimmed");
+impl<'a> CommandLine<'a> {
+    fn new<A: Args>(matches: &'a clap::ArgMatches) -> Self {
+        CommandLine {
+            command: A::augment_args(clap::Command::new("command")),
+            matches,
+        }
     }
 
-    #[test]
-    fn test_create_audiobookshelf_path() {
-        let dest = PathBuf::from("/dest");
+    /// Whether `id` was given on the command line or through its environment variable
+    fn is_given(&self, id: &str) -> bool {
+        self.matches
+            .value_source(id)
+            .is_some_and(|source| source != clap::parser::ValueSource::DefaultValue)
+    }
 
-        let book_without_narrator = Audiobook {
-            title: "The Great Book".to_string(),
-            author: "John Doe".to_string(),
-            narrator: None,
-            folder_id: "sha1-abc123".to_string(),
-            tracks: vec![],
+    /// The option given on the command line that rules out setting `id`
+    fn conflict(&self, id: &str) -> Option<String> {
+        let conflicts = |a: &clap::Arg, b: &clap::Arg| {
+            self.command
+                .get_arg_conflicts_with(a)
+                .iter()
+                .any(|arg| arg.get_id() == b.get_id())
         };
+        let arg = self
+            .command
+            .get_arguments()
+            .find(|arg| arg.get_id() == id)?;
+        self.command
+            .get_arguments()
+            .filter(|other| self.is_given(other.get_id().as_str()))
+            .find(|other| conflicts(arg, other) || conflicts(other, arg))
+            .map(|other| format!("--{}", other.get_long().unwrap_or_default()))
+    }
 
-        let path = create_audiobookshelf_path(&dest, &book_without_narrator);
-        assert_eq!(path, PathBuf::from("/dest/John Doe/The Great Book"));
+    /// Whether the command has an option `id`
+    fn has(&self, id: &str) -> bool {
+        self.command.get_arguments().any(|arg| arg.get_id() == id)
+    }
+}
 
-        let book_with_narrator = Audiobook {
-            title: "Another Book".to_string(),
-            author: "Jane Smith".to_string(),
-            narrator: Some("Bob Reader".to_string()),
-            folder_id: "sha1-def456".to_string(),
-            tracks: vec![],
-        };
+/// Settings of the config file are named after their options, except `layout`
+fn option_id(key: &str) -> String {
+    match key {
+        "layout" => "folder_layout".to_string(),
+        _ => key.replace('-', "_"),
+    }
+}
 
-        let path = create_audiobookshelf_path(&dest, &book_with_narrator);
-        assert_eq!(
-            path,
-            PathBuf::from("/dest/Jane Smith/Another Book {Bob Reader}")
-        );
+impl config::Given for CommandLine<'_> {
+    fn given(&self, key: &str) -> bool {
+        let id = option_id(key);
+        let switched_off = format!("no_{}", id);
+        [id, switched_off]
+            .iter()
+            .any(|id| self.has(id) && self.is_given(id))
     }
 
-    #[test]
-    fn test_create_audiobookshelf_path_with_special_chars() {
-        let dest = PathBuf::from("/dest");
+    fn clash(&self, key: &str) -> Option<String> {
+        self.conflict(&option_id(key))
+    }
+}
 
-        let book = Audiobook {
-            title: "Book: A Subtitle".to_string(),
-            author: "Author/Writer".to_string(),
-            narrator: Some("Narrator: The Voice".to_string()),
-            folder_id: "sha1-abc123".to_string(),
-            tracks: vec![],
-        };
+#[derive(Args, Debug)]
+#[command(group(clap::ArgGroup::new("selection").multiple(true)))]
+struct ExportArgs {
+    /// Source path to Apple Books audiobooks directory
+    /// Defaults to ~/Library/Containers/com.apple.BKAgentService/Data/Documents/iBooks/Books
+    #[arg(short, long, env = "EAB_SOURCE")]
+    source: Option<PathBuf>,
 
-        let path = create_audiobookshelf_path(&dest, &book);
-        assert_eq!(
-            path,
-            PathBuf::from("/dest/Author_Writer/Book_ A Subtitle {Narrator_ The Voice}")
-        );
-    }
+    /// Destination path for exported audiobooks (default: the one set up with `init`)
+    #[arg(short, long, env = "EAB_DEST")]
+    dest: Option<PathBuf>,
 
-    #[test]
-    fn test_remap_track_path() {
-        let source_base = PathBuf::from("/Volumes/charlie/Library/Containers/com.apple.BKAgentService/Data/Documents/iBooks/Books");
+    /// Use the destination, storage and filters of this profile from the config file
+    /// (`[profile.NAME]`) instead of its `[export]` section
+    #[arg(long, value_name = "NAME", env = "EAB_PROFILE")]
+    profile: Option<String>,
 
-        let original_path = PathBuf::from(
-            "/Users/charlie/Library/Containers/com.apple.BKAgentService/Data/Documents/iBooks/Books/Audiobooks/sha1-abc123/01 Track.mp3"
-        );
+    /// How to store books: full copies, symlinks, or compact Opus copies, instead of the
+    /// config file's choice. `--symlink` and `--transcode` take precedence
+    #[arg(long, value_enum, value_name = "MODE", env = "EAB_COPY_MODE")]
+    copy_mode: Option<config::Profile>,
 
-        let remapped = remap_track_path(&original_path, &source_base);
+    /// Dry run - show what would be copied without actually copying
+    #[arg(long, default_value = "false")]
+    dry_run: bool,
 
-        assert_eq!(
-            remapped,
-            PathBuf::from("/Volumes/charlie/Library/Containers/com.apple.BKAgentService/Data/Documents/iBooks/Books/Audiobooks/sha1-abc123/01 Track.mp3")
-        );
-    }
+    /// Use symlinks instead of copying files
+    #[arg(long, default_value = "false", conflicts_with_all = ["transcode", "renumber"])]
+    symlink: bool,
 
-    #[test]
-    fn test_parse_audiobook_entry_non_audiobook() {
-        let mut dict = plist::Dictionary::new();
-        dict.insert("BKBookType".to_string(), Value::String("ebook".to_string()));
+    #[command(flatten)]
+    layout: LayoutArgs,
+
+    /// Embed the book's artwork into each exported track (MP4 cover atom, ID3 picture),
+    /// for players that ignore cover.jpg. Needs ffmpeg
+    #[arg(long, default_value = "false", conflicts_with = "symlink")]
+    embed_cover: bool,
+
+    /// Number of parallel transcode workers (defaults to the number of CPUs)
+    #[arg(long, value_name = "N", env = "EAB_JOBS")]
+    jobs: Option<usize>,
+
+    /// Number of threads hashing source files, ahead of and alongside the copies
+    #[arg(long, value_name = "N", default_value_t = 2)]
+    hash_workers: usize,
+
+    /// Number of files copied to the destination at once
+    #[arg(long, value_name = "N", default_value_t = 1)]
+    copy_workers: usize,
+
+    /// Number of threads checking copies against their source before --move removes it
+    #[arg(long, value_name = "N", default_value_t = 1)]
+    verify_workers: usize,
+
+    /// Activation bytes of your Audible account (8 hex digits). Audible .aax files imported
+    /// into Apple Books are then converted to .m4b (requires ffmpeg); without them they're
+    /// skipped, as Audiobookshelf can't play them
+    #[arg(long, value_name = "HEX", value_parser = audible::parse_activation_bytes, env = "EAB_ACTIVATION_BYTES", hide_env_values = true)]
+    activation_bytes: Option<String>,
+
+    /// Normalize loudness of exported copies (EBU R128 via ffmpeg loudnorm).
+    /// Re-encodes copied files; the source library is never modified
+    #[arg(long, default_value = "false", conflicts_with = "symlink")]
+    normalize: bool,
+
+    /// Integrated loudness target in LUFS used by --normalize
+    #[arg(long, value_name = "LUFS", default_value_t = media::DEFAULT_LOUDNESS_TARGET, allow_negative_numbers = true)]
+    loudness_target: f64,
+
+    /// Rewrite album/artist/albumartist/track/disc tags (narrator as composer) on exported
+    /// copies to match the resolved metadata. The source library is never modified
+    #[arg(long, default_value = "false", conflicts_with = "symlink")]
+    fix_tags: bool,
+
+    /// Like --fix-tags, and also rewrite the tags of copies exported by earlier runs, so
+    /// metadata corrections reach them. The source library is never modified
+    #[arg(long, default_value = "false", conflicts_with = "symlink")]
+    retag: bool,
+
+    /// Remove Apple account name/email, purchase and ownership atoms from exported
+    /// m4b/m4a copies. The source library is never modified
+    #[arg(
+        long,
+        visible_alias = "strip-itunes-tags",
+        default_value = "false",
+        conflicts_with = "symlink"
+    )]
+    strip_personal_tags: bool,
+
+    /// Tag each exported book folder in Finder with the book's genre and set its Finder
+    /// comment to the narrator, for browsing and Spotlight search (macOS only)
+    #[arg(long)]
+    finder_tags: bool,
+
+    /// Date each exported book folder with the book's purchase (or import) date, so sorting
+    /// by date in Finder and Audiobookshelf follows when the book was added
+    #[arg(long)]
+    folder_dates: bool,
+
+    /// Remove the quarantine and provenance attributes macOS keeps on downloaded files from
+    /// exported copies, so other apps and servers can read them without Gatekeeper checks
+    #[arg(long, conflicts_with = "symlink")]
+    strip_quarantine: bool,
+
+    /// Before exporting, take a hardlink snapshot of the destination that `rollback` can
+    /// restore if the run replaces files it shouldn't have
+    #[arg(long)]
+    snapshot: bool,
+
+    /// Also export files found in book folders that aren't tracks, such as booklet PDFs,
+    /// cover images and bonus videos, into an `extras` subfolder of the book
+    #[arg(long)]
+    extras: bool,
+
+    /// Compare Books.plist with the Apple Books catalog database, report books only one of
+    /// them has, and export descriptions only the catalog has (requires sqlite3)
+    #[arg(long)]
+    check_catalog: bool,
+
+    /// Look books up on a metadata service (can be repeated): narrators, series and
+    /// chapters of books with an Audible ASIN on Audnexus, over what the library has, and
+    /// the ISBN, description and year of other books on OpenLibrary or Google Books, where
+    /// the library has none (requires curl)
+    #[arg(long, value_enum, value_name = "SERVICE")]
+    enrich: Vec<Enrichment>,
+
+    /// Audible region to look books up in with `--enrich audnexus`
+    #[arg(long, value_name = "REGION", default_value = "us")]
+    audnexus_region: String,
+
+    /// Where to save the run report (stats and failed files). Defaults to
+    /// <DEST>/.extract_apple_books/last-run.json
+    #[arg(long, value_name = "PATH", env = "EAB_REPORT")]
+    report: Option<PathBuf>,
+
+    /// When the destination disconnects mid-run and there is no terminal to ask, wait this
+    /// many seconds for it to come back before stopping
+    #[arg(long, value_name = "SECS", default_value_t = 600)]
+    reconnect_timeout: u64,
+
+    /// Exit with an error if any file failed or any warning was raised (missing sources,
+    /// incomplete books, folder collisions, failed verification, ...)
+    #[arg(long)]
+    strict: bool,
+
+    /// Print the run summary as text, or the run's stats as JSON for scripts (messages
+    /// then go to stderr)
+    #[arg(long, value_enum, value_name = "FORMAT", default_value_t = output::Format::Text)]
+    format: output::Format,
+
+    /// Re-attempt only the files that failed in a previous run, as recorded in its report,
+    /// without re-reading the library
+    #[arg(long, value_name = "REPORT", conflicts_with = "dry_run")]
+    retry_failed: Option<PathBuf>,
+
+    /// Move instead of copy: after copying, verify each destination file's SHA-256 against
+    /// the source and delete the source file. Sources that fail verification are kept
+    #[arg(
+        long = "move",
+        default_value = "false",
+        conflicts_with_all = ["symlink", "dry_run", "transcode", "normalize", "fix_tags", "retag", "embed_cover", "strip_personal_tags", "renumber", "split_chapters"]
+    )]
+    move_sources: bool,
+
+    /// Permanently delete files removed by destructive operations (e.g. --move) instead of
+    /// moving them to the Trash
+    #[arg(long, default_value = "false", conflicts_with = "quarantine_dir")]
+    permanent: bool,
+
+    /// Move files removed by destructive operations into timestamped subdirectories of
+    /// this folder instead of the Trash
+    #[arg(long, value_name = "DIR")]
+    quarantine_dir: Option<PathBuf>,
+
+    /// What to do when a destination file already exists
+    #[arg(long, value_enum, value_name = "STRATEGY", default_value_t = conflict::ConflictStrategy::Update)]
+    on_conflict: conflict::ConflictStrategy,
+
+    /// Detect changed source files by SHA-256 rather than size and modification time
+    #[arg(long)]
+    checksum: bool,
+
+    /// Check every destination file instead of skipping books that are unchanged since
+    /// the last run
+    #[arg(long)]
+    full: bool,
+
+    /// Only export the book with this Apple Books folder id (can be repeated)
+    #[arg(long = "book-id", value_name = "ID", group = "selection")]
+    book_ids: Vec<String>,
+
+    /// Only export this book, given by folder id, position in the library, or part of its
+    /// title or author (can be repeated)
+    #[arg(
+        long = "book",
+        value_name = "BOOK",
+        group = "selection",
+        conflicts_with = "retry_failed"
+    )]
+    books: Vec<String>,
+
+    /// Pick the books to export from a searchable list of the library (after any other
+    /// selection and filters)
+    #[arg(long, group = "selection", conflicts_with = "retry_failed")]
+    interactive: bool,
+
+    /// Only export books whose author matches this glob pattern, e.g. "Brandon*" (can be
+    /// repeated). `*` matches any text and `?` any single character, ignoring case
+    #[arg(long = "author", value_name = "PATTERN")]
+    authors: Vec<String>,
+
+    /// Only export books whose title matches this glob pattern (can be repeated)
+    #[arg(long = "title", value_name = "PATTERN")]
+    titles: Vec<String>,
+
+    /// Only export books whose "Author - Title" matches this regular expression (can be
+    /// repeated)
+    #[arg(long = "include-regex", value_name = "REGEX", value_parser = regex::Regex::new)]
+    include_regex: Vec<regex::Regex>,
+
+    /// Leave out books whose "Author - Title" matches this regular expression (can be
+    /// repeated)
+    #[arg(long = "exclude-regex", value_name = "REGEX", value_parser = regex::Regex::new)]
+    exclude_regex: Vec<regex::Regex>,
+
+    /// Leave out books matching any of the regular expressions in FILE, one per line.
+    /// Blank lines and lines starting with `#` are ignored
+    #[arg(long, value_name = "FILE")]
+    exclude_from: Option<PathBuf>,
+
+    /// Only export books purchased or added to Apple Books on or after this date
+    /// (YYYY-MM-DD, UTC). Books without a date are left out
+    #[arg(long, value_name = "DATE", value_parser = filter::parse_date)]
+    since: Option<std::time::SystemTime>,
+
+    /// Only export books purchased or added to Apple Books on or before this date
+    /// (YYYY-MM-DD, UTC)
+    #[arg(long, value_name = "DATE", value_parser = filter::parse_date)]
+    until: Option<std::time::SystemTime>,
+
+    /// Skip the first N selected books, in author and title order, e.g. to export a
+    /// library in slices
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 0,
+        conflicts_with = "retry_failed"
+    )]
+    skip: usize,
+
+    /// Export at most N books, in author and title order, e.g. to try out a new
+    /// destination with a few books first
+    #[arg(long, value_name = "N", conflicts_with = "retry_failed")]
+    limit: Option<usize>,
+
+    /// Re-export the selected books, overwriting files that already exist
+    #[arg(long, requires = "selection", conflicts_with_all = ["on_conflict", "dry_run"])]
+    force: bool,
+
+    /// Review and correct each selected book's title, author, narrator and series in
+    /// $EDITOR before exporting. Corrections are saved for future runs
+    #[arg(long, conflicts_with = "retry_failed")]
+    review: bool,
+
+    /// Switch off `symlink` from the config file
+    #[arg(long, hide = true, overrides_with = "symlink")]
+    no_symlink: bool,
+
+    /// Switch off `embed-cover` from the config file
+    #[arg(long, hide = true, overrides_with = "embed_cover")]
+    no_embed_cover: bool,
+
+    /// Switch off `normalize` from the config file
+    #[arg(long, hide = true, overrides_with = "normalize")]
+    no_normalize: bool,
+
+    /// Switch off `fix-tags` from the config file
+    #[arg(long, hide = true, overrides_with = "fix_tags")]
+    no_fix_tags: bool,
+
+    /// Switch off `retag` from the config file
+    #[arg(long, hide = true, overrides_with = "retag")]
+    no_retag: bool,
+
+    /// Switch off `strip-personal-tags` from the config file
+    #[arg(long, hide = true, overrides_with = "strip_personal_tags")]
+    no_strip_personal_tags: bool,
+
+    /// Switch off `finder-tags` from the config file
+    #[arg(long, hide = true, overrides_with = "finder_tags")]
+    no_finder_tags: bool,
+
+    /// Switch off `folder-dates` from the config file
+    #[arg(long, hide = true, overrides_with = "folder_dates")]
+    no_folder_dates: bool,
+
+    /// Switch off `strip-quarantine` from the config file
+    #[arg(long, hide = true, overrides_with = "strip_quarantine")]
+    no_strip_quarantine: bool,
+
+    /// Switch off `snapshot` from the config file
+    #[arg(long, hide = true, overrides_with = "snapshot")]
+    no_snapshot: bool,
+
+    /// Switch off `extras` from the config file
+    #[arg(long, hide = true, overrides_with = "extras")]
+    no_extras: bool,
+
+    /// Switch off `check-catalog` from the config file
+    #[arg(long, hide = true, overrides_with = "check_catalog")]
+    no_check_catalog: bool,
+
+    /// Switch off `strict` from the config file
+    #[arg(long, hide = true, overrides_with = "strict")]
+    no_strict: bool,
+
+    /// Switch off `checksum` from the config file
+    #[arg(long, hide = true, overrides_with = "checksum")]
+    no_checksum: bool,
+
+    /// Switch off `full` from the config file
+    #[arg(long, hide = true, overrides_with = "full")]
+    no_full: bool,
+}
 
-        let value = Value::Dictionary(dict);
-        let result = parse_audiobook_entry(&value).unwrap();
-        assert!(result.is_none());
+impl ExportArgs {
+    /// What destructive operations should do with removed files
+    fn disposal(&self) -> trash::Disposal {
+        if self.permanent {
+            trash::Disposal::Permanent
+        } else if let Some(dir) = &self.quarantine_dir {
+            trash::Disposal::Quarantine(dir.clone())
+        } else {
+            trash::Disposal::Trash
+        }
     }
 
-    #[test]
-    fn test_parse_audiobook_entry_valid() {
-        let mut track1 = plist::Dictionary::new();
-        track1.insert(
-            "itemName".to_string(),
-            Value::String("Test Book".to_string()),
-        );
-        track1.insert("BKTrackNumber".to_string(), Value::Integer(1.into()));
-        track1.insert("BKDiscNumber".to_string(), Value::Integer(0.into()));
-        track1.insert(
-            "BKTrackTitle".to_string(),
-            Value::String("Chapter 1".to_string()),
-        );
-        track1.insert(
-            "path".to_string(),
-            Value::String("/path/to/track1.mp3".to_string()),
-        );
+    /// Fill in the source and destination, and the flags only the command line acts on,
+    /// from the `export` section of the config file where the command line leaves them out
+    fn apply_defaults(
+        &mut self,
+        defaults: &config::ExportDefaults,
+        matches: &clap::ArgMatches,
+    ) -> Result<()> {
+        use config::Given;
+
+        let cli = CommandLine::new::<ExportArgs>(matches);
+        if self.source.is_none() {
+            self.source = defaults.source.clone();
+        }
+        // A retry goes to the destination of the run it retries
+        if self.dest.is_none() && self.retry_failed.is_none() {
+            self.dest = defaults.dest.clone();
+        }
+        for flag in &defaults.flags {
+            let option = match flag.as_str() {
+                "check-catalog" => &mut self.check_catalog,
+                "strict" => &mut self.strict,
+                _ => continue,
+            };
+            if *option || cli.given(flag) {
+                continue;
+            }
+            if let Some(other) = cli.clash(flag) {
+                eprintln!(
+                    "Warning: Ignoring {} from the config file, as it can't be used with {}",
+                    flag, other
+                );
+                continue;
+            }
+            *option = true;
+        }
+        Ok(())
+    }
 
-        let mut track2 = plist::Dictionary::new();
-        track2.insert(
-            "itemName".to_string(),
-            Value::String("Test Book".to_string()),
-        );
-        track2.insert("BKTrackNumber".to_string(), Value::Integer(2.into()));
-        track2.insert("BKDiscNumber".to_string(), Value::Integer(0.into()));
-        track2.insert(
-            "BKTrackTitle".to_string(),
-            Value::String("Chapter 2".to_string()),
-        );
-        track2.insert(
-            "path".to_string(),
-            Value::String("/path/to/track2.mp3".to_string()),
-        );
+    /// Export options as these arguments say, with what they leave out filled in from the
+    /// config file by [`config::ExportDefaults::apply`]
+    fn options(
+        &self,
+        defaults: &config::ExportDefaults,
+        matches: &clap::ArgMatches,
+    ) -> Result<ExportOptions> {
+        let mut exclude_regex = self.exclude_regex.clone();
+        if let Some(path) = &self.exclude_from {
+            exclude_regex.extend(filter::read_patterns(path)?);
+        }
+        let mut options = ExportOptions {
+            dry_run: false,
+            use_symlink: self.symlink,
+            enriched_chapters: Default::default(),
+            embed_cover: self.embed_cover,
+            jobs: self.jobs.unwrap_or_else(transcode::default_workers),
+            hash_workers: self.hash_workers,
+            copy_workers: self.copy_workers,
+            verify_workers: self.verify_workers,
+            activation_bytes: self.activation_bytes.clone(),
+            normalize: self.normalize.then_some(self.loudness_target),
+            fix_tags: self.fix_tags || self.retag,
+            retag: self.retag,
+            strip_personal_tags: self.strip_personal_tags,
+            finder_tags: self.finder_tags,
+            folder_dates: self.folder_dates,
+            snapshot: self.snapshot,
+            extras: self.extras,
+            strip_quarantine: self.strip_quarantine,
+            move_sources: self.move_sources,
+            disposal: self.disposal(),
+            on_conflict: if self.force {
+                conflict::ConflictStrategy::Overwrite
+            } else {
+                self.on_conflict
+            },
+            checksum: self.checksum,
+            full: self.full,
+            book_ids: self.book_ids.clone(),
+            filter: filter::BookFilter {
+                authors: self.authors.clone(),
+                titles: self.titles.clone(),
+                include: self.include_regex.clone(),
+                exclude: exclude_regex,
+                since: self.since,
+                until: self.until,
+            },
+            skip: self.skip,
+            limit: self.limit,
+            reconnect_timeout: std::time::Duration::from_secs(self.reconnect_timeout),
+            ..self.layout.options()
+        };
+        defaults.apply(
+            &mut options,
+            self.copy_mode,
+            &CommandLine::new::<ExportArgs>(matches),
+        )?;
+        options.normalize = options.normalize.map(|_| self.loudness_target);
+        Ok(options)
+    }
+}
 
-        let parts = vec![Value::Dictionary(track1), Value::Dictionary(track2)];
+/// Print the end-of-run summary, or the run's stats as JSON
+fn print_summary(stats: &ExportStats, format: output::Format) -> Result<()> {
+    match format {
+        output::Format::Text => print_export_summary(stats),
+        output::Format::Json => println!("{}", serde_json::to_string_pretty(stats)?),
+    }
+    Ok(())
+}
 
-        let mut dict = plist::Dictionary::new();
-        dict.insert(
-            "BKBookType".to_string(),
-            Value::String("audiobook".to_string()),
-        );
-        dict.insert(
-            "BKGeneratedItemId".to_string(),
-            Value::String("sha1-abc123".to_string()),
-        );
-        dict.insert(
-            "artistName".to_string(),
-            Value::String("Test Author".to_string()),
-        );
-        dict.insert("BKParts".to_string(), Value::Array(parts));
-
-        let value = Value::Dictionary(dict);
-        let result = parse_audiobook_entry(&value).unwrap();
-
-        assert!(result.is_some());
-        let audiobook = result.unwrap();
-
-        assert_eq!(audiobook.title, "Test Book");
-        assert_eq!(audiobook.author, "Test Author");
-        assert_eq!(audiobook.folder_id, "sha1-abc123");
-        assert_eq!(audiobook.tracks.len(), 2);
-        assert_eq!(audiobook.tracks[0].track_number, 1);
-        assert_eq!(audiobook.tracks[1].track_number, 2);
-    }
-
-    #[test]
-    fn test_export_creates_directory_structure() {
-        let temp_source = tempdir().unwrap();
-        let temp_dest = tempdir().unwrap();
-
-        // Create a mock audiobook folder with a track
-        // Source base is like: /path/to/iBooks/Books
-        let audiobook_dir = temp_source.path().join("Audiobooks/sha1-test123");
-        fs::create_dir_all(&audiobook_dir).unwrap();
-
-        let track_file = audiobook_dir.join("01 Chapter 1.mp3");
-        let mut file = File::create(&track_file).unwrap();
-        file.write_all(b"fake audio data").unwrap();
-
-        let audiobook = Audiobook {
-            title: "Test Book".to_string(),
-            author: "Test Author".to_string(),
-            narrator: None,
-            folder_id: "sha1-test123".to_string(),
-            tracks: vec![AudioTrack {
-                track_number: 1,
-                disc_number: 0,
-                title: "Chapter 1".to_string(),
-                path: PathBuf::from("/Users/charlie/Library/Containers/com.apple.BKAgentService/Data/Documents/iBooks/Books/Audiobooks/sha1-test123/01 Chapter 1.mp3"),
-                filename: "01 Chapter 1.mp3".to_string(),
-            }],
+/// Look the selected books up on Audnexus and use what it knows over the library. Returns
+/// the chapters found, by folder id, and the folder ids of the books Audnexus knows.
+fn enrich_from_audnexus(
+    audiobooks: &mut [Audiobook],
+    source_base: &Path,
+    region: &str,
+    options: &ExportOptions,
+) -> (HashMap<String, Vec<chapters::Chapter>>, HashSet<String>) {
+    let selected: HashSet<_> = options
+        .selected(audiobooks)
+        .iter()
+        .map(|book| book.folder_id.clone())
+        .collect();
+    let cache = audnexus::default_cache_dir();
+    let pb = progress::bar(selected.len(), "Looking up on Audnexus");
+    let (mut without_asin, mut updated) = (0, 0);
+    let mut chapters = HashMap::new();
+    let mut found_ids = HashSet::new();
+    for book in audiobooks
+        .iter_mut()
+        .filter(|book| selected.contains(&book.folder_id))
+    {
+        pb.inc(1);
+        pb.set_message(book.title.clone());
+        let Some(asin) = audnexus::find_asin(book, source_base) else {
+            without_asin += 1;
+            continue;
         };
+        match audnexus::lookup(&asin, region, cache.as_deref()) {
+            Ok(Some(found)) => {
+                found_ids.insert(book.folder_id.clone());
+                if audnexus::apply(book, &found) {
+                    updated += 1;
+                }
+                if !found.chapters.is_empty() {
+                    chapters.insert(book.folder_id.clone(), found.chapters);
+                }
+            }
+            Ok(None) => pb.suspend(|| {
+                eprintln!(
+                    "Warning: Audnexus doesn't know {} ({}) in region {}",
+                    book.title, asin, region
+                )
+            }),
+            Err(e) => pb.suspend(|| {
+                eprintln!(
+                    "Warning: Could not look up {} on Audnexus: {:#}",
+                    book.title, e
+                )
+            }),
+        }
+    }
+    pb.finish_and_clear();
+    output::message(format_args!(
+        "Audnexus: updated {} books, found chapters for {}, {} without an Audible ASIN",
+        updated,
+        chapters.len(),
+        without_asin
+    ));
+    (chapters, found_ids)
+}
 
-        let stats = export_audiobooks(
-            &[audiobook],
-            temp_source.path(),
-            temp_dest.path(),
-            false,
-            false,
-        )
-        .unwrap();
-
-        assert_eq!(stats.books_exported, 1);
-        assert_eq!(stats.files_copied, 1);
-
-        // Check the directory structure was created
-        let expected_dir = temp_dest.path().join("Test Author/Test Book");
-        assert!(expected_dir.exists());
-
-        let expected_file = expected_dir.join("01 Chapter 1.mp3");
-        assert!(expected_file.exists());
-    }
-
-    #[test]
-    fn test_dry_run_does_not_copy() {
-        let temp_source = tempdir().unwrap();
-        let temp_dest = tempdir().unwrap();
-
-        let audiobook = Audiobook {
-            title: "Dry Run Book".to_string(),
-            author: "Dry Run Author".to_string(),
-            narrator: None,
-            folder_id: "sha1-dryrun".to_string(),
-            tracks: vec![AudioTrack {
-                track_number: 1,
-                disc_number: 0,
-                title: "Chapter 1".to_string(),
-                path: PathBuf::from("/fake/path/track.mp3"),
-                filename: "track.mp3".to_string(),
-            }],
+/// Search a book database for the selected books Audnexus didn't find, and fill in what
+/// the library lacks
+fn enrich_from_search(
+    audiobooks: &mut [Audiobook],
+    service: booksearch::Service,
+    skip: &HashSet<String>,
+    options: &ExportOptions,
+) {
+    let selected: HashSet<_> = options
+        .selected(audiobooks)
+        .iter()
+        .map(|book| book.folder_id.clone())
+        .filter(|id| !skip.contains(id))
+        .collect();
+    let cache = booksearch::default_cache_dir();
+    let pb = progress::bar(selected.len(), "Searching book databases");
+    let (mut found_count, mut updated) = (0, 0);
+    for book in audiobooks
+        .iter_mut()
+        .filter(|book| selected.contains(&book.folder_id))
+    {
+        pb.inc(1);
+        pb.set_message(book.title.clone());
+        match booksearch::search(service, book, cache.as_deref()) {
+            Ok(Some(found)) => {
+                found_count += 1;
+                if booksearch::apply(book, &found) {
+                    updated += 1;
+                }
+            }
+            Ok(None) => {}
+            Err(e) => pb.suspend(|| {
+                eprintln!(
+                    "Warning: Could not search {} for {}: {:#}",
+                    service, book.title, e
+                )
+            }),
+        }
+    }
+    pb.finish_and_clear();
+    output::message(format_args!(
+        "{}: found {} of {} books, filled in details of {}",
+        service,
+        found_count,
+        selected.len(),
+        updated
+    ));
+}
+
+fn run_export(
+    mut args: ExportArgs,
+    config: &config::Config,
+    matches: &clap::ArgMatches,
+) -> Result<()> {
+    let defaults = config.export_defaults(args.profile.as_deref())?;
+    args.apply_defaults(defaults, matches)?;
+    output::set_format(args.format);
+    let mut options = args.options(defaults, matches)?;
+
+    if let Some(previous_report) = &args.retry_failed {
+        let previous = report::RunReport::read(previous_report)?;
+        let dest = args.dest.unwrap_or(previous.dest);
+        output::message(format_args!(
+            "Retrying {} failed files from {:?}",
+            previous.stats.failures.len(),
+            previous_report
+        ));
+
+        let stats = retry_failed(&previous.stats.failures, &options)?;
+        print_summary(&stats, args.format)?;
+        let strict = if args.strict {
+            check_strict(&stats)
+        } else {
+            Ok(())
         };
 
-        let stats = export_audiobooks(
-            &[audiobook],
-            temp_source.path(),
-            temp_dest.path(),
-            true, // dry_run = true
-            false,
-        )
-        .unwrap();
+        let report_path = args
+            .report
+            .unwrap_or_else(|| report::default_report_path(&dest));
+        save_report(&report_path, &dest, stats, config.email.as_ref())?;
+        return strict;
+    }
+
+    // Required unless --retry-failed is given, which returned above
+    let dest = args
+        .dest
+        .context("--dest is required (or run `init` to set up a default destination)")?;
 
-        assert_eq!(stats.files_would_copy, 1);
-        assert_eq!(stats.files_copied, 0);
+    let source_base = args.source.unwrap_or_else(default_apple_books_path);
+    let plist_path = source_base.join("Books.plist");
 
-        // Directory should NOT be created in dry run
-        let expected_dir = temp_dest.path().join("Dry Run Author/Dry Run Book");
-        assert!(!expected_dir.exists());
+    output::message(format_args!(
+        "{} {:?}",
+        tr("Reading audiobook library from:"),
+        plist_path
+    ));
+
+    let spinner = progress::spinner("Reading library");
+    let mut audiobooks = parse_books_plist(&plist_path)?;
+    spinner.finish_and_clear();
+
+    output::message(tr_args("Found {} audiobooks", &[&audiobooks.len()]));
+    let from_tags = tags::fill_from_tags(&mut audiobooks, &source_base);
+    if from_tags > 0 {
+        output::message(format_args!(
+            "Read the title or author of {} audiobooks from their tags",
+            from_tags
+        ));
+    }
+
+    let overrides_path = overrides::default_overrides_path();
+    let mut book_overrides = match &overrides_path {
+        Some(path) => overrides::Overrides::read(path)?,
+        None => overrides::Overrides::default(),
+    };
+    args.enrich.sort();
+    args.enrich.dedup();
+    let mut found_on_audnexus = HashSet::new();
+    for service in &args.enrich {
+        let service = match service {
+            Enrichment::Audnexus => {
+                (options.enriched_chapters, found_on_audnexus) = enrich_from_audnexus(
+                    &mut audiobooks,
+                    &source_base,
+                    &args.audnexus_region,
+                    &options,
+                );
+                continue;
+            }
+            Enrichment::Openlibrary => booksearch::Service::OpenLibrary,
+            Enrichment::GoogleBooks => booksearch::Service::GoogleBooks,
+        };
+        enrich_from_search(&mut audiobooks, service, &found_on_audnexus, &options);
+    }
+
+    let originals = audiobooks.clone();
+    book_overrides.apply(&mut audiobooks);
+
+    for query in &args.books {
+        let book = find_book(&audiobooks, query)?;
+        options.book_ids.push(book.folder_id.clone());
+    }
+
+    if args.interactive {
+        let candidates = options.selected(&audiobooks);
+        let items: Vec<_> = candidates
+            .iter()
+            .map(|book| match &book.narrator {
+                Some(narrator) => {
+                    format!("{} - {} (read by {})", book.author, book.title, narrator)
+                }
+                None => format!("{} - {}", book.author, book.title),
+            })
+            .collect();
+        let chosen = picker::pick("Choose the books to export", &items)?;
+        if chosen.is_empty() {
+            output::message("No books chosen");
+            return Ok(());
+        }
+        options.book_ids = chosen
+            .iter()
+            .map(|&i| candidates[i].folder_id.clone())
+            .collect();
+        options.skip = 0;
+        options.limit = None;
+    }
+
+    if args.review {
+        let selected: HashSet<_> = options
+            .selected(&audiobooks)
+            .iter()
+            .map(|b| b.folder_id.clone())
+            .collect();
+        for (book, original) in audiobooks.iter_mut().zip(&originals) {
+            if !selected.contains(&book.folder_id) {
+                continue;
+            }
+            match overrides::edit_in_editor(book) {
+                Ok(edited) => {
+                    book_overrides.set(original, &edited);
+                    *book = edited;
+                }
+                Err(e) => eprintln!(
+                    "Warning: Keeping metadata of {} - {}: {:#}",
+                    book.author, book.title, e
+                ),
+            }
+        }
+        let path = overrides_path.context("Could not determine config directory")?;
+        book_overrides.write(&path)?;
+        output::message(format_args!("Saved metadata corrections to {:?}", path));
+    }
+
+    let mut library_warnings = Vec::new();
+    let mut warn = |message: String| {
+        eprintln!("Warning: {}", message);
+        library_warnings.push(message);
+    };
+    for id in &options.book_ids {
+        if !audiobooks.iter().any(|b| &b.folder_id == id) {
+            warn(format!(
+                "No audiobook with folder id {:?} in the library",
+                id
+            ));
+        }
+    }
+
+    if !options.filter.is_empty() || options.skip > 0 || options.limit.is_some() {
+        let selected = options.selected(&audiobooks).len();
+        if selected == 0 {
+            warn("No audiobook is left after the filters, --skip and --limit".to_string());
+        } else {
+            output::message(format_args!(
+                "Selected {} of {} audiobooks",
+                selected,
+                audiobooks.len()
+            ));
+        }
+    }
+
+    let incomplete = audiobooks
+        .iter()
+        .filter(|book| !book.missing_tracks().is_empty())
+        .count();
+    if incomplete > 0 {
+        warn(format!(
+            "{} audiobooks have gaps in their track numbering (use --dry-run to list them)",
+            incomplete
+        ));
+    }
+
+    if args.check_catalog {
+        let entries = catalog::find(&source_base)
+            .with_context(|| format!("No Apple Books catalog found for {:?}", source_base))
+            .and_then(|path| Ok((catalog::read(&path)?, path)));
+        match entries {
+            Ok((entries, path)) => {
+                let comparison = catalog::compare(&audiobooks, &entries);
+                for (book, _) in audiobooks
+                    .iter()
+                    .zip(&comparison.matches)
+                    .filter(|(_, i)| i.is_none())
+                {
+                    warn(format!(
+                        "{} - {} ({}) is in Books.plist but not in the catalog",
+                        book.author, book.title, book.folder_id
+                    ));
+                }
+                for &i in &comparison.only_in_catalog {
+                    warn(format!(
+                        "{} - {} is in the catalog but not in Books.plist",
+                        entries[i].author.as_deref().unwrap_or("Unknown Author"),
+                        entries[i].title.as_deref().unwrap_or("Unknown Title")
+                    ));
+                }
+                let merged = catalog::merge(&mut audiobooks, &entries, &comparison);
+                output::message(format_args!(
+                    "Merged {} descriptions from {:?}",
+                    merged, path
+                ));
+            }
+            Err(e) => warn(format!("Could not check the catalog: {:#}", e)),
+        }
+    }
+
+    if args.dry_run {
+        output::message(format_args!(
+            "\n{}",
+            tr("=== DRY RUN - No files will be copied ===")
+        ));
+
+        // Compute and display diff
+        let diffs = compute_diff(&audiobooks, &source_base, &dest, &options);
+        if args.format == output::Format::Json {
+            println!("{}", diff_json(&source_base, &dest, &diffs)?);
+        } else {
+            display_diff(&diffs);
+            display_track_gaps(&audiobooks);
+        }
+
+        return Ok(());
+    }
+
+    let mut stats = export_audiobooks(&audiobooks, &source_base, &dest, &options)?;
+    stats.warnings.splice(0..0, library_warnings);
+    print_summary(&stats, args.format)?;
+    let strict = if args.strict {
+        check_strict(&stats)
+    } else {
+        Ok(())
+    };
+
+    if let Some(path) = snapshot::default_snapshot_path() {
+        if let Err(e) =
+            snapshot::LibrarySnapshot::from_library(&source_base, &audiobooks).write(&path)
+        {
+            eprintln!("Warning: Could not save library snapshot: {:#}", e);
+        }
+    }
+
+    let report_path = args
+        .report
+        .unwrap_or_else(|| report::default_report_path(&dest));
+    save_report(&report_path, &dest, stats, config.email.as_ref())?;
+    strict
+}
+
+fn run_changes(args: ChangesArgs) -> Result<()> {
+    let source_base = args.source.unwrap_or_else(default_apple_books_path);
+    let mut audiobooks = parse_books_plist(&source_base.join("Books.plist"))?;
+    tags::fill_from_tags(&mut audiobooks, &source_base);
+    let current = snapshot::LibrarySnapshot::from_library(&source_base, &audiobooks);
+
+    let path = snapshot::default_snapshot_path().context("Could not determine data directory")?;
+    match snapshot::LibrarySnapshot::read(&path)? {
+        Some(previous) => {
+            if previous.source != source_base {
+                eprintln!(
+                    "Warning: The last run exported from {:?}, not {:?}",
+                    previous.source, source_base
+                );
+            }
+            snapshot::display_changes(&previous.changes_to(&current));
+        }
+        None => println!(
+            "No previous run recorded; {} audiobooks in the library",
+            audiobooks.len()
+        ),
+    }
+
+    if args.update {
+        current.write(&path)?;
+    }
+    Ok(())
+}
+
+fn run_diff(args: DiffArgs, config: &config::Config, matches: &clap::ArgMatches) -> Result<()> {
+    let defaults = config.export_defaults(args.profile.as_deref())?;
+    let options = args.options(defaults, matches)?;
+    let dest = args
+        .dest
+        .or_else(|| defaults.dest.clone())
+        .context("--dest is required (or run `init` to set up a default destination)")?;
+    let source_base = args
+        .source
+        .or_else(|| defaults.source.clone())
+        .unwrap_or_else(default_apple_books_path);
+    let spinner = progress::spinner("Reading library");
+    let mut audiobooks = parse_books_plist(&source_base.join("Books.plist"))?;
+    tags::fill_from_tags(&mut audiobooks, &source_base);
+    spinner.finish_and_clear();
+    if let Some(path) = overrides::default_overrides_path() {
+        overrides::Overrides::read(&path)?.apply(&mut audiobooks);
+    }
+
+    let mut diffs = compute_diff(&audiobooks, &source_base, &dest, &options);
+    if !args.only.is_empty() {
+        diffs.retain(|diff| args.only.iter().any(|only| only.matches(&diff.status)));
+    }
+    if let Some(path) = &args.output {
+        save_diff(path, &source_base, &dest, &diffs)?;
+    }
+
+    if args.format == output::Format::Json {
+        println!("{}", diff_json(&source_base, &dest, &diffs)?);
+        return Ok(());
+    }
+    if args.paths {
+        write_diff_paths(&mut io::stdout().lock(), &diffs)?;
+        return Ok(());
+    }
+    if args.by_book {
+        write_diff_by_book(&mut io::stdout().lock(), &diffs)?;
+    } else if args.full {
+        let mut out = Vec::new();
+        write_diff(&mut out, &diffs, true)?;
+        page_output(&String::from_utf8_lossy(&out));
+    } else {
+        display_diff(&diffs);
+    }
+    display_track_gaps(&audiobooks);
+    Ok(())
+}
+
+/// The book a command line argument refers to: its folder id, its position in Books.plist
+/// (from 1), or part of its title or author, if only one book matches
+fn find_book<'a>(audiobooks: &'a [Audiobook], book: &str) -> Result<&'a Audiobook> {
+    if let Some(found) = audiobooks.iter().find(|b| b.folder_id == book) {
+        return Ok(found);
+    }
+    if let Ok(position) = book.parse::<usize>() {
+        return position
+            .checked_sub(1)
+            .and_then(|i| audiobooks.get(i))
+            .with_context(|| format!("The library has {} audiobooks", audiobooks.len()));
+    }
+
+    let query = book.to_lowercase();
+    let matches: Vec<_> = audiobooks
+        .iter()
+        .filter(|b| {
+            b.title.to_lowercase().contains(&query) || b.author.to_lowercase().contains(&query)
+        })
+        .collect();
+    match matches.as_slice() {
+        [] => bail!("No audiobook matches {:?}", book),
+        [found] => Ok(found),
+        books => {
+            eprintln!("{:?} matches {} audiobooks:", book, books.len());
+            for found in books {
+                eprintln!("  {} - {} ({})", found.author, found.title, found.folder_id);
+            }
+            bail!("Use the folder id to pick one");
+        }
+    }
+}
+
+fn run_preview(
+    args: PreviewArgs,
+    config: &config::Config,
+    matches: &clap::ArgMatches,
+) -> Result<()> {
+    let defaults = config.export_defaults(args.profile.as_deref())?;
+    let mut options = args.layout.options();
+    defaults.apply(
+        &mut options,
+        None,
+        &CommandLine::new::<PreviewArgs>(matches),
+    )?;
+    let source_base = args
+        .source
+        .or_else(|| defaults.source.clone())
+        .unwrap_or_else(default_apple_books_path);
+    let mut audiobooks = parse_books_plist(&source_base.join("Books.plist"))?;
+    tags::fill_from_tags(&mut audiobooks, &source_base);
+    if let Some(path) = overrides::default_overrides_path() {
+        overrides::Overrides::read(&path)?.apply(&mut audiobooks);
+    }
+
+    let audiobook = find_book(&audiobooks, &args.book)?;
+    println!("{}", preview_tree(audiobook, &source_base, &options));
+    Ok(())
+}
+
+/// The duration of each book, and with a destination whether its manifest records the book
+/// as exported, for a library report
+fn report_entries<'a>(
+    audiobooks: &'a [Audiobook],
+    source_base: &Path,
+    dest: Option<&Path>,
+) -> Vec<markdown::ReportEntry<'a>> {
+    let manifest = dest.map(|dest| {
+        (
+            dest,
+            manifest::Manifest::read(&manifest::manifest_path(dest)),
+        )
+    });
+    let pb = progress::bar(audiobooks.len(), "Scanning library");
+    let entries: Vec<_> = audiobooks
+        .iter()
+        .map(|book| {
+            let seconds = book
+                .tracks
+                .iter()
+                .filter_map(|t| media::probe_duration(&remap_track_path(&t.path, source_base)).ok())
+                .sum();
+            let status = manifest.as_ref().map(|(dest, manifest)| {
+                let dir = manifest
+                    .books
+                    .get(&book.folder_id)
+                    .map(|entry| manifest.relocate(&entry.dest_dir, dest))
+                    .filter(|dir| dir.is_dir());
+                match dir {
+                    Some(dir) => markdown::ExportStatus::Exported {
+                        cover: dir.join(cover::COVER_FILENAME).exists(),
+                        dir: dir
+                            .strip_prefix(dest)
+                            .map_or(dir.clone(), Path::to_path_buf),
+                    },
+                    None => markdown::ExportStatus::NotExported,
+                }
+            });
+            pb.inc(1);
+            markdown::ReportEntry {
+                book,
+                seconds,
+                status,
+            }
+        })
+        .collect();
+    pb.finish_and_clear();
+    if entries.iter().all(|entry| entry.seconds == 0.0) && !entries.is_empty() {
+        eprintln!("Warning: Could not read durations (is ffmpeg installed?)");
+    }
+    entries
+}
+
+fn run_report(args: ReportArgs) -> Result<()> {
+    let document = match (args.format, &args.run) {
+        (ReportFormat::Markdown, Some(path)) => {
+            markdown::run_report(&report::RunReport::read(path)?)
+        }
+        (ReportFormat::Html, Some(_)) => bail!("Run reports are only available as Markdown"),
+        (format, None) => {
+            let source_base = args.source.unwrap_or_else(default_apple_books_path);
+            let mut audiobooks = parse_books_plist(&source_base.join("Books.plist"))?;
+            tags::fill_from_tags(&mut audiobooks, &source_base);
+            if let Some(path) = overrides::default_overrides_path() {
+                overrides::Overrides::read(&path)?.apply(&mut audiobooks);
+            }
+            let entries = report_entries(&audiobooks, &source_base, args.dest.as_deref());
+            match format {
+                ReportFormat::Markdown => markdown::library_report(&entries),
+                ReportFormat::Html => html::library_page(&entries),
+            }
+        }
+    };
+
+    match args.output {
+        Some(path) => {
+            fs::write(&path, document).with_context(|| format!("Failed to write {:?}", path))
+        }
+        None => {
+            print!("{}", document);
+            Ok(())
+        }
+    }
+}
+
+fn run_history(args: HistoryArgs) -> Result<()> {
+    let Some(id) = args.run else {
+        let runs = history::list(&args.dest)?;
+        if runs.is_empty() {
+            println!("No runs recorded in {:?}", args.dest);
+            return Ok(());
+        }
+        println!(
+            "{:<20} {:>6} {:>7} {:>7}  Options",
+            "Run", "Books", "Copied", "Failed"
+        );
+        for (id, run) in &runs {
+            println!(
+                "{:<20} {:>6} {:>7} {:>7}  {}",
+                id,
+                run.stats.books_exported,
+                run.stats.files_copied,
+                run.stats.failures.len(),
+                run.options.join(" ")
+            );
+        }
+        return Ok(());
+    };
+
+    let (id, run) = history::find(&args.dest, &id)?;
+    println!("Run {} (version {})", id, run.tool_version);
+    println!("Destination: {:?}", run.dest);
+    if !run.options.is_empty() {
+        println!("Options: {}", run.options.join(" "));
+    }
+    print_export_summary(&run.stats);
+    if !run.stats.failures.is_empty() {
+        println!("\nFailed files:");
+        for failure in &run.stats.failures {
+            println!("  {}: {:?}", failure.book, failure.source);
+            println!("      {}", failure.error);
+        }
+    }
+    Ok(())
+}
+
+fn run_verify(args: VerifyArgs, config: &config::Config) -> Result<()> {
+    let defaults = config.export_defaults(args.profile.as_deref())?;
+    let dest = args
+        .dest
+        .or_else(|| defaults.dest.clone())
+        .context("--dest is required (or run `init` to set up a default destination)")?;
+    let manifest = manifest::Manifest::read(&manifest::manifest_path(&dest));
+    if manifest.books.is_empty() {
+        bail!("No exported books are recorded in {:?}", dest);
+    }
+    let against = if args.against_manifest {
+        verify::Against::Manifest
+    } else if !manifest.plain_copy {
+        bail!(
+            "Files in {:?} aren't plain copies of their sources; check them with --against-manifest",
+            dest
+        );
+    } else if args.quick {
+        verify::Against::Sizes
+    } else {
+        verify::Against::Sources
+    };
+
+    let files = verify::files(&manifest, &dest);
+    let pb = progress::bar(files.len(), "Verifying");
+    let (mut ok, mut bad, mut unverifiable) = (0, 0, 0);
+    for (file, dest) in &files {
+        pb.inc(1);
+        match verify::check(file, dest, against) {
+            verify::Status::Ok => ok += 1,
+            verify::Status::Missing => {
+                pb.suspend(|| println!("Missing: {:?}", dest));
+                bad += 1;
+            }
+            verify::Status::Changed => {
+                pb.suspend(|| println!("Changed: {:?}", dest));
+                bad += 1;
+            }
+            verify::Status::Truncated { expected, actual } => {
+                pb.suspend(|| {
+                    println!(
+                        "Truncated: {:?} ({} of {})",
+                        dest,
+                        format_bytes(actual),
+                        format_bytes(expected)
+                    )
+                });
+                bad += 1;
+            }
+            verify::Status::Unverifiable(reason) => {
+                pb.suspend(|| eprintln!("Warning: Could not verify {:?}: {}", dest, reason));
+                unverifiable += 1;
+            }
+        }
+    }
+    pb.finish_and_clear();
+
+    println!(
+        "{} files checked: {} intact, {} missing, changed or truncated, {} could not be verified",
+        files.len(),
+        ok,
+        bad,
+        unverifiable
+    );
+    if bad > 0 {
+        bail!(
+            "{} files in {:?} are missing, changed or truncated",
+            bad,
+            dest
+        );
+    }
+    Ok(())
+}
+
+fn run_relink(args: RelinkArgs) -> Result<()> {
+    let source_base = args.source.unwrap_or_else(default_apple_books_path);
+    let broken = relink::find_broken(&args.dest)?;
+    if broken.is_empty() {
+        println!("No broken symlinks in {:?}", args.dest);
+        return Ok(());
+    }
+
+    let mut new_sources = std::collections::HashMap::new();
+    let mut unresolved = 0;
+    for link in &broken {
+        let target = remap_track_path(&link.target, &source_base);
+        if !target.exists() {
+            eprintln!(
+                "Warning: No source found for {:?} (was {:?})",
+                link.link, link.target
+            );
+            unresolved += 1;
+            continue;
+        }
+        if args.dry_run {
+            println!("Would relink {:?} -> {:?}", link.link, target);
+        } else {
+            relink::relink(&link.link, &target)?;
+        }
+        new_sources.insert(link.link.clone(), target);
+    }
+
+    // Point the manifest at the new sources too, so the next export still skips these books
+    let manifest_path = manifest::manifest_path(&args.dest);
+    let mut manifest = manifest::Manifest::read(&manifest_path);
+    if !args.dry_run && !new_sources.is_empty() && !manifest.books.is_empty() {
+        let mut books = std::mem::take(&mut manifest.books);
+        for file in books.values_mut().flat_map(|entry| &mut entry.files) {
+            if let Some(target) = new_sources.get(&manifest.relocate(&file.dest, &args.dest)) {
+                file.source = target.clone();
+            }
+        }
+        manifest.books = books;
+        if let Err(e) = manifest.write(&manifest_path) {
+            eprintln!("Warning: Could not update manifest: {:#}", e);
+        }
+    }
+
+    println!(
+        "{} {} symlinks, {} without a source",
+        if args.dry_run {
+            "Would repair"
+        } else {
+            "Repaired"
+        },
+        new_sources.len(),
+        unresolved
+    );
+    Ok(())
+}
+
+fn run_clean(args: CleanArgs) -> Result<()> {
+    let source_base = args.source.unwrap_or_else(default_apple_books_path);
+    let broken = relink::find_broken(&args.dest)?;
+    let (mut removed, mut repairable, mut other) = (0, 0, 0);
+    for link in &broken {
+        if !link.points_into_books(&source_base) {
+            println!(
+                "Not into Apple Books, left alone: {:?} -> {:?}",
+                link.link, link.target
+            );
+            other += 1;
+        } else if remap_track_path(&link.target, &source_base).exists() {
+            println!("Repairable with relink, left alone: {:?}", link.link);
+            repairable += 1;
+        } else if args.dry_run {
+            println!("Would remove {:?} -> {:?}", link.link, link.target);
+            removed += 1;
+        } else {
+            match fs::remove_file(&link.link) {
+                Ok(()) => {
+                    println!("Removed {:?}", link.link);
+                    removed += 1;
+                }
+                Err(e) => eprintln!("Warning: Could not remove {:?}: {}", link.link, e),
+            }
+        }
+    }
+
+    println!(
+        "{} {} broken symlinks, {} repairable with relink, {} pointing elsewhere",
+        if args.dry_run {
+            "Would remove"
+        } else {
+            "Removed"
+        },
+        removed,
+        repairable,
+        other
+    );
+    Ok(())
+}
+
+fn run_prune(args: PruneArgs, config: &config::Config) -> Result<()> {
+    let defaults = config.export_defaults(args.profile.as_deref())?;
+    let dest = args
+        .dest
+        .or_else(|| defaults.dest.clone())
+        .context("--dest is required (or run `init` to set up a default destination)")?;
+    let source_base = args
+        .source
+        .or_else(|| defaults.source.clone())
+        .unwrap_or_else(default_apple_books_path);
+    let audiobooks = parse_books_plist(&source_base.join("Books.plist"))?;
+    let manifest_path = manifest::manifest_path(&dest);
+    let mut manifest = manifest::Manifest::read(&manifest_path);
+    let stale = prune::find(&manifest, &audiobooks, &dest);
+    if stale.is_empty() {
+        println!("No books to prune in {:?}", dest);
+        return Ok(());
+    }
+
+    let disposer = trash::Disposer::new(if args.permanent {
+        trash::Disposal::Permanent
+    } else if let Some(dir) = args.quarantine_dir {
+        trash::Disposal::Quarantine(dir)
+    } else {
+        trash::Disposal::Trash
+    });
+    let (mut books, mut files) = (0, 0);
+    for book in &stale {
+        if args.dry_run {
+            println!(
+                "Would remove {} - {} ({:?})",
+                book.author, book.title, book.dir
+            );
+            for file in &book.files {
+                println!("  {:?}", file);
+            }
+            books += 1;
+            files += book.files.len();
+            continue;
+        }
+        match prune::remove(book, &dest, &disposer) {
+            Ok(removed) => {
+                println!("Removed {} - {}", book.author, book.title);
+                books += 1;
+                files += removed;
+                manifest.books.remove(&book.folder_id);
+            }
+            Err(e) => eprintln!(
+                "Warning: Could not remove {} - {}: {:#}",
+                book.author, book.title, e
+            ),
+        }
+    }
+    if !args.dry_run {
+        if let Err(e) = manifest.write(&manifest_path) {
+            eprintln!("Warning: Could not update manifest: {:#}", e);
+        }
+    }
+
+    println!(
+        "{} {} books, {} files",
+        if args.dry_run {
+            "Would remove"
+        } else {
+            "Removed"
+        },
+        books,
+        files
+    );
+    Ok(())
+}
+
+fn run_rollback(args: RollbackArgs) -> Result<()> {
+    let Some(id) = args.snapshot else {
+        let ids = rollback::list(&args.dest)?;
+        if ids.is_empty() {
+            println!("No snapshots of {:?}", args.dest);
+        }
+        for id in ids {
+            println!("{}", id);
+        }
+        return Ok(());
+    };
+
+    let id = rollback::find(&args.dest, &id)?;
+    let restored = rollback::restore(&args.dest, &id)?;
+    println!(
+        "Restored {} files from snapshot {} ({} unchanged)",
+        restored.restored, id, restored.unchanged
+    );
+    if restored.added > 0 {
+        println!(
+            "{} files added since the snapshot were left in place",
+            restored.added
+        );
+    }
+    Ok(())
+}
+
+fn run_dump(args: DumpArgs) -> Result<()> {
+    let source_base = args.source.unwrap_or_else(default_apple_books_path);
+    let plist_path = source_base.join("Books.plist");
+    let json = if args.raw {
+        let value: plist::Value = plist::from_file(&plist_path)
+            .with_context(|| format!("Failed to parse plist at {:?}", plist_path))?;
+        dump::plist_to_json(&value)
+    } else {
+        serde_json::to_value(parse_books_plist(&plist_path)?)?
+    };
+    let mut out = io::stdout().lock();
+    serde_json::to_writer_pretty(&mut out, &json)?;
+    writeln!(out)?;
+    Ok(())
+}
+
+fn run_debug_bundle(args: DebugBundleArgs) -> Result<()> {
+    let source_base = args.source.unwrap_or_else(default_apple_books_path);
+    bundle::write(&source_base.join("Books.plist"), &args.output)?;
+    println!("Wrote {:?}", args.output);
+    println!(
+        "Titles, authors and paths in it are replaced by hashes; look through it before sharing."
+    );
+    Ok(())
+}
+
+fn run_validate(args: ValidateArgs) -> Result<()> {
+    let source_base = args.source.unwrap_or_else(default_apple_books_path);
+    let plist_path = source_base.join("Books.plist");
+    let problems = validate::validate_file(&plist_path)?;
+    if problems.is_empty() {
+        println!("No problems found in {:?}", plist_path);
+        return Ok(());
+    }
+
+    for problem in &problems {
+        println!(
+            "Entry {} ({}): {}",
+            problem.index, problem.book, problem.message
+        );
+    }
+    let books: HashSet<_> = problems.iter().map(|p| p.index).collect();
+    bail!(
+        "Found {} problems in {} audiobook entries of {:?}",
+        problems.len(),
+        books.len(),
+        plist_path
+    )
+}
+
+fn run_orphans(
+    args: OrphansArgs,
+    config: &config::Config,
+    matches: &clap::ArgMatches,
+) -> Result<()> {
+    let defaults = config.export_defaults(args.profile.as_deref())?;
+    let mut options = args.layout.options();
+    defaults.apply(
+        &mut options,
+        None,
+        &CommandLine::new::<OrphansArgs>(matches),
+    )?;
+    // Orphans are exported by folder id, whatever the filters of the config file
+    options.filter = Default::default();
+    let source_base = args
+        .source
+        .or_else(|| defaults.source.clone())
+        .unwrap_or_else(default_apple_books_path);
+    let audiobooks = parse_books_plist(&source_base.join("Books.plist"))?;
+    let ignored_path = orphans::default_ignored_path();
+    let mut ignored = match &ignored_path {
+        Some(path) => orphans::Ignored::read(path)?,
+        None => orphans::Ignored::default(),
+    };
+
+    let found: Vec<_> = orphans::find(&source_base, &audiobooks)?
+        .into_iter()
+        .filter(|orphan| args.all || !ignored.folders.contains(&orphan.folder_id))
+        .collect();
+    let stray = orphans::find_stray(&source_base, &audiobooks)?;
+    if !stray.is_empty() {
+        println!("Not referenced by any book:");
+        for item in &stray {
+            println!("{:>10}  {:?}", format_bytes(item.bytes), item.path);
+        }
+        println!();
+    }
+    if found.is_empty() {
+        println!("No orphaned book folders in {:?}", source_base);
+        return Ok(());
+    }
+
+    let mut exports = Vec::new();
+    for orphan in &found {
+        let book = orphans::identify(orphan);
+        println!(
+            "{}: {} - {} ({} tracks, {}){}",
+            orphan.folder_id,
+            book.author,
+            book.title,
+            book.tracks.len(),
+            format_bytes(orphan.bytes),
+            if ignored.folders.contains(&orphan.folder_id) {
+                " [ignored]"
+            } else {
+                ""
+            }
+        );
+        if let Some(narrator) = &book.narrator {
+            println!("    narrated by {}", narrator);
+        }
+        if args.dest.is_none() {
+            continue;
+        }
+        match orphans::ask() {
+            orphans::Action::Export => exports.push(book),
+            orphans::Action::Ignore => {
+                ignored.folders.insert(orphan.folder_id.clone());
+            }
+            orphans::Action::Skip => {}
+        }
+    }
+
+    if let Some(path) = &ignored_path {
+        if let Err(e) = ignored.write(path) {
+            eprintln!("Warning: Could not save ignored orphans: {:#}", e);
+        }
+    }
+
+    let Some(dest) = args.dest else {
+        println!();
+        println!(
+            "{} orphaned book folders, {}. Run again with --dest to export or ignore them",
+            found.len(),
+            format_bytes(found.iter().map(|orphan| orphan.bytes).sum())
+        );
+        return Ok(());
+    };
+    if exports.is_empty() {
+        return Ok(());
+    }
+
+    // The library goes along so books already in the destination aren't reported as removed
+    options.book_ids = exports.iter().map(|book| book.folder_id.clone()).collect();
+    let all: Vec<_> = audiobooks.into_iter().chain(exports).collect();
+    let stats = export_audiobooks(&all, &source_base, &dest, &options)?;
+    print_export_summary(&stats);
+    Ok(())
+}
+
+fn run_init(config: &config::Config) -> Result<()> {
+    if !io::stdin().is_terminal() {
+        bail!("init asks questions; run it in a terminal");
+    }
+    let config_path =
+        config::default_config_path().context("Could not determine config directory")?;
+
+    let sources = init::detect_sources(&init::candidate_homes());
+    let default_source = default_apple_books_path();
+    let source = match sources.as_slice() {
+        [] => {
+            println!("No Apple Books library found on this Mac or mounted volumes.");
+            PathBuf::from(init::ask("Apple Books folder:", None)?)
+        }
+        [source] => {
+            println!("Found an Apple Books library at {:?}", source);
+            source.clone()
+        }
+        _ => {
+            let names: Vec<_> = sources.iter().map(|s| s.to_string_lossy()).collect();
+            let choices: Vec<_> = names
+                .iter()
+                .map(|n| (n.as_ref(), "Apple Books library"))
+                .collect();
+            sources[init::choose("Which library should be exported?", &choices)?].clone()
+        }
+    };
+
+    let current_dest = config
+        .export
+        .dest
+        .as_ref()
+        .map(|d| d.to_string_lossy().to_string());
+    let dest = PathBuf::from(init::ask(
+        "Destination (your Audiobookshelf library folder):",
+        current_dest.as_deref(),
+    )?);
+
+    let profiles = [
+        (config::Profile::Copy, "copy", "full copies of every track"),
+        (
+            config::Profile::Symlink,
+            "symlink",
+            "links to the Apple Books files, on the same disk",
+        ),
+        (
+            config::Profile::Compact,
+            "compact",
+            "Opus at 64 kbit/s, for small disks (requires ffmpeg)",
+        ),
+    ];
+    let choices: Vec<_> = profiles
+        .iter()
+        .map(|(_, name, about)| (*name, *about))
+        .collect();
+    let profile = profiles[init::choose("How should books be stored?", &choices)?].0;
+
+    let modes = [
+        ("replace", "replace characters like : and ? with _"),
+        ("drop", "leave them out"),
+        ("lookalike", "replace them with similar-looking characters"),
+    ];
+    let mode = modes[init::choose("How should file and folder names be written?", &modes)?].0;
+
+    init::update_config(
+        &config_path,
+        (source != default_source).then_some(source.as_path()),
+        &dest,
+        profile,
+        mode,
+    )?;
+    println!("Saved settings to {:?}", config_path);
+
+    if cfg!(target_os = "macos") && init::confirm("Export automatically with launchd?")? {
+        let schedules = [
+            ("daily", "every day at 3:00"),
+            ("weekly", "on Sundays at 3:00"),
+        ];
+        let weekly = init::choose("How often?", &schedules)? == 1;
+        match init::install_schedule(weekly) {
+            Ok(path) => println!("Installed schedule {:?}", path),
+            Err(e) => eprintln!("Warning: Could not install schedule: {:#}", e),
+        }
+    }
+
+    println!();
+    println!("Run `extract_apple_books --dry-run` to preview the first export, or `extract_apple_books` to start it.");
+    Ok(())
+}
+
+fn run_list(args: ListArgs) -> Result<()> {
+    let source_base = args.source.unwrap_or_else(default_apple_books_path);
+    let mut audiobooks = parse_books_plist(&source_base.join("Books.plist"))?;
+    tags::fill_from_tags(&mut audiobooks, &source_base);
+    if let Some(path) = overrides::default_overrides_path() {
+        overrides::Overrides::read(&path)?.apply(&mut audiobooks);
+    }
+
+    let mut rows: Vec<_> = audiobooks
+        .iter()
+        .map(|book| list::Row::new(book, &source_base))
+        .collect();
+    list::sort(&mut rows, args.sort);
+    if args.reverse {
+        rows.reverse();
+    }
+    match args.format {
+        output::Format::Text => list::write_table(&mut io::stdout().lock(), &rows)?,
+        output::Format::Json => println!("{}", serde_json::to_string_pretty(&rows)?),
+    }
+    Ok(())
+}
+
+fn run_tui(args: TuiArgs, config: &config::Config, matches: &clap::ArgMatches) -> Result<()> {
+    let mut args = args.export;
+    let defaults = config.export_defaults(args.profile.as_deref())?;
+    args.apply_defaults(defaults, matches)?;
+    let dest = args
+        .dest
+        .clone()
+        .context("--dest is required (or run `init` to set up a default destination)")?;
+    let source_base = args.source.clone().unwrap_or_else(default_apple_books_path);
+    let mut audiobooks = parse_books_plist(&source_base.join("Books.plist"))?;
+    tags::fill_from_tags(&mut audiobooks, &source_base);
+    if let Some(path) = overrides::default_overrides_path() {
+        overrides::Overrides::read(&path)?.apply(&mut audiobooks);
+    }
+
+    let options = args.options(defaults, matches)?;
+    let diffs = compute_diff(&audiobooks, &source_base, &dest, &options);
+    let Some(marked) = tui::run(&audiobooks, &diffs)? else {
+        return Ok(());
+    };
+    if marked.is_empty() {
+        println!("No books marked for export");
+        return Ok(());
+    }
+
+    args.book_ids = marked;
+    args.books.clear();
+    args.interactive = false;
+    run_export(args, config, matches)
+}
+
+fn run_completions(args: CompletionsArgs) -> Result<()> {
+    let mut command = Cli::command();
+    let name = command.get_name().to_string();
+    clap_complete::generate(args.shell, &mut command, name, &mut io::stdout());
+    Ok(())
+}
+
+fn run_catalog(args: CatalogArgs) -> Result<()> {
+    let source_base = args.source.unwrap_or_else(default_apple_books_path);
+    let mut audiobooks = parse_books_plist(&source_base.join("Books.plist"))?;
+    tags::fill_from_tags(&mut audiobooks, &source_base);
+    if let Some(path) = overrides::default_overrides_path() {
+        overrides::Overrides::read(&path)?.apply(&mut audiobooks);
+    }
+
+    let mut rows: Vec<_> = audiobooks
+        .iter()
+        .map(|book| list::Row::new(book, &source_base))
+        .collect();
+    list::sort(&mut rows, args.sort);
+    match args.format {
+        list::CatalogFormat::Csv => list::write_csv(&mut io::stdout().lock(), &rows)?,
+        list::CatalogFormat::Json => println!("{}", serde_json::to_string_pretty(&rows)?),
+    }
+    Ok(())
+}
+
+fn run_info(args: InfoArgs) -> Result<()> {
+    let source_base = args.source.unwrap_or_else(default_apple_books_path);
+    let plist_path = source_base.join("Books.plist");
+    let mut audiobooks = parse_books_plist(&plist_path)?;
+    tags::fill_from_tags(&mut audiobooks, &source_base);
+    if let Some(path) = overrides::default_overrides_path() {
+        overrides::Overrides::read(&path)?.apply(&mut audiobooks);
+    }
+
+    let book = find_book(&audiobooks, &args.book)?;
+    let raw = raw_plist_entry(&plist_path, &book.folder_id)?;
+    info::write_info(&mut io::stdout().lock(), book, &source_base, raw.as_ref())?;
+    Ok(())
+}
+
+fn run_stats(args: StatsArgs) -> Result<()> {
+    let source_base = args.source.unwrap_or_else(default_apple_books_path);
+    let mut audiobooks = parse_books_plist(&source_base.join("Books.plist"))?;
+    tags::fill_from_tags(&mut audiobooks, &source_base);
+    if let Some(path) = overrides::default_overrides_path() {
+        overrides::Overrides::read(&path)?.apply(&mut audiobooks);
+    }
+
+    let pb = progress::bar(audiobooks.len(), "Scanning library");
+    let infos: Vec<_> = audiobooks
+        .iter()
+        .map(|book| {
+            let info = stats::book_info(book, &source_base);
+            pb.inc(1);
+            info
+        })
+        .collect();
+    pb.finish_and_clear();
+
+    let total = stats::total(&infos);
+    println!(
+        "{} books, {} tracks, {} ({} per book), {:.1} hours",
+        total.books,
+        total.tracks,
+        format_bytes(total.bytes),
+        format_bytes(total.average_bytes()),
+        total.seconds / 3600.0
+    );
+    if total.seconds == 0.0 && total.books > 0 {
+        eprintln!("Warning: Could not read durations or tags (is ffmpeg installed?)");
+    }
+
+    let largest = stats::largest(&infos, args.top);
+    if !largest.is_empty() {
+        println!();
+        println!("Largest books:");
+        for info in largest {
+            println!(
+                "{:>10}  {} - {}",
+                format_bytes(info.bytes),
+                info.author,
+                info.title
+            );
+        }
+    }
+
+    if let Some(by) = args.group_by {
+        let label = match by {
+            stats::GroupBy::Author => "Author",
+            stats::GroupBy::Genre => "Genre",
+            stats::GroupBy::Year => "Year",
+        };
+        println!();
+        stats::write_table(&mut io::stdout().lock(), label, &stats::group(&infos, by))?;
+    }
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    // The matches tell options given on the command line from their defaults
+    let matches = Cli::command().get_matches();
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    let subcommand = matches
+        .subcommand()
+        .map_or(&matches, |(_, matches)| matches);
+    output::init(cli.color, cli.plain);
+    i18n::init(cli.lang);
+
+    let config = match config::default_config_path() {
+        Some(path) => config::Config::read(&path)?,
+        None => config::Config::default(),
+    };
+    config.filenames.validate()?;
+    sanitize::init(config.filenames.clone());
+    subtitle::init(config.subtitles.clone());
+
+    match cli.command {
+        Some(Command::Export(args)) => run_export(*args, &config, subcommand),
+        Some(Command::Changes(args)) => run_changes(args),
+        Some(Command::List(args)) => run_list(args),
+        Some(Command::Catalog(args)) => run_catalog(args),
+        Some(Command::Info(args)) => run_info(args),
+        Some(Command::Preview(args)) => run_preview(args, &config, subcommand),
+        Some(Command::Diff(args)) => run_diff(args, &config, subcommand),
+        Some(Command::Report(args)) => run_report(args),
+        Some(Command::History(args)) => run_history(args),
+        Some(Command::Stats(args)) => run_stats(args),
+        Some(Command::Verify(args)) => run_verify(args, &config),
+        Some(Command::Relink(args)) => run_relink(args),
+        Some(Command::Prune(args)) => run_prune(args, &config),
+        Some(Command::Clean(args)) => run_clean(args),
+        Some(Command::Rollback(args)) => run_rollback(args),
+        Some(Command::Dump(args)) => run_dump(args),
+        Some(Command::DebugBundle(args)) => run_debug_bundle(args),
+        Some(Command::Validate(args)) => run_validate(args),
+        Some(Command::Orphans(args)) => run_orphans(args, &config, subcommand),
+        Some(Command::Tui(args)) => run_tui(*args, &config, subcommand),
+        Some(Command::Completions(args)) => run_completions(args),
+        Some(Command::Init) => run_init(&config),
+        None => run_export(cli.export, &config, &matches),
     }
 }
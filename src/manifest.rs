@@ -0,0 +1,237 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use crate::report::STATE_DIR;
+
+/// Record of what a previous run exported into a destination, used to skip books whose
+/// sources haven't changed without touching the (possibly slow) destination
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Manifest {
+    /// Output-affecting options of the run that wrote the manifest. Entries are only
+    /// trusted when the current run would produce the same output.
+    pub profile: String,
+    /// Destination the files were exported to, so the destination can be checked where
+    /// it's mounted elsewhere
+    #[serde(default)]
+    pub root: PathBuf,
+    /// Whether exported files are unmodified copies of their sources
+    #[serde(default)]
+    pub plain_copy: bool,
+    /// Version of the tool that wrote the manifest
+    #[serde(default)]
+    pub tool_version: String,
+    /// Exported books by Apple Books folder id
+    pub books: BTreeMap<String, BookEntry>,
+}
+
+/// A fully exported book
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BookEntry {
+    pub author: String,
+    pub title: String,
+    pub dest_dir: PathBuf,
+    pub files: Vec<ManifestFile>,
+    /// When the book was exported, in seconds since the Unix epoch; 0 in manifests from
+    /// before this was recorded
+    #[serde(default)]
+    pub exported_at: u64,
+}
+
+/// A source file and where it was exported to
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestFile {
+    pub source: PathBuf,
+    pub dest: PathBuf,
+    pub size: u64,
+    /// Source modification time in seconds since the Unix epoch
+    pub modified: u64,
+    /// SHA-256 of the exported file, if it was hashed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+}
+
+impl ManifestFile {
+    /// Record a source file's current size and modification time
+    pub fn new(source: &Path, dest: &Path) -> Option<Self> {
+        let (size, modified) = source_fingerprint(source)?;
+        Some(ManifestFile {
+            source: source.to_path_buf(),
+            dest: dest.to_path_buf(),
+            size,
+            modified,
+            sha256: None,
+        })
+    }
+}
+
+/// Size and modification time of a source file
+fn source_fingerprint(path: &Path) -> Option<(u64, u64)> {
+    let metadata = fs::metadata(path).ok()?;
+    let modified = metadata
+        .modified()
+        .ok()?
+        .duration_since(UNIX_EPOCH)
+        .ok()?
+        .as_secs();
+    Some((metadata.len(), modified))
+}
+
+impl BookEntry {
+    /// Whether the book was exported from exactly these `(source, dest)` pairs and none of
+    /// the sources changed since. Only the sources are stat-ed.
+    pub fn is_current(&self, files: &[(PathBuf, PathBuf)]) -> bool {
+        self.files.len() == files.len()
+            && self
+                .files
+                .iter()
+                .zip(files)
+                .all(|(recorded, (source, dest))| {
+                    recorded.source == *source
+                        && recorded.dest == *dest
+                        && source_fingerprint(source) == Some((recorded.size, recorded.modified))
+                })
+    }
+}
+
+impl Manifest {
+    /// Read the manifest of a destination. A missing or unreadable manifest is empty, so
+    /// the next run simply checks every file.
+    pub fn read(path: &Path) -> Self {
+        match fs::read_to_string(path) {
+            Ok(json) => serde_json::from_str(&json).unwrap_or_else(|e| {
+                eprintln!("Warning: Ignoring invalid manifest {:?}: {}", path, e);
+                Manifest::default()
+            }),
+            Err(_) => Manifest::default(),
+        }
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory {:?}", parent))?;
+        }
+        let json = serde_json::to_string_pretty(self)?;
+        fs::write(path, json).with_context(|| format!("Failed to write manifest {:?}", path))
+    }
+
+    /// Where a recorded destination path is when the destination is at `dest`
+    pub fn relocate(&self, path: &Path, dest: &Path) -> PathBuf {
+        match path.strip_prefix(&self.root) {
+            Ok(relative) if !self.root.as_os_str().is_empty() => dest.join(relative),
+            _ => path.to_path_buf(),
+        }
+    }
+
+    /// The entry for a book, if it was exported with the given output profile
+    pub fn current_entry(&self, profile: &str, folder_id: &str) -> Option<&BookEntry> {
+        if self.profile != profile {
+            return None;
+        }
+        self.books.get(folder_id)
+    }
+}
+
+/// Where a destination's manifest is kept
+pub fn manifest_path(dest: &Path) -> PathBuf {
+    dest.join(STATE_DIR).join("manifest.json")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_book_entry_is_current() {
+        let dir = tempdir().unwrap();
+        let source = dir.path().join("01.mp3");
+        let dest = dir.path().join("out/01.mp3");
+        fs::write(&source, b"audio").unwrap();
+
+        let entry = BookEntry {
+            author: "Author".to_string(),
+            title: "Book".to_string(),
+            dest_dir: dir.path().join("out"),
+            files: vec![ManifestFile::new(&source, &dest).unwrap()],
+            exported_at: 1_700_000_000,
+        };
+        let files = vec![(source.clone(), dest.clone())];
+        assert!(entry.is_current(&files));
+        assert!(!entry.is_current(&[(source.clone(), dir.path().join("out/1.mp3"))]));
+
+        fs::write(&source, b"fixed audio").unwrap();
+        assert!(!entry.is_current(&files));
+    }
+
+    #[test]
+    fn test_manifest_roundtrip() {
+        let dir = tempdir().unwrap();
+        let path = manifest_path(dir.path());
+        assert!(Manifest::read(&path).books.is_empty());
+
+        let mut manifest = Manifest {
+            profile: "copy".to_string(),
+            ..Default::default()
+        };
+        manifest.books.insert(
+            "sha1-abc".to_string(),
+            BookEntry {
+                author: "Author".to_string(),
+                title: "Book".to_string(),
+                dest_dir: dir.path().join("Author/Book"),
+                files: Vec::new(),
+                exported_at: 1_700_000_000,
+            },
+        );
+        manifest.write(&path).unwrap();
+
+        let manifest = Manifest::read(&path);
+        assert_eq!(
+            manifest
+                .current_entry("copy", "sha1-abc")
+                .unwrap()
+                .exported_at,
+            1_700_000_000
+        );
+        assert!(manifest.current_entry("transcode", "sha1-abc").is_none());
+    }
+
+    #[test]
+    fn test_read_manifest_without_provenance() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("manifest.json");
+        fs::write(
+            &path,
+            r#"{"profile":"copy","books":{"sha1-abc":{"author":"Author","title":"Book",
+                "dest_dir":"/x/Author/Book","files":[]}}}"#,
+        )
+        .unwrap();
+        let manifest = Manifest::read(&path);
+        assert_eq!(manifest.tool_version, "");
+        assert_eq!(manifest.books["sha1-abc"].exported_at, 0);
+    }
+
+    #[test]
+    fn test_relocate() {
+        let manifest = Manifest {
+            root: PathBuf::from("/Volumes/NAS/Audiobooks"),
+            ..Default::default()
+        };
+        assert_eq!(
+            manifest.relocate(
+                Path::new("/Volumes/NAS/Audiobooks/Author/Book/01.mp3"),
+                Path::new("/mnt/nas/Audiobooks")
+            ),
+            PathBuf::from("/mnt/nas/Audiobooks/Author/Book/01.mp3")
+        );
+        assert_eq!(
+            Manifest::default().relocate(Path::new("/a/01.mp3"), Path::new("/b")),
+            PathBuf::from("/a/01.mp3")
+        );
+    }
+}
@@ -0,0 +1,251 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::PathBuf;
+use time::{macros::format_description, OffsetDateTime};
+
+use crate::report::RunReport;
+use crate::{format_bytes, Audiobook};
+
+/// Escape text for a Markdown table cell or list item
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '|' | '*' | '_' | '`' | '[' | ']' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Whether a book is in the destination a library report was made for
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExportStatus {
+    /// Exported into this folder, relative to the destination
+    Exported {
+        dir: PathBuf,
+        cover: bool,
+    },
+    NotExported,
+}
+
+/// A book in a library report, with what only its audio files and the destination know
+#[derive(Debug, Clone)]
+pub struct ReportEntry<'a> {
+    pub book: &'a Audiobook,
+    /// Total duration in seconds, 0 if it couldn't be probed
+    pub seconds: f64,
+    /// `None` without a destination to check
+    pub status: Option<ExportStatus>,
+}
+
+impl ExportStatus {
+    pub fn label(&self) -> &'static str {
+        match self {
+            ExportStatus::Exported { .. } => "Exported",
+            ExportStatus::NotExported => "Not exported",
+        }
+    }
+}
+
+/// A duration like "12h 05m", or nothing if it is unknown
+pub fn format_duration(seconds: f64) -> String {
+    if seconds <= 0.0 {
+        return String::new();
+    }
+    let minutes = (seconds / 60.0).round() as u64;
+    format!("{}h {:02}m", minutes / 60, minutes % 60)
+}
+
+/// Books grouped by author, each author's sorted by series and title
+pub fn by_author<'a, 'b>(
+    entries: &'b [ReportEntry<'a>],
+) -> BTreeMap<&'a str, Vec<&'b ReportEntry<'a>>> {
+    let mut by_author: BTreeMap<&str, Vec<&ReportEntry>> = BTreeMap::new();
+    for entry in entries {
+        by_author.entry(&entry.book.author).or_default().push(entry);
+    }
+    for books in by_author.values_mut() {
+        books.sort_by(|a, b| (&a.book.series, &a.book.title).cmp(&(&b.book.series, &b.book.title)));
+    }
+    by_author
+}
+
+/// A Markdown document of the library: a table of every book and a section per author
+pub fn library_report(entries: &[ReportEntry]) -> String {
+    let by_author = by_author(entries);
+    let with_status = entries.iter().any(|entry| entry.status.is_some());
+
+    let mut out = String::new();
+    let _ = writeln!(out, "# Audiobook Library\n");
+    let _ = writeln!(
+        out,
+        "{} books by {} authors.\n",
+        entries.len(),
+        by_author.len()
+    );
+
+    let (status_header, status_rule) = if with_status {
+        (" Status |", " --- |")
+    } else {
+        ("", "")
+    };
+    let _ = writeln!(
+        out,
+        "| Author | Title | Series | Narrator | Tracks | Duration |{}",
+        status_header
+    );
+    let _ = writeln!(
+        out,
+        "| --- | --- | --- | --- | ---: | ---: |{}",
+        status_rule
+    );
+    for entry in by_author.values().flatten() {
+        let book = entry.book;
+        let status = match &entry.status {
+            Some(status) => format!(" {} |", status.label()),
+            None => String::new(),
+        };
+        let _ = writeln!(
+            out,
+            "| {} | {} | {} | {} | {} | {} |{}",
+            escape(&book.author),
+            escape(&book.title),
+            escape(book.series.as_deref().unwrap_or("")),
+            escape(book.narrator.as_deref().unwrap_or("")),
+            book.tracks.len(),
+            format_duration(entry.seconds),
+            status
+        );
+    }
+
+    for (author, books) in &by_author {
+        let _ = writeln!(out, "\n## {}\n", escape(author));
+        for book in books.iter().map(|entry| entry.book) {
+            let mut line = format!("- **{}**", escape(&book.title));
+            if let Some(series) = &book.series {
+                let _ = write!(line, " ({})", escape(series));
+            }
+            if let Some(narrator) = &book.narrator {
+                let _ = write!(line, ", narrated by {}", escape(narrator));
+            }
+            let _ = writeln!(out, "{}, {} tracks", line, book.tracks.len());
+        }
+    }
+    out
+}
+
+/// A Markdown document of an export run: its totals, a table of books and the failures
+pub fn run_report(report: &RunReport) -> String {
+    let stats = &report.stats;
+    let finished = OffsetDateTime::from_unix_timestamp(report.finished_at as i64)
+        .ok()
+        .and_then(|t| {
+            t.format(format_description!(
+                "[year]-[month]-[day] [hour]:[minute] UTC"
+            ))
+            .ok()
+        })
+        .unwrap_or_default();
+
+    let mut out = String::new();
+    let _ = writeln!(out, "# Export Run\n");
+    let _ = writeln!(out, "- Finished: {}", finished);
+    let _ = writeln!(out, "- Destination: `{}`", report.dest.display());
+    let _ = writeln!(out, "- Books processed: {}", stats.books_exported);
+    let _ = writeln!(out, "- Files copied: {}", stats.files_copied);
+    let _ = writeln!(out, "- Files failed: {}", stats.failures.len());
+
+    if !stats.book_results.is_empty() {
+        let _ = writeln!(out, "\n## Books\n");
+        let _ = writeln!(out, "| Book | Files | Size | Time | Status |");
+        let _ = writeln!(out, "| --- | ---: | ---: | ---: | --- |");
+        for row in &stats.book_results {
+            let _ = writeln!(
+                out,
+                "| {} | {} | {} | {:.1}s | {} |",
+                escape(&row.book),
+                row.files,
+                format_bytes(row.bytes),
+                row.elapsed_secs,
+                row.status.label()
+            );
+        }
+    }
+
+    if !stats.failures.is_empty() {
+        let _ = writeln!(out, "\n## Failures\n");
+        for failure in &stats.failures {
+            let _ = writeln!(
+                out,
+                "- {}: `{}`: {}",
+                escape(&failure.book),
+                failure.source.display(),
+                escape(&failure.error)
+            );
+        }
+    }
+
+    if !stats.extras.is_empty() {
+        let _ = writeln!(out, "\n## Extras\n");
+        for extra in &stats.extras {
+            let _ = writeln!(out, "- `{}`", extra.display());
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn book(author: &str, title: &str, series: Option<&str>) -> Audiobook {
+        Audiobook {
+            title: title.to_string(),
+            author: author.to_string(),
+            series: series.map(str::to_string),
+            folder_id: format!("sha1-{}", title),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_library_report() {
+        let books = [
+            book("Brandon Sanderson", "Warbreaker", None),
+            book("Andy Weir", "Project Hail Mary", None),
+            book(
+                "Brandon Sanderson",
+                "Elantris | Tenth Anniversary",
+                Some("Elantris"),
+            ),
+        ];
+        let entries: Vec<_> = books
+            .iter()
+            .map(|book| ReportEntry {
+                book,
+                seconds: 0.0,
+                status: None,
+            })
+            .collect();
+        let report = library_report(&entries);
+
+        assert!(report.contains("3 books by 2 authors."));
+        assert!(report.contains(
+            "| Brandon Sanderson | Elantris \\| Tenth Anniversary | Elantris |  | 0 |  |\n"
+        ));
+        let andy = report.find("## Andy Weir").unwrap();
+        let brandon = report.find("## Brandon Sanderson").unwrap();
+        assert!(andy < brandon);
+        assert!(report[brandon..].contains("- **Warbreaker**, 0 tracks"));
+
+        let entries = [ReportEntry {
+            book: &books[0],
+            seconds: 45_300.0,
+            status: Some(ExportStatus::NotExported),
+        }];
+        assert!(library_report(&entries)
+            .contains("| Brandon Sanderson | Warbreaker |  |  | 0 | 12h 35m | Not exported |\n"));
+        assert_eq!(format_duration(0.0), "");
+    }
+}
@@ -0,0 +1,318 @@
+use anyhow::{bail, Context, Result};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::process::Command;
+
+/// Probe the duration of an audio file in seconds using ffprobe
+pub fn probe_duration(path: &Path) -> Result<f64> {
+    let output = Command::new("ffprobe")
+        .args([
+            "-v",
+            "error",
+            "-show_entries",
+            "format=duration",
+            "-of",
+            "default=noprint_wrappers=1:nokey=1",
+        ])
+        .arg(path)
+        .output()
+        .context("Failed to run ffprobe (is ffmpeg installed?)")?;
+
+    if !output.status.success() {
+        bail!(
+            "ffprobe failed for {:?}: {}",
+            path,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    stdout
+        .trim()
+        .parse::<f64>()
+        .with_context(|| format!("Unexpected ffprobe duration output for {:?}", path))
+}
+
+/// Read the container-level tags of an audio file using ffprobe
+pub fn probe_format_tags(path: &Path) -> Result<BTreeMap<String, String>> {
+    let output = Command::new("ffprobe")
+        .args(["-v", "error", "-show_entries", "format_tags", "-of", "json"])
+        .arg(path)
+        .output()
+        .context("Failed to run ffprobe (is ffmpeg installed?)")?;
+
+    if !output.status.success() {
+        bail!(
+            "ffprobe failed for {:?}: {}",
+            path,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    let value: serde_json::Value = serde_json::from_slice(&output.stdout)
+        .with_context(|| format!("Unexpected ffprobe tag output for {:?}", path))?;
+    let mut tags = BTreeMap::new();
+    if let Some(map) = value
+        .get("format")
+        .and_then(|f| f.get("tags"))
+        .and_then(|t| t.as_object())
+    {
+        for (key, value) in map {
+            if let Some(value) = value.as_str() {
+                tags.insert(key.clone(), value.to_string());
+            }
+        }
+    }
+
+    Ok(tags)
+}
+
+/// The value of a tag, matching its name case-insensitively (containers differ)
+pub fn find_tag<'a>(tags: &'a BTreeMap<String, String>, name: &str) -> Option<&'a str> {
+    tags.iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.trim())
+        .filter(|value| !value.is_empty())
+}
+
+/// A chapter marker embedded in an audio file
+#[derive(Debug, Clone, PartialEq)]
+pub struct EmbeddedChapter {
+    pub start: f64,
+    pub end: f64,
+    pub title: String,
+}
+
+/// Read the embedded chapter list of an audio file (e.g. an m4b) using ffprobe
+pub fn probe_chapters(path: &Path) -> Result<Vec<EmbeddedChapter>> {
+    let output = Command::new("ffprobe")
+        .args(["-v", "error", "-show_chapters", "-of", "json"])
+        .arg(path)
+        .output()
+        .context("Failed to run ffprobe (is ffmpeg installed?)")?;
+
+    if !output.status.success() {
+        bail!(
+            "ffprobe failed for {:?}: {}",
+            path,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    parse_chapters_json(&output.stdout)
+        .with_context(|| format!("Unexpected ffprobe chapter output for {:?}", path))
+}
+
+/// Parse the JSON produced by `ffprobe -show_chapters -of json`
+fn parse_chapters_json(json: &[u8]) -> Result<Vec<EmbeddedChapter>> {
+    let value: serde_json::Value = serde_json::from_slice(json)?;
+    let mut chapters = Vec::new();
+
+    if let Some(entries) = value.get("chapters").and_then(|v| v.as_array()) {
+        for entry in entries {
+            let seconds = |key: &str| {
+                entry
+                    .get(key)
+                    .and_then(|v| v.as_str())
+                    .and_then(|s| s.parse::<f64>().ok())
+                    .unwrap_or(0.0)
+            };
+            let title = entry
+                .get("tags")
+                .and_then(|t| t.get("title"))
+                .and_then(|v| v.as_str())
+                .unwrap_or("")
+                .to_string();
+
+            chapters.push(EmbeddedChapter {
+                start: seconds("start_time"),
+                end: seconds("end_time"),
+                title,
+            });
+        }
+    }
+
+    Ok(chapters)
+}
+
+/// Copy the audio between `start` and `end` seconds of `source` into `dest` without re-encoding
+pub fn extract_segment(source: &Path, start: f64, end: f64, dest: &Path) -> Result<()> {
+    let output = Command::new("ffmpeg")
+        .args(["-v", "error", "-nostdin", "-y", "-ss"])
+        .arg(format!("{:.3}", start))
+        .arg("-to")
+        .arg(format!("{:.3}", end))
+        .arg("-i")
+        .arg(source)
+        .args(["-map", "0:a", "-c", "copy"])
+        .arg(dest)
+        .output()
+        .context("Failed to run ffmpeg (is ffmpeg installed?)")?;
+
+    if !output.status.success() {
+        bail!(
+            "ffmpeg failed to extract {:?}: {}",
+            dest,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(())
+}
+
+/// Whether an audio file carries embedded artwork (a video stream, as ffprobe sees it).
+/// False when ffprobe is missing.
+pub fn has_embedded_image(path: &Path) -> bool {
+    Command::new("ffprobe")
+        .args([
+            "-v",
+            "error",
+            "-select_streams",
+            "v",
+            "-show_entries",
+            "stream=index",
+            "-of",
+            "csv=p=0",
+        ])
+        .arg(path)
+        .output()
+        .is_ok_and(|output| output.status.success() && !output.stdout.trim_ascii().is_empty())
+}
+
+/// Write the first image of `source` (an image file, or the artwork embedded in an audio
+/// file) to `dest` as a JPEG
+pub fn write_jpeg(source: &Path, dest: &Path) -> Result<()> {
+    let output = Command::new("ffmpeg")
+        .args(["-v", "error", "-nostdin", "-y", "-i"])
+        .arg(source)
+        .args([
+            "-map",
+            "0:v:0",
+            "-frames:v",
+            "1",
+            "-c:v",
+            "mjpeg",
+            "-q:v",
+            "2",
+            "-f",
+            "image2",
+        ])
+        .arg(dest)
+        .output()
+        .context("Failed to run ffmpeg (is ffmpeg installed?)")?;
+
+    if !output.status.success() {
+        bail!(
+            "ffmpeg failed to write {:?} from {:?}: {}",
+            dest,
+            source,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(())
+}
+
+/// Default integrated loudness target in LUFS for EBU R128 normalization
+pub const DEFAULT_LOUDNESS_TARGET: f64 = -16.0;
+
+/// ffmpeg `loudnorm` filter (EBU R128) for an integrated loudness target in LUFS
+pub fn loudnorm_filter(target_lufs: f64) -> String {
+    format!("loudnorm=I={}:TP=-1.5:LRA=11", target_lufs)
+}
+
+/// Encoder that re-creates a file of the given extension when it has to be re-encoded
+fn encoder_for_extension(extension: &str) -> Option<&'static str> {
+    match extension.to_lowercase().as_str() {
+        "mp3" => Some("libmp3lame"),
+        "m4a" | "m4b" | "aac" | "mp4" => Some("aac"),
+        "opus" | "ogg" => Some("libopus"),
+        "flac" => Some("flac"),
+        _ => None,
+    }
+}
+
+/// Probe the overall bit rate of a file in bits per second
+fn probe_bit_rate(path: &Path) -> Option<u64> {
+    let output = Command::new("ffprobe")
+        .args([
+            "-v",
+            "error",
+            "-show_entries",
+            "format=bit_rate",
+            "-of",
+            "default=noprint_wrappers=1:nokey=1",
+        ])
+        .arg(path)
+        .output()
+        .ok()?;
+
+    String::from_utf8_lossy(&output.stdout).trim().parse().ok()
+}
+
+/// Normalize the loudness of an exported file in place, re-encoding it with the same
+/// codec family and (approximately) the same bit rate
+pub fn normalize_in_place(path: &Path, target_lufs: f64) -> Result<()> {
+    let extension = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let encoder = encoder_for_extension(&extension)
+        .with_context(|| format!("Don't know how to re-encode {:?}", path))?;
+    let bit_rate = probe_bit_rate(path).unwrap_or(128_000);
+    let partial = path.with_extension(format!("partial.{}", extension));
+
+    let output = Command::new("ffmpeg")
+        .args(["-v", "error", "-nostdin", "-y", "-i"])
+        .arg(path)
+        .args(["-map", "0:a", "-map_metadata", "0", "-af"])
+        .arg(loudnorm_filter(target_lufs))
+        .args(["-c:a", encoder, "-b:a"])
+        .arg(bit_rate.to_string())
+        .arg(&partial)
+        .output()
+        .context("Failed to run ffmpeg (is ffmpeg installed?)")?;
+
+    if !output.status.success() {
+        let _ = std::fs::remove_file(&partial);
+        bail!(
+            "ffmpeg failed to normalize {:?}: {}",
+            path,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    std::fs::rename(&partial, path)
+        .with_context(|| format!("Failed to move normalized {:?} into place", path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_chapters_json() {
+        let json = br#"{
+            "chapters": [
+                { "id": 0, "start_time": "0.000000", "end_time": "61.500000", "tags": { "title": "Opening Credits" } },
+                { "id": 1, "start_time": "61.500000", "end_time": "900.250000" }
+            ]
+        }"#;
+
+        let chapters = parse_chapters_json(json).unwrap();
+        assert_eq!(chapters.len(), 2);
+        assert_eq!(chapters[0].title, "Opening Credits");
+        assert_eq!(chapters[0].end, 61.5);
+        assert_eq!(chapters[1].start, 61.5);
+        assert_eq!(chapters[1].title, "");
+    }
+
+    #[test]
+    fn test_loudnorm_filter_and_encoders() {
+        assert_eq!(loudnorm_filter(-16.0), "loudnorm=I=-16:TP=-1.5:LRA=11");
+        assert_eq!(loudnorm_filter(-18.5), "loudnorm=I=-18.5:TP=-1.5:LRA=11");
+        assert_eq!(encoder_for_extension("MP3"), Some("libmp3lame"));
+        assert_eq!(encoder_for_extension("m4b"), Some("aac"));
+        assert_eq!(encoder_for_extension("aax"), None);
+    }
+}
@@ -0,0 +1,362 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use crate::{media, write_file, Audiobook};
+
+/// Name of the Audiobookshelf metadata file written into each book folder
+pub const METADATA_FILENAME: &str = "metadata.json";
+
+/// Name of the OPF metadata file written into each book folder
+pub const OPF_FILENAME: &str = "metadata.opf";
+
+/// Name of the Kodi/Jellyfin NFO file written into each book folder
+pub const NFO_FILENAME: &str = "album.nfo";
+
+/// A book's details in Audiobookshelf's `metadata.json` format
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AbsMetadata {
+    pub title: String,
+    pub subtitle: Option<String>,
+    pub authors: Vec<String>,
+    pub narrators: Vec<String>,
+    pub series: Vec<String>,
+    pub genres: Vec<String>,
+    pub published_year: Option<String>,
+    pub publisher: Option<String>,
+    pub isbn: Option<String>,
+    pub asin: Option<String>,
+    /// Not read by Audiobookshelf; for tools that match books against the Apple Books store
+    pub apple_store_id: Option<String>,
+    /// Not read by Audiobookshelf; kept for the OPF file
+    #[serde(skip)]
+    pub copyright: Option<String>,
+    pub description: Option<String>,
+}
+
+/// Split a list of people the way Audiobookshelf does, on commas, ampersands and
+/// semicolons
+pub fn split_names(names: &str) -> Vec<String> {
+    names
+        .split([',', '&', ';'])
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+impl AbsMetadata {
+    /// Metadata from the library, with genre and year from the tags of the book's first
+    /// track when the library doesn't have them
+    pub fn new(book: &Audiobook, tags: &BTreeMap<String, String>) -> Self {
+        let tag = |name: &str| media::find_tag(tags, name).map(str::to_string);
+        AbsMetadata {
+            title: book.title.clone(),
+            subtitle: book.subtitle.clone(),
+            authors: book.authors(),
+            narrators: book.narrators(),
+            series: book
+                .series
+                .iter()
+                .map(|series| match &book.series_sequence {
+                    Some(sequence) => format!("{} #{}", series, sequence),
+                    None => series.clone(),
+                })
+                .collect(),
+            genres: book
+                .details
+                .genre
+                .clone()
+                .or_else(|| tag("genre"))
+                .as_deref()
+                .map(split_names)
+                .unwrap_or_default(),
+            published_year: book.details.year.clone().or_else(|| {
+                tag("date")
+                    .or_else(|| tag("year"))
+                    .and_then(|date| date.get(..4).map(str::to_string))
+                    .filter(|year| year.chars().all(|c| c.is_ascii_digit()))
+            }),
+            publisher: book.details.publisher.clone(),
+            isbn: book.details.isbn.clone(),
+            asin: book.details.asin.clone(),
+            apple_store_id: book.details.store_id.clone(),
+            copyright: book.details.copyright.clone(),
+            description: book.description.clone(),
+        }
+    }
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// The metadata as an OPF package document with Dublin Core elements, for Calibre and
+/// other managers that read OPF. OPF has no subtitle, so it stays part of the title.
+/// Narrators are contributors with the `nrt` role, and the Apple Books folder id is the
+/// identifier, alongside any ISBN, ASIN and store ID.
+pub fn to_opf(metadata: &AbsMetadata, folder_id: &str) -> String {
+    let title = match &metadata.subtitle {
+        Some(subtitle) => format!("{}: {}", metadata.title, subtitle),
+        None => metadata.title.clone(),
+    };
+    let mut elements = vec![
+        format!(
+            "<dc:identifier id=\"book-id\" opf:scheme=\"AppleBooks\">{}</dc:identifier>",
+            escape_xml(folder_id)
+        ),
+        format!("<dc:title>{}</dc:title>", escape_xml(&title)),
+    ];
+    let identifiers = [
+        ("ISBN", &metadata.isbn),
+        ("ASIN", &metadata.asin),
+        ("AppleStore", &metadata.apple_store_id),
+    ];
+    for (scheme, identifier) in identifiers {
+        if let Some(identifier) = identifier {
+            elements.push(format!(
+                "<dc:identifier opf:scheme=\"{}\">{}</dc:identifier>",
+                scheme,
+                escape_xml(identifier)
+            ));
+        }
+    }
+    for author in &metadata.authors {
+        elements.push(format!(
+            "<dc:creator opf:role=\"aut\">{}</dc:creator>",
+            escape_xml(author)
+        ));
+    }
+    for narrator in &metadata.narrators {
+        elements.push(format!(
+            "<dc:contributor opf:role=\"nrt\">{}</dc:contributor>",
+            escape_xml(narrator)
+        ));
+    }
+    for genre in &metadata.genres {
+        elements.push(format!("<dc:subject>{}</dc:subject>", escape_xml(genre)));
+    }
+    if let Some(publisher) = &metadata.publisher {
+        elements.push(format!(
+            "<dc:publisher>{}</dc:publisher>",
+            escape_xml(publisher)
+        ));
+    }
+    if let Some(copyright) = &metadata.copyright {
+        elements.push(format!("<dc:rights>{}</dc:rights>", escape_xml(copyright)));
+    }
+    if let Some(year) = &metadata.published_year {
+        elements.push(format!("<dc:date>{}</dc:date>", escape_xml(year)));
+    }
+    if let Some(description) = &metadata.description {
+        elements.push(format!(
+            "<dc:description>{}</dc:description>",
+            escape_xml(description)
+        ));
+    }
+    if let Some(series) = metadata.series.first() {
+        let (series, index) = match series.rsplit_once(" #") {
+            Some((name, index)) => (name, Some(index)),
+            None => (series.as_str(), None),
+        };
+        elements.push(format!(
+            "<meta name=\"calibre:series\" content=\"{}\"/>",
+            escape_xml(series)
+        ));
+        if let Some(index) = index {
+            elements.push(format!(
+                "<meta name=\"calibre:series_index\" content=\"{}\"/>",
+                escape_xml(index)
+            ));
+        }
+    }
+
+    let mut opf = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <package xmlns=\"http://www.idpf.org/2007/opf\" version=\"2.0\" unique-identifier=\"book-id\">\n  \
+         <metadata xmlns:dc=\"http://purl.org/dc/elements/1.1/\" xmlns:opf=\"http://www.idpf.org/2007/opf\">\n",
+    );
+    for element in elements {
+        opf.push_str("    ");
+        opf.push_str(&element);
+        opf.push('\n');
+    }
+    opf.push_str("  </metadata>\n</package>\n");
+    opf
+}
+
+/// The metadata as a Kodi album NFO document, which Jellyfin reads for music albums and
+/// audiobooks too. Authors are the artists and narrators actors with the role
+/// "Narrator"; the description goes into both Kodi's `review` and Jellyfin's `plot`.
+pub fn to_nfo(metadata: &AbsMetadata) -> String {
+    let title = match &metadata.subtitle {
+        Some(subtitle) => format!("{}: {}", metadata.title, subtitle),
+        None => metadata.title.clone(),
+    };
+    let mut elements = vec![format!("<title>{}</title>", escape_xml(&title))];
+    for author in &metadata.authors {
+        elements.push(format!("<artist>{}</artist>", escape_xml(author)));
+    }
+    if !metadata.authors.is_empty() {
+        elements.push(format!(
+            "<albumartist>{}</albumartist>",
+            escape_xml(&metadata.authors.join(" / "))
+        ));
+    }
+    for genre in &metadata.genres {
+        elements.push(format!("<genre>{}</genre>", escape_xml(genre)));
+    }
+    if let Some(year) = &metadata.published_year {
+        elements.push(format!("<year>{}</year>", escape_xml(year)));
+    }
+    if let Some(publisher) = &metadata.publisher {
+        elements.push(format!("<label>{}</label>", escape_xml(publisher)));
+    }
+    if let Some(description) = &metadata.description {
+        elements.push(format!("<review>{}</review>", escape_xml(description)));
+        elements.push(format!("<plot>{}</plot>", escape_xml(description)));
+    }
+    for narrator in &metadata.narrators {
+        elements.push(format!(
+            "<actor><name>{}</name><role>Narrator</role></actor>",
+            escape_xml(narrator)
+        ));
+    }
+    elements.push("<type>Audiobook</type>".to_string());
+
+    let mut nfo =
+        String::from("<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n<album>\n");
+    for element in elements {
+        nfo.push_str("  ");
+        nfo.push_str(&element);
+        nfo.push('\n');
+    }
+    nfo.push_str("</album>\n");
+    nfo
+}
+
+/// Write `album.nfo` into the book's destination directory
+pub fn write_nfo_file(dest_dir: &Path, metadata: &AbsMetadata) -> Result<()> {
+    let path = dest_dir.join(NFO_FILENAME);
+    fs::create_dir_all(dest_dir)
+        .with_context(|| format!("Failed to create directory {:?}", dest_dir))?;
+    write_file(&path, to_nfo(metadata))
+}
+
+/// Write `metadata.opf` into the book's destination directory
+pub fn write_opf_file(dest_dir: &Path, metadata: &AbsMetadata, folder_id: &str) -> Result<()> {
+    let path = dest_dir.join(OPF_FILENAME);
+    fs::create_dir_all(dest_dir)
+        .with_context(|| format!("Failed to create directory {:?}", dest_dir))?;
+    write_file(&path, to_opf(metadata, folder_id))
+}
+
+/// Write `metadata.json` into the book's destination directory
+pub fn write_metadata_file(dest_dir: &Path, metadata: &AbsMetadata) -> Result<()> {
+    let path = dest_dir.join(METADATA_FILENAME);
+    let json = serde_json::to_string_pretty(metadata)?;
+    fs::create_dir_all(dest_dir)
+        .with_context(|| format!("Failed to create directory {:?}", dest_dir))?;
+    write_file(&path, json)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BookDetails;
+
+    #[test]
+    fn test_abs_metadata() {
+        let book = Audiobook {
+            title: "The Long Earth".to_string(),
+            author: "Terry Pratchett & Stephen Baxter".to_string(),
+            narrator: Some("Michael Fenton Stevens".to_string()),
+            series: Some("The Long Earth".to_string()),
+            series_sequence: Some("1".to_string()),
+            folder_id: "sha1-aaa".to_string(),
+            description: Some("Step sideways.".to_string()),
+            ..Default::default()
+        };
+        let tags = BTreeMap::from([
+            ("GENRE".to_string(), "Science Fiction".to_string()),
+            ("date".to_string(), "2012-06-21".to_string()),
+        ]);
+
+        let metadata = AbsMetadata::new(&book, &tags);
+        assert_eq!(metadata.authors, ["Terry Pratchett", "Stephen Baxter"]);
+        assert_eq!(metadata.genres, ["Science Fiction"]);
+        assert_eq!(metadata.published_year.as_deref(), Some("2012"));
+
+        let json = serde_json::to_value(&metadata).unwrap();
+        assert_eq!(json["publishedYear"], "2012");
+        assert_eq!(json["narrators"][0], "Michael Fenton Stevens");
+        assert_eq!(json["series"][0], "The Long Earth #1");
+
+        let opf = to_opf(&metadata, "sha1-aaa");
+        assert!(opf.contains("<dc:title>The Long Earth</dc:title>"));
+        assert!(opf.contains("<dc:creator opf:role=\"aut\">Stephen Baxter</dc:creator>"));
+        assert!(opf
+            .contains("<dc:contributor opf:role=\"nrt\">Michael Fenton Stevens</dc:contributor>"));
+        assert!(opf.contains(">sha1-aaa</dc:identifier>"));
+        assert!(opf.contains("<dc:date>2012</dc:date>"));
+        assert!(opf.contains("<meta name=\"calibre:series\" content=\"The Long Earth\"/>"));
+        assert!(opf.contains("<meta name=\"calibre:series_index\" content=\"1\"/>"));
+
+        assert_eq!(
+            escape_xml("Tom & Jerry <\"Live\">"),
+            "Tom &amp; Jerry &lt;&quot;Live&quot;&gt;"
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        write_metadata_file(dir.path(), &AbsMetadata::new(&book, &BTreeMap::new())).unwrap();
+        let written: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(dir.path().join(METADATA_FILENAME)).unwrap())
+                .unwrap();
+        assert_eq!(written["publishedYear"], serde_json::Value::Null);
+        assert_eq!(written["genres"], serde_json::json!([]));
+
+        // What the library records wins over the tags
+        let book = Audiobook {
+            subtitle: Some("A Novel".to_string()),
+            details: BookDetails {
+                genre: Some("Fantasy".to_string()),
+                year: Some("2013".to_string()),
+                publisher: Some("Random House Audio".to_string()),
+                copyright: Some("©2012 Terry Pratchett".to_string()),
+                isbn: Some("9780062067753".to_string()),
+                asin: Some("B00BHF5RU6".to_string()),
+                store_id: Some("1234567890".to_string()),
+            },
+            ..book
+        };
+        let metadata = AbsMetadata::new(&book, &tags);
+        assert_eq!(metadata.genres, ["Fantasy"]);
+        assert_eq!(metadata.published_year.as_deref(), Some("2013"));
+        let json = serde_json::to_value(&metadata).unwrap();
+        assert_eq!(json["publisher"], "Random House Audio");
+        assert_eq!(json["subtitle"], "A Novel");
+        assert_eq!(json["isbn"], "9780062067753");
+        assert_eq!(json["asin"], "B00BHF5RU6");
+        assert_eq!(json["appleStoreId"], "1234567890");
+        assert!(json.get("copyright").is_none());
+        let opf = to_opf(&metadata, "sha1-aaa");
+        assert!(opf.contains("<dc:publisher>Random House Audio</dc:publisher>"));
+        assert!(opf.contains("<dc:title>The Long Earth: A Novel</dc:title>"));
+        assert!(opf.contains("<dc:identifier opf:scheme=\"ISBN\">9780062067753</dc:identifier>"));
+        assert!(opf.contains("<dc:identifier opf:scheme=\"ASIN\">B00BHF5RU6</dc:identifier>"));
+        assert!(opf.contains("<dc:identifier opf:scheme=\"AppleStore\">1234567890</dc:identifier>"));
+        assert!(opf.contains("<dc:rights>©2012 Terry Pratchett</dc:rights>"));
+        let nfo = to_nfo(&metadata);
+        assert!(nfo.starts_with("<?xml"));
+        assert!(nfo.contains("  <title>The Long Earth: A Novel</title>\n"));
+        assert!(nfo.contains("  <label>Random House Audio</label>\n"));
+        assert!(nfo.contains("  <year>2013</year>\n"));
+        assert!(nfo.ends_with("  <type>Audiobook</type>\n</album>\n"));
+    }
+}
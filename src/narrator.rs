@@ -0,0 +1,81 @@
+use regex::Regex;
+use std::sync::OnceLock;
+
+/// Keys of a Books.plist entry (or its first part) that name the narrator, tried in order
+/// before the `composer` of the tracks
+pub const NARRATOR_KEYS: &[&str] = &["narrator", "narrators", "narratorName", "BKNarrator"];
+
+/// "Title (Narrated by X)", "Title [Read by X]", "Title, narrated by X", "Title - Read by X"
+fn in_title() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(
+            r"(?i)^(?P<title>.+?)(?:\s*[(\[]\s*(?:narrated|read) by\s+(?P<bracketed>[^()\[\]]+?)\s*[)\]]|\s*(?:,|\s-)\s*(?:narrated|read) by\s+(?P<trailing>.+?))\s*$",
+        )
+        .unwrap()
+    })
+}
+
+/// Take a "Narrated by" note off a title, returning the title without it and the narrator
+pub fn from_title(title: &str) -> Option<(String, String)> {
+    let captures = in_title().captures(title)?;
+    let narrator = captures
+        .name("bracketed")
+        .or_else(|| captures.name("trailing"))?;
+    Some((
+        captures["title"].trim().to_string(),
+        narrator.as_str().trim().to_string(),
+    ))
+}
+
+/// Split a list of narrators like "A, B and C" or "A & B"
+pub fn split(names: &str) -> Vec<String> {
+    names
+        .split([',', '&', ';'])
+        .flat_map(|name| name.split(" and "))
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_title() {
+        assert_eq!(
+            from_title("Dune (Narrated by Scott Brick)"),
+            Some(("Dune".to_string(), "Scott Brick".to_string()))
+        );
+        assert_eq!(
+            from_title("Dune [read by Scott Brick, Simon Vance]"),
+            Some(("Dune".to_string(), "Scott Brick, Simon Vance".to_string()))
+        );
+        assert_eq!(
+            from_title("Dune, Narrated by Scott Brick"),
+            Some(("Dune".to_string(), "Scott Brick".to_string()))
+        );
+        assert_eq!(
+            from_title("Dune - Read by Scott Brick"),
+            Some(("Dune".to_string(), "Scott Brick".to_string()))
+        );
+        assert_eq!(from_title("Dune"), None);
+        assert_eq!(from_title("The Man Who Was Read By Nobody"), None);
+    }
+
+    #[test]
+    fn test_split() {
+        assert_eq!(
+            split("Scott Brick, Euan Morton and Simon Vance"),
+            ["Scott Brick", "Euan Morton", "Simon Vance"]
+        );
+        assert_eq!(
+            split("Michael Kramer & Kate Reading"),
+            ["Michael Kramer", "Kate Reading"]
+        );
+        assert_eq!(split("Alyssa Bresnahan"), ["Alyssa Bresnahan"]);
+        assert!(split(" ").is_empty());
+    }
+}
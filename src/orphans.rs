@@ -0,0 +1,337 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashSet};
+use std::fs;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+
+use crate::{media, remap_track_path, AudioTrack, Audiobook, UNKNOWN_AUTHOR};
+
+/// Extensions of the files counted as tracks of an orphaned book
+const AUDIO_EXTENSIONS: &[&str] = &[
+    "m4b", "m4a", "mp3", "aac", "aax", "aa", "flac", "opus", "ogg", "wav",
+];
+
+/// A folder under `Audiobooks/` that no library entry refers to, left behind by a deleted
+/// or corrupted library record
+#[derive(Debug, Clone, PartialEq)]
+pub struct Orphan {
+    pub folder_id: String,
+    /// Audio files in the folder, by name
+    pub tracks: Vec<PathBuf>,
+    /// Size of everything in the folder
+    pub bytes: u64,
+}
+
+/// Something else under `Audiobooks/` that no book refers to: a file next to the book
+/// folders, a `sha1-...` folder without audio, or an audio file in a book's folder that
+/// isn't one of its tracks
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stray {
+    pub path: PathBuf,
+    pub bytes: u64,
+}
+
+/// Size of a file, or of everything in a folder. Symlinks aren't followed.
+pub fn size_of(path: &Path) -> u64 {
+    let Ok(metadata) = fs::symlink_metadata(path) else {
+        return 0;
+    };
+    if !metadata.is_dir() {
+        return metadata.len();
+    }
+    fs::read_dir(path)
+        .map(|entries| entries.flatten().map(|entry| size_of(&entry.path())).sum())
+        .unwrap_or(0)
+}
+
+fn is_audio(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| AUDIO_EXTENSIONS.iter().any(|x| e.eq_ignore_ascii_case(x)))
+}
+
+/// Folders under `Audiobooks/` holding audio files that none of the books refer to
+pub fn find(source_base: &Path, audiobooks: &[Audiobook]) -> Result<Vec<Orphan>> {
+    let dir = source_base.join("Audiobooks");
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    // Tracks of a book may live in another book's folder, so their folders count too
+    let referenced: HashSet<String> = audiobooks
+        .iter()
+        .map(|book| book.folder_id.clone())
+        .chain(audiobooks.iter().flat_map(|book| {
+            book.tracks.iter().filter_map(|track| {
+                let folder = track.path.parent()?.file_name()?;
+                Some(folder.to_string_lossy().to_string())
+            })
+        }))
+        .collect();
+
+    let mut orphans = Vec::new();
+    for entry in fs::read_dir(&dir).with_context(|| format!("Failed to read {:?}", dir))? {
+        let entry = entry.with_context(|| format!("Failed to read {:?}", dir))?;
+        let folder_id = entry.file_name().to_string_lossy().to_string();
+        if !entry.file_type()?.is_dir() || referenced.contains(&folder_id) {
+            continue;
+        }
+        let mut tracks: Vec<_> = fs::read_dir(entry.path())?
+            .flatten()
+            .map(|track| track.path())
+            .filter(|path| path.is_file() && is_audio(path))
+            .collect();
+        if tracks.is_empty() {
+            continue;
+        }
+        tracks.sort();
+        orphans.push(Orphan {
+            folder_id,
+            tracks,
+            bytes: size_of(&entry.path()),
+        });
+    }
+    orphans.sort_by(|a, b| a.folder_id.cmp(&b.folder_id));
+    Ok(orphans)
+}
+
+/// Files and folders under `Audiobooks/` that no book refers to and that [`find`] doesn't
+/// report as orphaned books, largest first
+pub fn find_stray(source_base: &Path, audiobooks: &[Audiobook]) -> Result<Vec<Stray>> {
+    let dir = source_base.join("Audiobooks");
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let folder_ids: HashSet<_> = audiobooks.iter().map(|book| &book.folder_id).collect();
+    let track_paths: HashSet<PathBuf> = audiobooks
+        .iter()
+        .flat_map(|book| &book.tracks)
+        .map(|track| remap_track_path(&track.path, source_base))
+        .collect();
+    let track_folders: HashSet<_> = track_paths.iter().filter_map(|p| p.parent()).collect();
+
+    let mut stray = Vec::new();
+    let mut add = |path: PathBuf| {
+        let bytes = size_of(&path);
+        stray.push(Stray { path, bytes });
+    };
+    for entry in fs::read_dir(&dir).with_context(|| format!("Failed to read {:?}", dir))? {
+        let entry = entry.with_context(|| format!("Failed to read {:?}", dir))?;
+        let path = entry.path();
+        if !entry.file_type()?.is_dir() {
+            if !track_paths.contains(&path) {
+                add(path);
+            }
+            continue;
+        }
+        let files: Vec<_> = fs::read_dir(&path)?.flatten().map(|f| f.path()).collect();
+        let referenced = folder_ids.contains(&entry.file_name().to_string_lossy().to_string())
+            || track_folders.contains(path.as_path());
+        if referenced {
+            for file in files {
+                if file.is_file() && is_audio(&file) && !track_paths.contains(&file) {
+                    add(file);
+                }
+            }
+        } else if !files.iter().any(|file| file.is_file() && is_audio(file)) {
+            add(path);
+        }
+    }
+    stray.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.path.cmp(&b.path)));
+    Ok(stray)
+}
+
+/// The book an orphaned folder probably holds, from the tags of its first track (requires
+/// ffprobe). Without tags, the folder id stands in for the title.
+pub fn identify(orphan: &Orphan) -> Audiobook {
+    let tags = orphan
+        .tracks
+        .first()
+        .and_then(|path| media::probe_format_tags(path).ok())
+        .unwrap_or_default();
+    let tag = |names: &[&str]| {
+        names
+            .iter()
+            .find_map(|name| media::find_tag(&tags, name))
+            .map(str::to_string)
+    };
+
+    let tracks = orphan
+        .tracks
+        .iter()
+        .enumerate()
+        .map(|(i, path)| AudioTrack {
+            track_number: i as u32 + 1,
+            disc_number: 0,
+            title: path
+                .file_stem()
+                .map(|s| s.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            path: path.clone(),
+            filename: path
+                .file_name()
+                .map(|s| s.to_string_lossy().to_string())
+                .unwrap_or_default(),
+        })
+        .collect();
+
+    Audiobook {
+        title: tag(&["album", "title"]).unwrap_or_else(|| orphan.folder_id.clone()),
+        author: tag(&["album_artist", "artist"]).unwrap_or_else(|| UNKNOWN_AUTHOR.to_string()),
+        narrator: tag(&["composer"]),
+        folder_id: orphan.folder_id.clone(),
+        tracks,
+        ..Default::default()
+    }
+}
+
+/// What to do with an orphaned folder
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Export,
+    Ignore,
+    Skip,
+}
+
+/// Ask what to do with an orphaned folder. Falls back to skipping when stdin is not a
+/// terminal.
+pub fn ask() -> Action {
+    if !io::stdin().is_terminal() {
+        return Action::Skip;
+    }
+    let stdin = io::stdin();
+    loop {
+        print!("[e]xport, [i]gnore from now on, or [s]kip? ");
+        let _ = io::stdout().flush();
+
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).unwrap_or(0) == 0 {
+            return Action::Skip;
+        }
+        if let Some(action) = parse_answer(&line) {
+            return action;
+        }
+    }
+}
+
+fn parse_answer(answer: &str) -> Option<Action> {
+    match answer.trim().to_lowercase().as_str() {
+        "e" | "export" => Some(Action::Export),
+        "i" | "ignore" => Some(Action::Ignore),
+        "s" | "skip" => Some(Action::Skip),
+        _ => None,
+    }
+}
+
+/// Orphaned folders the user chose to ignore, so they aren't offered again
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Ignored {
+    pub folders: BTreeSet<String>,
+}
+
+impl Ignored {
+    /// Read the ignore list; a missing file means nothing is ignored
+    pub fn read(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Ignored::default());
+        }
+        let json =
+            fs::read_to_string(path).with_context(|| format!("Failed to read {:?}", path))?;
+        serde_json::from_str(&json).with_context(|| format!("Invalid ignore list {:?}", path))
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory {:?}", parent))?;
+        }
+        let json = serde_json::to_string_pretty(self)?;
+        fs::write(path, json).with_context(|| format!("Failed to write {:?}", path))
+    }
+}
+
+/// Where the list of ignored orphans is kept
+pub fn default_ignored_path() -> Option<PathBuf> {
+    dirs::data_local_dir().map(|dir| dir.join("extract_apple_books").join("ignored-orphans.json"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_find_orphans() {
+        let dir = tempdir().unwrap();
+        let audiobooks_dir = dir.path().join("Audiobooks");
+        for (folder, file) in [
+            ("sha1-known", "01.mp3"),
+            ("sha1-orphan", "02 Part.mp3"),
+            ("sha1-orphan", "01 Part.mp3"),
+            ("sha1-orphan", "cover.jpg"),
+            ("sha1-no-audio", "notes.txt"),
+            ("sha1-known", "old.mp3"),
+            ("sha1-known", "cover.jpg"),
+        ] {
+            fs::create_dir_all(audiobooks_dir.join(folder)).unwrap();
+            fs::write(audiobooks_dir.join(folder).join(file), b"data").unwrap();
+        }
+
+        let known = Audiobook {
+            title: "Known".to_string(),
+            author: "Author".to_string(),
+            folder_id: "sha1-known".to_string(),
+            tracks: vec![AudioTrack {
+                track_number: 1,
+                disc_number: 0,
+                title: String::new(),
+                path: PathBuf::from("/elsewhere/Audiobooks/sha1-known/01.mp3"),
+                filename: "01.mp3".to_string(),
+            }],
+            ..Default::default()
+        };
+        fs::write(audiobooks_dir.join("download.tmp"), b"partial").unwrap();
+        let orphans = find(dir.path(), std::slice::from_ref(&known)).unwrap();
+        assert_eq!(orphans.len(), 1);
+        assert_eq!(orphans[0].folder_id, "sha1-orphan");
+        assert_eq!(
+            orphans[0].tracks,
+            vec![
+                audiobooks_dir.join("sha1-orphan/01 Part.mp3"),
+                audiobooks_dir.join("sha1-orphan/02 Part.mp3"),
+            ]
+        );
+        assert_eq!(orphans[0].bytes, 12);
+
+        let stray = find_stray(dir.path(), &[known]).unwrap();
+        assert_eq!(
+            stray,
+            vec![
+                Stray {
+                    path: audiobooks_dir.join("download.tmp"),
+                    bytes: 7
+                },
+                Stray {
+                    path: audiobooks_dir.join("sha1-known/old.mp3"),
+                    bytes: 4
+                },
+                Stray {
+                    path: audiobooks_dir.join("sha1-no-audio"),
+                    bytes: 4
+                },
+            ]
+        );
+
+        // Without tags, the folder id stands in for the title
+        let book = identify(&orphans[0]);
+        assert_eq!(book.folder_id, "sha1-orphan");
+        assert_eq!(book.tracks.len(), 2);
+        assert_eq!(book.tracks[1].track_number, 2);
+        assert_eq!(book.tracks[1].filename, "02 Part.mp3");
+
+        assert_eq!(parse_answer("E\n"), Some(Action::Export));
+        assert_eq!(parse_answer("ignore"), Some(Action::Ignore));
+        assert_eq!(parse_answer("x"), None);
+    }
+}
@@ -0,0 +1,249 @@
+use clap::ValueEnum;
+use console::{measure_text_width, Term};
+use std::fmt::Display;
+use std::io::{self, IsTerminal};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+
+/// When to use colored output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum ColorChoice {
+    /// Color when writing to a terminal and `NO_COLOR` is not set
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+/// How `list`, `diff` and `export` print their results
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum Format {
+    /// Tables and boxes for reading
+    #[default]
+    Text,
+    /// JSON on stdout, with progress messages moved to stderr
+    Json,
+}
+
+/// Colors used in reports
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+}
+
+impl Color {
+    fn ansi_code(self) -> u8 {
+        match self {
+            Color::Red => 31,
+            Color::Green => 32,
+            Color::Yellow => 33,
+            Color::Blue => 34,
+            Color::Magenta => 35,
+            Color::Cyan => 36,
+        }
+    }
+}
+
+/// Box width when output is not a terminal, e.g. redirected to a file
+const DEFAULT_WIDTH: usize = 67;
+const MIN_WIDTH: usize = 40;
+const MAX_WIDTH: usize = 100;
+
+#[derive(Debug)]
+struct Settings {
+    color: bool,
+    /// ASCII only: no box drawing, symbols or colors
+    plain: bool,
+    width: usize,
+}
+
+impl Settings {
+    fn detect(choice: ColorChoice, plain: bool) -> Self {
+        let terminal = io::stdout().is_terminal();
+        let no_color = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+        let color = !plain
+            && match choice {
+                ColorChoice::Always => true,
+                ColorChoice::Never => false,
+                ColorChoice::Auto => terminal && !no_color,
+            };
+        let width = Term::stdout()
+            .size_checked()
+            .filter(|_| terminal)
+            .map(|(_, columns)| (columns as usize).clamp(MIN_WIDTH, MAX_WIDTH))
+            .unwrap_or(DEFAULT_WIDTH);
+        Settings {
+            color,
+            plain,
+            width,
+        }
+    }
+
+    fn rule(&self, left: char, fill: char, right: char) -> String {
+        let inner = self.width.saturating_sub(2);
+        format!("{}{}{}", left, fill.to_string().repeat(inner), right)
+    }
+
+    fn boxed(&self, text: &str, edge: char) -> String {
+        let padding = self.width.saturating_sub(measure_text_width(text) + 3);
+        format!("{} {}{}{}", edge, text, " ".repeat(padding), edge)
+    }
+
+    fn title_box(&self, text: &str) -> String {
+        if self.plain {
+            return text.to_string();
+        }
+        [
+            self.rule('┌', '─', '┐'),
+            self.boxed(text, '│'),
+            self.rule('└', '─', '┘'),
+        ]
+        .join("\n")
+    }
+
+    fn table_box(&self, title: &str, rows: &[String]) -> String {
+        if self.plain {
+            let mut lines = vec![title.to_string()];
+            lines.extend(rows.iter().map(|row| format!("  {}", row.trim_start())));
+            return lines.join("\n");
+        }
+        let mut lines = vec![
+            self.rule('┌', '─', '┐'),
+            self.boxed(title, '│'),
+            self.rule('├', '─', '┤'),
+        ];
+        lines.extend(rows.iter().map(|row| self.boxed(row, '│')));
+        lines.push(self.rule('└', '─', '┘'));
+        lines.join("\n")
+    }
+
+    fn banner(&self, text: &str) -> String {
+        if self.plain {
+            return format!("== {} ==", text);
+        }
+        let inner = self.width.saturating_sub(2);
+        let left = inner.saturating_sub(measure_text_width(text)) / 2;
+        let centered = format!("{}{}", " ".repeat(left.saturating_sub(1)), text);
+        [
+            self.rule('╔', '═', '╗'),
+            self.boxed(&centered, '║'),
+            self.rule('╚', '═', '╝'),
+        ]
+        .join("\n")
+    }
+}
+
+static SETTINGS: OnceLock<Settings> = OnceLock::new();
+
+/// Configure output once at startup; later calls have no effect
+pub fn init(choice: ColorChoice, plain: bool) {
+    let _ = SETTINGS.set(Settings::detect(choice, plain));
+}
+
+static JSON: AtomicBool = AtomicBool::new(false);
+
+/// Keep stdout for a JSON result: progress messages go to stderr from now on
+pub fn set_format(format: Format) {
+    JSON.store(format == Format::Json, Ordering::Relaxed);
+}
+
+/// Print a progress message: to stdout, or to stderr when stdout carries JSON
+pub fn message(text: impl Display) {
+    if JSON.load(Ordering::Relaxed) {
+        eprintln!("{}", text);
+    } else {
+        println!("{}", text);
+    }
+}
+
+fn settings() -> &'static Settings {
+    SETTINGS.get_or_init(|| Settings::detect(ColorChoice::Auto, false))
+}
+
+/// Whether output is restricted to plain ASCII text
+pub fn is_plain() -> bool {
+    settings().plain
+}
+
+/// `text` in `color`, or unchanged when color is off
+pub fn paint(text: &str, color: Color) -> String {
+    if settings().color {
+        format!("\x1b[{}m{}\x1b[0m", color.ansi_code(), text)
+    } else {
+        text.to_string()
+    }
+}
+
+/// `fancy`, or its `ascii` stand-in in plain mode
+pub fn symbol(fancy: &'static str, ascii: &'static str) -> &'static str {
+    if is_plain() {
+        ascii
+    } else {
+        fancy
+    }
+}
+
+/// A title in a single-line box
+pub fn title_box(text: &str) -> String {
+    settings().title_box(text)
+}
+
+/// A box with a title and one row per line, e.g. the totals of a diff
+pub fn table_box(title: &str, rows: &[String]) -> String {
+    settings().table_box(title, rows)
+}
+
+/// A centered banner in a double-line box
+pub fn banner(text: &str) -> String {
+    settings().banner(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(plain: bool) -> Settings {
+        Settings {
+            color: false,
+            plain,
+            width: 30,
+        }
+    }
+
+    #[test]
+    fn test_boxed_pads_to_width_ignoring_color_codes() {
+        let fancy = settings(false);
+        let plain_text = fancy.boxed("+ New files", '│');
+        let colored = fancy.boxed("\x1b[32m+\x1b[0m New files", '│');
+        assert_eq!(measure_text_width(&plain_text), 30);
+        assert_eq!(measure_text_width(&colored), 30);
+        assert!(plain_text.ends_with(" │"));
+
+        // Overlong text is never cut
+        assert_eq!(
+            fancy.boxed(&"x".repeat(30), '│'),
+            format!("│ {}│", "x".repeat(30))
+        );
+    }
+
+    #[test]
+    fn test_plain_mode_is_ascii() {
+        let plain = settings(true);
+        let rendered = [
+            plain.title_box("TO ADD"),
+            plain.banner("DIFF SUMMARY"),
+            plain.table_box("TOTALS", &[" + New files:  3".to_string()]),
+        ]
+        .join("\n");
+        assert!(rendered.is_ascii());
+        assert_eq!(
+            rendered,
+            "TO ADD\n== DIFF SUMMARY ==\nTOTALS\n  + New files:  3"
+        );
+    }
+}
@@ -0,0 +1,256 @@
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::Audiobook;
+
+/// Metadata corrections for a single book. `None` keeps the library value; for narrator
+/// and series, an empty string removes it.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BookOverride {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub narrator: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub series: Option<String>,
+}
+
+impl BookOverride {
+    fn is_empty(&self) -> bool {
+        *self == BookOverride::default()
+    }
+}
+
+/// Metadata corrections by Apple Books folder id, applied to every export
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Overrides {
+    pub books: BTreeMap<String, BookOverride>,
+}
+
+fn optional(value: &str) -> Option<String> {
+    (!value.is_empty()).then(|| value.to_string())
+}
+
+impl Overrides {
+    /// Read the overrides file; a missing file has no overrides
+    pub fn read(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Overrides::default());
+        }
+        let json = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read overrides {:?}", path))?;
+        serde_json::from_str(&json).with_context(|| format!("Invalid overrides file {:?}", path))
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory {:?}", parent))?;
+        }
+        let json = serde_json::to_string_pretty(self)?;
+        fs::write(path, json).with_context(|| format!("Failed to write overrides {:?}", path))
+    }
+
+    /// Apply the overrides to the books they belong to
+    pub fn apply(&self, audiobooks: &mut [Audiobook]) {
+        for book in audiobooks.iter_mut() {
+            let Some(fix) = self.books.get(&book.folder_id) else {
+                continue;
+            };
+            if let Some(title) = &fix.title {
+                book.title = title.clone();
+                book.title_in_series = None;
+            }
+            if let Some(author) = &fix.author {
+                book.author = author.clone();
+            }
+            if let Some(narrator) = &fix.narrator {
+                book.narrator = optional(narrator);
+            }
+            if let Some(series) = &fix.series {
+                book.series = optional(series);
+                book.series_detected = false;
+                if book.series.is_none() {
+                    book.series_sequence = None;
+                }
+            }
+        }
+    }
+
+    /// Record `edited` as the corrected metadata of `original` (the book as read from the
+    /// library). Fields equal to the library value are dropped from the override.
+    pub fn set(&mut self, original: &Audiobook, edited: &Audiobook) {
+        let changed = |a: &str, b: &str| (a != b).then(|| b.to_string());
+        let fix = BookOverride {
+            title: changed(&original.title, &edited.title),
+            author: changed(&original.author, &edited.author),
+            narrator: changed(
+                original.narrator.as_deref().unwrap_or(""),
+                edited.narrator.as_deref().unwrap_or(""),
+            ),
+            series: changed(
+                original.series.as_deref().unwrap_or(""),
+                edited.series.as_deref().unwrap_or(""),
+            ),
+        };
+
+        if fix.is_empty() {
+            self.books.remove(&original.folder_id);
+        } else {
+            self.books.insert(original.folder_id.clone(), fix);
+        }
+    }
+}
+
+/// Where metadata overrides are kept
+pub fn default_overrides_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("extract_apple_books").join("overrides.json"))
+}
+
+/// The editable form shown for a book
+fn render_form(book: &Audiobook) -> String {
+    format!(
+        "# {} ({} tracks, folder {})\n\
+         # Edit the values below, then save and close the editor.\n\
+         # Leave narrator or series empty to remove them.\n\
+         title: {}\n\
+         author: {}\n\
+         narrator: {}\n\
+         series: {}\n",
+        book.title,
+        book.tracks.len(),
+        book.folder_id,
+        book.title,
+        book.author,
+        book.narrator.as_deref().unwrap_or(""),
+        book.series.as_deref().unwrap_or(""),
+    )
+}
+
+/// Apply an edited form to a copy of `book`
+fn parse_form(form: &str, book: &Audiobook) -> Result<Audiobook> {
+    let mut edited = book.clone();
+    for line in form.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let Some((key, value)) = line.split_once(':') else {
+            bail!("Expected 'field: value', got {:?}", line);
+        };
+        let value = value.trim();
+        match key.trim().to_lowercase().as_str() {
+            "title" if !value.is_empty() => edited.title = value.to_string(),
+            "author" if !value.is_empty() => edited.author = value.to_string(),
+            "title" | "author" => bail!("{} can't be empty", key.trim()),
+            "narrator" => edited.narrator = optional(value),
+            "series" => edited.series = optional(value),
+            other => bail!("Unknown field {:?}", other),
+        }
+    }
+    Ok(edited)
+}
+
+/// Open a book's metadata in `$VISUAL`/`$EDITOR` and return the edited book
+pub fn edit_in_editor(book: &Audiobook) -> Result<Audiobook> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    let path = std::env::temp_dir().join(format!("extract_apple_books-{}.txt", book.folder_id));
+    fs::write(&path, render_form(book)).with_context(|| format!("Failed to write {:?}", path))?;
+
+    // Run through the shell so that editors with arguments ("code --wait") work
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$1\"", editor))
+        .arg("sh")
+        .arg(&path)
+        .status()
+        .with_context(|| format!("Failed to run editor {:?}", editor))?;
+    let form = fs::read_to_string(&path);
+    let _ = fs::remove_file(&path);
+
+    if !status.success() {
+        bail!("Editor {:?} exited with {}", editor, status);
+    }
+    parse_form(&form?, book)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn book() -> Audiobook {
+        Audiobook {
+            title: "Warbreaker (Unabridged)".to_string(),
+            author: "Brandon Sanderson".to_string(),
+            narrator: Some("Alyssa Bresnahan".to_string()),
+            folder_id: "sha1-abc".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_form_roundtrip() {
+        let original = book();
+        assert_eq!(
+            parse_form(&render_form(&original), &original)
+                .unwrap()
+                .title,
+            original.title
+        );
+
+        let form = render_form(&original)
+            .replace("title: Warbreaker (Unabridged)", "title: Warbreaker")
+            .replace("narrator: Alyssa Bresnahan", "narrator:")
+            .replace("series: ", "series: Warbreaker");
+        let edited = parse_form(&form, &original).unwrap();
+        assert_eq!(edited.title, "Warbreaker");
+        assert_eq!(edited.narrator, None);
+        assert_eq!(edited.series.as_deref(), Some("Warbreaker"));
+
+        assert!(parse_form("title:\n", &original).is_err());
+        assert!(parse_form("genre: Fantasy\n", &original).is_err());
+    }
+
+    #[test]
+    fn test_set_and_apply_overrides() {
+        let original = book();
+        let mut edited = original.clone();
+        edited.narrator = None;
+        edited.series = Some("Warbreaker".to_string());
+
+        let mut overrides = Overrides::default();
+        overrides.set(&original, &edited);
+        assert_eq!(
+            overrides.books["sha1-abc"],
+            BookOverride {
+                narrator: Some(String::new()),
+                series: Some("Warbreaker".to_string()),
+                ..Default::default()
+            }
+        );
+
+        // A reviewed series replaces a detected one, and gets a folder in any layout
+        let mut books = vec![Audiobook {
+            series: Some("Cosmere".to_string()),
+            series_detected: true,
+            ..original.clone()
+        }];
+        overrides.apply(&mut books);
+        assert_eq!(books[0].narrator, None);
+        assert_eq!(books[0].series.as_deref(), Some("Warbreaker"));
+        assert!(!books[0].series_detected);
+        assert_eq!(books[0].title, original.title);
+
+        overrides.set(&original, &original);
+        assert!(overrides.books.is_empty());
+    }
+}
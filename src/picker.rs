@@ -0,0 +1,149 @@
+use anyhow::{bail, Result};
+use console::{Key, Term};
+use std::collections::BTreeSet;
+
+/// How well `text` matches a fuzzy query: its characters in order, not necessarily next to
+/// each other, ignoring case. Higher is better; `None` if it doesn't match. Runs of
+/// consecutive characters and matches at the start of words score higher.
+pub fn fuzzy_score(query: &str, text: &str) -> Option<i64> {
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+    let mut score = 0;
+    let mut next = 0;
+    let mut previous: Option<usize> = None;
+    for q in query.to_lowercase().chars().filter(|c| !c.is_whitespace()) {
+        let i = next + text[next..].iter().position(|&c| c == q)?;
+        score += 1;
+        if previous == Some(i.wrapping_sub(1)) {
+            score += 5;
+        }
+        if i == 0 || !text[i - 1].is_alphanumeric() {
+            score += 3;
+        }
+        previous = Some(i);
+        next = i + 1;
+    }
+    // Among equal matches, prefer shorter texts
+    Some(score * 1000 - text.len() as i64)
+}
+
+/// Indexes of the items matching a query, best first. All items, in order, for an empty
+/// query.
+pub fn filter(query: &str, items: &[String]) -> Vec<usize> {
+    let mut matches: Vec<_> = items
+        .iter()
+        .enumerate()
+        .filter_map(|(i, item)| fuzzy_score(query, item).map(|score| (score, i)))
+        .collect();
+    if !query.trim().is_empty() {
+        matches.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+    }
+    matches.into_iter().map(|(_, i)| i).collect()
+}
+
+/// Let the user pick any number of items in the terminal: typing narrows the list down,
+/// arrows move, Tab marks an item, Enter accepts the marked items (or the highlighted one
+/// when none is marked), and Esc cancels. Returns the indexes of the chosen items.
+pub fn pick(prompt: &str, items: &[String]) -> Result<Vec<usize>> {
+    let term = Term::stderr();
+    if !term.is_term() {
+        bail!("Picking books needs a terminal");
+    }
+
+    let mut query = String::new();
+    let mut chosen = BTreeSet::new();
+    let mut cursor = 0;
+    let mut drawn = 0;
+    term.hide_cursor()?;
+    let result = loop {
+        let matches = filter(&query, items);
+        cursor = cursor.min(matches.len().saturating_sub(1));
+
+        let (rows, columns) = term.size();
+        let visible = (rows as usize).saturating_sub(4).max(3);
+        let first = cursor.saturating_sub(visible - 1);
+        let mut lines = vec![
+            format!(
+                "{} ({} of {} shown, {} marked)",
+                prompt,
+                matches.len(),
+                items.len(),
+                chosen.len()
+            ),
+            "Type to search, Up/Down to move, Tab to mark, Enter to accept, Esc to cancel"
+                .to_string(),
+            format!("> {}", query),
+        ];
+        for (row, &i) in matches.iter().enumerate().skip(first).take(visible) {
+            let line = format!(
+                "{}{} {}",
+                if row == cursor { ">" } else { " " },
+                if chosen.contains(&i) { "[x]" } else { "[ ]" },
+                items[i]
+            );
+            lines.push(console::truncate_str(&line, columns as usize, "...").to_string());
+        }
+        term.clear_last_lines(drawn)?;
+        for line in &lines {
+            term.write_line(line)?;
+        }
+        drawn = lines.len();
+
+        match term.read_key()? {
+            Key::Escape => break Ok(Vec::new()),
+            Key::Enter => {
+                if chosen.is_empty() {
+                    chosen.extend(matches.get(cursor));
+                }
+                break Ok(chosen.into_iter().collect());
+            }
+            Key::Tab => {
+                if let Some(&i) = matches.get(cursor) {
+                    if !chosen.remove(&i) {
+                        chosen.insert(i);
+                    }
+                    cursor += 1;
+                }
+            }
+            Key::ArrowUp => cursor = cursor.saturating_sub(1),
+            Key::ArrowDown => cursor += 1,
+            Key::Backspace => {
+                query.pop();
+                cursor = 0;
+            }
+            Key::Char(c) if !c.is_control() => {
+                query.push(c);
+                cursor = 0;
+            }
+            _ => {}
+        }
+    };
+    term.clear_last_lines(drawn)?;
+    term.show_cursor()?;
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fuzzy_filter() {
+        assert!(fuzzy_score("wrbrkr", "Warbreaker").is_some());
+        assert!(fuzzy_score("sanderson war", "Brandon Sanderson - Warbreaker").is_some());
+        assert_eq!(fuzzy_score("xyz", "Warbreaker"), None);
+        assert_eq!(fuzzy_score("rw", "Warbreaker"), None);
+
+        let items: Vec<String> = [
+            "Andy Weir - Project Hail Mary",
+            "Brandon Sanderson - Warbreaker",
+            "Brandon Sanderson - The Way of Kings",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+        assert_eq!(filter("", &items), [0, 1, 2]);
+        assert_eq!(filter("way", &items), [2, 0]);
+        assert_eq!(filter("war", &items), [1, 0]);
+        assert_eq!(filter("hail", &items), [0]);
+    }
+}
@@ -0,0 +1,86 @@
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+
+/// One stage of a pipeline: what it does to each item, and on how many threads
+pub struct Stage<'a, T> {
+    pub workers: usize,
+    pub work: &'a (dyn Fn(&mut T) + Sync),
+}
+
+/// Pass every item through the stages in order. Each stage has its own worker threads and
+/// items move on as soon as a stage is done with them, so a fast stage (hashing on the
+/// internal SSD) runs ahead of a slow one (writing to a NAS) instead of taking turns with it.
+/// `done` is called as each item leaves the last stage. Returns the items in their
+/// original order.
+pub fn run<T: Send>(items: Vec<T>, stages: &[Stage<T>], mut done: impl FnMut(&T)) -> Vec<T> {
+    let mut finished: Vec<Option<T>> = items.iter().map(|_| None).collect();
+
+    thread::scope(|scope| {
+        let (input, mut queue) = mpsc::channel();
+        for item in items.into_iter().enumerate() {
+            let _ = input.send(item);
+        }
+        drop(input);
+
+        for stage in stages {
+            let (output, next) = mpsc::channel();
+            let shared = Arc::new(Mutex::new(queue));
+            for _ in 0..stage.workers.max(1) {
+                let (shared, output) = (Arc::clone(&shared), output.clone());
+                scope.spawn(move || loop {
+                    let received = shared.lock().unwrap().recv();
+                    let Ok((i, mut item)) = received else {
+                        break;
+                    };
+                    (stage.work)(&mut item);
+                    if output.send((i, item)).is_err() {
+                        break;
+                    }
+                });
+            }
+            queue = next;
+        }
+
+        for (i, item) in queue {
+            done(&item);
+            finished[i] = Some(item);
+        }
+    });
+
+    finished.into_iter().flatten().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_items_pass_every_stage_in_order() {
+        let items: Vec<(usize, Vec<&str>)> = (0..20).map(|i| (i, Vec::new())).collect();
+        let hash = |item: &mut (usize, Vec<&str>)| item.1.push("hash");
+        let copy = |item: &mut (usize, Vec<&str>)| item.1.push("copy");
+        let verify = |item: &mut (usize, Vec<&str>)| item.1.push("verify");
+        let stages = [
+            Stage {
+                workers: 2,
+                work: &hash,
+            },
+            Stage {
+                workers: 3,
+                work: &copy,
+            },
+            Stage {
+                workers: 0,
+                work: &verify,
+            },
+        ];
+
+        let mut count = 0;
+        let items = run(items, &stages, |_| count += 1);
+        assert_eq!(count, 20);
+        for (i, (id, stages)) in items.iter().enumerate() {
+            assert_eq!(*id, i);
+            assert_eq!(stages, &["hash", "copy", "verify"]);
+        }
+    }
+}
@@ -0,0 +1,43 @@
+use indicatif::{ProgressBar, ProgressStyle};
+use std::time::Duration;
+
+use crate::i18n::tr;
+use crate::output;
+
+/// Spinner frames for plain ASCII output
+const ASCII_TICKS: &str = "-\\|/ ";
+
+/// Spinner for a phase of unknown length, e.g. reading the library
+pub fn spinner(message: &'static str) -> ProgressBar {
+    let pb = ProgressBar::new_spinner();
+    let mut style = ProgressStyle::default_spinner()
+        .template("{spinner:.green} {msg}")
+        .unwrap();
+    if output::is_plain() {
+        style = style.tick_chars(ASCII_TICKS);
+    }
+    pb.set_style(style);
+    pb.set_message(tr(message));
+    pb.enable_steady_tick(Duration::from_millis(100));
+    pb
+}
+
+/// Progress bar for a phase over `len` items, labelled with the phase name. Empty phases
+/// draw nothing.
+pub fn bar(len: usize, phase: &'static str) -> ProgressBar {
+    if len == 0 {
+        return ProgressBar::hidden();
+    }
+    let pb = ProgressBar::new(len as u64);
+    let mut style = ProgressStyle::default_bar()
+        .template("{spinner:.green} {prefix:<20.bold} [{bar:40.cyan/blue}] {pos}/{len} {msg}")
+        .unwrap()
+        .progress_chars("#>-");
+    if output::is_plain() {
+        style = style.tick_chars(ASCII_TICKS);
+    }
+    pb.set_style(style);
+    pb.set_prefix(tr(phase));
+    pb.enable_steady_tick(Duration::from_millis(100));
+    pb
+}
@@ -0,0 +1,138 @@
+use anyhow::Result;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::chapters::CHAPTERS_FILENAME;
+use crate::cover::COVER_FILENAME;
+use crate::cue::CUE_FILENAME;
+use crate::extras::EXTRAS_DIR;
+use crate::manifest::Manifest;
+use crate::metadata::{METADATA_FILENAME, NFO_FILENAME, OPF_FILENAME};
+use crate::trash::Disposer;
+use crate::{Audiobook, DESCRIPTION_FILE, READER_FILE};
+
+/// A book exported into the destination that is no longer in the library, e.g. a returned
+/// Audible title
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stale {
+    pub folder_id: String,
+    pub author: String,
+    pub title: String,
+    /// The book's folder in the destination
+    pub dir: PathBuf,
+    /// Files written for the book that are still there: its tracks, and the chapters,
+    /// description, narrator, metadata, cover and extras written next to them
+    pub files: Vec<PathBuf>,
+}
+
+/// Books in the manifest of the destination at `dest` that the library no longer has
+pub fn find(manifest: &Manifest, audiobooks: &[Audiobook], dest: &Path) -> Vec<Stale> {
+    let in_library: HashSet<_> = audiobooks.iter().map(|b| &b.folder_id).collect();
+    manifest
+        .books
+        .iter()
+        .filter(|(folder_id, _)| !in_library.contains(folder_id))
+        .map(|(folder_id, entry)| {
+            let dir = manifest.relocate(&entry.dest_dir, dest);
+            let mut files: Vec<_> = entry
+                .files
+                .iter()
+                .map(|file| manifest.relocate(&file.dest, dest))
+                .collect();
+            files.push(dir.join(CHAPTERS_FILENAME));
+            files.push(dir.join(DESCRIPTION_FILE));
+            files.push(dir.join(READER_FILE));
+            files.push(dir.join(METADATA_FILENAME));
+            files.push(dir.join(OPF_FILENAME));
+            files.push(dir.join(NFO_FILENAME));
+            files.push(dir.join(CUE_FILENAME));
+            files.push(dir.join(COVER_FILENAME));
+            if let Ok(extras) = fs::read_dir(dir.join(EXTRAS_DIR)) {
+                files.extend(extras.flatten().map(|entry| entry.path()));
+            }
+            files.retain(|path| fs::symlink_metadata(path).is_ok_and(|m| !m.is_dir()));
+            Stale {
+                folder_id: folder_id.clone(),
+                author: entry.author.clone(),
+                title: entry.title.clone(),
+                dir,
+                files,
+            }
+        })
+        .collect()
+}
+
+/// Remove a stale book's files, then its folder and the series and author folders above
+/// it if that left them empty. Returns how many files were removed.
+pub fn remove(stale: &Stale, dest: &Path, disposer: &Disposer) -> Result<usize> {
+    for file in &stale.files {
+        disposer.dispose(file, dest)?;
+    }
+    let _ = fs::remove_dir(stale.dir.join(EXTRAS_DIR));
+    for dir in stale.dir.ancestors().take_while(|dir| *dir != dest) {
+        // Stops at the first folder that still has something in it
+        if fs::remove_dir(dir).is_err() {
+            break;
+        }
+    }
+    Ok(stale.files.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::{BookEntry, ManifestFile};
+    use crate::trash::Disposal;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_find_and_remove_stale_books() {
+        let dir = tempdir().unwrap();
+        let dest = dir.path().join("dest");
+        let source = dir.path().join("01.mp3");
+        fs::write(&source, b"audio").unwrap();
+
+        let mut manifest = Manifest {
+            root: dest.clone(),
+            ..Default::default()
+        };
+        for (folder_id, author, title) in [
+            ("sha1-kept", "Andy Weir", "Project Hail Mary"),
+            ("sha1-gone", "Someone", "Returned"),
+        ] {
+            let book_dir = dest.join(author).join(title);
+            fs::create_dir_all(book_dir.join(EXTRAS_DIR)).unwrap();
+            let track = book_dir.join("01.mp3");
+            fs::write(&track, b"audio").unwrap();
+            fs::write(book_dir.join(CHAPTERS_FILENAME), b"{}").unwrap();
+            fs::write(book_dir.join(EXTRAS_DIR).join("booklet.pdf"), b"pdf").unwrap();
+            manifest.books.insert(
+                folder_id.to_string(),
+                BookEntry {
+                    author: author.to_string(),
+                    title: title.to_string(),
+                    dest_dir: book_dir,
+                    files: vec![ManifestFile::new(&source, &track).unwrap()],
+                    exported_at: 0,
+                },
+            );
+        }
+        let kept = Audiobook {
+            title: "Project Hail Mary".to_string(),
+            author: "Andy Weir".to_string(),
+            folder_id: "sha1-kept".to_string(),
+            ..Default::default()
+        };
+
+        let stale = find(&manifest, &[kept], &dest);
+        assert_eq!(stale.len(), 1);
+        assert_eq!(stale[0].folder_id, "sha1-gone");
+        assert_eq!(stale[0].files.len(), 3);
+
+        let disposer = Disposer::new(Disposal::Permanent);
+        assert_eq!(remove(&stale[0], &dest, &disposer).unwrap(), 3);
+        assert!(!dest.join("Someone").exists());
+        assert!(dest.join("Andy Weir/Project Hail Mary/01.mp3").exists());
+    }
+}
@@ -0,0 +1,52 @@
+use anyhow::{bail, Context, Result};
+use std::path::Path;
+use std::process::Command;
+
+/// Extended attributes macOS adds to downloaded files, which `fs::copy` carries over to the
+/// exported copies. Gatekeeper checks files that have them when they're opened.
+pub const ATTRIBUTES: &[&str] = &["com.apple.quarantine", "com.apple.provenance"];
+
+/// Whether quarantine attributes exist (and can be removed) on this system
+pub fn is_supported() -> bool {
+    cfg!(target_os = "macos")
+}
+
+/// Remove an attribute from a folder and everything in it. Files that don't have it are
+/// skipped by `xattr -r`.
+fn remove_recursive(dir: &Path, name: &str) -> Result<()> {
+    let output = Command::new("xattr")
+        .args(["-dr", name])
+        .arg(dir)
+        .output()
+        .context("Failed to run xattr")?;
+    if !output.status.success() {
+        bail!(
+            "xattr failed for {:?}: {}",
+            dir,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+/// Remove the quarantine attributes from an exported book folder. Returns the attributes
+/// that couldn't be removed, with the error; recent macOS versions protect
+/// `com.apple.provenance`, for example.
+pub fn strip(dir: &Path) -> Vec<(&'static str, anyhow::Error)> {
+    ATTRIBUTES
+        .iter()
+        .filter_map(|&name| remove_recursive(dir, name).err().map(|e| (name, e)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strip_reports_each_attribute_it_could_not_remove() {
+        let failed = strip(Path::new("/nonexistent/extract_apple_books"));
+        let names: Vec<_> = failed.iter().map(|(name, _)| *name).collect();
+        assert_eq!(names, ATTRIBUTES);
+    }
+}
@@ -0,0 +1,115 @@
+use std::fs;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often an unattended run checks whether the destination is back
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The destination of a run, watched for being unmounted or ejected mid-run
+#[derive(Debug)]
+pub struct Destination {
+    root: PathBuf,
+    /// Device the destination was on when the run started. An unmounted volume often
+    /// leaves an empty mount point behind, which then sits on another device.
+    device: Option<u64>,
+}
+
+#[cfg(unix)]
+fn device(path: &Path) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    fs::metadata(path).ok().map(|m| m.dev())
+}
+
+#[cfg(not(unix))]
+fn device(_path: &Path) -> Option<u64> {
+    None
+}
+
+impl Destination {
+    pub fn new(root: &Path) -> Self {
+        Destination {
+            root: root.to_path_buf(),
+            device: device(root),
+        }
+    }
+
+    /// Whether the destination is still mounted and readable
+    pub fn is_available(&self) -> bool {
+        fs::read_dir(&self.root).is_ok()
+            && (self.device.is_none() || device(&self.root) == self.device)
+    }
+
+    /// Wait for the destination to come back. In a terminal, ask the user to reconnect it;
+    /// otherwise check every few seconds for up to `timeout`. Returns whether it's back.
+    pub fn wait(&self, timeout: Duration) -> bool {
+        if io::stdin().is_terminal() {
+            self.prompt()
+        } else {
+            self.poll(timeout)
+        }
+    }
+
+    fn prompt(&self) -> bool {
+        let stdin = io::stdin();
+        loop {
+            print!(
+                "\nDestination {:?} is not available. Reconnect it and press Enter to resume, \
+                 or type q to stop: ",
+                self.root
+            );
+            let _ = io::stdout().flush();
+
+            let mut answer = String::new();
+            if stdin.lock().read_line(&mut answer).unwrap_or(0) == 0
+                || answer.trim().eq_ignore_ascii_case("q")
+            {
+                return false;
+            }
+            if self.is_available() {
+                return true;
+            }
+        }
+    }
+
+    fn poll(&self, timeout: Duration) -> bool {
+        eprintln!(
+            "Destination {:?} is not available; waiting up to {}s for it to come back",
+            self.root,
+            timeout.as_secs()
+        );
+        let started = Instant::now();
+        while started.elapsed() < timeout {
+            thread::sleep(POLL_INTERVAL.min(timeout - started.elapsed()));
+            if self.is_available() {
+                eprintln!("Destination {:?} is back, resuming", self.root);
+                return true;
+            }
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_destination_availability() {
+        let dir = tempdir().unwrap();
+        let root = dir.path().join("NAS");
+        fs::create_dir(&root).unwrap();
+
+        let dest = Destination::new(&root);
+        assert!(dest.is_available());
+
+        fs::remove_dir(&root).unwrap();
+        assert!(!dest.is_available());
+        assert!(!dest.poll(Duration::ZERO));
+
+        fs::create_dir(&root).unwrap();
+        assert!(dest.is_available());
+    }
+}
@@ -0,0 +1,115 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::report::STATE_DIR;
+
+/// A symlink in the destination whose target no longer exists
+#[derive(Debug, Clone, PartialEq)]
+pub struct BrokenLink {
+    pub link: PathBuf,
+    pub target: PathBuf,
+}
+
+impl BrokenLink {
+    /// Whether the link pointed at a track in an Apple Books folder: under `source_base`,
+    /// inside the Books container, or into one of its `sha1-...` book folders
+    pub fn points_into_books(&self, source_base: &Path) -> bool {
+        self.target.starts_with(source_base)
+            || self
+                .target
+                .to_string_lossy()
+                .contains("com.apple.BKAgentService")
+            || self
+                .target
+                .parent()
+                .and_then(Path::file_name)
+                .is_some_and(|folder| folder.to_string_lossy().starts_with("sha1-"))
+    }
+}
+
+/// Find broken symlinks anywhere under the destination. Symlinked folders aren't followed.
+pub fn find_broken(dest: &Path) -> Result<Vec<BrokenLink>> {
+    let mut broken = Vec::new();
+    let mut dirs = vec![dest.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(&dir).with_context(|| format!("Failed to read {:?}", dir))? {
+            let entry = entry.with_context(|| format!("Failed to read {:?}", dir))?;
+            let path = entry.path();
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() && entry.file_name() != STATE_DIR {
+                dirs.push(path);
+            } else if file_type.is_symlink() && !path.exists() {
+                let target = fs::read_link(&path)
+                    .with_context(|| format!("Failed to read symlink {:?}", path))?;
+                broken.push(BrokenLink { link: path, target });
+            }
+        }
+    }
+    broken.sort_by(|a, b| a.link.cmp(&b.link));
+    Ok(broken)
+}
+
+/// Point a symlink at a new target
+pub fn relink(link: &Path, target: &Path) -> Result<()> {
+    fs::remove_file(link).with_context(|| format!("Failed to remove symlink {:?}", link))?;
+    symlink(target, link).with_context(|| format!("Failed to symlink {:?} -> {:?}", link, target))
+}
+
+#[cfg(unix)]
+fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(not(unix))]
+fn symlink(_target: &Path, _link: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "symlinks are not supported on this system",
+    ))
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_find_and_repair_broken_links() {
+        let dir = tempdir().unwrap();
+        let dest = dir.path().join("dest");
+        let book = dest.join("Author/Book");
+        fs::create_dir_all(&book).unwrap();
+        fs::create_dir_all(dest.join(STATE_DIR)).unwrap();
+
+        let old_source = dir.path().join("old/01.mp3");
+        let new_source = dir.path().join("new/01.mp3");
+        fs::create_dir_all(new_source.parent().unwrap()).unwrap();
+        fs::write(&new_source, b"audio").unwrap();
+        symlink(&old_source, &book.join("01.mp3")).unwrap();
+        symlink(&new_source, &book.join("02.mp3")).unwrap();
+        symlink(&old_source, &dest.join(STATE_DIR).join("ignored")).unwrap();
+
+        let broken = find_broken(&dest).unwrap();
+        assert_eq!(
+            broken,
+            vec![BrokenLink {
+                link: book.join("01.mp3"),
+                target: old_source,
+            }]
+        );
+
+        assert!(broken[0].points_into_books(&dir.path().join("old")));
+        assert!(!broken[0].points_into_books(&dir.path().join("new")));
+        let into_container = BrokenLink {
+            link: book.join("03.mp3"),
+            target: PathBuf::from("/Users/me/Books/Audiobooks/sha1-aaa/03.mp3"),
+        };
+        assert!(into_container.points_into_books(&dir.path().join("new")));
+
+        relink(&broken[0].link, &new_source).unwrap();
+        assert_eq!(fs::read(book.join("01.mp3")).unwrap(), b"audio");
+        assert!(find_broken(&dest).unwrap().is_empty());
+    }
+}
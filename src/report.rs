@@ -0,0 +1,173 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{Audiobook, ExportStats};
+
+/// Directory inside the destination where the tool keeps its own state
+pub const STATE_DIR: &str = ".extract_apple_books";
+
+/// A file that could not be exported
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FailedFile {
+    pub book: String,
+    pub folder_id: String,
+    pub source: PathBuf,
+    pub dest: PathBuf,
+    pub error: String,
+}
+
+impl FailedFile {
+    pub fn new(audiobook: &Audiobook, source: PathBuf, dest: PathBuf, error: String) -> Self {
+        FailedFile {
+            book: format!("{} - {}", audiobook.author, audiobook.title),
+            folder_id: audiobook.folder_id.clone(),
+            source,
+            dest,
+            error,
+        }
+    }
+}
+
+/// Outcome of exporting a single book
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BookStatus {
+    /// Every track was exported or already up to date
+    Ok,
+    /// Some tracks were exported, others are missing or failed
+    Partial,
+    /// No track could be exported
+    Failed,
+}
+
+impl BookStatus {
+    pub fn label(self) -> &'static str {
+        match self {
+            BookStatus::Ok => "ok",
+            BookStatus::Partial => "partial",
+            BookStatus::Failed => "failed",
+        }
+    }
+}
+
+/// Per-book results of an export run
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BookResult {
+    pub book: String,
+    pub folder_id: String,
+    /// Files written by this run
+    pub files: usize,
+    pub bytes: u64,
+    pub elapsed_secs: f64,
+    /// Tracks that are missing, not downloaded or failed to export
+    pub problems: usize,
+    pub status: BookStatus,
+}
+
+impl BookResult {
+    pub fn new(audiobook: &Audiobook) -> Self {
+        BookResult {
+            book: format!("{} - {}", audiobook.author, audiobook.title),
+            folder_id: audiobook.folder_id.clone(),
+            files: 0,
+            bytes: 0,
+            elapsed_secs: 0.0,
+            problems: 0,
+            status: BookStatus::Ok,
+        }
+    }
+
+    /// Set the status from the file and problem counts
+    pub fn finish(&mut self) {
+        self.status = if self.problems == 0 {
+            BookStatus::Ok
+        } else if self.files == 0 {
+            BookStatus::Failed
+        } else {
+            BookStatus::Partial
+        };
+    }
+}
+
+/// Summary of an export run, saved so that failed files can be retried later
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RunReport {
+    pub tool_version: String,
+    /// Unix timestamp (seconds) at which the run finished
+    pub finished_at: u64,
+    pub dest: PathBuf,
+    /// Command-line arguments of the run
+    #[serde(default)]
+    pub options: Vec<String>,
+    pub stats: ExportStats,
+}
+
+impl RunReport {
+    pub fn new(dest: &Path, stats: ExportStats) -> Self {
+        RunReport {
+            tool_version: env!("CARGO_PKG_VERSION").to_string(),
+            finished_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+            dest: dest.to_path_buf(),
+            options: std::env::args().skip(1).collect(),
+            stats,
+        }
+    }
+
+    /// Write the report as pretty-printed JSON, creating parent directories as needed
+    pub fn write(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory {:?}", parent))?;
+        }
+        let json = serde_json::to_string_pretty(self)?;
+        fs::write(path, json).with_context(|| format!("Failed to write report {:?}", path))
+    }
+
+    /// Read a report written by a previous run
+    pub fn read(path: &Path) -> Result<Self> {
+        let json = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read report {:?}", path))?;
+        serde_json::from_str(&json).with_context(|| format!("Invalid report file {:?}", path))
+    }
+}
+
+/// Where the report of the last run is saved unless `--report` is given
+pub fn default_report_path(dest: &Path) -> PathBuf {
+    dest.join(STATE_DIR).join("last-run.json")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_report_roundtrip() {
+        let dir = tempdir().unwrap();
+        let path = default_report_path(dir.path());
+
+        let stats = ExportStats {
+            files_copied: 3,
+            failures: vec![FailedFile {
+                book: "Test Book".to_string(),
+                folder_id: "sha1-abc".to_string(),
+                source: PathBuf::from("/src/01.mp3"),
+                dest: PathBuf::from("/dest/01.mp3"),
+                error: "disk full".to_string(),
+            }],
+            ..Default::default()
+        };
+        RunReport::new(dir.path(), stats).write(&path).unwrap();
+
+        let report = RunReport::read(&path).unwrap();
+        assert_eq!(report.stats.files_copied, 3);
+        assert_eq!(report.stats.failures.len(), 1);
+        assert_eq!(report.stats.failures[0].error, "disk full");
+    }
+}
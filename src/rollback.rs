@@ -0,0 +1,225 @@
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::history::run_id;
+use crate::report::STATE_DIR;
+
+/// Snapshots older than the newest few are removed when a new one is taken
+pub const KEEP_SNAPSHOTS: usize = 5;
+
+/// Where the snapshots of a destination are kept
+pub fn snapshots_dir(dest: &Path) -> PathBuf {
+    dest.join(STATE_DIR).join("snapshots")
+}
+
+/// Every file (and symlink) under `dir`, relative to it, skipping the state folder
+fn files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut dirs = vec![PathBuf::new()];
+    while let Some(relative) = dirs.pop() {
+        let path = dir.join(&relative);
+        for entry in fs::read_dir(&path).with_context(|| format!("Failed to read {:?}", path))? {
+            let entry = entry.with_context(|| format!("Failed to read {:?}", path))?;
+            let name = relative.join(entry.file_name());
+            if entry.file_type()?.is_dir() {
+                if entry.file_name() != STATE_DIR {
+                    dirs.push(name);
+                }
+            } else {
+                files.push(name);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Recreate `from` at `to` without copying data: symlinks are recreated, files hardlinked
+fn link(from: &Path, to: &Path) -> Result<()> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory {:?}", parent))?;
+    }
+    if fs::symlink_metadata(from)?.file_type().is_symlink() {
+        #[cfg(unix)]
+        {
+            let target = fs::read_link(from)?;
+            std::os::unix::fs::symlink(target, to)
+                .with_context(|| format!("Failed to symlink {:?}", to))?;
+            return Ok(());
+        }
+    }
+    fs::hard_link(from, to).with_context(|| format!("Failed to hardlink {:?} -> {:?}", to, from))
+}
+
+/// Snapshot the destination: a dated folder holding a hardlink to every file, so it takes
+/// no extra space until files are replaced. Exports remove an audio file before copying its
+/// replacement and rename rewritten files (tags, metadata, chapters, ...) into place, so they
+/// never write through a hardlink and the snapshot keeps the old contents. Returns the
+/// snapshot id.
+pub fn create(dest: &Path) -> Result<String> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let dir = snapshots_dir(dest);
+    let base = run_id(now);
+    let id = std::iter::once(base.clone())
+        .chain((2..).map(|n| format!("{}-{}", base, n)))
+        .find(|id| !dir.join(id).exists())
+        .expect("unbounded search always finds a free id");
+
+    let snapshot = dir.join(&id);
+    let linked = files(dest).and_then(|files| {
+        fs::create_dir_all(&snapshot)
+            .with_context(|| format!("Failed to create directory {:?}", snapshot))?;
+        files
+            .iter()
+            .try_for_each(|file| link(&dest.join(file), &snapshot.join(file)))
+    });
+    if let Err(e) = linked {
+        let _ = fs::remove_dir_all(&snapshot);
+        return Err(e.context(format!("Failed to snapshot {:?}", dest)));
+    }
+    Ok(id)
+}
+
+/// Snapshot ids, oldest first
+pub fn list(dest: &Path) -> Result<Vec<String>> {
+    let dir = snapshots_dir(dest);
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut ids = Vec::new();
+    for entry in fs::read_dir(&dir).with_context(|| format!("Failed to read {:?}", dir))? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            ids.push(entry.file_name().to_string_lossy().to_string());
+        }
+    }
+    ids.sort();
+    Ok(ids)
+}
+
+/// Remove all but the newest `keep` snapshots. Returns how many were removed.
+pub fn prune(dest: &Path, keep: usize) -> Result<usize> {
+    let ids = list(dest)?;
+    let old = ids.len().saturating_sub(keep);
+    for id in &ids[..old] {
+        let path = snapshots_dir(dest).join(id);
+        fs::remove_dir_all(&path).with_context(|| format!("Failed to remove {:?}", path))?;
+    }
+    Ok(old)
+}
+
+/// The snapshot with this id, or the only one whose id starts with it
+pub fn find(dest: &Path, id: &str) -> Result<String> {
+    let ids = list(dest)?;
+    if ids.iter().any(|snapshot| snapshot == id) {
+        return Ok(id.to_string());
+    }
+    let mut matches: Vec<_> = ids.into_iter().filter(|s| s.starts_with(id)).collect();
+    match matches.len() {
+        0 => bail!("No snapshot {:?} in {:?}", id, snapshots_dir(dest)),
+        1 => Ok(matches.remove(0)),
+        n => bail!("{:?} matches {} snapshots; use a longer id", id, n),
+    }
+}
+
+/// What restoring a snapshot did
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Restored {
+    /// Files put back because they were replaced or removed since the snapshot
+    pub restored: usize,
+    pub unchanged: usize,
+    /// Files added since the snapshot, which are left in place
+    pub added: usize,
+}
+
+#[cfg(unix)]
+fn same_file(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (fs::symlink_metadata(a), fs::symlink_metadata(b)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn same_file(_a: &Path, _b: &Path) -> bool {
+    false
+}
+
+/// Put back every file of a snapshot that was replaced or removed since it was taken
+pub fn restore(dest: &Path, id: &str) -> Result<Restored> {
+    let snapshot = snapshots_dir(dest).join(id);
+    let mut result = Restored::default();
+    let snapshot_files = files(&snapshot)?;
+    for file in &snapshot_files {
+        let (from, to) = (snapshot.join(file), dest.join(file));
+        if same_file(&from, &to) {
+            result.unchanged += 1;
+            continue;
+        }
+        if fs::symlink_metadata(&to).is_ok() {
+            fs::remove_file(&to).with_context(|| format!("Failed to remove {:?}", to))?;
+        }
+        link(&from, &to)?;
+        result.restored += 1;
+    }
+    result.added = files(dest)?
+        .iter()
+        .filter(|file| snapshot_files.binary_search(file).is_err())
+        .count();
+    Ok(result)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_snapshot_and_restore() {
+        let dir = tempdir().unwrap();
+        let dest = dir.path();
+        let book = dest.join("Author/Book");
+        fs::create_dir_all(&book).unwrap();
+        fs::write(book.join("01.mp3"), b"original").unwrap();
+        fs::write(book.join("02.mp3"), b"original").unwrap();
+
+        let id = create(dest).unwrap();
+        assert_eq!(list(dest).unwrap(), vec![id.clone()]);
+        assert_eq!(
+            fs::read(snapshots_dir(dest).join(&id).join("Author/Book/01.mp3")).unwrap(),
+            b"original"
+        );
+
+        // A bad sync replaces one file, removes another and adds a third
+        fs::remove_file(book.join("01.mp3")).unwrap();
+        fs::write(book.join("01.mp3"), b"broken").unwrap();
+        fs::remove_file(book.join("02.mp3")).unwrap();
+        fs::write(book.join("03.mp3"), b"new").unwrap();
+
+        let restored = restore(dest, &find(dest, &id[..8]).unwrap()).unwrap();
+        assert_eq!(
+            restored,
+            Restored {
+                restored: 2,
+                unchanged: 0,
+                added: 1
+            }
+        );
+        assert_eq!(fs::read(book.join("01.mp3")).unwrap(), b"original");
+        assert_eq!(fs::read(book.join("02.mp3")).unwrap(), b"original");
+
+        let again = restore(dest, &id).unwrap();
+        assert_eq!(again.unchanged, 2);
+
+        create(dest).unwrap();
+        assert_eq!(prune(dest, 1).unwrap(), 1);
+        assert_eq!(list(dest).unwrap().len(), 1);
+    }
+}
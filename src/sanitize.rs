@@ -0,0 +1,183 @@
+use anyhow::{bail, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::sync::OnceLock;
+
+/// Characters that can't (or shouldn't) appear in file and folder names on macOS, Windows
+/// shares and most NAS filesystems
+const UNSAFE: &[char] = &['/', '\\', ':', '*', '?', '"', '<', '>', '|'];
+
+/// What to do with a character that isn't allowed in filenames
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    /// Replace it with the replacement string ("_" by default)
+    #[default]
+    Replace,
+    /// Leave it out
+    Drop,
+    /// Replace it with a similar-looking Unicode character, e.g. ":" with "꞉"
+    Lookalike,
+}
+
+/// How names are turned into file and folder names, from the `filenames` section of the
+/// config file
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Sanitizer {
+    pub mode: Mode,
+    /// Used by the replace mode
+    pub replacement: String,
+    /// Replacements for single characters, taking precedence over the mode. Any character
+    /// can be mapped, e.g. `{":": " -", "&": "and"}`.
+    pub map: BTreeMap<char, String>,
+}
+
+impl Default for Sanitizer {
+    fn default() -> Self {
+        Sanitizer {
+            mode: Mode::Replace,
+            replacement: "_".to_string(),
+            map: BTreeMap::new(),
+        }
+    }
+}
+
+fn lookalike(c: char) -> char {
+    match c {
+        '/' => '∕',
+        '\\' => '⧵',
+        ':' => '꞉',
+        '*' => '∗',
+        '?' => '？',
+        '"' => '＂',
+        '<' => '‹',
+        '>' => '›',
+        '|' => 'ǀ',
+        _ => c,
+    }
+}
+
+impl Sanitizer {
+    /// Reject replacements that would themselves produce unsafe names
+    pub fn validate(&self) -> Result<()> {
+        let replacements = std::iter::once(&self.replacement).chain(self.map.values());
+        for replacement in replacements {
+            if replacement.contains(UNSAFE) {
+                bail!(
+                    "Filename replacement {:?} contains a character that isn't allowed in filenames",
+                    replacement
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// A file or folder name for `name`. Names that would end up empty (all characters
+    /// dropped), or as "." or "..", which would put a book folder into its parent or
+    /// outside it, become "_".
+    pub fn sanitize(&self, name: &str) -> String {
+        let sanitized = self.replace_unsafe(name);
+        if matches!(sanitized.as_str(), "." | "..")
+            || (sanitized.is_empty() && !name.trim().is_empty())
+        {
+            return "_".to_string();
+        }
+        sanitized
+    }
+
+    fn replace_unsafe(&self, name: &str) -> String {
+        let mut sanitized = String::with_capacity(name.len());
+        for c in name.chars() {
+            if let Some(replacement) = self.map.get(&c) {
+                sanitized.push_str(replacement);
+            } else if !UNSAFE.contains(&c) {
+                sanitized.push(c);
+            } else {
+                match self.mode {
+                    Mode::Replace => sanitized.push_str(&self.replacement),
+                    Mode::Drop => {}
+                    Mode::Lookalike => sanitized.push(lookalike(c)),
+                }
+            }
+        }
+
+        // Dropping or mapping characters can leave runs of spaces ("Book : Part"). Names
+        // are otherwise kept as they were, so existing exports keep their folder names.
+        if self.mode != Mode::Drop && self.map.is_empty() {
+            return sanitized.trim().to_string();
+        }
+        let mut collapsed = String::with_capacity(sanitized.len());
+        for c in sanitized.trim().chars() {
+            if !(c == ' ' && collapsed.ends_with(' ')) {
+                collapsed.push(c);
+            }
+        }
+        collapsed
+    }
+}
+
+static SANITIZER: OnceLock<Sanitizer> = OnceLock::new();
+
+/// Set the sanitizer once at startup; later calls have no effect
+pub fn init(sanitizer: Sanitizer) {
+    let _ = SANITIZER.set(sanitizer);
+}
+
+/// The sanitizer in effect
+pub fn current() -> &'static Sanitizer {
+    SANITIZER.get_or_init(Sanitizer::default)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_modes_and_map() {
+        let title = "Book: A Subtitle?";
+        let mut sanitizer = Sanitizer::default();
+        assert_eq!(sanitizer.sanitize(title), "Book_ A Subtitle_");
+
+        sanitizer.mode = Mode::Drop;
+        assert_eq!(sanitizer.sanitize(title), "Book A Subtitle");
+        assert_eq!(sanitizer.sanitize("Either / Or"), "Either Or");
+
+        sanitizer.mode = Mode::Lookalike;
+        assert_eq!(sanitizer.sanitize(title), "Book꞉ A Subtitle？");
+
+        sanitizer.map.insert(':', " -".to_string());
+        sanitizer.map.insert('&', "and".to_string());
+        assert_eq!(sanitizer.sanitize(title), "Book - A Subtitle？");
+        assert_eq!(sanitizer.sanitize("Salt & Pepper"), "Salt and Pepper");
+    }
+
+    #[test]
+    fn test_never_empty_or_dots() {
+        let mut sanitizer = Sanitizer {
+            mode: Mode::Drop,
+            ..Default::default()
+        };
+        assert_eq!(sanitizer.sanitize("???"), "_");
+        assert_eq!(sanitizer.sanitize(".?."), "_");
+        assert_eq!(sanitizer.sanitize(""), "");
+
+        sanitizer.map.insert('x', ".".to_string());
+        assert_eq!(sanitizer.sanitize("x"), "_");
+        assert_eq!(sanitizer.sanitize("xx"), "_");
+        assert_eq!(sanitizer.sanitize("xxx"), "...");
+        assert_eq!(Sanitizer::default().sanitize(".."), "_");
+    }
+
+    #[test]
+    fn test_config_and_validation() {
+        let sanitizer: Sanitizer =
+            serde_json::from_str(r#"{"mode": "drop", "map": {":": " -"}}"#).unwrap();
+        assert_eq!(sanitizer.mode, Mode::Drop);
+        assert_eq!(sanitizer.map[&':'], " -");
+        assert!(sanitizer.validate().is_ok());
+
+        let unsafe_map: Sanitizer = serde_json::from_str(r#"{"map": {":": "/"}}"#).unwrap();
+        assert!(unsafe_map.validate().is_err());
+    }
+}
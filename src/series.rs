@@ -0,0 +1,104 @@
+use regex::Regex;
+use std::sync::OnceLock;
+
+/// A series found in a book's title
+#[derive(Debug, Clone, PartialEq)]
+pub struct TitleSeries {
+    /// The title without the series part
+    pub title: String,
+    pub series: String,
+    pub sequence: String,
+}
+
+/// "Title (Series, Book 3)", "Title [Series #3]"
+fn bracketed() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(
+            r"(?i)^(?P<title>.+?)\s*[(\[](?P<series>[^()\[\]]+?),?\s*(?:(?:book|volume|vol\.?|part)\s+|#)(?P<sequence>\d+(?:\.\d+)?)[)\]]$",
+        )
+        .unwrap()
+    })
+}
+
+/// "Series, Book 3", "Title: Series, Book 3"
+fn trailing() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(
+            r"(?i)^(?P<series>.+?),\s*(?:book|volume|vol\.?|part)\s+(?P<sequence>\d+(?:\.\d+)?)$",
+        )
+        .unwrap()
+    })
+}
+
+/// A sequence number as written in a folder name: "03" becomes "3", "1.5" stays
+pub fn normalize_sequence(sequence: &str) -> String {
+    let sequence = sequence.trim();
+    let trimmed = sequence.trim_start_matches('0');
+    if trimmed.is_empty() || trimmed.starts_with('.') {
+        format!("0{}", trimmed)
+    } else {
+        trimmed.to_string()
+    }
+}
+
+/// Find the series in titles like "The Way of Kings: The Stormlight Archive, Book 1" or
+/// "Elantris (Cosmere #1)". Titles that are nothing but the series and a number, like
+/// "Harry Potter, Book 1", are kept whole.
+pub fn from_title(title: &str) -> Option<TitleSeries> {
+    let title = title.trim();
+    if let Some(captures) = bracketed().captures(title) {
+        return Some(TitleSeries {
+            title: captures["title"].trim().to_string(),
+            series: captures["series"].trim().to_string(),
+            sequence: normalize_sequence(&captures["sequence"]),
+        });
+    }
+    let captures = trailing().captures(title)?;
+    let series = captures["series"].trim();
+    let (title, series) = match series.split_once(": ") {
+        Some((name, series)) => (name.trim(), series.trim()),
+        None => (title, series),
+    };
+    Some(TitleSeries {
+        title: title.to_string(),
+        series: series.to_string(),
+        sequence: normalize_sequence(&captures["sequence"]),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_title() {
+        let found = from_title("The Way of Kings: The Stormlight Archive, Book 1").unwrap();
+        assert_eq!(found.title, "The Way of Kings");
+        assert_eq!(found.series, "The Stormlight Archive");
+        assert_eq!(found.sequence, "1");
+
+        let found = from_title("Elantris (Cosmere #01)").unwrap();
+        assert_eq!(
+            (
+                found.title.as_str(),
+                found.series.as_str(),
+                found.sequence.as_str()
+            ),
+            ("Elantris", "Cosmere", "1")
+        );
+
+        let found = from_title("Edgedancer [The Stormlight Archive, Book 2.5]").unwrap();
+        assert_eq!(found.title, "Edgedancer");
+        assert_eq!(found.sequence, "2.5");
+
+        let found = from_title("Harry Potter, Book 1").unwrap();
+        assert_eq!(found.title, "Harry Potter, Book 1");
+        assert_eq!(found.series, "Harry Potter");
+
+        assert_eq!(from_title("Project Hail Mary"), None);
+        assert_eq!(from_title("Catch-22"), None);
+        assert_eq!(normalize_sequence("0"), "0");
+    }
+}
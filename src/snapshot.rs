@@ -0,0 +1,237 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::i18n::{tr, tr_args};
+use crate::output::{paint, Color};
+use crate::Audiobook;
+
+/// The library as seen by an export run, kept so the next run can tell what changed
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct LibrarySnapshot {
+    pub source: PathBuf,
+    /// Unix timestamp (seconds) at which the snapshot was taken
+    pub taken_at: u64,
+    /// Books by Apple Books folder id
+    pub books: BTreeMap<String, SnapshotBook>,
+}
+
+/// Metadata of a single book in a snapshot
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SnapshotBook {
+    pub title: String,
+    pub author: String,
+    pub narrator: Option<String>,
+    pub tracks: usize,
+}
+
+impl SnapshotBook {
+    fn display_name(&self) -> String {
+        format!("{} - {}", self.author, self.title)
+    }
+}
+
+/// Differences between two snapshots
+#[derive(Debug, Default)]
+pub struct LibraryChanges {
+    pub added: Vec<SnapshotBook>,
+    pub removed: Vec<SnapshotBook>,
+    /// Book name (as of the new snapshot) and a description of each changed field
+    pub changed: Vec<(String, Vec<String>)>,
+}
+
+impl LibraryChanges {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+impl LibrarySnapshot {
+    pub fn from_library(source: &Path, audiobooks: &[Audiobook]) -> Self {
+        let books = audiobooks
+            .iter()
+            .map(|book| {
+                (
+                    book.folder_id.clone(),
+                    SnapshotBook {
+                        title: book.full_title(),
+                        author: book.author.clone(),
+                        narrator: book.narrator.clone(),
+                        tracks: book.tracks.len(),
+                    },
+                )
+            })
+            .collect();
+
+        LibrarySnapshot {
+            source: source.to_path_buf(),
+            taken_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+            books,
+        }
+    }
+
+    /// Read a snapshot, or `None` if no snapshot was recorded yet
+    pub fn read(path: &Path) -> Result<Option<Self>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let json = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read snapshot {:?}", path))?;
+        let snapshot = serde_json::from_str(&json)
+            .with_context(|| format!("Invalid snapshot file {:?}", path))?;
+        Ok(Some(snapshot))
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory {:?}", parent))?;
+        }
+        let json = serde_json::to_string_pretty(self)?;
+        fs::write(path, json).with_context(|| format!("Failed to write snapshot {:?}", path))
+    }
+
+    /// What changed between `self` (older) and `current`
+    pub fn changes_to(&self, current: &LibrarySnapshot) -> LibraryChanges {
+        let mut changes = LibraryChanges::default();
+
+        for (folder_id, book) in &current.books {
+            let Some(old) = self.books.get(folder_id) else {
+                changes.added.push(book.clone());
+                continue;
+            };
+
+            let mut fields = Vec::new();
+            if old.title != book.title {
+                fields.push(format!("title: {:?} -> {:?}", old.title, book.title));
+            }
+            if old.author != book.author {
+                fields.push(format!("author: {:?} -> {:?}", old.author, book.author));
+            }
+            if old.narrator != book.narrator {
+                fields.push(format!(
+                    "narrator: {:?} -> {:?}",
+                    old.narrator.as_deref().unwrap_or(""),
+                    book.narrator.as_deref().unwrap_or("")
+                ));
+            }
+            if old.tracks != book.tracks {
+                fields.push(format!("tracks: {} -> {}", old.tracks, book.tracks));
+            }
+            if !fields.is_empty() {
+                changes.changed.push((book.display_name(), fields));
+            }
+        }
+
+        changes.removed = self
+            .books
+            .iter()
+            .filter(|(folder_id, _)| !current.books.contains_key(*folder_id))
+            .map(|(_, book)| book.clone())
+            .collect();
+
+        changes
+    }
+}
+
+/// Where the library snapshot of the last export is kept
+pub fn default_snapshot_path() -> Option<PathBuf> {
+    dirs::data_local_dir().map(|dir| {
+        dir.join("extract_apple_books")
+            .join("library-snapshot.json")
+    })
+}
+
+/// Print newly purchased, removed and changed books
+pub fn display_changes(changes: &LibraryChanges) {
+    if changes.is_empty() {
+        println!("{}", tr("No changes since the last run."));
+        return;
+    }
+
+    let sections = [
+        ("+ NEW", Color::Green, &changes.added),
+        ("- REMOVED", Color::Red, &changes.removed),
+    ];
+    for (label, color, books) in sections {
+        if books.is_empty() {
+            continue;
+        }
+        println!(
+            "{} ({})",
+            paint(tr(label), color),
+            tr_args("{} books", &[&books.len()])
+        );
+        let mut names: Vec<_> = books.iter().map(|b| b.display_name()).collect();
+        names.sort();
+        for name in names {
+            println!("  {}", name);
+        }
+        println!();
+    }
+
+    if !changes.changed.is_empty() {
+        println!(
+            "{} ({})",
+            paint(tr("~ CHANGED"), Color::Yellow),
+            tr_args("{} books", &[&changes.changed.len()])
+        );
+        for (name, fields) in &changes.changed {
+            println!("  {}", name);
+            for field in fields {
+                println!("      {}", field);
+            }
+        }
+        println!();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn book(title: &str, narrator: Option<&str>, tracks: usize) -> SnapshotBook {
+        SnapshotBook {
+            title: title.to_string(),
+            author: "Author".to_string(),
+            narrator: narrator.map(str::to_string),
+            tracks,
+        }
+    }
+
+    #[test]
+    fn test_changes_to() {
+        let mut old = LibrarySnapshot::default();
+        old.books.insert("a".to_string(), book("Kept", None, 3));
+        old.books.insert("b".to_string(), book("Gone", None, 1));
+        old.books.insert("c".to_string(), book("Fixed", None, 10));
+
+        let mut current = LibrarySnapshot::default();
+        current.books.insert("a".to_string(), book("Kept", None, 3));
+        current
+            .books
+            .insert("c".to_string(), book("Fixed", Some("Reader"), 12));
+        current
+            .books
+            .insert("d".to_string(), book("Bought", None, 5));
+
+        let changes = old.changes_to(&current);
+        assert_eq!(changes.added, vec![book("Bought", None, 5)]);
+        assert_eq!(changes.removed, vec![book("Gone", None, 1)]);
+        assert_eq!(changes.changed.len(), 1);
+        assert_eq!(changes.changed[0].0, "Author - Fixed");
+        assert_eq!(
+            changes.changed[0].1,
+            vec![
+                "narrator: \"\" -> \"Reader\"".to_string(),
+                "tracks: 10 -> 12".to_string()
+            ]
+        );
+    }
+}
@@ -0,0 +1,206 @@
+use clap::ValueEnum;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+use crate::{format_bytes, media, remap_track_path, truncate, Audiobook};
+
+/// How to break down library statistics
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum GroupBy {
+    Author,
+    /// Genre tag of the first track
+    Genre,
+    /// Release year from the date tag of the first track
+    Year,
+}
+
+/// What a book contributes to the statistics
+#[derive(Debug, Clone, PartialEq)]
+pub struct BookInfo {
+    pub author: String,
+    pub title: String,
+    pub tracks: usize,
+    pub genre: Option<String>,
+    pub year: Option<String>,
+    pub bytes: u64,
+    /// Total duration, or 0 if it couldn't be probed
+    pub seconds: f64,
+}
+
+/// Size of the book's source files, and its duration from the audio files. Genre and year
+/// come from the library, or else from the tags of the first track. Duration and tags need
+/// ffprobe and are left empty without it.
+pub fn book_info(book: &Audiobook, source_base: &Path) -> BookInfo {
+    let sources: Vec<_> = book
+        .tracks
+        .iter()
+        .map(|t| remap_track_path(&t.path, source_base))
+        .collect();
+
+    let details = &book.details;
+    let tags = sources
+        .first()
+        .filter(|_| details.genre.is_none() || details.year.is_none())
+        .and_then(|path| media::probe_format_tags(path).ok())
+        .unwrap_or_default();
+    let tag = |name: &str| media::find_tag(&tags, name).map(str::to_string);
+    let year = details.year.clone().or_else(|| {
+        tag("date")
+            .or_else(|| tag("year"))
+            .and_then(|date| date.get(..4).map(str::to_string))
+            .filter(|year| year.chars().all(|c| c.is_ascii_digit()))
+    });
+
+    BookInfo {
+        author: book.author.clone(),
+        title: book.title.clone(),
+        tracks: book.tracks.len(),
+        genre: details.genre.clone().or_else(|| tag("genre")),
+        year,
+        bytes: sources
+            .iter()
+            .filter_map(|path| fs::metadata(path).ok())
+            .map(|m| m.len())
+            .sum(),
+        seconds: sources
+            .iter()
+            .filter_map(|path| media::probe_duration(path).ok())
+            .sum(),
+    }
+}
+
+/// Number of books and tracks, their size and duration
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Totals {
+    pub books: usize,
+    pub tracks: usize,
+    pub bytes: u64,
+    pub seconds: f64,
+}
+
+impl Totals {
+    fn add(&mut self, info: &BookInfo) {
+        self.books += 1;
+        self.tracks += info.tracks;
+        self.bytes += info.bytes;
+        self.seconds += info.seconds;
+    }
+}
+
+/// Totals per group, largest first. Books without the grouped value count as "Unknown".
+pub fn group(infos: &[BookInfo], by: GroupBy) -> Vec<(String, Totals)> {
+    let mut groups: BTreeMap<String, Totals> = BTreeMap::new();
+    for info in infos {
+        let key = match by {
+            GroupBy::Author => Some(info.author.clone()),
+            GroupBy::Genre => info.genre.clone(),
+            GroupBy::Year => info.year.clone(),
+        };
+        groups
+            .entry(key.unwrap_or_else(|| "Unknown".to_string()))
+            .or_default()
+            .add(info);
+    }
+
+    let mut rows: Vec<_> = groups.into_iter().collect();
+    rows.sort_by(|(a, x), (b, y)| y.bytes.cmp(&x.bytes).then_with(|| a.cmp(b)));
+    rows
+}
+
+impl Totals {
+    /// Average size of a book, 0 without books
+    pub fn average_bytes(&self) -> u64 {
+        self.bytes.checked_div(self.books as u64).unwrap_or(0)
+    }
+}
+
+/// The `count` largest books, largest first
+pub fn largest(infos: &[BookInfo], count: usize) -> Vec<&BookInfo> {
+    let mut books: Vec<_> = infos.iter().collect();
+    books.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.title.cmp(&b.title)));
+    books.truncate(count);
+    books
+}
+
+/// Sum of all books
+pub fn total(infos: &[BookInfo]) -> Totals {
+    let mut totals = Totals::default();
+    for info in infos {
+        totals.add(info);
+    }
+    totals
+}
+
+/// Write rows of totals as a table headed by `label`
+pub fn write_table(out: &mut impl Write, label: &str, rows: &[(String, Totals)]) -> io::Result<()> {
+    writeln!(
+        out,
+        "{:<40} {:>6} {:>10} {:>8}",
+        label, "Books", "Size", "Hours"
+    )?;
+    for (name, totals) in rows {
+        writeln!(
+            out,
+            "{:<40} {:>6} {:>10} {:>8.1}",
+            truncate(name, 40),
+            totals.books,
+            format_bytes(totals.bytes),
+            totals.seconds / 3600.0
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(author: &str, genre: Option<&str>, bytes: u64, hours: f64) -> BookInfo {
+        BookInfo {
+            author: author.to_string(),
+            title: format!("{} {}", author, bytes),
+            tracks: 2,
+            genre: genre.map(str::to_string),
+            year: None,
+            bytes,
+            seconds: hours * 3600.0,
+        }
+    }
+
+    #[test]
+    fn test_group() {
+        let infos = vec![
+            info("Sanderson", Some("Fantasy"), 300, 10.0),
+            info("Weir", Some("Science Fiction"), 500, 16.0),
+            info("Sanderson", None, 400, 12.0),
+        ];
+
+        let by_author = group(&infos, GroupBy::Author);
+        assert_eq!(by_author[0].0, "Sanderson");
+        assert_eq!(
+            by_author[0].1,
+            Totals {
+                books: 2,
+                tracks: 4,
+                bytes: 700,
+                seconds: 22.0 * 3600.0
+            }
+        );
+        assert_eq!(by_author[1].0, "Weir");
+
+        let genres: Vec<_> = group(&infos, GroupBy::Genre)
+            .into_iter()
+            .map(|(genre, _)| genre)
+            .collect();
+        assert_eq!(genres, vec!["Science Fiction", "Unknown", "Fantasy"]);
+        assert_eq!(group(&infos, GroupBy::Year)[0].1.books, 3);
+        assert_eq!(total(&infos).bytes, 1200);
+        assert_eq!(total(&infos).average_bytes(), 400);
+        assert_eq!(total(&[]).average_bytes(), 0);
+
+        let titles: Vec<_> = largest(&infos, 2).iter().map(|i| &i.title).collect();
+        assert_eq!(titles, ["Weir 500", "Sanderson 400"]);
+    }
+}
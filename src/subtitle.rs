@@ -0,0 +1,83 @@
+use serde::Deserialize;
+use std::sync::OnceLock;
+
+/// How titles are split into a title and a subtitle, from the `subtitles` section of the
+/// config file
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Subtitles {
+    /// Separators between a title and its subtitle, tried in order at their first
+    /// occurrence in the title. Empty to keep titles whole.
+    pub separators: Vec<String>,
+    /// Keep the subtitle in book folder names; without it, "Title: A Very Long Subtitle"
+    /// is exported to a folder named after the title alone
+    pub in_folder_names: bool,
+}
+
+impl Default for Subtitles {
+    fn default() -> Self {
+        Subtitles {
+            separators: vec![": ".to_string()],
+            in_folder_names: true,
+        }
+    }
+}
+
+impl Subtitles {
+    /// The title and the subtitle after the first separator found, or the whole title if
+    /// it has none (or nothing on one side of it)
+    pub fn split(&self, title: &str) -> (String, Option<String>) {
+        for separator in self.separators.iter().filter(|s| !s.is_empty()) {
+            if let Some((main, subtitle)) = title.split_once(separator.as_str()) {
+                let (main, subtitle) = (main.trim(), subtitle.trim());
+                if !main.is_empty() && !subtitle.is_empty() {
+                    return (main.to_string(), Some(subtitle.to_string()));
+                }
+            }
+        }
+        (title.to_string(), None)
+    }
+}
+
+static SUBTITLES: OnceLock<Subtitles> = OnceLock::new();
+
+/// Set the subtitle rule once at startup; later calls have no effect
+pub fn init(subtitles: Subtitles) {
+    let _ = SUBTITLES.set(subtitles);
+}
+
+/// The subtitle rule in effect
+pub fn current() -> &'static Subtitles {
+    SUBTITLES.get_or_init(Subtitles::default)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split() {
+        let mut subtitles = Subtitles::default();
+        assert_eq!(
+            subtitles.split("Sapiens: A Brief History of Humankind"),
+            (
+                "Sapiens".to_string(),
+                Some("A Brief History of Humankind".to_string())
+            )
+        );
+        assert_eq!(
+            subtitles.split("Project Hail Mary"),
+            ("Project Hail Mary".to_string(), None)
+        );
+        assert_eq!(subtitles.split("Re: "), ("Re: ".to_string(), None));
+
+        subtitles.separators = vec![" - ".to_string(), ": ".to_string()];
+        assert_eq!(
+            subtitles.split("Dune - Part One: Arrakis").1.as_deref(),
+            Some("Part One: Arrakis")
+        );
+
+        subtitles.separators.clear();
+        assert_eq!(subtitles.split("Sapiens: A Brief History").1, None);
+    }
+}
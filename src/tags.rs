@@ -0,0 +1,470 @@
+use anyhow::{bail, Context, Result};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use crate::{
+    media, narrator, remap_track_path, series, subtitle, AudioTrack, Audiobook, UNKNOWN_AUTHOR,
+    UNKNOWN_TITLE,
+};
+
+/// iTunes store tags identifying the purchasing account (as exposed by ffprobe)
+const PERSONAL_TAG_KEYS: &[&str] = &[
+    "apid",
+    "account_id",
+    "akid",
+    "account_type",
+    "ownr",
+    "owner",
+    "purd",
+    "purchase_date",
+    "sfid",
+    "cnid",
+    "atid",
+    "plid",
+    "geid",
+    "xid",
+    "itunes_account",
+    "itunes_purchase",
+];
+
+/// Whether a tag identifies the Apple account or purchase
+pub fn is_personal_tag(key: &str) -> bool {
+    let key = key.to_lowercase();
+    PERSONAL_TAG_KEYS.contains(&key.as_str()) || key.contains("email")
+}
+
+/// Whether a file is an MP4-family container that can carry iTunes store atoms
+pub fn has_store_atoms(path: &Path) -> bool {
+    path.extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .is_some_and(|e| matches!(e.as_str(), "m4a" | "m4b" | "mp4" | "aac"))
+}
+
+/// Tag values written onto an exported file
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TagSet {
+    pub album: String,
+    pub artist: String,
+    pub album_artist: String,
+    pub composer: Option<String>,
+    pub title: Option<String>,
+    pub track: u32,
+    pub track_total: u32,
+    pub disc: Option<u32>,
+}
+
+/// Strip store decorations like "(Unabridged)" from the end of a title
+pub fn clean_title(title: &str) -> String {
+    const SUFFIXES: &[&str] = &[
+        "(unabridged)",
+        "[unabridged]",
+        "(abridged)",
+        "[abridged]",
+        "(unabridged edition)",
+    ];
+
+    let mut cleaned = title.trim();
+    while let Some(suffix) = SUFFIXES.iter().find(|s| {
+        let split = cleaned.len().wrapping_sub(s.len());
+        cleaned.is_char_boundary(split) && cleaned[split..].eq_ignore_ascii_case(s)
+    }) {
+        cleaned = cleaned[..cleaned.len() - suffix.len()].trim_end();
+    }
+
+    if cleaned.is_empty() {
+        title.trim().to_string()
+    } else {
+        cleaned.to_string()
+    }
+}
+
+/// Fill in what Books.plist doesn't know about a book from the tags of its first track:
+/// the title from the album, the author from the album artist or artist, and the
+/// narrator from the composer. Returns whether anything changed.
+pub fn fill_missing(book: &mut Audiobook, tags: &BTreeMap<String, String>) -> bool {
+    let tag = |names: &[&str]| {
+        names
+            .iter()
+            .find_map(|name| media::find_tag(tags, name))
+            .map(str::to_string)
+    };
+    let mut changed = false;
+    if book.title == UNKNOWN_TITLE {
+        if let Some(mut title) = tag(&["album", "title"]).map(|title| clean_title(&title)) {
+            // The same notes as in Books.plist titles
+            if let Some((without_note, named)) = narrator::from_title(&title) {
+                title = without_note;
+                book.narrator = book.narrator.take().or(Some(named));
+            }
+            if book.series.is_none() {
+                if let Some(found) = series::from_title(&title) {
+                    book.title_in_series = Some(found.title);
+                    book.series = Some(found.series);
+                    book.series_sequence = Some(found.sequence);
+                    book.series_detected = true;
+                }
+            }
+            (book.title, book.subtitle) = subtitle::current().split(&title);
+            changed = true;
+        }
+    }
+    if book.author == UNKNOWN_AUTHOR {
+        if let Some(author) = tag(&["album_artist", "artist"]) {
+            book.author = author;
+            changed = true;
+        }
+    }
+    if book.narrator.is_none() {
+        if let Some(narrator) = tag(&["narrator", "composer"]) {
+            book.narrator = Some(narrator);
+            changed = true;
+        }
+    }
+    changed
+}
+
+/// Read the tags of the books Books.plist has no title or author for, as is common for
+/// sideloaded books, and fill in what they have (requires ffprobe; books stay as they are
+/// without it). Returns how many books changed.
+pub fn fill_from_tags(audiobooks: &mut [Audiobook], source_base: &Path) -> usize {
+    let mut changed = 0;
+    for book in audiobooks
+        .iter_mut()
+        .filter(|book| book.title == UNKNOWN_TITLE || book.author == UNKNOWN_AUTHOR)
+    {
+        let Some(first) = book.tracks.first() else {
+            continue;
+        };
+        if let Ok(tags) = media::probe_format_tags(&remap_track_path(&first.path, source_base)) {
+            if fill_missing(book, &tags) {
+                changed += 1;
+            }
+        }
+    }
+    changed
+}
+
+impl TagSet {
+    /// Resolve the tags for the `index`-th track (0-based, in export order) of a book.
+    /// With `continuous`, tracks are numbered 1..N across all discs.
+    pub fn for_track(
+        audiobook: &Audiobook,
+        track: &AudioTrack,
+        index: usize,
+        continuous: bool,
+    ) -> Self {
+        let title = if track.title.trim().is_empty() {
+            None
+        } else {
+            Some(track.title.trim().to_string())
+        };
+
+        TagSet {
+            album: clean_title(&audiobook.full_title()),
+            artist: audiobook.author.clone(),
+            album_artist: audiobook.author.clone(),
+            composer: audiobook.narrator.clone(),
+            title,
+            ..Self::numbering(audiobook, track, index, continuous)
+        }
+    }
+
+    /// Only the track/disc numbering of a track, leaving all other tags untouched
+    pub fn numbering(
+        audiobook: &Audiobook,
+        track: &AudioTrack,
+        index: usize,
+        continuous: bool,
+    ) -> Self {
+        let (track_number, disc) = if continuous {
+            (index as u32 + 1, Some(1))
+        } else if track.track_number > 0 {
+            (
+                track.track_number,
+                (track.disc_number > 0).then_some(track.disc_number),
+            )
+        } else {
+            (
+                index as u32 + 1,
+                (track.disc_number > 0).then_some(track.disc_number),
+            )
+        };
+
+        TagSet {
+            track: track_number,
+            track_total: audiobook.tracks.len() as u32,
+            disc,
+            ..Default::default()
+        }
+    }
+
+    /// ffmpeg `-metadata key=value` pairs for these tags; empty values are left untouched
+    fn metadata_pairs(&self) -> Vec<(&'static str, String)> {
+        let mut pairs: Vec<_> = [
+            ("album", &self.album),
+            ("artist", &self.artist),
+            ("album_artist", &self.album_artist),
+        ]
+        .into_iter()
+        .filter(|(_, value)| !value.is_empty())
+        .map(|(key, value)| (key, value.clone()))
+        .collect();
+        pairs.push(("track", format!("{}/{}", self.track, self.track_total)));
+        if let Some(composer) = &self.composer {
+            pairs.push(("composer", composer.clone()));
+        }
+        if let Some(title) = &self.title {
+            pairs.push(("title", title.clone()));
+        }
+        if let Some(disc) = self.disc {
+            pairs.push(("disc", disc.to_string()));
+        }
+        pairs
+    }
+}
+
+/// Rewrite the tags of an exported file in place (streams are copied, not re-encoded)
+pub fn rewrite_tags(path: &Path, tags: &TagSet) -> Result<()> {
+    let extension = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let partial = path.with_extension(format!("partial.{}", extension));
+
+    let mut command = Command::new("ffmpeg");
+    command
+        .args(["-v", "error", "-nostdin", "-y", "-i"])
+        .arg(path)
+        .args(["-map", "0", "-c", "copy", "-map_metadata", "0"]);
+    for (key, value) in tags.metadata_pairs() {
+        command.arg("-metadata").arg(format!("{}={}", key, value));
+    }
+    if extension.eq_ignore_ascii_case("mp3") {
+        command.args(["-id3v2_version", "3"]);
+    }
+
+    let output = command
+        .arg(&partial)
+        .output()
+        .context("Failed to run ffmpeg (is ffmpeg installed?)")?;
+
+    if !output.status.success() {
+        let _ = fs::remove_file(&partial);
+        bail!(
+            "ffmpeg failed to retag {:?}: {}",
+            path,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    fs::rename(&partial, path)
+        .with_context(|| format!("Failed to move retagged {:?} into place", path))
+}
+
+/// Embed a cover image into an exported audio file in place, replacing any artwork it
+/// has: an MP4 `covr` atom or an ID3 `APIC` frame. Streams are copied, not re-encoded.
+pub fn embed_cover(path: &Path, image: &Path) -> Result<()> {
+    let extension = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let partial = path.with_extension(format!("partial.{}", extension));
+
+    let mut command = Command::new("ffmpeg");
+    command
+        .args(["-v", "error", "-nostdin", "-y", "-i"])
+        .arg(path)
+        .arg("-i")
+        .arg(image)
+        .args([
+            "-map",
+            "0:a",
+            "-map",
+            "1:v",
+            "-c",
+            "copy",
+            "-map_metadata",
+            "0",
+        ])
+        .args(["-disposition:v:0", "attached_pic"]);
+    if extension.eq_ignore_ascii_case("mp3") {
+        command.args(["-id3v2_version", "3"]);
+    }
+
+    let output = command
+        .arg(&partial)
+        .output()
+        .context("Failed to run ffmpeg (is ffmpeg installed?)")?;
+
+    if !output.status.success() {
+        let _ = fs::remove_file(&partial);
+        bail!(
+            "ffmpeg failed to embed {:?} in {:?}: {}",
+            image,
+            path,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    fs::rename(&partial, path)
+        .with_context(|| format!("Failed to move {:?} with its cover into place", path))
+}
+
+/// Remove account-identifying tags and store atoms (apID, ownr, purchase receipts, ...)
+/// from an exported m4b/m4a in place. The file is remuxed keeping only non-personal tags,
+/// which also drops store atoms that ffmpeg doesn't expose as tags. Returns the number of
+/// personal tags that were found.
+pub fn strip_personal_tags(path: &Path) -> Result<usize> {
+    let existing = media::probe_format_tags(path)?;
+    let (personal, kept): (Vec<_>, Vec<_>) = existing
+        .into_iter()
+        .partition(|(key, _)| is_personal_tag(key));
+
+    let extension = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let partial = path.with_extension(format!("partial.{}", extension));
+
+    let mut command = Command::new("ffmpeg");
+    command
+        .args(["-v", "error", "-nostdin", "-y", "-i"])
+        .arg(path)
+        .args(["-map", "0", "-c", "copy", "-map_metadata", "-1"])
+        .args(["-map_chapters", "0"]);
+    for (key, value) in &kept {
+        command.arg("-metadata").arg(format!("{}={}", key, value));
+    }
+
+    let output = command
+        .arg(&partial)
+        .output()
+        .context("Failed to run ffmpeg (is ffmpeg installed?)")?;
+
+    if !output.status.success() {
+        let _ = fs::remove_file(&partial);
+        bail!(
+            "ffmpeg failed to strip tags from {:?}: {}",
+            path,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    fs::rename(&partial, path)
+        .with_context(|| format!("Failed to move stripped {:?} into place", path))?;
+
+    Ok(personal.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_clean_title() {
+        assert_eq!(
+            clean_title("Children of Time (Unabridged)"),
+            "Children of Time"
+        );
+        assert_eq!(clean_title("Dune [Unabridged] "), "Dune");
+        assert_eq!(clean_title("Project Hail Mary"), "Project Hail Mary");
+        assert_eq!(clean_title("(Unabridged)"), "(Unabridged)");
+    }
+
+    #[test]
+    fn test_fill_missing() {
+        let mut book = Audiobook {
+            title: UNKNOWN_TITLE.to_string(),
+            author: UNKNOWN_AUTHOR.to_string(),
+            folder_id: "sha1-abc".to_string(),
+            ..Default::default()
+        };
+        let tags: BTreeMap<_, _> = [
+            ("ALBUM", "Leviathan Wakes (The Expanse #1) (Unabridged)"),
+            ("title", "Chapter 1"),
+            ("artist", "James S. A. Corey"),
+            ("composer", "Jefferson Mays"),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+
+        assert!(fill_missing(&mut book, &tags));
+        assert_eq!(book.title, "Leviathan Wakes (The Expanse #1)");
+        assert_eq!(book.title_in_series.as_deref(), Some("Leviathan Wakes"));
+        assert_eq!(book.series.as_deref(), Some("The Expanse"));
+        assert_eq!(book.series_sequence.as_deref(), Some("1"));
+        assert_eq!(book.author, "James S. A. Corey");
+        assert_eq!(book.narrator.as_deref(), Some("Jefferson Mays"));
+        assert!(!fill_missing(&mut book, &tags));
+
+        // What the library has wins
+        let mut book = Audiobook {
+            title: "Dune".to_string(),
+            author: "Frank Herbert".to_string(),
+            narrator: None,
+            series: None,
+            series_sequence: None,
+            ..book
+        };
+        assert!(fill_missing(&mut book, &tags));
+        assert_eq!(book.title, "Dune");
+        assert_eq!(book.author, "Frank Herbert");
+        assert_eq!(book.narrator.as_deref(), Some("Jefferson Mays"));
+        assert_eq!(book.series, None);
+    }
+
+    #[test]
+    fn test_is_personal_tag() {
+        assert!(is_personal_tag("apID"));
+        assert!(is_personal_tag("ownr"));
+        assert!(is_personal_tag("account_type"));
+        assert!(is_personal_tag("purchase_date"));
+        assert!(is_personal_tag("account_email"));
+        assert!(!is_personal_tag("title"));
+        assert!(!is_personal_tag("album_artist"));
+        assert!(has_store_atoms(Path::new("book.M4B")));
+        assert!(!has_store_atoms(Path::new("track.mp3")));
+    }
+
+    #[test]
+    fn test_tags_for_track() {
+        let track = AudioTrack {
+            track_number: 0,
+            disc_number: 2,
+            title: "Part One".to_string(),
+            path: PathBuf::from("/x/01.mp3"),
+            filename: "01.mp3".to_string(),
+        };
+        let book = Audiobook {
+            title: "The Book (Unabridged)".to_string(),
+            author: "Jane Smith".to_string(),
+            narrator: Some("Bob Reader".to_string()),
+            folder_id: "sha1-abc".to_string(),
+            tracks: vec![track.clone(), track.clone()],
+            ..Default::default()
+        };
+
+        let tags = TagSet::for_track(&book, &track, 1, false);
+        assert_eq!(tags.album, "The Book");
+        assert_eq!(tags.album_artist, "Jane Smith");
+        assert_eq!(tags.composer.as_deref(), Some("Bob Reader"));
+        assert_eq!(tags.track, 2);
+        assert_eq!(tags.track_total, 2);
+        assert_eq!(tags.disc, Some(2));
+        assert!(tags
+            .metadata_pairs()
+            .contains(&("track", "2/2".to_string())));
+
+        let numbering = TagSet::numbering(&book, &track, 1, true);
+        assert_eq!(numbering.track, 2);
+        assert_eq!(numbering.disc, Some(1));
+        assert_eq!(
+            numbering.metadata_pairs(),
+            vec![("track", "2/2".to_string()), ("disc", "1".to_string())]
+        );
+    }
+}
@@ -0,0 +1,616 @@
+use anyhow::{anyhow, bail, Result};
+use clap::ValueEnum;
+use serde::Deserialize;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::{chapters, sanitize_filename, subtitle, Audiobook, ExportOptions};
+
+/// Placeholders a folder template can use
+pub const FIELDS: &[&str] = &[
+    "author",
+    "title",
+    "title_in_series",
+    "subtitle",
+    "series",
+    "sequence",
+    "narrator",
+    "year",
+    "genre",
+    "publisher",
+    "asin",
+    "isbn",
+    "folder_id",
+];
+
+/// Placeholders a track filename template can use besides the book's
+pub const TRACK_FIELDS: &[&str] = &["disc", "track", "track_title", "filename", "ext"];
+
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Text(String),
+    /// A value, zero-padded to `width` digits if it is a number
+    Field {
+        name: &'static str,
+        width: Option<usize>,
+    },
+    /// Left out unless every field in it has a value
+    Optional(Vec<Part>),
+    /// Kept only if the field has a value, or with `negate` only if it hasn't
+    If {
+        field: &'static str,
+        negate: bool,
+        parts: Vec<Part>,
+    },
+}
+
+/// A layout for book folders like `{author}/{?series}{series}/{/series}{title}`. `/`
+/// separates folders and `{field}` is a book's value. `{?field}...{/field}` is kept only
+/// when the field has a value and `{!field}...{/field}` only when it hasn't, `[...]` is
+/// left out when a field in it has no value, and `{{`, `}}`, `[[` and `]]` are literal
+/// braces and brackets.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PathTemplate {
+    source: String,
+    parts: Vec<Part>,
+}
+
+/// Where a run of parts ends
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Close {
+    End,
+    Bracket,
+    Block(&'static str),
+}
+
+/// Fields a template can name
+type Fields<'a> = &'a [&'static [&'static str]];
+
+fn field(fields: Fields, name: &str) -> Result<&'static str> {
+    fields
+        .iter()
+        .flat_map(|fields| fields.iter())
+        .find(|field| **field == name)
+        .copied()
+        .ok_or_else(|| {
+            anyhow!(
+                "Unknown field {{{}}} in the template (known: {})",
+                name,
+                fields.concat().join(", ")
+            )
+        })
+}
+
+fn parse_parts(chars: &[char], pos: &mut usize, fields: Fields, close: Close) -> Result<Vec<Part>> {
+    let mut parts = Vec::new();
+    let mut text = String::new();
+    while *pos < chars.len() {
+        let c = chars[*pos];
+        let next = chars.get(*pos + 1).copied();
+        match c {
+            '{' | '}' | '[' | ']' if next == Some(c) => {
+                text.push(c);
+                *pos += 2;
+                continue;
+            }
+            '{' => {
+                let end = chars[*pos..]
+                    .iter()
+                    .position(|&c| c == '}')
+                    .ok_or_else(|| anyhow!("Unclosed {{ in the template"))?;
+                let name: String = chars[*pos + 1..*pos + end].iter().collect();
+                *pos += end + 1;
+                parts.push(Part::Text(std::mem::take(&mut text)));
+                if let Some(name) = name.strip_prefix('/') {
+                    if close != Close::Block(field(fields, name)?) {
+                        bail!(
+                            "{{/{}}} in the template closes no {{?{}}} block",
+                            name,
+                            name
+                        );
+                    }
+                    return Ok(parts);
+                }
+                let negate = match name.chars().next() {
+                    Some('?') => false,
+                    Some('!') => true,
+                    _ => {
+                        let (name, width) = match name.split_once(':') {
+                            Some((name, width)) => {
+                                let width = width.parse().map_err(|_| {
+                                    anyhow!(
+                                        "{{{}:{}}} in the template needs a number of digits, \
+                                         e.g. {{{}:02}}",
+                                        name,
+                                        width,
+                                        name
+                                    )
+                                })?;
+                                (name, Some(width))
+                            }
+                            None => (name.as_str(), None),
+                        };
+                        parts.push(Part::Field {
+                            name: field(fields, name)?,
+                            width,
+                        });
+                        continue;
+                    }
+                };
+                let field = field(fields, &name[1..])?;
+                let block = parse_parts(chars, pos, fields, Close::Block(field))?;
+                parts.push(Part::If {
+                    field,
+                    negate,
+                    parts: block,
+                });
+                continue;
+            }
+            '}' => bail!("Unmatched }} in the template (write }}}} for a literal one)"),
+            '[' => {
+                *pos += 1;
+                parts.push(Part::Text(std::mem::take(&mut text)));
+                parts.push(Part::Optional(parse_parts(
+                    chars,
+                    pos,
+                    fields,
+                    Close::Bracket,
+                )?));
+                continue;
+            }
+            ']' if close == Close::Bracket => {
+                *pos += 1;
+                parts.push(Part::Text(text));
+                return Ok(parts);
+            }
+            ']' => bail!("Unmatched ] in the template (write ]] for a literal one)"),
+            _ => text.push(c),
+        }
+        *pos += 1;
+    }
+    match close {
+        Close::End => {}
+        Close::Bracket => bail!("Unclosed [ in the template"),
+        Close::Block(field) => bail!("Unclosed {{?{}}} block in the template", field),
+    }
+    parts.push(Part::Text(text));
+    Ok(parts)
+}
+
+/// Whether the parts use one of `fields` anywhere
+fn names_field(parts: &[Part], fields: &[&str]) -> bool {
+    parts.iter().any(|part| match part {
+        Part::Text(_) => false,
+        Part::Field { name, .. } => fields.contains(name),
+        Part::If { field, parts, .. } => fields.contains(field) || names_field(parts, fields),
+        Part::Optional(parts) => names_field(parts, fields),
+    })
+}
+
+/// Whether any literal text of the parts contains `c`
+fn text_contains(parts: &[Part], c: char) -> bool {
+    parts.iter().any(|part| match part {
+        Part::Text(text) => text.contains(c),
+        Part::Field { .. } => false,
+        Part::If { parts, .. } | Part::Optional(parts) => text_contains(parts, c),
+    })
+}
+
+impl FromStr for PathTemplate {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let chars: Vec<char> = s.chars().collect();
+        let parts = parse_parts(&chars, &mut 0, &[FIELDS], Close::End)?;
+        if !names_field(&parts, FIELDS) {
+            bail!("The template names no field, so every book would get the same folder");
+        }
+        Ok(PathTemplate {
+            source: s.to_string(),
+            parts,
+        })
+    }
+}
+
+impl fmt::Display for PathTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+/// Clap value parser for `--template`
+pub fn parse_template(s: &str) -> Result<PathTemplate> {
+    s.parse()
+}
+
+/// A book's value for a field
+fn book_value(book: &Audiobook, options: &ExportOptions, field: &str) -> Option<String> {
+    match field {
+        "author" => Some(options.author_folder.name(book, options.author_format)),
+        "title" if subtitle::current().in_folder_names => Some(book.full_title()),
+        "title" => Some(book.title.clone()),
+        "title_in_series" => match &book.title_in_series {
+            Some(title) if subtitle::current().in_folder_names => Some(title.clone()),
+            Some(title) => Some(subtitle::current().split(title).0),
+            None => book_value(book, options, "title"),
+        },
+        "subtitle" => book.subtitle.clone(),
+        "series" => book.series.clone(),
+        "sequence" => book.series_sequence.clone(),
+        "narrator" => Some(book.narrators().join(", ")),
+        "year" => book.details.year.clone(),
+        "genre" => book.details.genre.clone(),
+        "publisher" => book.details.publisher.clone(),
+        "asin" => book.details.asin.clone(),
+        "isbn" => book.details.isbn.clone(),
+        "folder_id" => Some(book.folder_id.clone()),
+        _ => None,
+    }
+}
+
+/// A value as it goes into a file or folder name, zero-padded to `width` digits if it is
+/// a number
+fn name_value(value: Option<String>, width: Option<usize>) -> Option<String> {
+    let value = sanitize_filename(value?.trim());
+    if value.is_empty() {
+        return None;
+    }
+    match width {
+        Some(width) if value.chars().all(|c| c.is_ascii_digit()) => {
+            Some(format!("{:0>width$}", value, width = width))
+        }
+        _ => Some(value),
+    }
+}
+
+/// Render parts, or with `strict`, `None` if a field has no value
+fn render_parts(
+    parts: &[Part],
+    value: &dyn Fn(&str) -> Option<String>,
+    strict: bool,
+    out: &mut String,
+) -> Option<()> {
+    for part in parts {
+        match part {
+            Part::Text(text) => out.push_str(text),
+            Part::Field { name, width } => match name_value(value(name), *width) {
+                Some(value) => out.push_str(&value),
+                None if strict => return None,
+                None => {}
+            },
+            Part::Optional(parts) => {
+                let mut section = String::new();
+                if render_parts(parts, value, true, &mut section).is_some() {
+                    out.push_str(&section);
+                }
+            }
+            Part::If {
+                field,
+                negate,
+                parts,
+            } => {
+                if name_value(value(field), None).is_some() != *negate {
+                    render_parts(parts, value, strict, out)?;
+                }
+            }
+        }
+    }
+    Some(())
+}
+
+impl PathTemplate {
+    /// The book's folder, relative to the destination. Fields outside `[...]` without a
+    /// value are left empty, and folders that end up empty are left out.
+    pub fn render(&self, book: &Audiobook, options: &ExportOptions) -> PathBuf {
+        let mut rendered = String::new();
+        let value = |field: &str| book_value(book, options, field);
+        render_parts(&self.parts, &value, false, &mut rendered);
+        let path: PathBuf = rendered
+            .split('/')
+            .map(|folder| folder.split_whitespace().collect::<Vec<_>>().join(" "))
+            .filter(|folder| !folder.is_empty() && folder != "." && folder != "..")
+            .collect();
+        if path.as_os_str().is_empty() {
+            PathBuf::from(sanitize_filename(&book.title))
+        } else {
+            path
+        }
+    }
+}
+
+/// How book folders are arranged under the destination
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Layout {
+    /// `Author/Title {Narrator}/`, as Audiobookshelf expects
+    #[default]
+    Nested,
+    /// `Author/Series/Vol N - Title {Narrator}/` for books in a series, which Audiobookshelf
+    /// groups by series
+    Series,
+    /// `Author - Title {Narrator}/` directly under the destination, for Plex and other
+    /// libraries of one folder per book
+    Flat,
+}
+
+impl Layout {
+    /// The template laying out book folders, or `None` for the default layout
+    pub fn template(self) -> Option<PathTemplate> {
+        match self {
+            Layout::Nested => None,
+            Layout::Series => Some(
+                "{author}/{?series}{series}/[Vol {sequence} - ]{/series}{title_in_series}\
+                 [ {{{narrator}}}]"
+                    .parse()
+                    .expect("the series layout is a valid template"),
+            ),
+            Layout::Flat => Some(
+                "{author} - {title}[ {{{narrator}}}]"
+                    .parse()
+                    .expect("the flat layout is a valid template"),
+            ),
+        }
+    }
+}
+
+/// A filename for exported tracks like `{disc}-{track:02} - {track_title}.{ext}`, with the
+/// fields of a [`PathTemplate`] plus the track's
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrackTemplate {
+    source: String,
+    parts: Vec<Part>,
+}
+
+impl FromStr for TrackTemplate {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let chars: Vec<char> = s.chars().collect();
+        let parts = parse_parts(&chars, &mut 0, &[TRACK_FIELDS, FIELDS], Close::End)?;
+        if !names_field(&parts, &TRACK_FIELDS[..4]) {
+            bail!(
+                "The track template names none of disc, track, track_title or filename, so \
+                 every track of a book would get the same name"
+            );
+        }
+        if text_contains(&parts, '/') {
+            bail!("A track template can't contain /");
+        }
+        Ok(TrackTemplate {
+            source: s.to_string(),
+            parts,
+        })
+    }
+}
+
+impl fmt::Display for TrackTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+/// Clap value parser for `--track-template`
+pub fn parse_track_template(s: &str) -> Result<TrackTemplate> {
+    s.parse()
+}
+
+impl TrackTemplate {
+    /// Filename of the `index`-th track (0-based, in sorted order) of a book. With
+    /// `renumber`, or for tracks without a number, `{track}` counts through the whole book.
+    /// Tracks without a disc number have no `{disc}`. The source's extension is added
+    /// when the template has no `{ext}`.
+    pub fn render(
+        &self,
+        book: &Audiobook,
+        index: usize,
+        options: &ExportOptions,
+        renumber: bool,
+    ) -> String {
+        let track = &book.tracks[index];
+        let source = Path::new(&track.filename);
+        let stem = source
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let ext = source
+            .extension()
+            .map(|e| e.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let value = |field: &str| match field {
+            "disc" => (track.disc_number > 0).then(|| track.disc_number.to_string()),
+            "track" if renumber || track.track_number == 0 => Some((index + 1).to_string()),
+            "track" => Some(track.track_number.to_string()),
+            "track_title" => {
+                let tracks: Vec<_> = book.tracks.iter().collect();
+                Some(chapters::track_titles(&tracks).swap_remove(index))
+            }
+            "filename" => Some(stem.clone()),
+            "ext" => Some(ext.clone()),
+            field => book_value(book, options, field),
+        };
+        let mut rendered = String::new();
+        render_parts(&self.parts, &value, false, &mut rendered);
+        let mut name = rendered.split_whitespace().collect::<Vec<_>>().join(" ");
+        if name.trim_matches('.').is_empty() {
+            name = stem.clone();
+        }
+        if !names_field(&self.parts, &["ext"]) && !ext.is_empty() {
+            name = format!("{}.{}", name, ext);
+        }
+        name
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AudioTrack;
+
+    fn book() -> Audiobook {
+        Audiobook {
+            title: "Warbreaker".to_string(),
+            author: "Brandon Sanderson".to_string(),
+            narrator: Some("Alyssa Bresnahan".to_string()),
+            folder_id: "sha1-aaa".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_render() {
+        let options = ExportOptions::default();
+        let template: PathTemplate = "{author}/{series}/{title} {{{narrator}}}".parse().unwrap();
+        assert_eq!(
+            template.render(&book(), &options),
+            PathBuf::from("Brandon Sanderson/Warbreaker {Alyssa Bresnahan}")
+        );
+
+        let template: PathTemplate = "{author}/{series}/[Vol {sequence} - ]{title}[ ({year})]"
+            .parse()
+            .unwrap();
+        let mut book = book();
+        assert_eq!(
+            template.render(&book, &options),
+            PathBuf::from("Brandon Sanderson/Warbreaker")
+        );
+        book.series = Some("Cosmere".to_string());
+        book.series_sequence = Some("4".to_string());
+        book.details.year = Some("2009".to_string());
+        book.title = "War/breaker".to_string();
+        assert_eq!(
+            template.render(&book, &options),
+            PathBuf::from("Brandon Sanderson/Cosmere/Vol 4 - War_breaker (2009)")
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!("{author}/{titel}".parse::<PathTemplate>().is_err());
+        assert!("{author".parse::<PathTemplate>().is_err());
+        assert!("{author}}".parse::<PathTemplate>().is_err());
+        assert!("{author}/[{series}".parse::<PathTemplate>().is_err());
+        assert!("{author}/{?series}{series}/"
+            .parse::<PathTemplate>()
+            .is_err());
+        assert!("{?series}{title}{/author}".parse::<PathTemplate>().is_err());
+        assert!("{author}/{title}{/title}".parse::<PathTemplate>().is_err());
+        assert!("Audiobooks".parse::<PathTemplate>().is_err());
+        assert_eq!(
+            "[[{title}]]"
+                .parse::<PathTemplate>()
+                .unwrap()
+                .render(&book(), &Default::default()),
+            PathBuf::from("[Warbreaker]")
+        );
+    }
+
+    #[test]
+    fn test_conditionals() {
+        let options = ExportOptions::default();
+        let template: PathTemplate =
+            "{author}/{?series}{series}/{?sequence}{sequence}. {/sequence}{/series}{title}\
+             {?narrator} {{{narrator}}}{/narrator}{!narrator} (unknown narrator){/narrator}"
+                .parse()
+                .unwrap();
+        let mut book = book();
+        assert_eq!(
+            template.render(&book, &options),
+            PathBuf::from("Brandon Sanderson/Warbreaker {Alyssa Bresnahan}")
+        );
+        book.narrator = None;
+        book.series = Some("Cosmere".to_string());
+        book.series_sequence = Some("4".to_string());
+        assert_eq!(
+            template.render(&book, &options),
+            PathBuf::from("Brandon Sanderson/Cosmere/4. Warbreaker (unknown narrator)")
+        );
+
+        let template: PathTemplate = "{author}[/{series}/Vol {sequence}]/{title}"
+            .parse()
+            .unwrap();
+        book.series_sequence = None;
+        assert_eq!(
+            template.render(&book, &options),
+            PathBuf::from("Brandon Sanderson/Warbreaker")
+        );
+    }
+
+    #[test]
+    fn test_track_template() {
+        let mut book = book();
+        for (disc, track, title) in [(1, 1, "Prologue"), (1, 2, "Warbreaker"), (2, 1, "")] {
+            book.tracks.push(AudioTrack {
+                track_number: track,
+                disc_number: disc,
+                title: title.to_string(),
+                path: PathBuf::from(format!("/books/sha1-aaa/WB{}{}.mp3", disc, track)),
+                filename: format!("WB{}{}.mp3", disc, track),
+            });
+        }
+        let options = ExportOptions::default();
+        let template: TrackTemplate = "{disc}-{track:02} - {track_title}.{ext}".parse().unwrap();
+        assert_eq!(
+            template.render(&book, 0, &options, false),
+            "1-01 - Prologue.mp3"
+        );
+        assert_eq!(
+            template.render(&book, 2, &options, false),
+            "2-01 - WB21.mp3"
+        );
+        assert_eq!(template.render(&book, 2, &options, true), "2-03 - WB21.mp3");
+
+        let template: TrackTemplate = "{title} {track:03}".parse().unwrap();
+        assert_eq!(
+            template.render(&book, 1, &options, false),
+            "Warbreaker 002.mp3"
+        );
+        let options = ExportOptions {
+            track_template: Some(template),
+            transcode: Some("opus".parse().unwrap()),
+            ..Default::default()
+        };
+        assert_eq!(options.dest_filename(&book, 1), "Warbreaker 002.opus");
+
+        // Tracks numbered per disc collide without the disc in the template
+        let options = ExportOptions {
+            track_template: Some("{track:02}".parse().unwrap()),
+            ..Default::default()
+        };
+        assert_eq!(
+            options.dest_filenames(&book),
+            ["01.mp3", "02.mp3", "01 2.mp3"]
+        );
+        assert_eq!(options.dest_filename(&book, 2), "01 2.mp3");
+
+        book.tracks[0].disc_number = 0;
+        let template: TrackTemplate = "[{disc}-]{track:02}".parse().unwrap();
+        assert_eq!(template.render(&book, 0, &options, false), "01.mp3");
+
+        assert!("{title}.{ext}".parse::<TrackTemplate>().is_err());
+        assert!("{disc}/{track}".parse::<TrackTemplate>().is_err());
+        assert!("{track:two}".parse::<TrackTemplate>().is_err());
+        assert!("{track}".parse::<PathTemplate>().is_err());
+    }
+
+    #[test]
+    fn test_flat_layout() {
+        let options = ExportOptions {
+            template: Layout::Flat.template(),
+            ..Default::default()
+        };
+        let mut book = book();
+        assert_eq!(
+            crate::create_audiobookshelf_path(Path::new("/dest"), &book, &options),
+            PathBuf::from("/dest/Brandon Sanderson - Warbreaker {Alyssa Bresnahan}")
+        );
+        book.narrator = None;
+        book.series = Some("Cosmere".to_string());
+        book.series_sequence = Some("4".to_string());
+        assert_eq!(
+            Layout::Flat.template().unwrap().render(&book, &options),
+            PathBuf::from("Brandon Sanderson - Warbreaker")
+        );
+        assert_eq!(Layout::Nested.template(), None);
+    }
+}
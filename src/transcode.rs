@@ -0,0 +1,241 @@
+use anyhow::{anyhow, bail, Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use crate::progress;
+
+/// Audio codec to re-encode tracks with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Codec {
+    Opus,
+    Aac,
+}
+
+/// Transcoding target parsed from `--transcode codec[:bitrate]`, e.g. `opus:64k`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TranscodeTarget {
+    pub codec: Codec,
+    pub bitrate: String,
+}
+
+impl FromStr for TranscodeTarget {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (codec, bitrate) = match s.split_once(':') {
+            Some((codec, bitrate)) => (codec, Some(bitrate)),
+            None => (s, None),
+        };
+
+        let codec = match codec.to_lowercase().as_str() {
+            "opus" => Codec::Opus,
+            "aac" => Codec::Aac,
+            other => return Err(format!("unsupported codec '{}' (use opus or aac)", other)),
+        };
+
+        let bitrate = match bitrate {
+            Some(b) if is_valid_bitrate(b) => b.to_lowercase(),
+            Some(b) => return Err(format!("invalid bitrate '{}' (e.g. 64k)", b)),
+            None => match codec {
+                Codec::Opus => "64k".to_string(),
+                Codec::Aac => "96k".to_string(),
+            },
+        };
+
+        Ok(TranscodeTarget { codec, bitrate })
+    }
+}
+
+/// Accepts bitrates like `64k`, `128K` or `96000`
+fn is_valid_bitrate(bitrate: &str) -> bool {
+    let digits = bitrate.strip_suffix(['k', 'K']).unwrap_or(bitrate);
+    !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit())
+}
+
+impl TranscodeTarget {
+    /// File extension of the transcoded output for a given source file
+    pub fn output_extension(&self, source: &Path) -> &'static str {
+        match self.codec {
+            Codec::Opus => "opus",
+            Codec::Aac => {
+                let is_m4b = source
+                    .extension()
+                    .is_some_and(|e| e.eq_ignore_ascii_case("m4b"));
+                if is_m4b {
+                    "m4b"
+                } else {
+                    "m4a"
+                }
+            }
+        }
+    }
+
+    /// Destination filename for a transcoded track
+    pub fn output_filename(&self, filename: &str) -> String {
+        let path = Path::new(filename);
+        path.with_extension(self.output_extension(path))
+            .to_string_lossy()
+            .to_string()
+    }
+
+    fn encoder(&self) -> &'static str {
+        match self.codec {
+            Codec::Opus => "libopus",
+            Codec::Aac => "aac",
+        }
+    }
+}
+
+/// A single file queued for transcoding
+#[derive(Debug, Clone)]
+pub struct TranscodeJob {
+    pub source: PathBuf,
+    pub dest: PathBuf,
+}
+
+/// Re-encode one file with ffmpeg. Output is written to a temporary file and renamed
+/// into place, so an interrupted run never leaves a half-written file that would later
+/// be mistaken for a finished transcode. `audio_filter` is an optional ffmpeg `-af` chain.
+pub fn transcode_file(
+    target: &TranscodeTarget,
+    source: &Path,
+    dest: &Path,
+    audio_filter: Option<&str>,
+) -> Result<()> {
+    let extension = dest
+        .extension()
+        .map(|e| e.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let partial = dest.with_extension(format!("partial.{}", extension));
+
+    let mut command = Command::new("ffmpeg");
+    command
+        .args(["-v", "error", "-nostdin", "-y", "-i"])
+        .arg(source)
+        .args(["-map", "0:a", "-map_metadata", "0"]);
+    if let Some(filter) = audio_filter {
+        command.args(["-af", filter]);
+    }
+    let output = command
+        .args(["-c:a", target.encoder(), "-b:a", &target.bitrate])
+        .arg(&partial)
+        .output()
+        .context("Failed to run ffmpeg (is ffmpeg installed?)")?;
+
+    if !output.status.success() {
+        let _ = fs::remove_file(&partial);
+        bail!(
+            "ffmpeg failed to transcode {:?}: {}",
+            source,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    fs::rename(&partial, dest).with_context(|| format!("Failed to move {:?} into place", dest))
+}
+
+/// Result of running a batch of transcode jobs
+#[derive(Debug, Default)]
+pub struct TranscodeOutcome {
+    pub transcoded: usize,
+    /// Index into the job list and error message of each failed job
+    pub failed: Vec<(usize, String)>,
+}
+
+/// Run transcode jobs on `workers` parallel threads. A failing job doesn't stop the
+/// others; failures are collected in the returned outcome. `done` is called with the index
+/// of each job as it finishes.
+pub fn run_jobs(
+    target: &TranscodeTarget,
+    jobs: &[TranscodeJob],
+    workers: usize,
+    audio_filter: Option<&str>,
+    done: impl Fn(usize) + Sync,
+) -> TranscodeOutcome {
+    if jobs.is_empty() {
+        return TranscodeOutcome::default();
+    }
+
+    let pb = progress::bar(jobs.len(), "Transcoding");
+
+    let next = AtomicUsize::new(0);
+    let outcome = Mutex::new(TranscodeOutcome::default());
+
+    std::thread::scope(|scope| {
+        for _ in 0..workers.max(1).min(jobs.len()) {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::SeqCst);
+                let Some(job) = jobs.get(i) else {
+                    break;
+                };
+
+                pb.set_message(
+                    job.dest
+                        .file_name()
+                        .map(|s| s.to_string_lossy().to_string())
+                        .unwrap_or_default(),
+                );
+                let result = transcode_file(target, &job.source, &job.dest, audio_filter);
+                let mut outcome = outcome.lock().unwrap();
+                match result {
+                    Ok(()) => outcome.transcoded += 1,
+                    Err(e) => outcome.failed.push((i, format!("{:#}", e))),
+                }
+                drop(outcome);
+                pb.inc(1);
+                done(i);
+            });
+        }
+    });
+
+    pb.finish_and_clear();
+
+    let mut outcome = outcome.into_inner().unwrap();
+    outcome.failed.sort_by_key(|(i, _)| *i);
+    outcome
+}
+
+/// Default number of parallel transcode workers
+pub fn default_workers() -> usize {
+    std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+}
+
+/// Clap value parser for `--transcode`
+pub fn parse_target(s: &str) -> Result<TranscodeTarget> {
+    s.parse().map_err(|e: String| anyhow!(e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_transcode_target() {
+        let t: TranscodeTarget = "opus:64k".parse().unwrap();
+        assert_eq!(t.codec, Codec::Opus);
+        assert_eq!(t.bitrate, "64k");
+
+        let t: TranscodeTarget = "AAC".parse().unwrap();
+        assert_eq!(t.codec, Codec::Aac);
+        assert_eq!(t.bitrate, "96k");
+
+        assert!("mp3:64k".parse::<TranscodeTarget>().is_err());
+        assert!("opus:fast".parse::<TranscodeTarget>().is_err());
+    }
+
+    #[test]
+    fn test_output_filename() {
+        let opus: TranscodeTarget = "opus".parse().unwrap();
+        assert_eq!(opus.output_filename("01 Chapter.mp3"), "01 Chapter.opus");
+
+        let aac: TranscodeTarget = "aac:64k".parse().unwrap();
+        assert_eq!(aac.output_filename("Book.m4b"), "Book.m4b");
+        assert_eq!(aac.output_filename("01 Chapter.mp3"), "01 Chapter.m4a");
+    }
+}
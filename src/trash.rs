@@ -0,0 +1,270 @@
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use time::{macros::format_description, OffsetDateTime};
+
+/// What destructive operations do with the files they remove
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum Disposal {
+    /// Move files to the user's Trash (macOS `~/.Trash`, XDG trash elsewhere)
+    #[default]
+    Trash,
+    /// Move files into timestamped subdirectories of a quarantine folder
+    Quarantine(PathBuf),
+    /// Unlink files permanently
+    Permanent,
+}
+
+/// Removes files according to a [`Disposal`] policy. All files disposed by one instance
+/// share a timestamped quarantine subdirectory, so one run can be restored as a unit.
+#[derive(Debug)]
+pub struct Disposer {
+    disposal: Disposal,
+    run_stamp: String,
+}
+
+impl Disposer {
+    pub fn new(disposal: Disposal) -> Self {
+        let stamp_format = format_description!("[year][month][day]-[hour][minute][second]");
+        let run_stamp = OffsetDateTime::now_utc()
+            .format(&stamp_format)
+            .unwrap_or_else(|_| "run".to_string());
+
+        Disposer {
+            disposal,
+            run_stamp,
+        }
+    }
+
+    /// Remove `path`. `root` is the directory the file is relative to (e.g. the
+    /// destination), used to recreate its location inside a quarantine folder.
+    /// Returns where the file went, or `None` if it was deleted permanently.
+    pub fn dispose(&self, path: &Path, root: &Path) -> Result<Option<PathBuf>> {
+        match &self.disposal {
+            Disposal::Permanent => {
+                fs::remove_file(path).with_context(|| format!("Failed to delete {:?}", path))?;
+                Ok(None)
+            }
+            Disposal::Quarantine(dir) => {
+                let relative = path.strip_prefix(root).unwrap_or(path);
+                let relative = relative.strip_prefix("/").unwrap_or(relative);
+                let target = dir.join(&self.run_stamp).join(relative);
+                move_file(path, &target)?;
+                Ok(Some(target))
+            }
+            Disposal::Trash => {
+                let target = if cfg!(target_os = "macos") {
+                    let home = dirs::home_dir()
+                        .context("Could not determine home directory for the Trash")?;
+                    let trash = home.join(".Trash");
+                    fs::create_dir_all(&trash)
+                        .with_context(|| format!("Failed to create {:?}", trash))?;
+                    let name = path
+                        .file_name()
+                        .with_context(|| format!("Not a file: {:?}", path))?;
+                    let target = unique_path(&trash.join(name));
+                    rename_to_trash(path, &target)?;
+                    target
+                } else {
+                    let trash = dirs::data_local_dir()
+                        .or_else(|| dirs::home_dir().map(|home| home.join(".local/share")))
+                        .context("Could not determine home directory for the Trash")?
+                        .join("Trash");
+                    move_to_xdg_trash(path, &trash)?
+                };
+                Ok(Some(target))
+            }
+        }
+    }
+}
+
+fn rename_to_trash(path: &Path, target: &Path) -> Result<()> {
+    fs::rename(path, target).map_err(|e| {
+        if is_cross_device(&e) {
+            anyhow::anyhow!(
+                "Can't move {:?} to the Trash on another volume; use --quarantine-dir \
+                 on the same volume or --permanent",
+                path
+            )
+        } else {
+            anyhow::Error::new(e).context(format!("Failed to move {:?} to the Trash", path))
+        }
+    })
+}
+
+/// Percent-encode a path for the `Path` key of a `.trashinfo` file
+fn encode_path(path: &Path) -> String {
+    let mut encoded = String::new();
+    for byte in path.to_string_lossy().bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// Move a file into a freedesktop.org Trash (`files/` and `info/` under `trash`), with the
+/// `.trashinfo` file desktop trash tools need to show and restore it. The info file is
+/// created first, which claims the name.
+fn move_to_xdg_trash(path: &Path, trash: &Path) -> Result<PathBuf> {
+    let (files, info) = (trash.join("files"), trash.join("info"));
+    for dir in [&files, &info] {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {:?}", dir))?;
+    }
+    let original =
+        std::path::absolute(path).with_context(|| format!("Failed to resolve {:?}", path))?;
+    let name = path
+        .file_name()
+        .with_context(|| format!("Not a file: {:?}", path))?;
+    // The spec asks for local time, which the time crate can't tell reliably
+    let deletion_date = OffsetDateTime::now_utc()
+        .format(format_description!(
+            "[year]-[month]-[day]T[hour]:[minute]:[second]"
+        ))
+        .context("Failed to format the deletion date")?;
+
+    let mut candidate = files.join(name);
+    loop {
+        candidate = unique_path(&candidate);
+        let file_name = candidate.file_name().unwrap_or(name).to_os_string();
+        let mut info_name = file_name.clone();
+        info_name.push(".trashinfo");
+        let info_path = info.join(info_name);
+        let mut info_file = match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&info_path)
+        {
+            Ok(file) => file,
+            // Taken by a file trashed elsewhere that has no entry in files/ (yet)
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                candidate = next_path(&candidate);
+                continue;
+            }
+            Err(e) => return Err(e).with_context(|| format!("Failed to create {:?}", info_path)),
+        };
+        let moved = write!(
+            info_file,
+            "[Trash Info]\nPath={}\nDeletionDate={}\n",
+            encode_path(&original),
+            deletion_date
+        )
+        .with_context(|| format!("Failed to write {:?}", info_path))
+        .and_then(|()| rename_to_trash(path, &candidate));
+        if let Err(e) = moved {
+            let _ = fs::remove_file(&info_path);
+            return Err(e);
+        }
+        return Ok(candidate);
+    }
+}
+
+/// Append " 2", " 3", ... to the file stem until the path is free
+pub fn unique_path(path: &Path) -> PathBuf {
+    if fs::symlink_metadata(path).is_err() {
+        return path.to_path_buf();
+    }
+    next_path(path)
+}
+
+/// The first free path after `path` with " 2", " 3", ... appended to the file stem
+fn next_path(path: &Path) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let extension = path
+        .extension()
+        .map(|e| format!(".{}", e.to_string_lossy()))
+        .unwrap_or_default();
+
+    (2..)
+        .map(|n| path.with_file_name(format!("{} {}{}", stem, n, extension)))
+        .find(|candidate| fs::symlink_metadata(candidate).is_err())
+        .expect("unbounded search always finds a free name")
+}
+
+fn is_cross_device(e: &io::Error) -> bool {
+    e.kind() == io::ErrorKind::CrossesDevices || e.raw_os_error() == Some(18)
+}
+
+/// Move a file, falling back to copy-and-delete across filesystems
+fn move_file(from: &Path, to: &Path) -> Result<()> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent).with_context(|| format!("Failed to create {:?}", parent))?;
+    }
+    if fs::symlink_metadata(to).is_ok() {
+        bail!("Refusing to overwrite {:?}", to);
+    }
+
+    match fs::rename(from, to) {
+        Ok(()) => Ok(()),
+        Err(e) if is_cross_device(&e) => {
+            fs::copy(from, to).with_context(|| format!("Failed to copy {:?} -> {:?}", from, to))?;
+            fs::remove_file(from).with_context(|| format!("Failed to delete {:?}", from))
+        }
+        Err(e) => Err(e).with_context(|| format!("Failed to move {:?} -> {:?}", from, to)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_quarantine_preserves_relative_path() {
+        let root = tempdir().unwrap();
+        let quarantine = tempdir().unwrap();
+        let file = root.path().join("Author/Book/01.mp3");
+        fs::create_dir_all(file.parent().unwrap()).unwrap();
+        fs::write(&file, b"audio").unwrap();
+
+        let disposer = Disposer::new(Disposal::Quarantine(quarantine.path().to_path_buf()));
+        let target = disposer.dispose(&file, root.path()).unwrap().unwrap();
+
+        assert!(!file.exists());
+        assert!(target.ends_with("Author/Book/01.mp3"));
+        assert!(target.starts_with(quarantine.path().join(&disposer.run_stamp)));
+        assert_eq!(fs::read(&target).unwrap(), b"audio");
+    }
+
+    #[test]
+    fn test_xdg_trash_info() {
+        let root = tempdir().unwrap();
+        let trash = root.path().join("Trash");
+        let file = root.path().join("Book #1/01 Part.mp3");
+        fs::create_dir_all(file.parent().unwrap()).unwrap();
+        fs::write(&file, b"audio").unwrap();
+        fs::create_dir_all(trash.join("info")).unwrap();
+        fs::write(trash.join("info/01 Part.mp3.trashinfo"), b"").unwrap();
+
+        let target = move_to_xdg_trash(&file, &trash).unwrap();
+        assert!(!file.exists());
+        assert_eq!(target, trash.join("files/01 Part 2.mp3"));
+        assert_eq!(fs::read(&target).unwrap(), b"audio");
+        let info = fs::read_to_string(trash.join("info/01 Part 2.mp3.trashinfo")).unwrap();
+        let mut lines = info.lines();
+        assert_eq!(lines.next(), Some("[Trash Info]"));
+        assert_eq!(
+            lines.next().map(str::to_string),
+            Some(format!("Path={}", encode_path(&file)))
+        );
+        assert!(info.contains("Book%20%231/01%20Part.mp3\n"));
+        assert!(lines.next().unwrap().starts_with("DeletionDate=20"));
+    }
+
+    #[test]
+    fn test_unique_path() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("01.mp3");
+        assert_eq!(unique_path(&path), path);
+
+        fs::write(&path, b"x").unwrap();
+        assert_eq!(unique_path(&path), dir.path().join("01 2.mp3"));
+    }
+}
@@ -0,0 +1,452 @@
+use anyhow::Result;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph};
+use ratatui::Frame;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+use crate::{Audiobook, FileDiff, FileStatus};
+
+/// Where a book stands against the destination, from the statuses of its files
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BookStatus {
+    /// None of its files are at the destination yet
+    New,
+    /// Some of its files are at the destination, others still to be copied
+    Incomplete,
+    /// Exported, but some source files changed since
+    Changed,
+    /// Nothing to copy, but some tracks are missing or not downloaded
+    NotDownloaded,
+    /// Every file is at the destination
+    Exported,
+}
+
+impl BookStatus {
+    pub fn from_files(statuses: &[&FileStatus]) -> Self {
+        let count = |status: FileStatus| statuses.iter().filter(|s| ***s == status).count();
+        let new = count(FileStatus::New);
+        if new > 0 && new + count(FileStatus::Duplicate) == statuses.len() {
+            BookStatus::New
+        } else if new > 0 {
+            BookStatus::Incomplete
+        } else if count(FileStatus::Changed) > 0 {
+            BookStatus::Changed
+        } else if count(FileStatus::SourceMissing) + count(FileStatus::SourcePlaceholder) > 0 {
+            BookStatus::NotDownloaded
+        } else {
+            BookStatus::Exported
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            BookStatus::New => "new",
+            BookStatus::Incomplete => "incomplete",
+            BookStatus::Changed => "changed",
+            BookStatus::NotDownloaded => "not downloaded",
+            BookStatus::Exported => "exported",
+        }
+    }
+
+    fn color(self) -> Color {
+        match self {
+            BookStatus::New => Color::Green,
+            BookStatus::Incomplete => Color::Yellow,
+            BookStatus::Changed => Color::Blue,
+            BookStatus::NotDownloaded => Color::Red,
+            BookStatus::Exported => Color::DarkGray,
+        }
+    }
+}
+
+/// A line of the tree: an author, one of their books, or one of its tracks, by index
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Row {
+    Author(usize),
+    Book(usize),
+    Track(usize, usize),
+}
+
+/// The library as a tree of authors, books and tracks, with the books marked for export
+struct Browser<'a> {
+    audiobooks: &'a [Audiobook],
+    statuses: Vec<BookStatus>,
+    /// Authors in order, with the indexes of their books sorted by title
+    authors: Vec<(String, Vec<usize>)>,
+    collapsed_authors: HashSet<usize>,
+    expanded_books: HashSet<usize>,
+    marked: BTreeSet<usize>,
+    cursor: usize,
+}
+
+impl<'a> Browser<'a> {
+    fn new(audiobooks: &'a [Audiobook], diffs: &[FileDiff]) -> Self {
+        let mut files: BTreeMap<(&str, &str), Vec<&FileStatus>> = BTreeMap::new();
+        for diff in diffs {
+            files
+                .entry((&diff.author, &diff.book_title))
+                .or_default()
+                .push(&diff.status);
+        }
+        let statuses = audiobooks
+            .iter()
+            .map(|book| {
+                let statuses = files
+                    .get(&(book.author.as_str(), book.title.as_str()))
+                    .map(Vec::as_slice)
+                    .unwrap_or_default();
+                BookStatus::from_files(statuses)
+            })
+            .collect();
+
+        let mut by_author: BTreeMap<String, (String, Vec<usize>)> = BTreeMap::new();
+        for (i, book) in audiobooks.iter().enumerate() {
+            by_author
+                .entry(book.author.to_lowercase())
+                .or_insert_with(|| (book.author.clone(), Vec::new()))
+                .1
+                .push(i);
+        }
+        let mut authors: Vec<_> = by_author.into_values().collect();
+        for (_, books) in &mut authors {
+            books.sort_by_cached_key(|&i| audiobooks[i].title.to_lowercase());
+        }
+
+        Browser {
+            audiobooks,
+            statuses,
+            authors,
+            collapsed_authors: HashSet::new(),
+            expanded_books: HashSet::new(),
+            marked: BTreeSet::new(),
+            cursor: 0,
+        }
+    }
+
+    /// The lines currently shown: every author, the books of expanded authors, and the
+    /// tracks of expanded books
+    fn rows(&self) -> Vec<Row> {
+        let mut rows = Vec::new();
+        for (a, (_, books)) in self.authors.iter().enumerate() {
+            rows.push(Row::Author(a));
+            if self.collapsed_authors.contains(&a) {
+                continue;
+            }
+            for &b in books {
+                rows.push(Row::Book(b));
+                if self.expanded_books.contains(&b) {
+                    rows.extend((0..self.audiobooks[b].tracks.len()).map(|t| Row::Track(b, t)));
+                }
+            }
+        }
+        rows
+    }
+
+    fn current(&self) -> Option<Row> {
+        self.rows().get(self.cursor).copied()
+    }
+
+    fn move_cursor(&mut self, by: isize) {
+        let last = self.rows().len().saturating_sub(1);
+        self.cursor = self.cursor.saturating_add_signed(by).min(last);
+    }
+
+    fn move_to(&mut self, row: Row) {
+        if let Some(i) = self.rows().iter().position(|r| *r == row) {
+            self.cursor = i;
+        }
+    }
+
+    fn expand(&mut self) {
+        match self.current() {
+            Some(Row::Author(a)) => {
+                self.collapsed_authors.remove(&a);
+            }
+            Some(Row::Book(b)) => {
+                self.expanded_books.insert(b);
+            }
+            _ => {}
+        }
+    }
+
+    /// Collapse the current author or book, or move up to the book of a track
+    fn collapse(&mut self) {
+        match self.current() {
+            Some(Row::Author(a)) => {
+                self.collapsed_authors.insert(a);
+            }
+            Some(Row::Book(b)) if self.expanded_books.contains(&b) => {
+                self.expanded_books.remove(&b);
+            }
+            Some(Row::Book(b)) => {
+                let author = self.author_of(b);
+                self.collapsed_authors.insert(author);
+                self.move_to(Row::Author(author));
+            }
+            Some(Row::Track(b, _)) => {
+                self.expanded_books.remove(&b);
+                self.move_to(Row::Book(b));
+            }
+            None => {}
+        }
+    }
+
+    fn toggle_expanded(&mut self) {
+        let expanded = match self.current() {
+            Some(Row::Author(a)) => !self.collapsed_authors.contains(&a),
+            Some(Row::Book(b)) => self.expanded_books.contains(&b),
+            _ => return,
+        };
+        if expanded {
+            self.collapse();
+        } else {
+            self.expand();
+        }
+    }
+
+    fn author_of(&self, book: usize) -> usize {
+        self.authors
+            .iter()
+            .position(|(_, books)| books.contains(&book))
+            .unwrap_or(0)
+    }
+
+    /// Mark or unmark the current book, or all books of the current author
+    fn toggle_mark(&mut self) {
+        let books = match self.current() {
+            Some(Row::Author(a)) => self.authors[a].1.clone(),
+            Some(Row::Book(b)) | Some(Row::Track(b, _)) => vec![b],
+            None => return,
+        };
+        if books.iter().all(|b| self.marked.contains(b)) {
+            for b in &books {
+                self.marked.remove(b);
+            }
+        } else {
+            self.marked.extend(books);
+        }
+    }
+
+    fn line(&self, row: Row) -> Line<'static> {
+        let mark = |marked: bool| if marked { "[x] " } else { "[ ] " };
+        match row {
+            Row::Author(a) => {
+                let (name, books) = &self.authors[a];
+                let all_marked = books.iter().all(|b| self.marked.contains(b));
+                Line::from(vec![
+                    Span::raw(mark(all_marked)),
+                    Span::raw(if self.collapsed_authors.contains(&a) {
+                        "+ "
+                    } else {
+                        "- "
+                    }),
+                    Span::styled(name.clone(), Style::new().add_modifier(Modifier::BOLD)),
+                    Span::styled(
+                        format!("  {} books", books.len()),
+                        Style::new().fg(Color::DarkGray),
+                    ),
+                ])
+            }
+            Row::Book(b) => {
+                let book = &self.audiobooks[b];
+                let status = self.statuses[b];
+                Line::from(vec![
+                    Span::raw("    "),
+                    Span::raw(mark(self.marked.contains(&b))),
+                    Span::raw(if self.expanded_books.contains(&b) {
+                        "- "
+                    } else {
+                        "+ "
+                    }),
+                    Span::raw(book.title.clone()),
+                    Span::styled(
+                        format!("  {} tracks  ", book.tracks.len()),
+                        Style::new().fg(Color::DarkGray),
+                    ),
+                    Span::styled(status.label(), Style::new().fg(status.color())),
+                ])
+            }
+            Row::Track(b, t) => {
+                let track = &self.audiobooks[b].tracks[t];
+                let name = if track.title.is_empty() {
+                    &track.filename
+                } else {
+                    &track.title
+                };
+                Line::from(vec![
+                    Span::raw("              "),
+                    Span::styled(
+                        format!("{:>3}  ", track.track_number),
+                        Style::new().fg(Color::DarkGray),
+                    ),
+                    Span::raw(name.clone()),
+                ])
+            }
+        }
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let [list_area, help_area] =
+            Layout::vertical([Constraint::Min(1), Constraint::Length(1)]).areas(frame.area());
+        let rows = self.rows();
+        let items: Vec<_> = rows
+            .iter()
+            .map(|&row| ListItem::new(self.line(row)))
+            .collect();
+        let title = format!(
+            " {} audiobooks, {} marked ",
+            self.audiobooks.len(),
+            self.marked.len()
+        );
+        let list = List::new(items)
+            .block(Block::bordered().title(title))
+            .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        let mut state = ListState::default().with_selected(Some(self.cursor));
+        frame.render_stateful_widget(list, list_area, &mut state);
+        frame.render_widget(
+            Paragraph::new(
+                "Up/Down move  Right/Left open/close  Enter toggle  Space mark  e export marked  q quit",
+            )
+            .style(Style::new().fg(Color::DarkGray)),
+            help_area,
+        );
+    }
+}
+
+/// Browse the library in the terminal as a tree of authors, books and tracks, each book
+/// with its status against the destination from `diffs`. Returns the folder ids of the
+/// books marked for export, or `None` if the user quit without exporting.
+pub fn run(audiobooks: &[Audiobook], diffs: &[FileDiff]) -> Result<Option<Vec<String>>> {
+    let mut browser = Browser::new(audiobooks, diffs);
+    let mut terminal = ratatui::init();
+    let result = loop {
+        if let Err(e) = terminal.draw(|frame| browser.draw(frame)) {
+            break Err(e.into());
+        }
+        let key = match event::read() {
+            Ok(Event::Key(key)) if key.kind == KeyEventKind::Press => key,
+            Ok(_) => continue,
+            Err(e) => break Err(e.into()),
+        };
+        let page = terminal
+            .size()
+            .map(|size| size.height as isize - 3)
+            .unwrap_or(10);
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => break Ok(None),
+            KeyCode::Char('e') => {
+                let ids = browser
+                    .marked
+                    .iter()
+                    .map(|&b| audiobooks[b].folder_id.clone())
+                    .collect();
+                break Ok(Some(ids));
+            }
+            KeyCode::Up | KeyCode::Char('k') => browser.move_cursor(-1),
+            KeyCode::Down | KeyCode::Char('j') => browser.move_cursor(1),
+            KeyCode::PageUp => browser.move_cursor(-page),
+            KeyCode::PageDown => browser.move_cursor(page),
+            KeyCode::Home => browser.cursor = 0,
+            KeyCode::End => browser.move_cursor(isize::MAX),
+            KeyCode::Right | KeyCode::Char('l') => browser.expand(),
+            KeyCode::Left | KeyCode::Char('h') => browser.collapse(),
+            KeyCode::Enter => browser.toggle_expanded(),
+            KeyCode::Char(' ') => browser.toggle_mark(),
+            _ => {}
+        }
+    };
+    ratatui::restore();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AudioTrack;
+    use std::path::PathBuf;
+
+    fn book(author: &str, title: &str, tracks: usize) -> Audiobook {
+        Audiobook {
+            title: title.to_string(),
+            author: author.to_string(),
+            folder_id: format!("sha1-{}", title.len()),
+            tracks: (1..=tracks)
+                .map(|n| AudioTrack {
+                    track_number: n as u32,
+                    disc_number: 1,
+                    title: String::new(),
+                    path: PathBuf::from(format!("/src/{:02}.mp3", n)),
+                    filename: format!("{:02}.mp3", n),
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn diff(book: &Audiobook, status: FileStatus) -> FileDiff {
+        FileDiff {
+            source_path: PathBuf::new(),
+            dest_path: PathBuf::new(),
+            status,
+            book_title: book.title.clone(),
+            author: book.author.clone(),
+        }
+    }
+
+    #[test]
+    fn test_browser_tree_and_marks() {
+        let audiobooks = vec![
+            book("Weir", "Project Hail Mary", 1),
+            book("Sanderson", "Warbreaker", 2),
+            book("Sanderson", "Elantris", 1),
+        ];
+        let diffs = vec![
+            diff(&audiobooks[0], FileStatus::Exists),
+            diff(&audiobooks[1], FileStatus::New),
+            diff(&audiobooks[1], FileStatus::Exists),
+            diff(&audiobooks[2], FileStatus::SourcePlaceholder),
+        ];
+        let mut browser = Browser::new(&audiobooks, &diffs);
+        assert_eq!(
+            browser.statuses,
+            [
+                BookStatus::Exported,
+                BookStatus::Incomplete,
+                BookStatus::NotDownloaded
+            ]
+        );
+        assert_eq!(
+            browser.rows(),
+            [
+                Row::Author(0),
+                Row::Book(2),
+                Row::Book(1),
+                Row::Author(1),
+                Row::Book(0)
+            ]
+        );
+
+        browser.move_cursor(2);
+        browser.expand();
+        assert_eq!(browser.rows()[3..5], [Row::Track(1, 0), Row::Track(1, 1)]);
+        browser.move_cursor(1);
+        browser.collapse();
+        assert_eq!(browser.current(), Some(Row::Book(1)));
+        assert_eq!(browser.rows().len(), 5);
+
+        browser.toggle_mark();
+        browser.cursor = 0;
+        browser.toggle_mark();
+        assert_eq!(browser.marked, BTreeSet::from([1, 2]));
+        browser.toggle_mark();
+        assert!(browser.marked.is_empty());
+        browser.collapse();
+        assert_eq!(
+            browser.rows(),
+            [Row::Author(0), Row::Author(1), Row::Book(0)]
+        );
+    }
+}
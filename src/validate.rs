@@ -0,0 +1,210 @@
+use anyhow::{Context, Result};
+use plist::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+use crate::ExportError;
+
+/// Something wrong with an audiobook entry of Books.plist
+#[derive(Debug, Clone, PartialEq)]
+pub struct Problem {
+    /// Position of the entry in the `Books` array
+    pub index: usize,
+    /// The book's folder id, or its title when it has none
+    pub book: String,
+    pub message: String,
+}
+
+/// Check every audiobook entry of the Books.plist at `plist_path`
+pub fn validate_file(plist_path: &Path) -> Result<Vec<Problem>> {
+    if !plist_path.exists() {
+        return Err(ExportError::PlistNotFound(plist_path.to_path_buf()).into());
+    }
+    let value: Value = plist::from_file(plist_path)
+        .with_context(|| format!("Failed to parse plist at {:?}", plist_path))?;
+    validate(&value)
+}
+
+/// Check every audiobook entry of a parsed Books.plist for what makes a book fail to load
+/// or export incompletely: missing `BKParts`, tracks without a path, folder ids used by more
+/// than one book, and track numbers that skip or repeat
+pub fn validate(plist: &Value) -> Result<Vec<Problem>> {
+    let books = plist
+        .as_dictionary()
+        .ok_or_else(|| ExportError::InvalidPlistStructure("Root is not a dictionary".into()))?
+        .get("Books")
+        .and_then(|v| v.as_array())
+        .ok_or_else(|| ExportError::InvalidPlistStructure("Missing 'Books' array".into()))?;
+
+    let mut problems = Vec::new();
+    let mut first_use: HashMap<&str, usize> = HashMap::new();
+    for (index, book) in books.iter().enumerate() {
+        let Some(dict) = book.as_dictionary() else {
+            continue;
+        };
+        if dict.get("BKBookType").and_then(|v| v.as_string()) != Some("audiobook") {
+            continue;
+        }
+        let string = |key: &str| dict.get(key).and_then(|v| v.as_string()).unwrap_or("");
+        let parts = dict.get("BKParts").and_then(|v| v.as_array());
+        let folder_id = string("BKGeneratedItemId");
+        let book = if folder_id.is_empty() {
+            parts
+                .and_then(|parts| parts.first())
+                .and_then(|part| part.as_dictionary())
+                .and_then(|part| part.get("itemName"))
+                .and_then(|v| v.as_string())
+                .unwrap_or("untitled")
+                .to_string()
+        } else {
+            folder_id.to_string()
+        };
+        let mut report = |message: String| {
+            problems.push(Problem {
+                index,
+                book: book.clone(),
+                message,
+            })
+        };
+
+        if folder_id.is_empty() {
+            report("No BKGeneratedItemId".to_string());
+        } else if let Some(first) = first_use.get(folder_id) {
+            report(format!("Same folder id as entry {}", first));
+        } else {
+            first_use.insert(folder_id, index);
+        }
+
+        let Some(parts) = parts.filter(|parts| !parts.is_empty()) else {
+            report("No BKParts, so no tracks".to_string());
+            continue;
+        };
+        // Track numbers by disc, ignoring unnumbered tracks
+        let mut discs: BTreeMap<u64, Vec<u64>> = BTreeMap::new();
+        for (i, part) in parts.iter().enumerate() {
+            let Some(part) = part.as_dictionary() else {
+                report(format!("Part {} is not a dictionary", i + 1));
+                continue;
+            };
+            let path = part.get("path").and_then(|v| v.as_string()).unwrap_or("");
+            if path.trim().is_empty() {
+                report(format!("Part {} has no path", i + 1));
+            }
+            let number = |key: &str| part.get(key).and_then(|v| v.as_unsigned_integer());
+            if let Some(track) = number("BKTrackNumber").filter(|&n| n > 0) {
+                discs
+                    .entry(number("BKDiscNumber").unwrap_or(0))
+                    .or_default()
+                    .push(track);
+            }
+        }
+
+        let multi_disc = discs.len() > 1;
+        for (disc, mut numbers) in discs {
+            let on_disc = if multi_disc {
+                format!(" on disc {}", disc)
+            } else {
+                String::new()
+            };
+            numbers.sort_unstable();
+            let mut repeated: Vec<_> = numbers
+                .windows(2)
+                .filter(|w| w[0] == w[1])
+                .map(|w| w[0])
+                .collect();
+            repeated.dedup();
+            numbers.dedup();
+            let skipped: Vec<_> = (1..numbers.last().copied().unwrap_or(0))
+                .filter(|n| numbers.binary_search(n).is_err())
+                .collect();
+            if !skipped.is_empty() {
+                report(format!("Track numbers{} skip {}", on_disc, join(&skipped)));
+            }
+            if !repeated.is_empty() {
+                report(format!(
+                    "Track numbers{} repeat {}",
+                    on_disc,
+                    join(&repeated)
+                ));
+            }
+        }
+    }
+    Ok(problems)
+}
+
+fn join(numbers: &[u64]) -> String {
+    numbers
+        .iter()
+        .map(|n| n.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use plist::Dictionary;
+
+    fn part(track: u64, path: &str) -> Value {
+        let mut dict = Dictionary::new();
+        dict.insert("itemName".to_string(), Value::String("Book".to_string()));
+        dict.insert("BKTrackNumber".to_string(), Value::Integer(track.into()));
+        dict.insert("path".to_string(), Value::String(path.to_string()));
+        Value::Dictionary(dict)
+    }
+
+    fn book(folder_id: &str, parts: Option<Vec<Value>>) -> Value {
+        let mut dict = Dictionary::new();
+        dict.insert(
+            "BKBookType".to_string(),
+            Value::String("audiobook".to_string()),
+        );
+        dict.insert(
+            "BKGeneratedItemId".to_string(),
+            Value::String(folder_id.to_string()),
+        );
+        if let Some(parts) = parts {
+            dict.insert("BKParts".to_string(), Value::Array(parts));
+        }
+        Value::Dictionary(dict)
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut root = Dictionary::new();
+        root.insert(
+            "Books".to_string(),
+            Value::Array(vec![
+                book(
+                    "sha1-good",
+                    Some(vec![part(1, "/a/01.mp3"), part(2, "/a/02.mp3")]),
+                ),
+                book(
+                    "sha1-bad",
+                    Some(vec![
+                        part(1, "/b/01.mp3"),
+                        part(3, ""),
+                        part(3, "/b/03.mp3"),
+                    ]),
+                ),
+                book("sha1-good", None),
+            ]),
+        );
+
+        let problems: Vec<_> = validate(&Value::Dictionary(root))
+            .unwrap()
+            .into_iter()
+            .map(|p| (p.index, p.message))
+            .collect();
+        assert_eq!(
+            problems,
+            [
+                (1, "Part 2 has no path".to_string()),
+                (1, "Track numbers skip 2".to_string()),
+                (1, "Track numbers repeat 3".to_string()),
+                (2, "Same folder id as entry 0".to_string()),
+                (2, "No BKParts, so no tracks".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,132 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::hash;
+use crate::manifest::{Manifest, ManifestFile};
+
+/// What exported files are checked against
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Against {
+    /// The source files in the library
+    Sources,
+    /// The hashes recorded in the manifest, without reading the sources
+    Manifest,
+    /// The sizes of the source files (or as recorded, for sources that are gone), without
+    /// hashing anything
+    Sizes,
+}
+
+/// The state of one exported file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Status {
+    Ok,
+    Missing,
+    /// The contents differ from what was exported
+    Changed,
+    /// Shorter than its source, e.g. a copy cut off when the destination disconnected
+    Truncated {
+        expected: u64,
+        actual: u64,
+    },
+    /// Nothing to compare with: no recorded hash, or the source is gone
+    Unverifiable(String),
+}
+
+/// Check one exported file, found at `dest`. Against the sources, sizes are compared first,
+/// so truncated copies are told apart from changed ones without hashing them.
+pub fn check(file: &ManifestFile, dest: &Path, against: Against) -> Status {
+    let Ok(metadata) = fs::metadata(dest) else {
+        return Status::Missing;
+    };
+    if against != Against::Manifest {
+        let expected = match fs::metadata(&file.source) {
+            Ok(source) => source.len(),
+            Err(_) if against == Against::Sizes => file.size,
+            Err(e) => return Status::Unverifiable(format!("{:?}: {}", file.source, e)),
+        };
+        let actual = metadata.len();
+        if actual < expected {
+            return Status::Truncated { expected, actual };
+        } else if actual != expected {
+            return Status::Changed;
+        }
+    }
+    let expected = match against {
+        Against::Sizes => return Status::Ok,
+        Against::Manifest => match &file.sha256 {
+            Some(sha256) => sha256.clone(),
+            None => return Status::Unverifiable("no hash recorded".to_string()),
+        },
+        Against::Sources => match hash::sha256_file(&file.source) {
+            Ok(sha256) => sha256,
+            Err(e) => return Status::Unverifiable(format!("{:#}", e)),
+        },
+    };
+    match hash::sha256_file(dest) {
+        Ok(actual) if actual == expected => Status::Ok,
+        Ok(_) => Status::Changed,
+        Err(e) => Status::Unverifiable(format!("{:#}", e)),
+    }
+}
+
+/// Every exported file in the manifest, with where it is when the destination is at `dest`
+pub fn files<'a>(manifest: &'a Manifest, dest: &Path) -> Vec<(&'a ManifestFile, PathBuf)> {
+    manifest
+        .books
+        .values()
+        .flat_map(|entry| &entry.files)
+        .map(|file| (file, manifest.relocate(&file.dest, dest)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_check_against_manifest_and_sources() {
+        let dir = tempdir().unwrap();
+        let source = dir.path().join("01.mp3");
+        let dest = dir.path().join("Author/Book/01.mp3");
+        fs::create_dir_all(dest.parent().unwrap()).unwrap();
+        fs::write(&source, b"audio").unwrap();
+        fs::write(&dest, b"audio").unwrap();
+
+        let mut file = ManifestFile::new(&source, &dest).unwrap();
+        assert_eq!(
+            check(&file, &dest, Against::Manifest),
+            Status::Unverifiable("no hash recorded".to_string())
+        );
+        file.sha256 = Some(hash::sha256_file(&source).unwrap());
+        assert_eq!(check(&file, &dest, Against::Manifest), Status::Ok);
+        assert_eq!(check(&file, &dest, Against::Sources), Status::Ok);
+
+        fs::write(&dest, b"aud").unwrap();
+        let truncated = Status::Truncated {
+            expected: 5,
+            actual: 3,
+        };
+        assert_eq!(check(&file, &dest, Against::Sizes), truncated);
+        assert_eq!(check(&file, &dest, Against::Sources), truncated);
+        assert_eq!(check(&file, &dest, Against::Manifest), Status::Changed);
+        fs::write(&dest, b"AUDIO").unwrap();
+        assert_eq!(check(&file, &dest, Against::Sizes), Status::Ok);
+        assert_eq!(check(&file, &dest, Against::Sources), Status::Changed);
+        fs::write(&dest, b"audio").unwrap();
+
+        fs::remove_file(&source).unwrap();
+        assert_eq!(check(&file, &dest, Against::Sizes), Status::Ok);
+        assert_eq!(check(&file, &dest, Against::Manifest), Status::Ok);
+        assert!(matches!(
+            check(&file, &dest, Against::Sources),
+            Status::Unverifiable(_)
+        ));
+
+        fs::write(&dest, b"corrupted").unwrap();
+        assert_eq!(check(&file, &dest, Against::Manifest), Status::Changed);
+        fs::remove_file(&dest).unwrap();
+        assert_eq!(check(&file, &dest, Against::Manifest), Status::Missing);
+    }
+}